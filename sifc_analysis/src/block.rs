@@ -55,7 +55,7 @@ impl SifBlock {
     pub fn new(name: &str, id: usize) -> SifBlockRef {
         let block = SifBlock {
             name: name.to_string(),
-            id: id,
+            id,
             instrs: Vec::new(),
             edges: Vec::new(),
             preds: Vec::new(),
@@ -98,13 +98,13 @@ impl fmt::Debug for SifBlock {
         bl.push_str(&format!("DOM Frontier BIDs: {:#?}\n", self.dom_front));
         bl.push_str(&format!("Phis: {:#?}\n", self.phis));
 
-        bl.push_str(&format!("Instrs:\n"));
+        bl.push_str("Instrs:\n");
         for i in &self.instrs {
             // better to use display and not debug here, but technically both work
             bl.push_str(&format!("{:#}\n", i));
         }
 
-        bl.push_str("}");
+        bl.push('}');
         write!(f, "{}", bl)
     }
 }
@@ -2,12 +2,11 @@ use crate::{
     block::{SifBlock, SifBlockRef},
     dom,
 };
-use sifc_bytecode::{
-    instr::Instr,
-    opc::{JmpOpKind, Op},
-};
+use sifc_bytecode::{instr::Instr, opc::Op};
 use std::{cell::RefCell, collections::HashSet, rc::Rc};
 
+// CFG is how control flow graphs are usually written, and renaming it would break the api.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone)]
 pub struct CFG {
     pub num_nodes: usize,
@@ -34,8 +33,8 @@ impl CFG {
     // Overall we still run in O(n) time.
     // TODO: do we build an inter procedural cfg or treat each method as a separate cfg?
     // TODO: Do function calls split blocks from code section to decl section?
-    pub fn build(instrs: &Vec<Instr>) -> CFG {
-        if instrs.len() == 0 {
+    pub fn build(instrs: &[Instr]) -> CFG {
+        if instrs.is_empty() {
             Rc::new(RefCell::new(SifBlock::new("entry", usize::MAX)));
         }
 
@@ -80,9 +79,21 @@ impl CFG {
             let curr = &instrs[i];
             let prev = &instrs[i - 1];
 
+            // A block leader is reachable from the previous block, unless that block ended
+            // on a jump or a return.
+            if curr.lblidx != prev.lblidx {
+                match prev.op {
+                    Op::JmpCnd { .. } | Op::Jmpa { .. } | Op::FnRet => {}
+                    _ => {
+                        let prev_block = &mut nodes[prev.lblidx].borrow_mut();
+                        prev_block.edges.push(Rc::clone(&nodes[curr.lblidx]));
+                    }
+                };
+            }
+
             match &curr.op {
                 Op::JmpCnd {
-                    kind,
+                    kind: _,
                     src: _,
                     lblidx,
                 } => {
@@ -92,39 +103,18 @@ impl CFG {
                     let mut curr_block = nodes[curr.lblidx].borrow_mut();
                     curr_block.edges.push(Rc::clone(&nodes[*lblidx]));
 
-                    let target_idx = match kind {
-                        JmpOpKind::Jmpt => {
-                            let tidx = curr.lblidx + 1;
-                            if tidx >= nodes.len() {
-                                None
-                            } else {
-                                Some(tidx)
-                            }
-                        }
-                        JmpOpKind::Jmpf => Some(lblidx - 1),
-                    };
-
-                    if target_idx.is_some() {
-                        curr_block
-                            .edges
-                            .push(Rc::clone(&nodes[target_idx.unwrap()]));
+                    // Both kinds of jump fall through to the block directly after this one,
+                    // if there is one.
+                    let tidx = curr.lblidx + 1;
+                    if tidx < nodes.len() {
+                        curr_block.edges.push(Rc::clone(&nodes[tidx]));
                     }
                 }
                 Op::Jmpa { lblidx } => {
                     let curr_block = &mut nodes[curr.lblidx].borrow_mut();
                     curr_block.edges.push(Rc::clone(&nodes[*lblidx]));
                 }
-                _ => {
-                    if curr.lblidx != prev.lblidx {
-                        match prev.op {
-                            Op::JmpCnd { .. } | Op::Jmpa { .. } | Op::FnRet => {}
-                            _ => {
-                                let curr_block = &mut nodes[prev.lblidx].borrow_mut();
-                                curr_block.edges.push(Rc::clone(&nodes[curr.lblidx]));
-                            }
-                        };
-                    }
-                }
+                _ => {}
             };
             i += 1;
         }
@@ -137,7 +127,7 @@ impl CFG {
 
         CFG {
             num_nodes: nodes.len(),
-            nodes: nodes,
+            nodes,
             graph: entry_block,
             dom_tree: dtree,
         }
@@ -149,21 +139,20 @@ impl CFG {
 /// contains a list of nodes rather than just the direct predecessor. Because we use a HashSet to
 /// store nodes here, the order is not guaranteed and thus we cannot determine the direct predecessor
 /// from this list at a later point.
-fn build_preds(nodes: &Vec<SifBlockRef>, entry: SifBlockRef) {
+fn build_preds(nodes: &[SifBlockRef], entry: SifBlockRef) {
     let mut seen = HashSet::new();
     let mut stack = Vec::new();
     stack.push(Rc::clone(&entry));
 
-    while stack.len() != 0 {
-        let curr = stack.pop().unwrap();
-        let curr_id = curr.borrow().id.clone();
+    while let Some(curr) = stack.pop() {
+        let curr_id = curr.borrow().id;
 
         if !seen.contains(&curr_id) {
             for adj in &curr.borrow().edges {
                 let pred = Rc::clone(&nodes[curr_id]);
-                if !(adj.borrow().id == curr_id) {
+                if adj.borrow().id != curr_id {
                     adj.borrow_mut().preds.push(pred);
-                    stack.push(Rc::clone(&adj));
+                    stack.push(Rc::clone(adj));
                 }
             }
             seen.insert(curr_id);
@@ -108,7 +108,7 @@ fn dom_front_calc(nodes: &Vec<SifBlockRef>) {
 
         if node.borrow().preds.len() > 1 {
             for pred in &node.borrow().preds {
-                let mut runner = Rc::clone(&pred);
+                let mut runner = Rc::clone(pred);
 
                 while runner.borrow().id != node_idom {
                     if runner.borrow().id != node_id {
@@ -132,8 +132,8 @@ fn dom_front_calc(nodes: &Vec<SifBlockRef>) {
 /// to be called on the predecessor list of a block. It processes the
 /// preds and returns a set of block id's that are common in each
 /// predecessor's dominance set.
-fn dom_intersection(preds: &Vec<SifBlockRef>) -> HashSet<BlockID> {
-    if preds.len() == 0 {
+fn dom_intersection(preds: &[SifBlockRef]) -> HashSet<BlockID> {
+    if preds.is_empty() {
         return HashSet::new();
     }
 
@@ -198,19 +198,16 @@ impl DomTree {
         queue.push_front(Rc::clone(&nodes[0]));
         seen.insert(nodes[0].borrow().id);
 
-        while queue.len() != 0 {
+        while !queue.is_empty() {
             let curr = queue.pop_front().unwrap();
 
             for adj in &curr.borrow().edges {
                 if !seen.contains(&adj.borrow().id) {
-                    match adj.borrow().idom {
-                        Some(id) => {
-                            domtree_nodes[id].edges.push(adj.borrow().id);
-                        }
-                        None => {}
+                    if let Some(id) = adj.borrow().idom {
+                        domtree_nodes[id].edges.push(adj.borrow().id);
                     }
                     seen.insert(adj.borrow().id);
-                    queue.push_back(Rc::clone(&adj));
+                    queue.push_back(Rc::clone(adj));
                 }
             }
         }
@@ -304,7 +301,7 @@ mod tests {
         dom_front_calc(&blocks);
 
         let b0_dom_front = &blocks[0].borrow().dom_front;
-        assert!(b0_dom_front.len() == 0);
+        assert!(b0_dom_front.is_empty());
 
         let b1_dom_front = &blocks[1].borrow().dom_front;
         assert!(b1_dom_front.contains(&3));
@@ -315,7 +312,7 @@ mod tests {
         assert!(b2_dom_front.len() == 1);
 
         let b3_dom_front = &blocks[3].borrow().dom_front;
-        assert!(b3_dom_front.len() == 0);
+        assert!(b3_dom_front.is_empty());
     }
 
     #[test]
@@ -340,15 +337,15 @@ mod tests {
 
         let b1 = &domtree.nodes[1];
         assert!(b1.id == 1);
-        assert!(b1.edges.len() == 0);
+        assert!(b1.edges.is_empty());
 
         let b2 = &domtree.nodes[2];
         assert!(b2.id == 2);
-        assert!(b2.edges.len() == 0);
+        assert!(b2.edges.is_empty());
 
         let b3 = &domtree.nodes[3];
         assert!(b3.id == 3);
-        assert!(b3.edges.len() == 0);
+        assert!(b3.edges.is_empty());
     }
 
     /// Build a simple 4 node cfg that looks like this:
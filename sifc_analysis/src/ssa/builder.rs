@@ -33,18 +33,10 @@ pub struct SSABuilder<'c> {
     rwstack: HashMap<String, Vec<usize>>,
 }
 
-struct RWInstrs {
-    /// Vector of rewritten instructions with new variable names
-    pub instrs: Vec<Instr>,
-
-    /// Amount of instructions that were changed during rw_instrs phase
-    pub changed: usize,
-}
-
 impl<'c> SSABuilder<'c> {
     pub fn new(cfg: &'c CFG) -> SSABuilder<'c> {
         SSABuilder {
-            cfg: cfg,
+            cfg,
             globs: HashSet::new(),
             blks: HashMap::new(),
             rwcounter: HashMap::new(),
@@ -59,6 +51,7 @@ impl<'c> SSABuilder<'c> {
     ///    stored in SifBlock.phis, but can be considered to be at the "head" of the block.
     /// 3. Rewrite variable names in each block to ensure there is only 1 occurrence of each, including
     ///    in phi function operands.
+    ///
     /// This overwrites the blocks in the given CFG rather than returning a copy. After required analysis
     /// is done on SSA form, it can be translated back into regular SifIR form before execution or
     /// further translation.
@@ -177,7 +170,7 @@ impl<'c> SSABuilder<'c> {
                 queue.push_front(Rc::clone(&bref));
             }
 
-            while queue.len() != 0 {
+            while !queue.is_empty() {
                 let curr = queue.pop_front().unwrap();
 
                 // For each block in the dominance frontier, if that block does
@@ -198,7 +191,7 @@ impl<'c> SSABuilder<'c> {
                         let pops = Vec::new();
                         let phi = PhiFn::new(name.to_string(), name.to_string(), pops);
                         d.borrow_mut().phis.insert(name.to_string(), phi);
-                        queue.push_back(Rc::clone(&d));
+                        queue.push_back(Rc::clone(d));
                     }
                 }
             }
@@ -225,7 +218,7 @@ impl<'c> SSABuilder<'c> {
 
         // Rename instructions
         let rwinsts = self.rw_instrs(block.borrow().instrs.clone());
-        block.borrow_mut().instrs = rwinsts.instrs.clone();
+        block.borrow_mut().instrs = rwinsts.clone();
 
         // Recursively rename each immediate successor in the dom tree. dom_tree_node
         // is cloned, but as long as we recursively call with a reference
@@ -272,15 +265,15 @@ impl<'c> SSABuilder<'c> {
         }
 
         // Pop subscripts from rwstack for dest names in phis and instrs
-        for (_on, phi) in &block.borrow().phis {
+        for phi in block.borrow().phis.values() {
             self.pop_discard(&phi.dest);
         }
-        self.pop_remaining(rwinsts.instrs);
+        self.pop_remaining(rwinsts);
     }
 
-    fn rw_instrs(&mut self, instrs: Vec<Instr>) -> RWInstrs {
+    /// Returns the instructions with the variable names they set or load rewritten.
+    fn rw_instrs(&mut self, instrs: Vec<Instr>) -> Vec<Instr> {
         let mut newinsts = Vec::new();
-        let mut changed = 0;
 
         for i in &instrs {
             // we only care about instructions that set or load variables. Loading or setting
@@ -290,11 +283,8 @@ impl<'c> SSABuilder<'c> {
                     if let Some(subscript_stack) = self.rwstack.get(&name) {
                         let nn = format!("{}{}", name, self.top(subscript_stack));
 
-                        let new_op = Op::Ldn {
-                            dest: dest,
-                            name: nn,
-                        };
-                        let new_inst = Instr::new(i.lblidx, new_op, i.line);
+                        let new_op = Op::Ldn { dest, name: nn };
+                        let new_inst = i.with_op(new_op);
                         newinsts.push(new_inst);
                     }
                 }
@@ -302,11 +292,8 @@ impl<'c> SSABuilder<'c> {
                     if let Some(subscript_stack) = self.rwstack.get(&name) {
                         let nn = format!("{}{}", name, self.top(subscript_stack));
 
-                        let new_op = Op::Ldas {
-                            name: nn,
-                            dest: dest,
-                        };
-                        let new_inst = Instr::new(i.lblidx, new_op, i.line);
+                        let new_op = Op::Ldas { name: nn, dest };
+                        let new_inst = i.with_op(new_op);
                         newinsts.push(new_inst);
                     }
                 }
@@ -320,10 +307,10 @@ impl<'c> SSABuilder<'c> {
 
                         let new_op = Op::Ldav {
                             name: nn,
-                            idx_reg: idx_reg,
-                            dest: dest,
+                            idx_reg,
+                            dest,
                         };
-                        let new_inst = Instr::new(i.lblidx, new_op, i.line);
+                        let new_inst = i.with_op(new_op);
                         newinsts.push(new_inst);
                     }
                 }
@@ -337,10 +324,10 @@ impl<'c> SSABuilder<'c> {
 
                         let new_op = Op::Upda {
                             name: nn,
-                            idx_reg: idx_reg,
-                            val_reg: val_reg,
+                            idx_reg,
+                            val_reg,
                         };
-                        let new_inst = Instr::new(i.lblidx, new_op, i.line);
+                        let new_inst = i.with_op(new_op);
                         newinsts.push(new_inst);
                     }
                 }
@@ -348,30 +335,27 @@ impl<'c> SSABuilder<'c> {
                     if let Some(srcsubscript) = self.rwstack.get(&srcname) {
                         let nsrc = format!("{}{}", srcname, self.top(srcsubscript));
                         let ndest = self.newname(&destname);
-                        changed += 1;
 
                         let new_op = Op::Stn {
                             srcname: nsrc,
                             destname: ndest,
                         };
-                        let new_inst = Instr::new(i.lblidx, new_op, i.line);
+                        let new_inst = i.with_op(new_op);
                         newinsts.push(new_inst);
                     }
                 }
                 Op::Stc { val, name } => {
                     let nn = self.newname(&name);
-                    changed += 1;
 
-                    let new_op = Op::Stc { val: val, name: nn };
-                    let new_inst = Instr::new(i.lblidx, new_op, i.line);
+                    let new_op = Op::Stc { val, name: nn };
+                    let new_inst = i.with_op(new_op);
                     newinsts.push(new_inst);
                 }
                 Op::Str { src, name } => {
                     let nn = self.newname(&name);
-                    changed += 1;
 
-                    let new_op = Op::Str { src: src, name: nn };
-                    let new_inst = Instr::new(i.lblidx, new_op, i.line);
+                    let new_op = Op::Str { src, name: nn };
+                    let new_inst = i.with_op(new_op);
                     newinsts.push(new_inst);
                 }
                 Op::Tbli { tabname, key, src } => {
@@ -380,10 +364,10 @@ impl<'c> SSABuilder<'c> {
 
                         let new_op = Op::Tbli {
                             tabname: nn,
-                            key: key,
-                            src: src,
+                            key,
+                            src,
                         };
-                        let new_inst = Instr::new(i.lblidx, new_op, i.line);
+                        let new_inst = i.with_op(new_op);
                         newinsts.push(new_inst);
                     }
                 }
@@ -393,10 +377,10 @@ impl<'c> SSABuilder<'c> {
 
                         let new_op = Op::Tblg {
                             tabname: nn,
-                            key: key,
-                            dest: dest,
+                            key,
+                            dest,
                         };
-                        let new_inst = Instr::new(i.lblidx, new_op, i.line);
+                        let new_inst = i.with_op(new_op);
                         newinsts.push(new_inst);
                     }
                 }
@@ -404,10 +388,7 @@ impl<'c> SSABuilder<'c> {
             }
         }
 
-        RWInstrs {
-            instrs: newinsts,
-            changed: changed,
-        }
+        newinsts
     }
 
     // Anytime we call newname in rw_instrs, we need to pop from the appropriate stack here.
@@ -440,7 +421,7 @@ impl<'c> SSABuilder<'c> {
         format!("{}{}", old, i)
     }
 
-    fn top(&self, stack: &Vec<usize>) -> usize {
+    fn top(&self, stack: &[usize]) -> usize {
         stack[stack.len() - 1]
     }
 
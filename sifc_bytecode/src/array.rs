@@ -18,14 +18,14 @@ impl<'c> Compiler<'c> {
             Some(ast) => {
                 let items = self.arrayitems(ast);
                 self.push_op(Op::Stc {
-                    name: name,
-                    val: SifVal::Arr(items),
+                    name,
+                    val: SifVal::new_arr(items),
                 });
             }
             None => {
                 self.push_op(Op::Stc {
-                    name: name,
-                    val: SifVal::Arr(Vec::new()),
+                    name,
+                    val: SifVal::new_arr(Vec::new()),
                 });
             }
         };
@@ -37,7 +37,7 @@ impl<'c> Compiler<'c> {
         self.expr(index_expr);
 
         let op = Op::Ldav {
-            name: name,
+            name,
             idx_reg: self.prevreg(),
             dest: self.nextreg(),
         };
@@ -48,20 +48,17 @@ impl<'c> Compiler<'c> {
     fn arrayitems(&mut self, ast: &AstNode) -> Vec<SifVal> {
         let mut vals = Vec::new();
 
-        match ast {
-            AstNode::ArrayItems { items } => {
-                for item in items {
-                    match item {
-                        AstNode::PrimaryExpr { tkn } => {
-                            let sv = self.val_from_primary(tkn);
-                            vals.push(sv);
-                        }
-                        // TODO: need to process exprs inside array decls
-                        _ => unimplemented!("cannot generate ir for exprs in array decls"),
+        if let AstNode::ArrayItems { items } = ast {
+            for item in items {
+                match item {
+                    AstNode::PrimaryExpr { tkn } => {
+                        let sv = self.val_from_primary(tkn);
+                        vals.push(sv);
                     }
+                    // TODO: need to process exprs inside array decls
+                    _ => unimplemented!("cannot generate ir for exprs in array decls"),
                 }
             }
-            _ => {}
         };
         vals
     }
@@ -0,0 +1,908 @@
+use crate::{
+    compiler::CompileResult,
+    instr::Instr,
+    opc::{BinOpKind, JmpOpKind, Op, UnOpKind},
+    sifv::SifVal,
+};
+use sifc_err::{
+    asm_err::{AsmErr, AsmErrTy},
+    span::Span,
+};
+use std::collections::HashMap;
+
+/// Assembles the textual form of a program back into instructions. The text is expected
+/// to be in the same format that the printer writes (and that --emit-ir prints):
+///
+/// SECTION_ decls.
+/// lbl0:
+/// fn @add ["x", "y"]      ; 1, lbl0
+///      fstpop r0          ; 1, lbl0
+///      ...
+/// SECTION_ code.
+/// lbl1:
+///      ldc 10 r2          ; 3, lbl1
+///      call add 2         ; 3, lbl1
+///
+/// Each instr is placed under the most recent label line. The comment after an instr
+/// holds its line number, followed by an optional source span in the form 'line:col', and
+/// is optional so that programs can be written by hand. The label in the comment is
+/// ignored. Section lines are optional as well: without them, every instr is treated as
+/// part of the code section.
+///
+/// The jump and fn tables are rebuilt from the assembled program, so the result can be
+/// passed directly to the vm.
+pub fn assemble(src: &str) -> Result<CompileResult, AsmErr> {
+    let mut program = Vec::new();
+    let mut code_start = None;
+    let mut has_decls = false;
+    let mut lblidx = 0;
+
+    for (i, text) in src.lines().enumerate() {
+        let mut ln = AsmLine::new(text, i + 1);
+        if ln.at_end() {
+            continue;
+        }
+
+        let first = ln.word()?;
+
+        if first == "SECTION_" {
+            let section = ln.word()?;
+            match section.as_str() {
+                "decls." => has_decls = true,
+                "code." => code_start = Some(program.len()),
+                _ => return Err(ln.err(AsmErrTy::UnknownSection(section))),
+            };
+            ln.end()?;
+            continue;
+        }
+
+        ln.skip_ws();
+        if ln.peek() == Some(':') {
+            ln.pos += 1;
+            lblidx = lbl_idx(&first).ok_or(ln.err(AsmErrTy::InvalidLbl(first)))?;
+            ln.end()?;
+            continue;
+        }
+
+        let op = ln.op(&first)?;
+        let (line, span) = ln.comment()?;
+        let mut instr = Instr::new(lblidx, op, line);
+        instr.span = span;
+        program.push(instr);
+    }
+
+    let code_start = match code_start {
+        Some(start) => start,
+        None if has_decls => program.len(),
+        None => 0,
+    };
+    let (jumptab, fntab) = crate::tables::compute(&program);
+
+    Ok(CompileResult {
+        code: program[code_start..].to_vec(),
+        decls: program[..code_start].to_vec(),
+        program,
+        jumptab,
+        fntab,
+        code_start,
+        err: None,
+    })
+}
+
+fn lbl_idx(s: &str) -> Option<usize> {
+    match s.strip_prefix("lbl") {
+        Some(idx) => idx.parse::<usize>().ok(),
+        None => None,
+    }
+}
+
+fn bin_kind(s: &str) -> Option<BinOpKind> {
+    match s {
+        "add" => Some(BinOpKind::Add),
+        "sub" => Some(BinOpKind::Sub),
+        "mul" => Some(BinOpKind::Mul),
+        "div" => Some(BinOpKind::Div),
+        "mod" => Some(BinOpKind::Modu),
+        "eq" => Some(BinOpKind::Eq),
+        "neq" => Some(BinOpKind::Neq),
+        "lteq" => Some(BinOpKind::LtEq),
+        "lt" => Some(BinOpKind::Lt),
+        "gteq" => Some(BinOpKind::GtEq),
+        "gt" => Some(BinOpKind::Gt),
+        "and" => Some(BinOpKind::Land),
+        "not" => Some(BinOpKind::Lnot),
+        "or" => Some(BinOpKind::Lor),
+        _ => None,
+    }
+}
+
+fn un_kind(s: &str) -> Option<UnOpKind> {
+    match s {
+        "lneg" => Some(UnOpKind::Lneg),
+        "nneg" => Some(UnOpKind::Nneg),
+        _ => None,
+    }
+}
+
+fn jmp_kind(s: &str) -> Option<JmpOpKind> {
+    match s {
+        "jmpt" => Some(JmpOpKind::Jmpt),
+        "jmpf" => Some(JmpOpKind::Jmpf),
+        _ => None,
+    }
+}
+
+/// Characters that end a word, in addition to whitespace.
+const DELIMS: &str = ",[]{}<>:;\"";
+
+/// AsmLine reads the operands of a single line of assembly text.
+struct AsmLine {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl AsmLine {
+    fn new(text: &str, line: usize) -> AsmLine {
+        AsmLine {
+            chars: text.chars().collect(),
+            pos: 0,
+            line,
+        }
+    }
+
+    fn err(&self, ty: AsmErrTy) -> AsmErr {
+        AsmErr::new(ty, self.line)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn rest(&self) -> String {
+        self.chars[self.pos..].iter().collect()
+    }
+
+    /// Returns true if there is nothing left on the line except a comment.
+    fn at_end(&mut self) -> bool {
+        self.skip_ws();
+        matches!(self.peek(), None | Some(';'))
+    }
+
+    /// Ensures that nothing other than a comment follows on the line.
+    fn end(&mut self) -> Result<(), AsmErr> {
+        match self.at_end() {
+            true => Ok(()),
+            false => Err(self.err(AsmErrTy::TrailingInput(self.rest()))),
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), AsmErr> {
+        self.skip_ws();
+        match self.peek() {
+            Some(next) if next == c => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(self.err(AsmErrTy::TrailingInput(self.rest()))),
+            None => Err(self.err(AsmErrTy::UnexpectedEol)),
+        }
+    }
+
+    fn word(&mut self) -> Result<String, AsmErr> {
+        self.skip_ws();
+        let start = self.pos;
+        while self.pos < self.chars.len()
+            && !self.chars[self.pos].is_whitespace()
+            && !DELIMS.contains(self.chars[self.pos])
+        {
+            self.pos += 1;
+        }
+
+        if start == self.pos {
+            return match self.peek() {
+                None => Err(self.err(AsmErrTy::UnexpectedEol)),
+                Some(_) => Err(self.err(AsmErrTy::TrailingInput(self.rest()))),
+            };
+        }
+
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn reg(&mut self) -> Result<usize, AsmErr> {
+        let w = self.word()?;
+        let reg = match w.strip_prefix('r') {
+            Some(r) => r.parse::<usize>().ok(),
+            None => None,
+        };
+        reg.ok_or(self.err(AsmErrTy::InvalidReg(w)))
+    }
+
+    fn slot(&mut self) -> Result<usize, AsmErr> {
+        let w = self.word()?;
+        let slot = match w.strip_prefix('g') {
+            Some(g) => g.parse::<usize>().ok(),
+            None => None,
+        };
+        slot.ok_or(self.err(AsmErrTy::InvalidSlot(w)))
+    }
+
+    fn lbl(&mut self) -> Result<usize, AsmErr> {
+        let w = self.word()?;
+        lbl_idx(&w).ok_or(self.err(AsmErrTy::InvalidLbl(w)))
+    }
+
+    fn count(&mut self) -> Result<usize, AsmErr> {
+        let w = self.word()?;
+        w.parse::<usize>()
+            .map_err(|_| self.err(AsmErrTy::InvalidNum(w)))
+    }
+
+    /// Reads a fn name, which is prefixed with '@'.
+    fn fn_name(&mut self) -> Result<String, AsmErr> {
+        let w = self.word()?;
+        match w.strip_prefix('@') {
+            Some(name) if !name.is_empty() => Ok(name.to_string()),
+            _ => Err(self.err(AsmErrTy::TrailingInput(w))),
+        }
+    }
+
+    /// Reads a quoted string, using the same escapes that the printer writes.
+    fn string(&mut self) -> Result<String, AsmErr> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err(self.err(AsmErrTy::UnterminatedStr)),
+            };
+            self.pos += 1;
+
+            match c {
+                '"' => return Ok(s),
+                '\\' => s.push(self.escape()?),
+                _ => s.push(c),
+            };
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, AsmErr> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err(self.err(AsmErrTy::UnterminatedStr)),
+        };
+        self.pos += 1;
+
+        match c {
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            '0' => Ok('\0'),
+            '\\' | '"' | '\'' => Ok(c),
+            'u' => {
+                self.expect('{')?;
+                let start = self.pos;
+                while self.pos < self.chars.len() && self.chars[self.pos].is_ascii_hexdigit() {
+                    self.pos += 1;
+                }
+                let hex: String = self.chars[start..self.pos].iter().collect();
+                self.expect('}')?;
+
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(ch) => Ok(ch),
+                    None => Err(self.err(AsmErrTy::InvalidEscape('u'))),
+                }
+            }
+            _ => Err(self.err(AsmErrTy::InvalidEscape(c))),
+        }
+    }
+
+    /// Reads a list of quoted strings, like the params of a fn.
+    fn str_list(&mut self) -> Result<Vec<String>, AsmErr> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_ws();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(items);
+        }
+
+        loop {
+            items.push(self.string()?);
+            self.skip_ws();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                _ => break,
+            };
+        }
+
+        self.expect(']')?;
+        Ok(items)
+    }
+
+    /// Reads a constant value, as written by the ldc and stc instrs.
+    fn val(&mut self) -> Result<SifVal, AsmErr> {
+        self.skip_ws();
+        match self.peek() {
+            Some('"') => Ok(SifVal::Str(self.string()?)),
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_ws();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(SifVal::new_arr(items));
+                }
+
+                loop {
+                    items.push(self.val()?);
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        _ => break,
+                    };
+                }
+
+                self.expect(']')?;
+                Ok(SifVal::new_arr(items))
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut items = HashMap::new();
+                self.skip_ws();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(SifVal::new_tab(items));
+                }
+
+                loop {
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    items.insert(key, self.val()?);
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        _ => break,
+                    };
+                }
+
+                self.expect('}')?;
+                Ok(SifVal::new_tab(items))
+            }
+            Some('<') => {
+                self.pos += 1;
+                let kw = self.word()?;
+                if kw != "fn" {
+                    return Err(self.err(AsmErrTy::InvalidVal(kw)));
+                }
+                let name = self.word()?;
+                self.expect('>')?;
+                Ok(SifVal::Fn {
+                    name,
+                    env: HashMap::new(),
+                })
+            }
+            _ => {
+                let w = self.word()?;
+                match w.as_str() {
+                    "true" => Ok(SifVal::Bl(true)),
+                    "false" => Ok(SifVal::Bl(false)),
+                    "null" => Ok(SifVal::Null),
+                    _ => match w.parse::<f64>() {
+                        Ok(n) => Ok(SifVal::Num(n)),
+                        Err(_) => Err(self.err(AsmErrTy::InvalidVal(w))),
+                    },
+                }
+            }
+        }
+    }
+
+    /// Reads the operands of the op with the given mnemonic.
+    fn op(&mut self, mnemonic: &str) -> Result<Op, AsmErr> {
+        if let Some(kind) = bin_kind(mnemonic) {
+            return Ok(Op::Binary {
+                kind,
+                src1: self.reg()?,
+                src2: self.reg()?,
+                dest: self.reg()?,
+            });
+        };
+
+        if let Some(kind) = un_kind(mnemonic) {
+            return Ok(Op::Unary {
+                kind,
+                src1: self.reg()?,
+                dest: self.reg()?,
+            });
+        };
+
+        if let Some(kind) = jmp_kind(mnemonic) {
+            return Ok(Op::JmpCnd {
+                kind,
+                src: self.reg()?,
+                lblidx: self.lbl()?,
+            });
+        };
+
+        let op = match mnemonic {
+            "ldc" => {
+                let val = self.val()?;
+                Op::Ldc {
+                    val,
+                    dest: self.reg()?,
+                }
+            }
+            "ldn" => {
+                let name = self.word()?;
+                Op::Ldn {
+                    name,
+                    dest: self.reg()?,
+                }
+            }
+            "mv" => Op::Mv {
+                src: self.reg()?,
+                dest: self.reg()?,
+            },
+            "ldas" => {
+                let name = self.word()?;
+                Op::Ldas {
+                    name,
+                    dest: self.reg()?,
+                }
+            }
+            "ldav" => {
+                let name = self.word()?;
+                Op::Ldav {
+                    name,
+                    idx_reg: self.reg()?,
+                    dest: self.reg()?,
+                }
+            }
+            "upda" => {
+                let name = self.word()?;
+                Op::Upda {
+                    name,
+                    idx_reg: self.reg()?,
+                    val_reg: self.reg()?,
+                }
+            }
+            "stc" => {
+                let val = self.val()?;
+                Op::Stc {
+                    val,
+                    name: self.word()?,
+                }
+            }
+            "stn" => Op::Stn {
+                srcname: self.word()?,
+                destname: self.word()?,
+            },
+            "str" => {
+                let src = self.reg()?;
+                Op::Str {
+                    src,
+                    name: self.word()?,
+                }
+            }
+            "jmpa" => Op::Jmpa {
+                lblidx: self.lbl()?,
+            },
+            "incrr" => Op::Incrr { src: self.reg()? },
+            "decrr" => Op::Decrr { src: self.reg()? },
+            "fn" => {
+                let name = self.fn_name()?;
+                let params = self.str_list()?;
+                // Locals are only printed when the fn has any.
+                self.skip_ws();
+                let locals = match self.peek() {
+                    Some('[') => self.str_list()?,
+                    _ => Vec::new(),
+                };
+
+                Op::Fn {
+                    name,
+                    params,
+                    locals,
+                }
+            }
+            "ret" => Op::FnRet,
+            "call" => {
+                let name = self.word()?;
+                Op::Call {
+                    name,
+                    param_count: self.count()?,
+                }
+            }
+            "callr" => {
+                let src = self.reg()?;
+                Op::Callr {
+                    src,
+                    param_count: self.count()?,
+                }
+            }
+            "ldf" => {
+                let name = self.fn_name()?;
+                let env = self.str_list()?;
+                Op::Ldf {
+                    name,
+                    env,
+                    dest: self.reg()?,
+                }
+            }
+            "stdcall" => {
+                let name = self.word()?;
+                Op::StdCall {
+                    name,
+                    param_count: self.count()?,
+                }
+            }
+            "fstpush" => Op::FnStackPush { src: self.reg()? },
+            "fstpop" => Op::FnStackPop { dest: self.reg()? },
+            "tbli" => {
+                let src = self.reg()?;
+                let key = self.word()?;
+                Op::Tbli {
+                    src,
+                    key,
+                    tabname: self.word()?,
+                }
+            }
+            "tblg" => {
+                let tabname = self.word()?;
+                let key = self.word()?;
+                Op::Tblg {
+                    tabname,
+                    key,
+                    dest: self.reg()?,
+                }
+            }
+            "ldg" => Op::Ldg {
+                slot: self.slot()?,
+                dest: self.reg()?,
+            },
+            "strg" => Op::Strg {
+                src: self.reg()?,
+                slot: self.slot()?,
+            },
+            "stcg" => {
+                let val = self.val()?;
+                Op::Stcg {
+                    val,
+                    slot: self.slot()?,
+                }
+            }
+            "ldasg" => Op::Ldasg {
+                slot: self.slot()?,
+                dest: self.reg()?,
+            },
+            "ldavg" => Op::Ldavg {
+                slot: self.slot()?,
+                idx_reg: self.reg()?,
+                dest: self.reg()?,
+            },
+            "updag" => Op::Updag {
+                slot: self.slot()?,
+                idx_reg: self.reg()?,
+                val_reg: self.reg()?,
+            },
+            "tblig" => {
+                let src = self.reg()?;
+                let key = self.word()?;
+                Op::Tblig {
+                    src,
+                    key,
+                    slot: self.slot()?,
+                }
+            }
+            "tblgg" => {
+                let slot = self.slot()?;
+                let key = self.word()?;
+                Op::Tblgg {
+                    slot,
+                    key,
+                    dest: self.reg()?,
+                }
+            }
+            "nop" => Op::Nop,
+            "stop" => Op::Stop,
+            _ => return Err(self.err(AsmErrTy::UnknownOp(mnemonic.to_string()))),
+        };
+
+        Ok(op)
+    }
+
+    /// Reads the comment following an instr, in the form '; line, label' or
+    /// '; line, label, line:col', and returns the line and the source span. If there is no
+    /// comment, the line is 0, and the span is unknown if it's left out.
+    fn comment(&mut self) -> Result<(usize, Span), AsmErr> {
+        self.skip_ws();
+        match self.peek() {
+            None => return Ok((0, Span::default())),
+            Some(';') => self.pos += 1,
+            Some(_) => return Err(self.err(AsmErrTy::TrailingInput(self.rest()))),
+        };
+
+        let text = self.rest();
+        let invalid = self.err(AsmErrTy::InvalidComment(text.trim().to_string()));
+        let mut fields = text.split(',').map(|f| f.trim());
+
+        let line = match fields.next() {
+            Some(l) => l.parse::<usize>().map_err(|_| invalid.clone())?,
+            None => return Err(invalid),
+        };
+        fields.next();
+        let span = match fields.next() {
+            Some(pos) => match pos.split_once(':') {
+                Some((l, c)) => match (l.parse::<usize>(), c.parse::<usize>()) {
+                    (Ok(l), Ok(c)) => Span::new(l, c),
+                    _ => return Err(invalid),
+                },
+                None => return Err(invalid),
+            },
+            None => Span::default(),
+        };
+
+        Ok((line, span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemble_sections() {
+        let src = "
+SECTION_ decls.
+lbl0:
+fn @add [\"x\", \"y\"]\t ; 1, lbl0
+\t fstpop r0\t ; 1, lbl0
+\t ret \t\t ; 1, lbl0
+SECTION_ code.
+lbl1:
+\t ldc 10 r1\t ; 3, lbl1
+\t fstpush r1\t ; 3, lbl1
+\t call add 1\t ; 3, lbl1
+\tstop\t\t ; 4
+";
+        let result = assemble(src).unwrap();
+        assert_eq!(result.code_start, 3);
+        assert_eq!(result.decls.len(), 3);
+        assert_eq!(result.code.len(), 4);
+        assert_eq!(result.fntab.get("add"), Some(&0));
+        assert_eq!(result.jumptab.get(&1), Some(&3));
+        assert_eq!(
+            result.program[0].op,
+            Op::Fn {
+                name: String::from("add"),
+                params: vec![String::from("x"), String::from("y")],
+                locals: Vec::new(),
+            }
+        );
+        assert_eq!(
+            result.program[5].op,
+            Op::Call {
+                name: String::from("add"),
+                param_count: 1,
+            }
+        );
+        assert_eq!(result.program[5].line, 3);
+        assert_eq!(result.program[5].lblidx, 1);
+        assert_eq!(result.program[6].line, 4);
+    }
+
+    #[test]
+    fn assemble_no_sections() {
+        let src = "ldc 1 r0\nldc 2 r1\nadd r0 r1 r2\n";
+        let result = assemble(src).unwrap();
+        assert_eq!(result.code_start, 0);
+        assert_eq!(result.code.len(), 3);
+        assert_eq!(result.program[2].line, 0);
+        assert_eq!(result.program[2].lblidx, 0);
+    }
+
+    #[test]
+    fn assemble_vals() {
+        let src = r#"stc "a \"b\"; c\n" x
+ldc [1, "two", [true, null]] r0
+ldc {"k": -1.5} r1
+ldc <fn f> r2"#;
+        let result = assemble(src).unwrap();
+
+        let mut tab = HashMap::new();
+        tab.insert(String::from("k"), SifVal::Num(-1.5));
+        let vals: Vec<Op> = result.program.into_iter().map(|i| i.op).collect();
+        assert_eq!(
+            vals,
+            vec![
+                Op::Stc {
+                    val: SifVal::Str(String::from("a \"b\"; c\n")),
+                    name: String::from("x"),
+                },
+                Op::Ldc {
+                    val: SifVal::new_arr(vec![
+                        SifVal::Num(1.0),
+                        SifVal::Str(String::from("two")),
+                        SifVal::new_arr(vec![SifVal::Bl(true), SifVal::Null]),
+                    ]),
+                    dest: 0,
+                },
+                Op::Ldc {
+                    val: SifVal::new_tab(tab),
+                    dest: 1,
+                },
+                Op::Ldc {
+                    val: SifVal::Fn {
+                        name: String::from("f"),
+                        env: HashMap::new(),
+                    },
+                    dest: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_spans() {
+        let result = assemble("ldc 1 r0\t ; 1, lbl0, 4:9\nmv r0 r1\t ; 2, lbl0\n")
+            .ok()
+            .unwrap();
+        assert_eq!(result.program[0].line, 1);
+        assert_eq!(result.program[0].span, Span::new(4, 9));
+        assert!(!result.program[1].span.is_known());
+        assert_eq!(
+            format!("{:?}", result.program[0]),
+            "\t ldc 1 r0\t ; 1, lbl0, 4:9\n"
+        );
+
+        let err = assemble("ldc 1 r0\t ; 1, lbl0, 4\n").err().unwrap();
+        assert_eq!(err.ty, AsmErrTy::InvalidComment(String::from("1, lbl0, 4")));
+    }
+
+    #[test]
+    fn assemble_slots() {
+        let program = vec![
+            Instr::new(0, Op::Ldg { dest: 0, slot: 1 }, 1),
+            Instr::new(0, Op::Strg { src: 0, slot: 0 }, 1),
+            Instr::new(
+                0,
+                Op::Stcg {
+                    val: SifVal::Str(String::from("s")),
+                    slot: 2,
+                },
+                2,
+            ),
+            Instr::new(
+                0,
+                Op::Updag {
+                    slot: 3,
+                    idx_reg: 1,
+                    val_reg: 2,
+                },
+                3,
+            ),
+            Instr::new(
+                0,
+                Op::Tblgg {
+                    slot: 4,
+                    key: String::from("k"),
+                    dest: 5,
+                },
+                4,
+            ),
+        ];
+
+        let result = assemble(&crate::printer::code_str(program.clone())).unwrap();
+        assert_eq!(result.program, program);
+        assert_eq!(
+            asm_err("ldg x r0").ty,
+            AsmErrTy::InvalidSlot(String::from("x"))
+        );
+    }
+
+    #[test]
+    fn assemble_roundtrip_printer() {
+        let program = vec![
+            Instr::new(
+                0,
+                Op::Fn {
+                    name: String::from("anon$0"),
+                    params: vec![String::from("n")],
+                    locals: vec![String::from("c")],
+                },
+                1,
+            ),
+            Instr::new(0, Op::FnStackPop { dest: 0 }, 1),
+            Instr::new(0, Op::FnRet, 1),
+            Instr::new(
+                1,
+                Op::Ldf {
+                    name: String::from("anon$0"),
+                    env: vec![String::from("c")],
+                    dest: 1,
+                },
+                2,
+            ),
+            Instr::new(
+                1,
+                Op::Upda {
+                    name: String::from("a"),
+                    idx_reg: 2,
+                    val_reg: 3,
+                },
+                3,
+            ),
+            Instr::new(
+                2,
+                Op::Tbli {
+                    tabname: String::from("t"),
+                    key: String::from("k"),
+                    src: 1,
+                },
+                4,
+            ),
+            Instr::new(
+                2,
+                Op::Callr {
+                    src: 1,
+                    param_count: 2,
+                },
+                5,
+            ),
+            Instr::new(2, Op::Stop, 6),
+        ];
+
+        let text = format!(
+            "{}{}",
+            crate::printer::decls_str(program[..3].to_vec()),
+            crate::printer::code_str(program[3..].to_vec())
+        );
+        let result = assemble(&text).unwrap();
+        assert_eq!(result.program, program);
+        assert_eq!(result.code_start, 3);
+    }
+
+    fn asm_err(src: &str) -> AsmErr {
+        match assemble(src) {
+            Ok(_) => panic!("expected assembler error"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn unknown_op() {
+        let err = asm_err("nop\nfoo r0");
+        assert_eq!(err.ty, AsmErrTy::UnknownOp(String::from("foo")));
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn invalid_reg() {
+        let err = asm_err("mv r0 x1");
+        assert_eq!(err.ty, AsmErrTy::InvalidReg(String::from("x1")));
+    }
+
+    #[test]
+    fn missing_operand() {
+        let err = asm_err("add r0 r1");
+        assert_eq!(err.ty, AsmErrTy::UnexpectedEol);
+    }
+
+    #[test]
+    fn unterminated_str() {
+        let err = asm_err("ldc \"abc r0");
+        assert_eq!(err.ty, AsmErrTy::UnterminatedStr);
+    }
+
+    #[test]
+    fn trailing_input() {
+        let err = asm_err("incrr r0 r1");
+        assert_eq!(err.ty, AsmErrTy::TrailingInput(String::from("r1")));
+    }
+}
@@ -0,0 +1,150 @@
+use sifc_parse::{
+    ast::AstNode,
+    token::{Token, TokenTy},
+};
+
+/// Computes the names a function captures from the function enclosing it. These are the
+/// names referenced anywhere in the function body (including nested functions) that
+/// are locals of the enclosing function, excluding names shadowed by the function params.
+pub fn captured_names(fn_params: &AstNode, fn_body: &AstNode, enclosing: &[String]) -> Vec<String> {
+    if enclosing.is_empty() {
+        return Vec::new();
+    }
+
+    let mut params = Vec::new();
+    add_names(fn_params, &mut params);
+
+    let mut referenced = Vec::new();
+    add_names(fn_body, &mut referenced);
+
+    let mut captures = Vec::new();
+    for name in referenced {
+        if enclosing.contains(&name) && !params.contains(&name) && !captures.contains(&name) {
+            captures.push(name);
+        }
+    }
+
+    captures
+}
+
+/// Adds every identifier found in the ast to names. Names may be added more than once.
+fn add_names(ast: &AstNode, names: &mut Vec<String>) {
+    match ast {
+        AstNode::Program { blocks } => add_all_names(blocks, names),
+        AstNode::Block { decls, .. } => add_all_names(decls, names),
+        AstNode::IfStmt {
+            cond_expr,
+            if_stmts,
+            elif_exprs,
+            else_stmts,
+        } => {
+            add_names(cond_expr, names);
+            add_names(if_stmts, names);
+            add_all_names(elif_exprs, names);
+            add_all_names(else_stmts, names);
+        }
+        AstNode::ElifStmt { cond_expr, stmts } | AstNode::WhileStmt { cond_expr, stmts } => {
+            add_names(cond_expr, names);
+            add_names(stmts, names);
+        }
+        AstNode::ForStmt {
+            var_list,
+            in_expr_list,
+            stmts,
+        } => {
+            add_names(var_list, names);
+            add_names(in_expr_list, names);
+            add_names(stmts, names);
+        }
+        AstNode::ReturnStmt { ret_expr } => {
+            if let Some(expr) = ret_expr {
+                add_names(expr, names)
+            }
+        }
+        AstNode::ExprStmt { expr } => add_names(expr, names),
+        AstNode::VarDecl { ident_tkn, rhs, .. } => {
+            add_tkn_name(ident_tkn, names);
+            if let Some(expr) = rhs {
+                add_names(expr, names)
+            };
+        }
+        AstNode::FnDecl {
+            fn_params, fn_body, ..
+        }
+        | AstNode::FnExpr {
+            fn_params, fn_body, ..
+        } => {
+            add_names(fn_params, names);
+            add_names(fn_body, names);
+        }
+        AstNode::FnParams { params } => add_all_names(params, names),
+        AstNode::IdentPair { idents } => add_all_names(idents, names),
+        AstNode::ItemList { items } => {
+            for item in items.values() {
+                add_names(item, names);
+            }
+        }
+        AstNode::TableItem { key, val } => {
+            add_names(key, names);
+            add_names(val, names);
+        }
+        AstNode::Table { ident_tkn, items } => {
+            add_tkn_name(ident_tkn, names);
+            add_names(items, names);
+        }
+        AstNode::TableAccess { table_tkn, .. } => add_tkn_name(table_tkn, names),
+        AstNode::Array {
+            ident_tkn, body, ..
+        } => {
+            add_tkn_name(ident_tkn, names);
+            if let Some(items) = body {
+                add_names(items, names)
+            };
+        }
+        AstNode::ArrayItems { items } => add_all_names(items, names),
+        AstNode::ArrayAccess { array_tkn, index } => {
+            add_tkn_name(array_tkn, names);
+            add_names(index, names);
+        }
+        AstNode::ArrayMutExpr {
+            array_tkn,
+            index,
+            rhs,
+        } => {
+            add_tkn_name(array_tkn, names);
+            add_names(index, names);
+            add_names(rhs, names);
+        }
+        AstNode::FnCallExpr {
+            fn_ident_tkn,
+            fn_params,
+            ..
+        } => {
+            add_tkn_name(fn_ident_tkn, names);
+            add_all_names(fn_params, names);
+        }
+        AstNode::VarAssignExpr { ident_tkn, rhs, .. } => {
+            add_tkn_name(ident_tkn, names);
+            add_names(rhs, names);
+        }
+        AstNode::BinaryExpr { lhs, rhs, .. } => {
+            add_names(lhs, names);
+            add_names(rhs, names);
+        }
+        AstNode::UnaryExpr { rhs, .. } => add_names(rhs, names),
+        AstNode::PrimaryExpr { tkn } => add_tkn_name(tkn, names),
+        AstNode::BreakStmt | AstNode::ContinueStmt | AstNode::Null => {}
+    };
+}
+
+fn add_all_names(asts: &Vec<AstNode>, names: &mut Vec<String>) {
+    for ast in asts {
+        add_names(ast, names);
+    }
+}
+
+fn add_tkn_name(tkn: &Token, names: &mut Vec<String>) {
+    if let TokenTy::Ident(name) = &tkn.ty {
+        names.push(name.clone())
+    };
+}
@@ -2,9 +2,13 @@ use crate::{
     instr::Instr,
     opc::{BinOpKind, Op, UnOpKind},
     sifv::SifVal,
+    stmt::LoopCtx,
 };
 
-use sifc_err::compile_err::{CompileErr, CompileErrTy};
+use sifc_err::{
+    compile_err::{CompileErr, CompileErrTy},
+    span::Span,
+};
 
 use sifc_parse::{
     ast::AstNode,
@@ -89,6 +93,32 @@ pub struct Compiler<'c> {
     // This is really more of a hack, we should process things without having to
     // switch this flag on and off for decls...
     decl_scope: bool,
+
+    /// Names declared inside the function currently being compiled, starting with its
+    /// params. These are stored on the fn declaration so the vm can place them in the
+    /// call frame instead of the global heap.
+    locals: Vec<String>,
+
+    /// Names captured from the enclosing function by the function currently being compiled.
+    /// Captures are copies of the enclosing locals, so assigning to one is an error unless
+    /// the function declares the name again itself.
+    captures: Vec<String>,
+
+    /// Number of anonymous functions compiled so far, used to generate a unique name
+    /// for each of them.
+    anoncnt: usize,
+
+    /// Stack of loops enclosing the statement being compiled. The innermost loop is last,
+    /// and receives the jumps generated by any break or continue statements.
+    loops: Vec<LoopCtx>,
+
+    /// Span of the statement or expression being compiled. Each instr is given the span
+    /// that was current when it was pushed.
+    span: Span,
+
+    /// First error found while compiling. Compilation carries on to the end, but the
+    /// result must not be run if this is set.
+    err: Option<CompileErr>,
 }
 
 impl<'c> Compiler<'c> {
@@ -100,17 +130,33 @@ impl<'c> Compiler<'c> {
             lblcnt: 0,
             ri: 0,
             decl_scope: false,
+            locals: Vec::new(),
+            captures: Vec::new(),
+            anoncnt: 0,
+            loops: Vec::new(),
+            span: Span::default(),
+            err: None,
         }
     }
 
-    pub fn compile(&mut self) -> CompileResult {
-        let mut currerr = None;
+    /// Continues numbering anonymous functions from count, instead of from 0. Programs that
+    /// are compiled separately but loaded into the same vm must not reuse fn names, so each
+    /// compiler should resume from the anon_count() of the one before it.
+    pub fn resume_anon(&mut self, count: usize) {
+        self.anoncnt = count;
+    }
+
+    /// Returns the number of anonymous functions numbered so far.
+    pub fn anon_count(&self) -> usize {
+        self.anoncnt
+    }
 
+    pub fn compile(&mut self) -> CompileResult {
         match self.ast {
             AstNode::Program { blocks } => {
                 self.blocks(blocks.to_vec());
             }
-            _ => currerr = Some(CompileErr::new(CompileErrTy::InvalidAst)),
+            _ => self.error(CompileErr::new(CompileErrTy::InvalidAst)),
         };
 
         let mut prog_vec = self.decls.clone();
@@ -122,15 +168,22 @@ impl<'c> Compiler<'c> {
             code: self.ops.to_vec(),
             decls: self.decls.to_vec(),
             program: prog_vec,
-            jumptab: jumptab,
-            fntab: fntab,
+            jumptab,
+            fntab,
             code_start: self.decls.len(),
-            err: currerr,
+            err: self.err.take(),
+        }
+    }
+
+    /// Records an error, unless an earlier one was already found.
+    fn error(&mut self, err: CompileErr) {
+        if self.err.is_none() {
+            self.err = Some(err);
         }
     }
 
     pub fn blocks(&mut self, blocks: Vec<AstNode>) {
-        if blocks.len() == 0 {
+        if blocks.is_empty() {
             self.push_op(Op::Nop);
             return;
         }
@@ -141,6 +194,8 @@ impl<'c> Compiler<'c> {
     }
 
     pub fn block(&mut self, block: &AstNode) {
+        let outer_span = self.enter_span(block);
+
         match block {
             AstNode::Block { decls, .. } => self.blocks(decls.to_vec()),
             AstNode::ExprStmt { expr } => self.expr(expr),
@@ -165,6 +220,9 @@ impl<'c> Compiler<'c> {
                 in_expr_list,
                 stmts,
             } => self.forstmt(var_list, in_expr_list, stmts),
+            AstNode::WhileStmt { cond_expr, stmts } => self.whilestmt(cond_expr, stmts),
+            AstNode::BreakStmt => self.breakstmt(),
+            AstNode::ContinueStmt => self.contstmt(),
             AstNode::FnDecl {
                 ident_tkn,
                 fn_params,
@@ -176,7 +234,20 @@ impl<'c> Compiler<'c> {
                 // generate nothing if we find some unknown block
                 // TODO: eventually error here
             }
-        }
+        };
+
+        self.span = outer_span;
+    }
+
+    /// Makes the span of a node the current span, if it has one, and returns the previous
+    /// span. Callers restore the previous span once the node is compiled, so that instrs
+    /// generated after a nested node are given the span of the enclosing one.
+    fn enter_span(&mut self, node: &AstNode) -> Span {
+        let outer_span = self.span;
+        if let Some(span) = node.span() {
+            self.span = span
+        };
+        outer_span
     }
 
     pub fn lblcnt(&self) -> usize {
@@ -184,7 +255,7 @@ impl<'c> Compiler<'c> {
     }
 
     pub fn newlbl(&mut self) {
-        self.lblcnt = self.lblcnt + 1;
+        self.lblcnt += 1;
     }
 
     pub fn buildlbl(&self, cnt: usize) -> String {
@@ -197,7 +268,7 @@ impl<'c> Compiler<'c> {
 
     pub fn nextreg(&mut self) -> usize {
         let reg = self.ri;
-        self.ri = self.ri + 1;
+        self.ri += 1;
         reg
     }
 
@@ -213,6 +284,20 @@ impl<'c> Compiler<'c> {
         self.decl_scope
     }
 
+    pub fn loops(&mut self) -> &mut Vec<LoopCtx> {
+        &mut self.loops
+    }
+
+    /// Records a name declared inside a function body as a local of that function.
+    /// Names declared outside of functions are globals, and are not recorded. A declared
+    /// name is no longer a capture, since it now refers to the function's own variable.
+    pub fn declare_local(&mut self, name: &str) {
+        self.captures.retain(|c| c != name);
+        if self.decl_scope && !self.locals.iter().any(|l| l == name) {
+            self.locals.push(name.to_string());
+        }
+    }
+
     pub fn instr_count_in_scope(&self) -> usize {
         match self.decl_scope {
             true => self.decls.len(),
@@ -222,10 +307,12 @@ impl<'c> Compiler<'c> {
 
     pub fn push_op(&mut self, op: Op) {
         if self.decl_scope {
-            let i = Instr::new(self.lblcnt, op, self.decls.len() + 1);
+            let mut i = Instr::new(self.lblcnt, op, self.decls.len() + 1);
+            i.span = self.span;
             self.decls.push(i);
         } else {
-            let i = Instr::new(self.lblcnt, op, self.ops.len() + 1);
+            let mut i = Instr::new(self.lblcnt, op, self.ops.len() + 1);
+            i.span = self.span;
             self.ops.push(i);
         }
     }
@@ -239,6 +326,8 @@ impl<'c> Compiler<'c> {
     }
 
     pub fn expr(&mut self, expr: &AstNode) {
+        let outer_span = self.enter_span(expr);
+
         match expr {
             AstNode::BinaryExpr { op_tkn, lhs, rhs } => match op_tkn.ty {
                 TokenTy::Plus => self.binop(BinOpKind::Add, lhs, rhs),
@@ -267,18 +356,27 @@ impl<'c> Compiler<'c> {
                 rhs,
             } => {
                 let st_name = ident_tkn.get_name();
+                if self.captures.contains(&st_name) {
+                    let err = CompileErr::new(CompileErrTy::CapturedAssign(st_name.clone()));
+                    self.error(err.with_span(ident_tkn.span()));
+                }
                 self.assign(st_name, rhs);
             }
             AstNode::FnCallExpr {
                 fn_ident_tkn,
                 fn_params,
                 is_std,
-            } => self.fncallexpr(fn_ident_tkn, fn_params, *is_std),
+                is_var,
+            } => self.fncallexpr(fn_ident_tkn, fn_params, *is_std, *is_var),
+            AstNode::FnExpr {
+                fn_params, fn_body, ..
+            } => self.fnexpr(fn_params, fn_body),
             AstNode::ArrayMutExpr {
                 array_tkn,
                 index,
                 rhs,
             } => self.arraymutexpr(array_tkn, index, rhs),
+            AstNode::TableAccess { table_tkn, index } => self.tableaccess(table_tkn, index),
             AstNode::PrimaryExpr { tkn } => {
                 match &tkn.ty {
                     TokenTy::Val(v) => {
@@ -295,6 +393,20 @@ impl<'c> Compiler<'c> {
                         };
                         self.push_op(op);
                     }
+                    TokenTy::True => {
+                        let op = Op::Ldc {
+                            dest: self.nextreg(),
+                            val: SifVal::Bl(true),
+                        };
+                        self.push_op(op);
+                    }
+                    TokenTy::False => {
+                        let op = Op::Ldc {
+                            dest: self.nextreg(),
+                            val: SifVal::Bl(false),
+                        };
+                        self.push_op(op);
+                    }
                     TokenTy::Ident(i) => {
                         let op = Op::Ldn {
                             dest: self.nextreg(),
@@ -306,7 +418,9 @@ impl<'c> Compiler<'c> {
                 };
             }
             _ => (),
-        }
+        };
+
+        self.span = outer_span;
     }
 
     fn arraymutexpr(&mut self, array_tkn: &Token, index: &AstNode, rhs: &AstNode) {
@@ -321,12 +435,28 @@ impl<'c> Compiler<'c> {
         self.push_op(op);
     }
 
+    /// Loads the value at a key of a table, as in `t.key`. Keys are names, so the key is
+    /// taken from the name after the period rather than evaluated.
+    fn tableaccess(&mut self, table_tkn: &Token, key: &AstNode) {
+        match key {
+            AstNode::PrimaryExpr { tkn } => {
+                let op = Op::Tblg {
+                    tabname: table_tkn.get_name(),
+                    key: tkn.get_name(),
+                    dest: self.nextreg(),
+                };
+                self.push_op(op);
+            }
+            _ => self.error(CompileErr::new(CompileErrTy::InvalidAst).with_span(table_tkn.span())),
+        };
+    }
+
     fn binop(&mut self, kind: BinOpKind, lhs: &AstNode, rhs: &AstNode) {
         let r0 = self.binarg(lhs);
         let r1 = self.binarg(rhs);
 
         let op = Op::Binary {
-            kind: kind,
+            kind,
             src1: r0,
             src2: r1,
             dest: self.nextreg(),
@@ -337,7 +467,7 @@ impl<'c> Compiler<'c> {
     fn unop(&mut self, kind: UnOpKind, rhs: &AstNode) {
         let r0 = self.binarg(rhs);
         let op = Op::Unary {
-            kind: kind,
+            kind,
             src1: r0,
             dest: self.nextreg(),
         };
@@ -346,6 +476,7 @@ impl<'c> Compiler<'c> {
 
     // Returns the index of the register in which the last stored value is
     fn binarg(&mut self, arg: &AstNode) -> usize {
+        let outer_span = self.enter_span(arg);
         match arg {
             AstNode::PrimaryExpr { tkn } => match &tkn.ty {
                 TokenTy::Val(v) => {
@@ -386,6 +517,7 @@ impl<'c> Compiler<'c> {
                 self.expr(arg);
             }
         };
+        self.span = outer_span;
         self.ri - 1
     }
 
@@ -405,41 +537,80 @@ impl<'c> Compiler<'c> {
     }
 
     fn fndecl(&mut self, ident_tkn: &Token, fn_params: &AstNode, fn_body: &AstNode) {
-        let fn_name = ident_tkn.get_name();
+        self.fnbody(ident_tkn.get_name(), fn_params, fn_body, Vec::new());
+    }
+
+    /// Generates the fn declaration and body of a function in the decl section. Captures
+    /// are names from an enclosing function that are bound as locals of this function,
+    /// and are filled in from the env of the function value when it's called.
+    pub fn fnbody(
+        &mut self,
+        fn_name: String,
+        fn_params: &AstNode,
+        fn_body: &AstNode,
+        captures: Vec<String>,
+    ) {
         let mut param_names = Vec::new();
         let mut stkops = Vec::new();
+
+        // If we're already inside of a function, the body is generated inline in the decl
+        // section. We jump over it so it's only executed when called, and restore the state
+        // of the enclosing function afterwards.
+        let is_nested = self.decl_scope;
+        let outer_locals = std::mem::take(&mut self.locals);
+        let outer_captures = std::mem::take(&mut self.captures);
+        let outer_loops = std::mem::take(&mut self.loops);
+        let jmpa_idx = self.instr_count_in_scope();
+        if is_nested {
+            self.push_op(Op::Jmpa { lblidx: usize::MAX });
+        }
+
+        // The body always starts a new label. Top level fns are otherwise placed under the
+        // current label of the code section, and a jump to that label would land in the fn.
+        self.newlbl();
+
         // set our section to the decl section for function declaration instructions.
         self.decl_scope = true;
 
-        match fn_params {
-            AstNode::FnParams { params } => {
-                for p in params {
-                    match p {
-                        AstNode::PrimaryExpr { tkn } => {
-                            // TODO: probably don't need to pop and then store params
-                            // here for subsequent loads. We should just refer to the proper
-                            // reg instead (this could be done in an optimizer)
-                            param_names.push(tkn.get_name());
-                            let stkop = Op::FnStackPop {
-                                dest: self.nextreg(),
-                            };
-                            stkops.push(stkop);
-                            let strop = Op::Str {
-                                src: self.prevreg(),
-                                name: tkn.get_name(),
-                            };
-                            stkops.push(strop);
-                        }
-                        _ => {}
-                    }
+        if let AstNode::FnParams { params } = fn_params {
+            // Params are pushed onto the fn stack in order by the caller, so we pop
+            // them off in reverse to bind each value to the correct name.
+            for p in params.iter().rev() {
+                if let AstNode::PrimaryExpr { tkn } = p {
+                    // TODO: probably don't need to pop and then store params
+                    // here for subsequent loads. We should just refer to the proper
+                    // reg instead (this could be done in an optimizer)
+                    let stkop = Op::FnStackPop {
+                        dest: self.nextreg(),
+                    };
+                    stkops.push(stkop);
+                    let strop = Op::Str {
+                        src: self.prevreg(),
+                        name: tkn.get_name(),
+                    };
+                    stkops.push(strop);
+                }
+            }
+
+            for p in params {
+                if let AstNode::PrimaryExpr { tkn } = p {
+                    param_names.push(tkn.get_name())
                 }
             }
-            _ => {}
         };
 
+        self.locals.extend(param_names.iter().cloned());
+        for c in &captures {
+            self.declare_local(c);
+        }
+        self.captures = captures;
+
+        // The fn op is updated with the names of any locals after the body is compiled.
+        let fn_idx = self.instr_count_in_scope();
         self.push_op(Op::Fn {
-            name: fn_name,
-            params: param_names,
+            name: fn_name.clone(),
+            params: param_names.clone(),
+            locals: Vec::new(),
         });
 
         // Add the stack pop operations for param handling.
@@ -447,16 +618,58 @@ impl<'c> Compiler<'c> {
             self.push_op(op);
         }
 
-        // TODO: param scoping - need to be able to access names in vm using the stack
         self.block(fn_body);
 
-        // go back to code section and open a new label
-        self.decl_scope = false;
+        let locals = self.locals.split_off(param_names.len());
+        self.update_op_at(
+            fn_idx,
+            Op::Fn {
+                name: fn_name,
+                params: param_names,
+                locals,
+            },
+        );
+        self.locals = outer_locals;
+        self.captures = outer_captures;
+        self.loops = outer_loops;
+
+        // go back to the enclosing section and open a new label
+        self.decl_scope = is_nested;
         self.newlbl();
+        if is_nested {
+            self.update_op_at(
+                jmpa_idx,
+                Op::Jmpa {
+                    lblidx: self.lblcnt(),
+                },
+            );
+            self.push_op(Op::Nop);
+        }
+    }
+
+    /// Generates an anonymous function and loads it as a value into the next register.
+    /// Any locals of the enclosing function referenced in the body are captured by value
+    /// when the function value is loaded. Assigning to a capture is a compile error, since
+    /// the enclosing function would never see the new value.
+    fn fnexpr(&mut self, fn_params: &AstNode, fn_body: &AstNode) {
+        // Identifiers can't contain '$', so this can't conflict with a declared function.
+        let fn_name = format!("anon${}", self.anoncnt);
+        self.anoncnt += 1;
+
+        let captures = crate::closure::captured_names(fn_params, fn_body, &self.locals);
+        self.fnbody(fn_name.clone(), fn_params, fn_body, captures.clone());
+
+        let op = Op::Ldf {
+            name: fn_name,
+            env: captures,
+            dest: self.nextreg(),
+        };
+        self.push_op(op);
     }
 
     fn vardecl(&mut self, tkn: &Token, rhs: Option<Box<AstNode>>) {
         let st_name = tkn.get_name();
+        self.declare_local(&st_name);
         if rhs.is_none() {
             // We generate a store for an empty value here, to ensure that the name is present
             // in memory if we try to assign to it later. We can detect null value accesses at some
@@ -474,7 +687,7 @@ impl<'c> Compiler<'c> {
 
     pub fn assign(&mut self, st_name: String, rhs: &AstNode) {
         match rhs {
-            AstNode::PrimaryExpr { tkn } => self.match_primary_assign(&st_name, &tkn),
+            AstNode::PrimaryExpr { tkn } => self.match_primary_assign(&st_name, tkn),
             AstNode::Table {
                 ident_tkn: _,
                 items,
@@ -487,11 +700,12 @@ impl<'c> Compiler<'c> {
                 fn_ident_tkn,
                 fn_params,
                 is_std,
-            } => self.fn_call_assign(&st_name, &fn_ident_tkn, fn_params, *is_std),
+                is_var,
+            } => self.fn_call_assign(&st_name, fn_ident_tkn, fn_params, *is_std, *is_var),
             _ => {
                 // We assume that if we aren't assigning a declaration to a constant, we are using an
                 // expression. We store based on the correct register from the expression.
-                self.expr(&rhs);
+                self.expr(rhs);
                 let op = Op::Str {
                     name: st_name,
                     src: self.prevreg(),
@@ -501,84 +715,93 @@ impl<'c> Compiler<'c> {
         };
     }
 
-    fn match_primary_assign(&mut self, st_name: &String, tkn: &Token) {
+    fn match_primary_assign(&mut self, st_name: &str, tkn: &Token) {
         match &tkn.ty {
             TokenTy::Val(v) => {
                 self.push_op(Op::Stc {
                     val: SifVal::Num(*v),
-                    name: st_name.clone(),
+                    name: st_name.to_string(),
                 });
             }
             TokenTy::Str(s) => {
                 self.push_op(Op::Stc {
                     val: SifVal::Str(s.clone()),
-                    name: st_name.clone(),
+                    name: st_name.to_string(),
                 });
             }
             TokenTy::Ident(i) => {
                 self.push_op(Op::Stn {
                     srcname: i.clone(),
-                    destname: st_name.clone(),
+                    destname: st_name.to_string(),
                 });
             }
             TokenTy::False => {
                 self.push_op(Op::Stc {
                     val: SifVal::Bl(false),
-                    name: st_name.clone(),
+                    name: st_name.to_string(),
                 });
             }
             TokenTy::True => {
                 self.push_op(Op::Stc {
                     val: SifVal::Bl(false),
-                    name: st_name.clone(),
+                    name: st_name.to_string(),
                 });
             }
             _ => {}
         };
     }
 
-    fn match_table_assign(&mut self, st_name: &String, items: &AstNode) {
+    fn match_table_assign(&mut self, st_name: &str, items: &AstNode) {
         self.push_op(Op::Stc {
-            val: SifVal::Tab(HashMap::new()),
-            name: st_name.clone(),
+            val: SifVal::new_tab(HashMap::new()),
+            name: st_name.to_string(),
         });
 
-        match items {
-            AstNode::ItemList { items } => {
-                for (k, v) in items.iter() {
-                    self.expr(v);
-                    let tabop = Op::Tbli {
-                        tabname: st_name.clone(),
-                        key: k.clone(),
-                        src: self.prevreg(),
-                    };
-                    self.push_op(tabop);
-                }
+        if let AstNode::ItemList { items } = items {
+            for (k, v) in items.iter() {
+                self.expr(v);
+                let tabop = Op::Tbli {
+                    tabname: st_name.to_string(),
+                    key: k.clone(),
+                    src: self.prevreg(),
+                };
+                self.push_op(tabop);
             }
-            _ => {}
         };
     }
 
     fn fn_call_assign(
         &mut self,
-        st_name: &String,
+        st_name: &str,
         fn_ident_tkn: &Token,
         fn_params: &Vec<AstNode>,
         is_std: bool,
+        is_var: bool,
     ) {
-        self.fncallexpr(fn_ident_tkn, fn_params, is_std);
+        // The call is located at the fn name, so that errors inside of it point to the
+        // call rather than to the variable.
+        let outer_span = self.span;
+        self.span = fn_ident_tkn.span();
+        self.fncallexpr(fn_ident_tkn, fn_params, is_std, is_var);
+        self.span = outer_span;
 
         // After the call returns, store the popped return value in the next
         // available reg, and then store that register in the variable
         // being assigned to.
         let strop = Op::Str {
             src: self.prevreg(),
-            name: st_name.clone(),
+            name: st_name.to_string(),
         };
         self.push_op(strop);
     }
 
-    fn fncallexpr(&mut self, fn_ident_tkn: &Token, fn_params: &Vec<AstNode>, is_std: bool) {
+    fn fncallexpr(
+        &mut self,
+        fn_ident_tkn: &Token,
+        fn_params: &Vec<AstNode>,
+        is_std: bool,
+        is_var: bool,
+    ) {
         for param in fn_params {
             self.expr(param);
             let param_op = Op::FnStackPush {
@@ -587,20 +810,30 @@ impl<'c> Compiler<'c> {
             self.push_op(param_op)
         }
 
-        match is_std {
-            true => {
-                self.push_op(Op::StdCall {
-                    name: fn_ident_tkn.get_name(),
-                    param_count: fn_params.len(),
-                });
-            }
-            false => {
-                self.push_op(Op::Call {
-                    name: fn_ident_tkn.get_name(),
-                    param_count: fn_params.len(),
-                });
-            }
-        };
+        if is_std {
+            self.push_op(Op::StdCall {
+                name: fn_ident_tkn.get_name(),
+                param_count: fn_params.len(),
+            });
+        } else if is_var {
+            // Load the function value held by the variable, and call through the
+            // register it's loaded into.
+            let ldop = Op::Ldn {
+                dest: self.nextreg(),
+                name: fn_ident_tkn.get_name(),
+            };
+            self.push_op(ldop);
+            let callop = Op::Callr {
+                src: self.prevreg(),
+                param_count: fn_params.len(),
+            };
+            self.push_op(callop);
+        } else {
+            self.push_op(Op::Call {
+                name: fn_ident_tkn.get_name(),
+                param_count: fn_params.len(),
+            });
+        }
 
         // Pop return value, if the fn call has one. If it doesn't, when the pop
         // is executed it will return None, and any registers that attempt to store
@@ -2,6 +2,7 @@ use crate::{
     opc::{BinOpKind, JmpOpKind, Op, UnOpKind},
     sifv::SifVal,
 };
+use sifc_err::span::Span;
 use std::fmt;
 
 #[derive(Clone, PartialEq)]
@@ -10,6 +11,10 @@ pub struct Instr {
     pub lblidx: usize,
     pub op: Op,
     pub line: usize,
+
+    /// Span of the source that the instr was compiled from. This is unknown for instrs
+    /// that weren't compiled from source, such as hand written assembly.
+    pub span: Span,
 }
 
 impl Instr {
@@ -19,6 +24,28 @@ impl Instr {
             lblidx: idx,
             op: o,
             line: l,
+            span: Span::default(),
+        }
+    }
+
+    /// Returns a copy of the instr with a different op. The label, line and span are kept,
+    /// so that passes rewriting an op don't lose where it came from.
+    pub fn with_op(&self, op: Op) -> Instr {
+        Instr {
+            lbl: self.lbl.clone(),
+            lblidx: self.lblidx,
+            op,
+            line: self.line,
+            span: self.span,
+        }
+    }
+
+    /// Returns the comment printed after the instr, in the form '; line, label'. The
+    /// span is added to the end in the form 'line:col' if it's known.
+    fn comment(&self) -> String {
+        match self.span.is_known() {
+            true => format!("; {}, {}, {}", self.line, self.lbl, self.span),
+            false => format!("; {}, {}", self.line, self.lbl),
         }
     }
 }
@@ -86,9 +113,9 @@ impl fmt::Display for Instr {
                 idx_reg,
                 val_reg,
             } => {
-                let dstr = reg_str(idx_reg);
-                let istr = reg_str(val_reg);
-                let line = format!("upda {} {} {}", name, istr, dstr);
+                let istr = reg_str(idx_reg);
+                let vstr = reg_str(val_reg);
+                let line = format!("upda {} {} {}", name, istr, vstr);
                 initial.push_str(&line);
             }
             Op::Stc { name, val } => {
@@ -118,7 +145,7 @@ impl fmt::Display for Instr {
                 initial.push_str(&line);
             }
             Op::Nop => {
-                let line = format!("{}", "nop");
+                let line = "nop".to_string();
                 initial.push_str(&line);
             }
             Op::Incrr { src } => {
@@ -131,20 +158,34 @@ impl fmt::Display for Instr {
                 let line = format!("decrr {}", rstr);
                 initial.push_str(&line);
             }
-            Op::Fn { name, params } => {
-                let line = format!("fn @{} {:?}", name, params);
+            Op::Fn {
+                name,
+                params,
+                locals,
+            } => {
+                let line = fn_str(name, params, locals);
                 initial.push_str(&line);
             }
             Op::FnRet => {
-                let line = format!("ret");
+                let line = "ret".to_string();
                 initial.push_str(&line);
             }
-            Op::Call { name, .. } => {
-                let line = format!("call {}", name);
+            Op::Call { name, param_count } => {
+                let line = format!("call {} {}", name, param_count);
                 initial.push_str(&line);
             }
-            Op::StdCall { name, .. } => {
-                let line = format!("stdcall {}", name);
+            Op::Callr { src, param_count } => {
+                let rstr = reg_str(src);
+                let line = format!("callr {} {}", rstr, param_count);
+                initial.push_str(&line);
+            }
+            Op::Ldf { name, env, dest } => {
+                let rstr = reg_str(dest);
+                let line = format!("ldf @{} {:?} {}", name, env, rstr);
+                initial.push_str(&line);
+            }
+            Op::StdCall { name, param_count } => {
+                let line = format!("stdcall {} {}", name, param_count);
                 initial.push_str(&line);
             }
             Op::FnStackPush { src } => {
@@ -167,8 +208,58 @@ impl fmt::Display for Instr {
                 let line = format!("tblg {} {} {}", tabname, key, rstr);
                 initial.push_str(&line);
             }
+            Op::Ldg { dest, slot } => {
+                let line = format!("ldg {} {}", slot_str(slot), reg_str(dest));
+                initial.push_str(&line);
+            }
+            Op::Strg { src, slot } => {
+                let line = format!("strg {} {}", reg_str(src), slot_str(slot));
+                initial.push_str(&line);
+            }
+            Op::Stcg { val, slot } => {
+                let line = format!("stcg {} {}", val_str(val), slot_str(slot));
+                initial.push_str(&line);
+            }
+            Op::Ldasg { slot, dest } => {
+                let line = format!("ldasg {} {}", slot_str(slot), reg_str(dest));
+                initial.push_str(&line);
+            }
+            Op::Ldavg {
+                slot,
+                idx_reg,
+                dest,
+            } => {
+                let line = format!(
+                    "ldavg {} {} {}",
+                    slot_str(slot),
+                    reg_str(idx_reg),
+                    reg_str(dest)
+                );
+                initial.push_str(&line);
+            }
+            Op::Updag {
+                slot,
+                idx_reg,
+                val_reg,
+            } => {
+                let line = format!(
+                    "updag {} {} {}",
+                    slot_str(slot),
+                    reg_str(idx_reg),
+                    reg_str(val_reg)
+                );
+                initial.push_str(&line);
+            }
+            Op::Tblig { slot, key, src } => {
+                let line = format!("tblig {} {} {}", reg_str(src), key, slot_str(slot));
+                initial.push_str(&line);
+            }
+            Op::Tblgg { slot, key, dest } => {
+                let line = format!("tblgg {} {} {}", slot_str(slot), key, reg_str(dest));
+                initial.push_str(&line);
+            }
             Op::Stop => {
-                let line = format!("{}", "stop");
+                let line = "stop".to_string();
                 initial.push_str(&line);
             }
         };
@@ -193,8 +284,12 @@ impl fmt::Debug for Instr {
                 let reg2 = reg_str(src2);
                 let dstr = reg_str(dest);
                 let line = format!(
-                    "\t {} {} {} {}\t ; {}, {}\n",
-                    op_str, reg1, reg2, dstr, self.line, self.lbl
+                    "\t {} {} {} {}\t {}\n",
+                    op_str,
+                    reg1,
+                    reg2,
+                    dstr,
+                    self.comment()
                 );
                 initial.push_str(&line);
             }
@@ -202,35 +297,29 @@ impl fmt::Debug for Instr {
                 let op_str = un_kind_str(kind);
                 let reg1 = reg_str(src1);
                 let dstr = reg_str(dest);
-                let line = format!(
-                    "\t {} {} {}\t ; {}, {}\n",
-                    op_str, reg1, dstr, self.line, self.lbl
-                );
+                let line = format!("\t {} {} {}\t {}\n", op_str, reg1, dstr, self.comment());
                 initial.push_str(&line);
             }
             Op::Ldc { dest, val } => {
                 let dstr = reg_str(dest);
                 let vstr = val_str(val);
-                let line = format!("\t ldc {} {}\t ; {}, {}\n", vstr, dstr, self.line, self.lbl);
+                let line = format!("\t ldc {} {}\t {}\n", vstr, dstr, self.comment());
                 initial.push_str(&line);
             }
             Op::Ldn { dest, name } => {
                 let dstr = reg_str(dest);
-                let line = format!("\t ldn {} {}\t ; {}, {}\n", name, dstr, self.line, self.lbl);
+                let line = format!("\t ldn {} {}\t {}\n", name, dstr, self.comment());
                 initial.push_str(&line);
             }
             Op::Mv { src, dest } => {
                 let rstr = reg_str(src);
                 let dstr = reg_str(dest);
-                let line = format!("\t mv {} {}\t ; {}, {}\n", rstr, dstr, self.line, self.lbl);
+                let line = format!("\t mv {} {}\t {}\n", rstr, dstr, self.comment());
                 initial.push_str(&line);
             }
             Op::Ldas { name, dest } => {
                 let dstr = reg_str(dest);
-                let line = format!(
-                    "\t ldas {} {}\t ; {}, {}\n",
-                    name, dstr, self.line, self.lbl
-                );
+                let line = format!("\t ldas {} {}\t {}\n", name, dstr, self.comment());
                 initial.push_str(&line);
             }
             Op::Ldav {
@@ -240,10 +329,7 @@ impl fmt::Debug for Instr {
             } => {
                 let dstr = reg_str(dest);
                 let istr = reg_str(idx_reg);
-                let line = format!(
-                    "\t ldav {} {} {}\t ; {}, {}\n",
-                    name, istr, dstr, self.line, self.lbl
-                );
+                let line = format!("\t ldav {} {} {}\t {}\n", name, istr, dstr, self.comment());
                 initial.push_str(&line);
             }
             Op::Upda {
@@ -253,97 +339,192 @@ impl fmt::Debug for Instr {
             } => {
                 let dstr = reg_str(val_reg);
                 let istr = reg_str(idx_reg);
-                let line = format!(
-                    "\t upda {} {} {}\t ; {}, {}\n",
-                    name, istr, dstr, self.line, self.lbl
-                );
+                let line = format!("\t upda {} {} {}\t {}\n", name, istr, dstr, self.comment());
                 initial.push_str(&line);
             }
             Op::Stc { name, val } => {
                 let vstr = val_str(val);
-                let line = format!("\t stc {} {}\t ; {}, {}\n", vstr, name, self.line, self.lbl);
+                let line = format!("\t stc {} {}\t {}\n", vstr, name, self.comment());
                 initial.push_str(&line);
             }
             Op::Stn { srcname, destname } => {
-                let line = format!(
-                    "\t stn {} {}\t ; {}, {}\n",
-                    srcname, destname, self.line, self.lbl
-                );
+                let line = format!("\t stn {} {}\t {}\n", srcname, destname, self.comment());
                 initial.push_str(&line);
             }
             Op::Str { name, src } => {
                 let rstr = reg_str(src);
-                let line = format!("\t str {} {}\t ; {}, {}\n", rstr, name, self.line, self.lbl);
+                let line = format!("\t str {} {}\t {}\n", rstr, name, self.comment());
                 initial.push_str(&line);
             }
             Op::JmpCnd { kind, src, lblidx } => {
                 let op_str = jmp_kind_str(kind);
                 let rstr = reg_str(src);
                 let lbl = lbl_str(lblidx);
-                let line = format!(
-                    "\t {} {} {}\t ; {}, {}\n",
-                    op_str, rstr, lbl, self.line, self.lbl
-                );
+                let line = format!("\t {} {} {}\t {}\n", op_str, rstr, lbl, self.comment());
                 initial.push_str(&line);
             }
             Op::Jmpa { lblidx } => {
                 let lbl = lbl_str(lblidx);
-                let line = format!("\t jmpa {}\t ; {}, {}\n", lbl, self.line, self.lbl);
+                let line = format!("\t jmpa {}\t {}\n", lbl, self.comment());
                 initial.push_str(&line);
             }
             Op::Nop => {
-                let line = format!("\t {}\t\t ; {}, {}\n", "nop", self.line, self.lbl);
+                let line = format!("\t {}\t\t {}\n", "nop", self.comment());
                 initial.push_str(&line);
             }
             Op::Incrr { src } => {
                 let rstr = reg_str(src);
-                let line = format!("\t incrr {}\t ; {}, {}\n", rstr, self.line, self.lbl);
+                let line = format!("\t incrr {}\t {}\n", rstr, self.comment());
                 initial.push_str(&line);
             }
             Op::Decrr { src } => {
                 let rstr = reg_str(src);
-                let line = format!("\t decrr {}\t ; {}, {}\n", rstr, self.line, self.lbl);
+                let line = format!("\t decrr {}\t {}\n", rstr, self.comment());
                 initial.push_str(&line);
             }
-            Op::Fn { name, params } => {
-                let line = format!("fn @{} {:?}\n", name, params);
+            Op::Fn {
+                name,
+                params,
+                locals,
+            } => {
+                let line = format!("{}\t {}\n", fn_str(name, params, locals), self.comment());
                 initial.push_str(&line);
             }
             Op::FnRet => {
-                let line = format!("\t ret \t\t ; {}, {}\n", self.line, self.lbl);
+                let line = format!("\t ret \t\t {}\n", self.comment());
+                initial.push_str(&line);
+            }
+            Op::Call { name, param_count } => {
+                let line = format!("\t call {} {}\t {}\n", name, param_count, self.comment());
+                initial.push_str(&line);
+            }
+            Op::Callr { src, param_count } => {
+                let rstr = reg_str(src);
+                let line = format!("\t callr {} {}\t {}\n", rstr, param_count, self.comment());
                 initial.push_str(&line);
             }
-            Op::Call { name, .. } => {
-                let line = format!("\t call {}\t ; {}, {}\n", name, self.line, self.lbl);
+            Op::Ldf { name, env, dest } => {
+                let rstr = reg_str(dest);
+                let line = format!("\t ldf @{} {:?} {}\t {}\n", name, env, rstr, self.comment());
                 initial.push_str(&line);
             }
-            Op::StdCall { name, .. } => {
-                let line = format!("\t stdcall {}\t ; {}, {}\n", name, self.line, self.lbl);
+            Op::StdCall { name, param_count } => {
+                let line = format!("\t stdcall {} {}\t {}\n", name, param_count, self.comment());
                 initial.push_str(&line);
             }
             Op::FnStackPush { src } => {
                 let rstr = reg_str(src);
-                let line = format!("\t fstpush {}\t ; {}, {}\n", rstr, self.line, self.lbl);
+                let line = format!("\t fstpush {}\t {}\n", rstr, self.comment());
                 initial.push_str(&line);
             }
             Op::FnStackPop { dest } => {
                 let rstr = reg_str(dest);
-                let line = format!("\t fstpop {}\t ; {}, {}\n", rstr, self.line, self.lbl);
+                let line = format!("\t fstpop {}\t {}\n", rstr, self.comment());
                 initial.push_str(&line);
             }
             Op::Tbli { tabname, key, src } => {
                 let rstr = reg_str(src);
                 let line = format!(
-                    "\t tbli {} {} {}\t ; {}, {}\n",
-                    rstr, key, tabname, self.line, self.lbl
+                    "\t tbli {} {} {}\t {}\n",
+                    rstr,
+                    key,
+                    tabname,
+                    self.comment()
                 );
                 initial.push_str(&line);
             }
             Op::Tblg { tabname, key, dest } => {
                 let rstr = reg_str(dest);
                 let line = format!(
-                    "\t tblg {} {} {}\t ; {}, {}\n",
-                    tabname, key, rstr, self.line, self.lbl
+                    "\t tblg {} {} {}\t {}\n",
+                    tabname,
+                    key,
+                    rstr,
+                    self.comment()
+                );
+                initial.push_str(&line);
+            }
+            Op::Ldg { dest, slot } => {
+                let line = format!(
+                    "\t ldg {} {}\t {}\n",
+                    slot_str(slot),
+                    reg_str(dest),
+                    self.comment()
+                );
+                initial.push_str(&line);
+            }
+            Op::Strg { src, slot } => {
+                let line = format!(
+                    "\t strg {} {}\t {}\n",
+                    reg_str(src),
+                    slot_str(slot),
+                    self.comment()
+                );
+                initial.push_str(&line);
+            }
+            Op::Stcg { val, slot } => {
+                let line = format!(
+                    "\t stcg {} {}\t {}\n",
+                    val_str(val),
+                    slot_str(slot),
+                    self.comment()
+                );
+                initial.push_str(&line);
+            }
+            Op::Ldasg { slot, dest } => {
+                let line = format!(
+                    "\t ldasg {} {}\t {}\n",
+                    slot_str(slot),
+                    reg_str(dest),
+                    self.comment()
+                );
+                initial.push_str(&line);
+            }
+            Op::Ldavg {
+                slot,
+                idx_reg,
+                dest,
+            } => {
+                let line = format!(
+                    "\t ldavg {} {} {}\t {}\n",
+                    slot_str(slot),
+                    reg_str(idx_reg),
+                    reg_str(dest),
+                    self.comment()
+                );
+                initial.push_str(&line);
+            }
+            Op::Updag {
+                slot,
+                idx_reg,
+                val_reg,
+            } => {
+                let line = format!(
+                    "\t updag {} {} {}\t {}\n",
+                    slot_str(slot),
+                    reg_str(idx_reg),
+                    reg_str(val_reg),
+                    self.comment()
+                );
+                initial.push_str(&line);
+            }
+            Op::Tblig { slot, key, src } => {
+                let line = format!(
+                    "\t tblig {} {} {}\t {}\n",
+                    reg_str(src),
+                    key,
+                    slot_str(slot),
+                    self.comment()
+                );
+                initial.push_str(&line);
+            }
+            Op::Tblgg { slot, key, dest } => {
+                let line = format!(
+                    "\t tblgg {} {} {}\t {}\n",
+                    slot_str(slot),
+                    key,
+                    reg_str(dest),
+                    self.comment()
                 );
                 initial.push_str(&line);
             }
@@ -394,17 +575,45 @@ fn reg_str(reg: usize) -> String {
     format!("r{}", reg)
 }
 
+/// Formats a constant value so that it can be read back by the assembler. Strings are
+/// quoted and escaped, and table keys are sorted so the output is stable.
 fn val_str(v: SifVal) -> String {
     match v {
         SifVal::Num(v) => v.to_string(),
-        SifVal::Str(s) => s,
+        SifVal::Str(s) => format!("{:?}", s),
         SifVal::Bl(b) => b.to_string(),
         SifVal::Null => "null".to_string(),
-        SifVal::Arr(a) => format!("{:?}", a),
-        SifVal::Tab(t) => format!("{:?}", t),
+        SifVal::Arr(a) => {
+            let items: Vec<String> = a.borrow().iter().cloned().map(val_str).collect();
+            format!("[{}]", items.join(", "))
+        }
+        SifVal::Tab(t) => {
+            let t = t.borrow();
+            let mut keys: Vec<&String> = t.keys().collect();
+            keys.sort();
+            let items: Vec<String> = keys
+                .into_iter()
+                .map(|k| format!("{:?}: {}", k, val_str(t[k].clone())))
+                .collect();
+            format!("{{{}}}", items.join(", "))
+        }
+        SifVal::Fn { name, .. } => format!("<fn {}>", name),
+    }
+}
+
+fn fn_str(name: String, params: Vec<String>, locals: Vec<String>) -> String {
+    // Locals are only shown when the function declares any, so simple functions
+    // print the same as their declaration in source.
+    match locals.len() {
+        0 => format!("fn @{} {:?}", name, params),
+        _ => format!("fn @{} {:?} {:?}", name, params, locals),
     }
 }
 
+fn slot_str(slot: usize) -> String {
+    format!("g{}", slot)
+}
+
 fn lbl_str(v: usize) -> String {
     format!("lbl{}", v)
 }
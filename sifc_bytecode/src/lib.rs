@@ -1,10 +1,14 @@
+pub mod asm;
 pub mod compiler;
 pub mod instr;
+pub mod link;
 pub mod opc;
 pub mod optimize;
 pub mod printer;
+pub mod sifb;
 pub mod sifv;
 
 mod array;
+mod closure;
 mod stmt;
 mod tables;
@@ -0,0 +1,369 @@
+use crate::{instr::Instr, opc::Op};
+use std::collections::{HashMap, HashSet};
+
+/// GlobalTable assigns a slot to each global name. Slots are indices into the globals of
+/// the vm, and are assigned in the order names are first seen. The table is kept alongside
+/// a linked program so slots can be mapped back to names for tracing and error messages.
+#[derive(Clone, Debug)]
+pub struct GlobalTable {
+    /// Names of the globals, indexed by slot.
+    names: Vec<String>,
+
+    /// Slots of the globals, keyed by name.
+    slots: HashMap<String, usize>,
+}
+
+impl Default for GlobalTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlobalTable {
+    pub fn new() -> GlobalTable {
+        GlobalTable {
+            names: Vec::new(),
+            slots: HashMap::new(),
+        }
+    }
+
+    /// Returns the slot of a name, assigning it the next free slot if the name doesn't
+    /// have one yet.
+    pub fn intern(&mut self, name: &str) -> usize {
+        match self.slots.get(name) {
+            Some(slot) => *slot,
+            None => {
+                let slot = self.names.len();
+                self.names.push(name.to_string());
+                self.slots.insert(name.to_string(), slot);
+                slot
+            }
+        }
+    }
+
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.get(name).copied()
+    }
+
+    pub fn name(&self, slot: usize) -> Option<&str> {
+        match self.names.get(slot) {
+            Some(name) => Some(name),
+            None => None,
+        }
+    }
+
+    /// Returns the names in the table, in slot order.
+    pub fn names(&self) -> &Vec<String> {
+        &self.names
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.len() == 0
+    }
+}
+
+/// Links a program, rewriting ops that access globals by name into their slot indexed
+/// variants. Global names are interned into the given table, so a program can be linked
+/// against the globals of a program that was linked before it.
+///
+/// Names declared by a fn (its params and locals) live in the call frame and are left as
+/// they are. Each instruction belongs to the fn decl it can be reached from without passing
+/// through another fn decl, and instructions reached from the start of the code section
+/// belong to no fn. Instructions that can't be reached at all are left as they are, and
+/// the vm still resolves any name they use at runtime.
+pub fn link(
+    program: &mut [Instr],
+    code_start: usize,
+    jumptab: &HashMap<usize, usize>,
+    globals: &mut GlobalTable,
+) {
+    let mut scopes = Vec::new();
+    for (idx, instr) in program.iter().enumerate() {
+        if let Op::Fn { params, locals, .. } = &instr.op {
+            let names: HashSet<String> = params.iter().chain(locals).cloned().collect();
+            scopes.push((idx, names));
+        };
+    }
+
+    if code_start < program.len() {
+        scopes.push((code_start, HashSet::new()));
+    }
+
+    for (entry, locals) in scopes {
+        for idx in reachable(program, jumptab, entry) {
+            if let Some(op) = link_op(&program[idx].op, &locals, globals) {
+                program[idx].op = op
+            };
+        }
+    }
+}
+
+/// Relocates a program so that it can be appended to the end of another one. Instruction
+/// indices in the program are moved forward by idx_off and label indices by lbl_off, which
+/// should be past the last instruction and label of the program being appended to. The jump
+/// and fn tables of the program are updated in the same way.
+pub fn relocate(
+    program: &mut [Instr],
+    jumptab: &mut HashMap<usize, usize>,
+    fntab: &mut HashMap<String, usize>,
+    idx_off: usize,
+    lbl_off: usize,
+) {
+    for instr in program.iter_mut() {
+        instr.lblidx += lbl_off;
+        instr.lbl = format!("lbl{}", instr.lblidx);
+        match &mut instr.op {
+            Op::Jmpa { lblidx } | Op::JmpCnd { lblidx, .. } => *lblidx += lbl_off,
+            _ => {}
+        };
+    }
+
+    *jumptab = jumptab
+        .iter()
+        .map(|(lbl, idx)| (lbl + lbl_off, idx + idx_off))
+        .collect();
+    for idx in fntab.values_mut() {
+        *idx += idx_off;
+    }
+}
+
+/// Returns the slot indexed variant of an op that accesses a global by name. Returns None
+/// if the op doesn't access a name, or if the name is one of the locals.
+fn link_op(op: &Op, locals: &HashSet<String>, globals: &mut GlobalTable) -> Option<Op> {
+    let linked = match op {
+        Op::Ldn { dest, name } if !locals.contains(name) => Op::Ldg {
+            dest: *dest,
+            slot: globals.intern(name),
+        },
+        Op::Str { src, name } if !locals.contains(name) => Op::Strg {
+            src: *src,
+            slot: globals.intern(name),
+        },
+        Op::Stc { val, name } if !locals.contains(name) => Op::Stcg {
+            val: val.clone(),
+            slot: globals.intern(name),
+        },
+        Op::Ldas { name, dest } if !locals.contains(name) => Op::Ldasg {
+            slot: globals.intern(name),
+            dest: *dest,
+        },
+        Op::Ldav {
+            name,
+            idx_reg,
+            dest,
+        } if !locals.contains(name) => Op::Ldavg {
+            slot: globals.intern(name),
+            idx_reg: *idx_reg,
+            dest: *dest,
+        },
+        Op::Upda {
+            name,
+            idx_reg,
+            val_reg,
+        } if !locals.contains(name) => Op::Updag {
+            slot: globals.intern(name),
+            idx_reg: *idx_reg,
+            val_reg: *val_reg,
+        },
+        Op::Tbli { tabname, key, src } if !locals.contains(tabname) => Op::Tblig {
+            slot: globals.intern(tabname),
+            key: key.clone(),
+            src: *src,
+        },
+        Op::Tblg { tabname, key, dest } if !locals.contains(tabname) => Op::Tblgg {
+            slot: globals.intern(tabname),
+            key: key.clone(),
+            dest: *dest,
+        },
+        _ => return None,
+    };
+
+    Some(linked)
+}
+
+/// Returns the indices of the instructions that can be reached from entry. Paths end at
+/// fn returns, the end of the program and at fn decls other than the entry itself.
+fn reachable(program: &[Instr], jumptab: &HashMap<usize, usize>, entry: usize) -> Vec<usize> {
+    let mut visited = vec![false; program.len()];
+    let mut worklist = vec![entry];
+    let mut found = Vec::new();
+
+    while let Some(idx) = worklist.pop() {
+        if visited[idx] {
+            continue;
+        }
+        visited[idx] = true;
+        found.push(idx);
+
+        let mut succs = Vec::new();
+        match &program[idx].op {
+            Op::Jmpa { lblidx } => succs.extend(jumptab.get(lblidx).copied()),
+            Op::JmpCnd { lblidx, .. } => {
+                succs.extend(jumptab.get(lblidx).copied());
+                succs.push(idx + 1);
+            }
+            Op::FnRet => {}
+            _ => succs.push(idx + 1),
+        };
+
+        for succ in succs {
+            if let Some(instr) = program.get(succ) {
+                match instr.op {
+                    Op::Fn { .. } => {}
+                    _ => worklist.push(succ),
+                }
+            };
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    fn link_asm(src: &str) -> (Vec<Op>, GlobalTable) {
+        let result = match asm::assemble(src) {
+            Ok(r) => r,
+            Err(e) => panic!("failed to assemble: {:?}", e),
+        };
+        let mut program = result.program;
+        let mut globals = GlobalTable::new();
+        link(
+            &mut program,
+            result.code_start,
+            &result.jumptab,
+            &mut globals,
+        );
+
+        (program.into_iter().map(|i| i.op).collect(), globals)
+    }
+
+    #[test]
+    fn link_globals() {
+        let (ops, globals) = link_asm(
+            "
+ldc 1 r0
+str r0 x
+stc [1, 2] y
+ldn x r1
+ldav y r1 r2
+upda y r1 r0
+stn x z
+",
+        );
+
+        assert_eq!(globals.len(), 2);
+        assert_eq!(globals.slot("x"), Some(0));
+        assert_eq!(globals.name(1), Some("y"));
+        assert_eq!(ops[1], Op::Strg { src: 0, slot: 0 });
+        assert_eq!(ops[3], Op::Ldg { dest: 1, slot: 0 });
+        assert_eq!(
+            ops[5],
+            Op::Updag {
+                slot: 1,
+                idx_reg: 1,
+                val_reg: 0,
+            }
+        );
+
+        // Name to name stores are resolved by the vm.
+        assert_eq!(ops[6].slot(), None);
+    }
+
+    #[test]
+    fn link_fn_locals() {
+        let (ops, globals) = link_asm(
+            "
+SECTION_ decls.
+lbl0:
+fn @f [\"a\"] [\"b\"]
+\t ldn a r0
+\t str r0 b
+\t str r0 g
+\t jmpa lbl1
+fn @inner [] [\"g\"]
+\t str r0 g
+\t ret
+lbl1:
+\t ldn b r1
+\t ret
+SECTION_ code.
+lbl2:
+\t ldn b r0
+\t ldn inner r1
+",
+        );
+
+        // Params and locals of f stay as names, and the jump skips over the nested
+        // fn, which declares g as a local.
+        assert_eq!(ops[1].slot(), None);
+        assert_eq!(ops[2].slot(), None);
+        assert_eq!(ops[3], Op::Strg { src: 0, slot: 0 });
+        assert_eq!(ops[6].slot(), None);
+        assert_eq!(ops[8].slot(), None);
+
+        // The code section has no locals.
+        assert_eq!(ops[10], Op::Ldg { dest: 0, slot: 1 });
+        assert_eq!(ops[11], Op::Ldg { dest: 1, slot: 2 });
+        assert_eq!(globals.name(2), Some("inner"));
+    }
+
+    #[test]
+    fn link_existing_globals() {
+        let mut globals = GlobalTable::new();
+        globals.intern("y");
+
+        let result = asm::assemble("ldn x r0\nldn y r1\n").ok().unwrap();
+        let mut program = result.program;
+        link(&mut program, 0, &result.jumptab, &mut globals);
+
+        assert_eq!(program[0].op, Op::Ldg { dest: 0, slot: 1 });
+        assert_eq!(program[1].op, Op::Ldg { dest: 1, slot: 0 });
+    }
+
+    #[test]
+    fn relocate_program() {
+        let result = asm::assemble(
+            "
+SECTION_ decls.
+lbl0:
+fn @f [] []
+\t ret
+SECTION_ code.
+lbl1:
+\t ldc true r0
+\t jmpf r0 lbl2
+\t call @f 0
+lbl2:
+\t nop
+",
+        )
+        .ok()
+        .unwrap();
+        let mut program = result.program;
+        let mut jumptab = result.jumptab;
+        let mut fntab = result.fntab;
+        relocate(&mut program, &mut jumptab, &mut fntab, 10, 3);
+
+        assert_eq!(program[0].lblidx, 3);
+        assert_eq!(program[5].lbl, "lbl5");
+        assert_eq!(
+            program[3].op,
+            Op::JmpCnd {
+                kind: crate::opc::JmpOpKind::Jmpf,
+                src: 0,
+                lblidx: 5,
+            }
+        );
+        assert_eq!(jumptab.get(&5), Some(&15));
+        assert_eq!(jumptab.get(&2), None);
+        assert_eq!(fntab.get("f"), Some(&10));
+    }
+}
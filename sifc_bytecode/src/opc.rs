@@ -53,6 +53,9 @@ pub enum JmpOpKind {
 ///
 /// str r2 y
 /// stores the value located in r2 into the address for "y"
+///
+/// ldg g0 r1
+/// loads the global in slot 0 into r1. Slots are written as the slot number prefixed with "g".
 #[derive(Clone, Debug, PartialEq)]
 pub enum Op {
     /// Binary operator with 2 register sources.
@@ -150,10 +153,12 @@ pub enum Op {
         src: usize,
     },
 
-    /// Function declaration
+    /// Function declaration. Params and locals are the names that live in the
+    /// call frame of the function, rather than in the global heap.
     Fn {
         name: String,
         params: Vec<String>,
+        locals: Vec<String>,
     },
 
     /// Function call
@@ -162,6 +167,21 @@ pub enum Op {
         param_count: usize,
     },
 
+    /// Call the function value in src register. Used when calling through a variable
+    /// rather than a declared function name.
+    Callr {
+        src: usize,
+        param_count: usize,
+    },
+
+    /// Load a function value into dest register. The current values of the names in env
+    /// are captured into the function, and bound as locals when it's called.
+    Ldf {
+        name: String,
+        env: Vec<String>,
+        dest: usize,
+    },
+
     /// Std lib function call
     StdCall {
         name: String,
@@ -196,6 +216,78 @@ pub enum Op {
         dest: usize,
     },
 
+    /// Load a global from memory. This and the following ops are the slot indexed
+    /// variants of the ops that access names. They aren't emitted by the compiler, and
+    /// are produced by the link step for names that are known to be globals. The slot
+    /// is an index into the globals of the vm.
+    Ldg {
+        dest: usize,
+        slot: usize,
+    },
+
+    /// Store a register into a global.
+    Strg {
+        src: usize,
+        slot: usize,
+    },
+
+    /// Store a constant into a global.
+    Stcg {
+        val: SifVal,
+        slot: usize,
+    },
+
+    /// Loads the size of the array in a global.
+    Ldasg {
+        slot: usize,
+        dest: usize,
+    },
+
+    /// Loads the value of the array in a global at the index given.
+    Ldavg {
+        slot: usize,
+        idx_reg: usize,
+        dest: usize,
+    },
+
+    /// Updates a value in the array in a global.
+    Updag {
+        slot: usize,
+        idx_reg: usize,
+        val_reg: usize,
+    },
+
+    /// Insert a value from src register into the table in a global.
+    Tblig {
+        slot: usize,
+        key: String,
+        src: usize,
+    },
+
+    /// Retrieve a value from the table in a global and place it into dest register.
+    Tblgg {
+        slot: usize,
+        key: String,
+        dest: usize,
+    },
+
     Nop,  // no-op
     Stop, // halt vm execution
 }
+
+impl Op {
+    /// Returns the global slot accessed by the op, if it is a slot indexed op.
+    pub fn slot(&self) -> Option<usize> {
+        match self {
+            Op::Ldg { slot, .. }
+            | Op::Strg { slot, .. }
+            | Op::Stcg { slot, .. }
+            | Op::Ldasg { slot, .. }
+            | Op::Ldavg { slot, .. }
+            | Op::Updag { slot, .. }
+            | Op::Tblig { slot, .. }
+            | Op::Tblgg { slot, .. } => Some(*slot),
+            _ => None,
+        }
+    }
+}
@@ -8,7 +8,7 @@ use std::collections::HashMap;
 /// Implement this trait when writing optimize passes.
 pub trait BytecodePass<'b> {
     fn name(&self) -> String;
-    fn run_pass(&self, bytecode: &'b Vec<Instr>) -> Vec<Instr>;
+    fn run_pass(&self, bytecode: &'b [Instr]) -> Vec<Instr>;
 }
 
 /// Returns the optimized program as well as additional runtime information. Because instructions
@@ -58,8 +58,8 @@ impl BytecodeOptimizer {
     pub fn new(decls: Vec<Instr>, code: Vec<Instr>, code_start: usize) -> BytecodeOptimizer {
         BytecodeOptimizer {
             init_code_start: code_start,
-            decls: decls,
-            code: code,
+            decls,
+            code,
             redundant_jmp: RedundantJmp,
             remove_after_ret: RemoveAfterRet,
             remove_nop: RemoveNop,
@@ -83,12 +83,20 @@ impl BytecodeOptimizer {
             new_code_start: code_start,
             removed: instrs_removed,
             optimized: full_prog,
-            jumptab: jumptab,
-            fntab: fntab,
+            jumptab,
+            fntab,
         }
     }
 
     fn optimize_decls(&mut self) -> SectionPassResult {
+        // Passes expect at least one instruction, so empty sections are left as is.
+        if self.decls.is_empty() {
+            return SectionPassResult {
+                removed: 0,
+                optimized: Vec::new(),
+            };
+        }
+
         let r1 = self.redundant_jmp.run_pass(&self.decls);
         let r2 = self.remove_after_ret.run_pass(&r1);
         let r3 = self.remove_nop.run_pass(&r2);
@@ -101,6 +109,13 @@ impl BytecodeOptimizer {
     }
 
     fn optimize_code(&mut self) -> SectionPassResult {
+        if self.code.is_empty() {
+            return SectionPassResult {
+                removed: 0,
+                optimized: Vec::new(),
+            };
+        }
+
         let r1 = self.redundant_jmp.run_pass(&self.code);
         let r2 = self.remove_after_ret.run_pass(&r1);
         let r3 = self.remove_nop.run_pass(&r2);
@@ -12,28 +12,25 @@ impl<'b> BytecodePass<'b> for RemoveAfterRet {
         String::from("RemoveAfterRet")
     }
 
-    fn run_pass(&self, bytecode: &'b Vec<Instr>) -> Vec<Instr> {
+    fn run_pass(&self, bytecode: &'b [Instr]) -> Vec<Instr> {
         let mut i = 0;
         let mut result = Vec::new();
 
         while i < bytecode.len() {
             let instr = &bytecode[i];
             let currlbl = instr.lblidx;
-            match instr.op {
-                Op::FnRet => {
-                    // At a return instruction, we look ahead to any further
-                    // instructions in the block and skip past them so they
-                    // won't be added to the result array.
-                    let mut j = i;
-                    while bytecode[j].lblidx == currlbl {
-                        j += 1;
-                        if j == bytecode.len() {
-                            break;
-                        }
+            if instr.op == Op::FnRet {
+                // At a return instruction, we look ahead to any further
+                // instructions in the block and skip past them so they
+                // won't be added to the result array.
+                let mut j = i;
+                while bytecode[j].lblidx == currlbl {
+                    j += 1;
+                    if j == bytecode.len() {
+                        break;
                     }
-                    i = j - 1;
                 }
-                _ => {}
+                i = j - 1;
             };
 
             result.push(instr.clone());
@@ -10,7 +10,7 @@ impl<'b> BytecodePass<'b> for RemoveNop {
         String::from("RemoveNop")
     }
 
-    fn run_pass(&self, bytecode: &'b Vec<Instr>) -> Vec<Instr> {
+    fn run_pass(&self, bytecode: &'b [Instr]) -> Vec<Instr> {
         let mut result = Vec::new();
         result.push(bytecode[0].clone());
 
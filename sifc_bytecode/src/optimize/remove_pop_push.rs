@@ -12,7 +12,7 @@ impl<'b> BytecodePass<'b> for RemovePopPush {
         String::from("RemovePopPush")
     }
 
-    fn run_pass(&self, bytecode: &'b Vec<Instr>) -> Vec<Instr> {
+    fn run_pass(&self, bytecode: &'b [Instr]) -> Vec<Instr> {
         let mut i = 0;
         let mut result = Vec::new();
 
@@ -2,32 +2,49 @@ use crate::instr::Instr;
 
 /// Prints the declaration section to stdout.
 pub fn dump_decls(decls: Vec<Instr>) {
-    if decls.len() == 0 {
+    if decls.is_empty() {
         return;
     }
 
-    let mut dble = String::from("SECTION_ decls.\n");
-
-    dump(decls, &mut dble)
+    println!("{}", decls_str(decls));
 }
 
 /// Prints the code section to stdout.
 pub fn dump_code(code: Vec<Instr>) {
-    if code.len() == 0 {
+    if code.is_empty() {
         return;
     }
 
-    let mut dble = String::from("SECTION_ code.\n");
-    let currlbl = code[0].lbl.clone();
-    dble.push_str(&format!("{}:\n", currlbl));
+    println!("{}", code_str(code));
+}
+
+/// Formats the declaration section in the same way as dump_decls. The result can be
+/// read back in by the assembler.
+pub fn decls_str(decls: Vec<Instr>) -> String {
+    let mut dble = String::from("SECTION_ decls.\n");
+    dump(decls, &mut dble);
+    dble
+}
 
+/// Formats the code section in the same way as dump_code. The result can be read back
+/// in by the assembler.
+pub fn code_str(code: Vec<Instr>) -> String {
+    let mut dble = String::from("SECTION_ code.\n");
     dump(code, &mut dble);
+    dble
 }
 
 /// dump will parse the vector of instrs and transform it into typical
-/// asm-looking strings for printing.
+/// asm-looking strings. Each section starts with the label of its first instr, so
+/// that every instr in the output falls under a label.
 fn dump(ir: Vec<Instr>, dble: &mut String) {
+    if ir.is_empty() {
+        return;
+    }
+
     let mut currlbl = ir[0].lbl.clone();
+    dble.push_str(&format!("{}:\n", currlbl));
+
     for i in ir {
         if i.lbl != currlbl {
             let line = format!("{}:\n", &i.lbl);
@@ -37,6 +54,4 @@ fn dump(ir: Vec<Instr>, dble: &mut String) {
 
         dble.push_str(&format!("{:#?}", i));
     }
-
-    println!("{}", dble);
 }
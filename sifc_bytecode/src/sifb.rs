@@ -0,0 +1,1017 @@
+use crate::{
+    compiler::CompileResult,
+    instr::Instr,
+    opc::{BinOpKind, JmpOpKind, Op, UnOpKind},
+    sifv::SifVal,
+};
+use sifc_err::{
+    sifb_err::{SifbErr, SifbErrTy},
+    span::Span,
+};
+use std::{collections::HashMap, convert::TryFrom};
+
+/// Magic bytes at the start of every sif bytecode file.
+pub const SIFB_MAGIC: &[u8; 4] = b"SIFB";
+
+/// Version of the bytecode format. This must be incremented whenever the layout of the
+/// file or the encoding of any op changes, so older files are rejected instead of being
+/// read incorrectly.
+pub const SIFB_VERSION: u16 = 3;
+
+// Tags for each kind of SifVal in the constant section.
+const VAL_NUM: u8 = 0;
+const VAL_STR: u8 = 1;
+const VAL_BL: u8 = 2;
+const VAL_ARR: u8 = 3;
+const VAL_TAB: u8 = 4;
+const VAL_FN: u8 = 5;
+const VAL_NULL: u8 = 6;
+
+/// Maximum depth of arrays, tables and fn envs nested in a constant. Values are read
+/// recursively, so this keeps a crafted file from overflowing the stack.
+const MAX_VAL_DEPTH: usize = 64;
+
+/// Returns true if the bytes begin with the sif bytecode magic.
+pub fn is_sifb(bytes: &[u8]) -> bool {
+    bytes.starts_with(SIFB_MAGIC)
+}
+
+/// Serializes a compiled program into the sif bytecode format. The file is laid out
+/// in the following sections, in order:
+///
+/// 1. Header: the magic bytes, the format version and the index of the code section start.
+/// 2. Constants: every SifVal loaded or stored by a constant op, without duplicates.
+/// 3. Instructions: the label index, line, source span and op for each instruction in the
+///    program. The span is stored as its line followed by its column.
+///    Constant ops refer to values by their index in the constant section.
+/// 4. Tables: the jump table followed by the fn table.
+///
+/// All integers are little endian. Indices, registers and lengths are stored as u32, and
+/// an error is returned if any of them don't fit.
+pub fn encode(
+    program: &Vec<Instr>,
+    code_start: usize,
+    jumptab: &HashMap<usize, usize>,
+    fntab: &HashMap<String, usize>,
+) -> Result<Vec<u8>, SifbErr> {
+    let mut w = Writer::new();
+    w.bytes.extend_from_slice(SIFB_MAGIC);
+    w.bytes.extend_from_slice(&SIFB_VERSION.to_le_bytes());
+    w.u32(code_start)?;
+
+    let consts = w.consts(program)?;
+
+    w.u32(program.len())?;
+    for instr in program {
+        w.u32(instr.lblidx)?;
+        w.u32(instr.line)?;
+        w.u32(instr.span.line)?;
+        w.u32(instr.span.col)?;
+        w.op(&instr.op, &consts)?;
+    }
+
+    // Tables are sorted so that encoding the same program always produces the same bytes.
+    let mut jumps: Vec<(&usize, &usize)> = jumptab.iter().collect();
+    jumps.sort();
+    w.u32(jumps.len())?;
+    for (lbl, idx) in jumps {
+        w.u32(*lbl)?;
+        w.u32(*idx)?;
+    }
+
+    let mut fns: Vec<(&String, &usize)> = fntab.iter().collect();
+    fns.sort();
+    w.u32(fns.len())?;
+    for (name, idx) in fns {
+        w.str(name)?;
+        w.u32(*idx)?;
+    }
+
+    Ok(w.bytes)
+}
+
+/// Deserializes a program from the sif bytecode format. The resulting CompileResult
+/// can be run in a vm exactly like one returned from the compiler.
+pub fn decode(bytes: &[u8]) -> Result<CompileResult, SifbErr> {
+    let mut r = Reader::new(bytes);
+
+    if !is_sifb(bytes) {
+        return Err(r.err(SifbErrTy::InvalidMagic));
+    }
+    r.pos = SIFB_MAGIC.len();
+
+    let version = u16::from_le_bytes([r.u8()?, r.u8()?]);
+    if version != SIFB_VERSION {
+        return Err(r.err(SifbErrTy::UnsupportedVersion(version)));
+    }
+    let code_start_pos = r.pos;
+    let code_start = r.u32()?;
+
+    let const_count = r.u32()?;
+    let mut consts = Vec::new();
+    for _ in 0..const_count {
+        consts.push(r.val()?);
+    }
+
+    let instr_count = r.u32()?;
+    let mut program = Vec::new();
+    for _ in 0..instr_count {
+        let lblidx = r.u32()?;
+        let line = r.u32()?;
+        let span = Span::new(r.u32()?, r.u32()?);
+        let op = r.op(&consts)?;
+        let mut instr = Instr::new(lblidx, op, line);
+        instr.span = span;
+        program.push(instr);
+    }
+
+    let jump_count = r.u32()?;
+    let mut jumptab = HashMap::new();
+    for _ in 0..jump_count {
+        let lbl = r.u32()?;
+        let idx = r.u32()?;
+        jumptab.insert(lbl, idx);
+    }
+
+    let fn_count = r.u32()?;
+    let mut fntab = HashMap::new();
+    for _ in 0..fn_count {
+        let name = r.str()?;
+        let idx = r.u32()?;
+        fntab.insert(name, idx);
+    }
+
+    if code_start > program.len() {
+        return Err(SifbErr::new(
+            SifbErrTy::InvalidCodeStart(code_start),
+            code_start_pos,
+        ));
+    }
+
+    Ok(CompileResult {
+        code: program[code_start..].to_vec(),
+        decls: program[..code_start].to_vec(),
+        program,
+        jumptab,
+        fntab,
+        code_start,
+        err: None,
+    })
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer { bytes: Vec::new() }
+    }
+
+    fn err(&self, ty: SifbErrTy) -> SifbErr {
+        SifbErr::new(ty, self.bytes.len())
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.bytes.push(v);
+    }
+
+    fn u32(&mut self, v: usize) -> Result<(), SifbErr> {
+        match u32::try_from(v) {
+            Ok(v) => {
+                self.bytes.extend_from_slice(&v.to_le_bytes());
+                Ok(())
+            }
+            Err(_) => Err(self.err(SifbErrTy::IntTooLarge(v))),
+        }
+    }
+
+    fn str(&mut self, s: &str) -> Result<(), SifbErr> {
+        self.u32(s.len())?;
+        self.bytes.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+
+    fn strs(&mut self, strs: &Vec<String>) -> Result<(), SifbErr> {
+        self.u32(strs.len())?;
+        for s in strs {
+            self.str(s)?;
+        }
+        Ok(())
+    }
+
+    fn val(&mut self, val: &SifVal) -> Result<(), SifbErr> {
+        match val {
+            SifVal::Num(n) => {
+                self.u8(VAL_NUM);
+                self.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            SifVal::Str(s) => {
+                self.u8(VAL_STR);
+                self.str(s)?;
+            }
+            SifVal::Bl(b) => {
+                self.u8(VAL_BL);
+                self.u8(*b as u8);
+            }
+            SifVal::Arr(vals) => {
+                let vals = vals.borrow();
+                self.u8(VAL_ARR);
+                self.u32(vals.len())?;
+                for v in vals.iter() {
+                    self.val(v)?;
+                }
+            }
+            SifVal::Tab(map) => {
+                self.u8(VAL_TAB);
+                self.map(&map.borrow())?;
+            }
+            SifVal::Fn { name, env } => {
+                self.u8(VAL_FN);
+                self.str(name)?;
+                self.map(env)?;
+            }
+            SifVal::Null => self.u8(VAL_NULL),
+        };
+        Ok(())
+    }
+
+    fn map(&mut self, map: &HashMap<String, SifVal>) -> Result<(), SifbErr> {
+        let mut entries: Vec<(&String, &SifVal)> = map.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        self.u32(entries.len())?;
+        for (k, v) in entries {
+            self.str(k)?;
+            self.val(v)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the constant section, containing every constant loaded or stored by the
+    /// program once. Constants are compared by their encoding, and the index of each
+    /// encoding in the section is returned.
+    fn consts(&mut self, program: &[Instr]) -> Result<HashMap<Vec<u8>, usize>, SifbErr> {
+        // The count is filled in once the constants have been written.
+        let count_pos = self.bytes.len();
+        self.bytes.extend_from_slice(&[0; 4]);
+
+        let mut consts = HashMap::new();
+        for val in program.iter().filter_map(|instr| const_val(&instr.op)) {
+            let start = self.bytes.len();
+            self.val(val)?;
+            let encoded = self.bytes[start..].to_vec();
+            match consts.contains_key(&encoded) {
+                true => self.bytes.truncate(start),
+                false => {
+                    let idx = consts.len();
+                    consts.insert(encoded, idx);
+                }
+            };
+        }
+
+        let count = match u32::try_from(consts.len()) {
+            Ok(count) => count,
+            Err(_) => {
+                return Err(SifbErr::new(
+                    SifbErrTy::IntTooLarge(consts.len()),
+                    count_pos,
+                ))
+            }
+        };
+        self.bytes[count_pos..count_pos + 4].copy_from_slice(&count.to_le_bytes());
+        Ok(consts)
+    }
+
+    /// Writes the index of a constant in the constant section.
+    fn constant(&mut self, val: &SifVal, consts: &HashMap<Vec<u8>, usize>) -> Result<(), SifbErr> {
+        let mut encoded = Writer::new();
+        encoded.val(val)?;
+        self.u32(consts[&encoded.bytes])
+    }
+
+    fn op(&mut self, op: &Op, consts: &HashMap<Vec<u8>, usize>) -> Result<(), SifbErr> {
+        match op {
+            Op::Binary {
+                kind,
+                src1,
+                src2,
+                dest,
+            } => {
+                self.u8(0);
+                self.u8(binop_kind_code(kind));
+                self.u32(*src1)?;
+                self.u32(*src2)?;
+                self.u32(*dest)?;
+            }
+            Op::Unary { kind, src1, dest } => {
+                self.u8(1);
+                self.u8(unop_kind_code(kind));
+                self.u32(*src1)?;
+                self.u32(*dest)?;
+            }
+            Op::Ldc { dest, val } => {
+                self.u8(2);
+                self.u32(*dest)?;
+                self.constant(val, consts)?;
+            }
+            Op::Ldn { dest, name } => {
+                self.u8(3);
+                self.u32(*dest)?;
+                self.str(name)?;
+            }
+            Op::Mv { src, dest } => {
+                self.u8(4);
+                self.u32(*src)?;
+                self.u32(*dest)?;
+            }
+            Op::Ldas { name, dest } => {
+                self.u8(5);
+                self.str(name)?;
+                self.u32(*dest)?;
+            }
+            Op::Ldav {
+                name,
+                idx_reg,
+                dest,
+            } => {
+                self.u8(6);
+                self.str(name)?;
+                self.u32(*idx_reg)?;
+                self.u32(*dest)?;
+            }
+            Op::Upda {
+                name,
+                idx_reg,
+                val_reg,
+            } => {
+                self.u8(7);
+                self.str(name)?;
+                self.u32(*idx_reg)?;
+                self.u32(*val_reg)?;
+            }
+            Op::Stc { val, name } => {
+                self.u8(8);
+                self.constant(val, consts)?;
+                self.str(name)?;
+            }
+            Op::Stn { srcname, destname } => {
+                self.u8(9);
+                self.str(srcname)?;
+                self.str(destname)?;
+            }
+            Op::Str { src, name } => {
+                self.u8(10);
+                self.u32(*src)?;
+                self.str(name)?;
+            }
+            Op::JmpCnd { kind, src, lblidx } => {
+                self.u8(11);
+                self.u8(jmp_kind_code(kind));
+                self.u32(*src)?;
+                self.u32(*lblidx)?;
+            }
+            Op::Jmpa { lblidx } => {
+                self.u8(12);
+                self.u32(*lblidx)?;
+            }
+            Op::Incrr { src } => {
+                self.u8(13);
+                self.u32(*src)?;
+            }
+            Op::Decrr { src } => {
+                self.u8(14);
+                self.u32(*src)?;
+            }
+            Op::Fn {
+                name,
+                params,
+                locals,
+            } => {
+                self.u8(15);
+                self.str(name)?;
+                self.strs(params)?;
+                self.strs(locals)?;
+            }
+            Op::Call { name, param_count } => {
+                self.u8(16);
+                self.str(name)?;
+                self.u32(*param_count)?;
+            }
+            Op::Callr { src, param_count } => {
+                self.u8(17);
+                self.u32(*src)?;
+                self.u32(*param_count)?;
+            }
+            Op::Ldf { name, env, dest } => {
+                self.u8(18);
+                self.str(name)?;
+                self.strs(env)?;
+                self.u32(*dest)?;
+            }
+            Op::StdCall { name, param_count } => {
+                self.u8(19);
+                self.str(name)?;
+                self.u32(*param_count)?;
+            }
+            Op::FnRet => self.u8(20),
+            Op::FnStackPush { src } => {
+                self.u8(21);
+                self.u32(*src)?;
+            }
+            Op::FnStackPop { dest } => {
+                self.u8(22);
+                self.u32(*dest)?;
+            }
+            Op::Tbli { tabname, key, src } => {
+                self.u8(23);
+                self.str(tabname)?;
+                self.str(key)?;
+                self.u32(*src)?;
+            }
+            Op::Tblg { tabname, key, dest } => {
+                self.u8(24);
+                self.str(tabname)?;
+                self.str(key)?;
+                self.u32(*dest)?;
+            }
+            Op::Nop => self.u8(25),
+            Op::Stop => self.u8(26),
+            Op::Ldg { dest, slot } => {
+                self.u8(27);
+                self.u32(*dest)?;
+                self.u32(*slot)?;
+            }
+            Op::Strg { src, slot } => {
+                self.u8(28);
+                self.u32(*src)?;
+                self.u32(*slot)?;
+            }
+            Op::Stcg { val, slot } => {
+                self.u8(29);
+                self.constant(val, consts)?;
+                self.u32(*slot)?;
+            }
+            Op::Ldasg { slot, dest } => {
+                self.u8(30);
+                self.u32(*slot)?;
+                self.u32(*dest)?;
+            }
+            Op::Ldavg {
+                slot,
+                idx_reg,
+                dest,
+            } => {
+                self.u8(31);
+                self.u32(*slot)?;
+                self.u32(*idx_reg)?;
+                self.u32(*dest)?;
+            }
+            Op::Updag {
+                slot,
+                idx_reg,
+                val_reg,
+            } => {
+                self.u8(32);
+                self.u32(*slot)?;
+                self.u32(*idx_reg)?;
+                self.u32(*val_reg)?;
+            }
+            Op::Tblig { slot, key, src } => {
+                self.u8(33);
+                self.u32(*slot)?;
+                self.str(key)?;
+                self.u32(*src)?;
+            }
+            Op::Tblgg { slot, key, dest } => {
+                self.u8(34);
+                self.u32(*slot)?;
+                self.str(key)?;
+                self.u32(*dest)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+struct Reader<'r> {
+    bytes: &'r [u8],
+    pos: usize,
+
+    /// Number of values currently being read, including the one at the current position.
+    depth: usize,
+}
+
+impl<'r> Reader<'r> {
+    fn new(bytes: &'r [u8]) -> Reader<'r> {
+        Reader {
+            bytes,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn err(&self, ty: SifbErrTy) -> SifbErr {
+        SifbErr::new(ty, self.pos)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'r [u8], SifbErr> {
+        if self.bytes.len() - self.pos < len {
+            return Err(self.err(SifbErrTy::UnexpectedEof));
+        }
+
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, SifbErr> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, SifbErr> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    }
+
+    fn str(&mut self) -> Result<String, SifbErr> {
+        let len = self.u32()?;
+        let start = self.pos;
+        let b = self.take(len)?;
+        match String::from_utf8(b.to_vec()) {
+            Ok(s) => Ok(s),
+            Err(_) => Err(SifbErr::new(SifbErrTy::InvalidStr, start)),
+        }
+    }
+
+    fn strs(&mut self) -> Result<Vec<String>, SifbErr> {
+        let len = self.u32()?;
+        let mut strs = Vec::new();
+        for _ in 0..len {
+            strs.push(self.str()?);
+        }
+        Ok(strs)
+    }
+
+    fn val(&mut self) -> Result<SifVal, SifbErr> {
+        if self.depth == MAX_VAL_DEPTH {
+            return Err(self.err(SifbErrTy::ValTooDeep(MAX_VAL_DEPTH)));
+        }
+
+        self.depth += 1;
+        let val = self.val_contents();
+        self.depth -= 1;
+        val
+    }
+
+    fn val_contents(&mut self) -> Result<SifVal, SifbErr> {
+        let tag = self.u8()?;
+        match tag {
+            VAL_NUM => {
+                let b = self.take(8)?;
+                let mut arr = [0; 8];
+                arr.copy_from_slice(b);
+                Ok(SifVal::Num(f64::from_le_bytes(arr)))
+            }
+            VAL_STR => Ok(SifVal::Str(self.str()?)),
+            VAL_BL => Ok(SifVal::Bl(self.u8()? != 0)),
+            VAL_ARR => {
+                let len = self.u32()?;
+                let mut vals = Vec::new();
+                for _ in 0..len {
+                    vals.push(self.val()?);
+                }
+                Ok(SifVal::new_arr(vals))
+            }
+            VAL_TAB => Ok(SifVal::new_tab(self.map()?)),
+            VAL_FN => {
+                let name = self.str()?;
+                let env = self.map()?;
+                Ok(SifVal::Fn { name, env })
+            }
+            VAL_NULL => Ok(SifVal::Null),
+            _ => Err(SifbErr::new(SifbErrTy::InvalidValTag(tag), self.pos - 1)),
+        }
+    }
+
+    fn map(&mut self) -> Result<HashMap<String, SifVal>, SifbErr> {
+        let len = self.u32()?;
+        let mut map = HashMap::new();
+        for _ in 0..len {
+            let k = self.str()?;
+            let v = self.val()?;
+            map.insert(k, v);
+        }
+        Ok(map)
+    }
+
+    fn constant(&mut self, consts: &[SifVal]) -> Result<SifVal, SifbErr> {
+        let start = self.pos;
+        let idx = self.u32()?;
+        match consts.get(idx) {
+            Some(v) => Ok(v.clone()),
+            None => Err(SifbErr::new(SifbErrTy::InvalidConstIdx(idx), start)),
+        }
+    }
+
+    fn op(&mut self, consts: &[SifVal]) -> Result<Op, SifbErr> {
+        let opcode = self.u8()?;
+        let op = match opcode {
+            0 => Op::Binary {
+                kind: self.binop_kind()?,
+                src1: self.u32()?,
+                src2: self.u32()?,
+                dest: self.u32()?,
+            },
+            1 => Op::Unary {
+                kind: self.unop_kind()?,
+                src1: self.u32()?,
+                dest: self.u32()?,
+            },
+            2 => Op::Ldc {
+                dest: self.u32()?,
+                val: self.constant(consts)?,
+            },
+            3 => Op::Ldn {
+                dest: self.u32()?,
+                name: self.str()?,
+            },
+            4 => Op::Mv {
+                src: self.u32()?,
+                dest: self.u32()?,
+            },
+            5 => Op::Ldas {
+                name: self.str()?,
+                dest: self.u32()?,
+            },
+            6 => Op::Ldav {
+                name: self.str()?,
+                idx_reg: self.u32()?,
+                dest: self.u32()?,
+            },
+            7 => Op::Upda {
+                name: self.str()?,
+                idx_reg: self.u32()?,
+                val_reg: self.u32()?,
+            },
+            8 => Op::Stc {
+                val: self.constant(consts)?,
+                name: self.str()?,
+            },
+            9 => Op::Stn {
+                srcname: self.str()?,
+                destname: self.str()?,
+            },
+            10 => Op::Str {
+                src: self.u32()?,
+                name: self.str()?,
+            },
+            11 => Op::JmpCnd {
+                kind: self.jmp_kind()?,
+                src: self.u32()?,
+                lblidx: self.u32()?,
+            },
+            12 => Op::Jmpa {
+                lblidx: self.u32()?,
+            },
+            13 => Op::Incrr { src: self.u32()? },
+            14 => Op::Decrr { src: self.u32()? },
+            15 => Op::Fn {
+                name: self.str()?,
+                params: self.strs()?,
+                locals: self.strs()?,
+            },
+            16 => Op::Call {
+                name: self.str()?,
+                param_count: self.u32()?,
+            },
+            17 => Op::Callr {
+                src: self.u32()?,
+                param_count: self.u32()?,
+            },
+            18 => Op::Ldf {
+                name: self.str()?,
+                env: self.strs()?,
+                dest: self.u32()?,
+            },
+            19 => Op::StdCall {
+                name: self.str()?,
+                param_count: self.u32()?,
+            },
+            20 => Op::FnRet,
+            21 => Op::FnStackPush { src: self.u32()? },
+            22 => Op::FnStackPop { dest: self.u32()? },
+            23 => Op::Tbli {
+                tabname: self.str()?,
+                key: self.str()?,
+                src: self.u32()?,
+            },
+            24 => Op::Tblg {
+                tabname: self.str()?,
+                key: self.str()?,
+                dest: self.u32()?,
+            },
+            25 => Op::Nop,
+            26 => Op::Stop,
+            27 => Op::Ldg {
+                dest: self.u32()?,
+                slot: self.u32()?,
+            },
+            28 => Op::Strg {
+                src: self.u32()?,
+                slot: self.u32()?,
+            },
+            29 => Op::Stcg {
+                val: self.constant(consts)?,
+                slot: self.u32()?,
+            },
+            30 => Op::Ldasg {
+                slot: self.u32()?,
+                dest: self.u32()?,
+            },
+            31 => Op::Ldavg {
+                slot: self.u32()?,
+                idx_reg: self.u32()?,
+                dest: self.u32()?,
+            },
+            32 => Op::Updag {
+                slot: self.u32()?,
+                idx_reg: self.u32()?,
+                val_reg: self.u32()?,
+            },
+            33 => Op::Tblig {
+                slot: self.u32()?,
+                key: self.str()?,
+                src: self.u32()?,
+            },
+            34 => Op::Tblgg {
+                slot: self.u32()?,
+                key: self.str()?,
+                dest: self.u32()?,
+            },
+            _ => return Err(SifbErr::new(SifbErrTy::InvalidOpcode(opcode), self.pos - 1)),
+        };
+
+        Ok(op)
+    }
+
+    fn binop_kind(&mut self) -> Result<BinOpKind, SifbErr> {
+        let code = self.u8()?;
+        let kind = match code {
+            0 => BinOpKind::Add,
+            1 => BinOpKind::Sub,
+            2 => BinOpKind::Mul,
+            3 => BinOpKind::Div,
+            4 => BinOpKind::Modu,
+            5 => BinOpKind::Eq,
+            6 => BinOpKind::Neq,
+            7 => BinOpKind::LtEq,
+            8 => BinOpKind::Lt,
+            9 => BinOpKind::GtEq,
+            10 => BinOpKind::Gt,
+            11 => BinOpKind::Land,
+            12 => BinOpKind::Lnot,
+            13 => BinOpKind::Lor,
+            _ => return Err(SifbErr::new(SifbErrTy::InvalidOpKind(code), self.pos - 1)),
+        };
+        Ok(kind)
+    }
+
+    fn unop_kind(&mut self) -> Result<UnOpKind, SifbErr> {
+        let code = self.u8()?;
+        match code {
+            0 => Ok(UnOpKind::Lneg),
+            1 => Ok(UnOpKind::Nneg),
+            _ => Err(SifbErr::new(SifbErrTy::InvalidOpKind(code), self.pos - 1)),
+        }
+    }
+
+    fn jmp_kind(&mut self) -> Result<JmpOpKind, SifbErr> {
+        let code = self.u8()?;
+        match code {
+            0 => Ok(JmpOpKind::Jmpt),
+            1 => Ok(JmpOpKind::Jmpf),
+            _ => Err(SifbErr::new(SifbErrTy::InvalidOpKind(code), self.pos - 1)),
+        }
+    }
+}
+
+/// Returns the constant loaded or stored by an op, if it has one.
+fn const_val(op: &Op) -> Option<&SifVal> {
+    match op {
+        Op::Ldc { val, .. } | Op::Stc { val, .. } | Op::Stcg { val, .. } => Some(val),
+        _ => None,
+    }
+}
+
+fn binop_kind_code(kind: &BinOpKind) -> u8 {
+    match kind {
+        BinOpKind::Add => 0,
+        BinOpKind::Sub => 1,
+        BinOpKind::Mul => 2,
+        BinOpKind::Div => 3,
+        BinOpKind::Modu => 4,
+        BinOpKind::Eq => 5,
+        BinOpKind::Neq => 6,
+        BinOpKind::LtEq => 7,
+        BinOpKind::Lt => 8,
+        BinOpKind::GtEq => 9,
+        BinOpKind::Gt => 10,
+        BinOpKind::Land => 11,
+        BinOpKind::Lnot => 12,
+        BinOpKind::Lor => 13,
+    }
+}
+
+fn unop_kind_code(kind: &UnOpKind) -> u8 {
+    match kind {
+        UnOpKind::Lneg => 0,
+        UnOpKind::Nneg => 1,
+    }
+}
+
+fn jmp_kind_code(kind: &JmpOpKind) -> u8 {
+    match kind {
+        JmpOpKind::Jmpt => 0,
+        JmpOpKind::Jmpf => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let program = build_program();
+        let (jumptab, fntab) = crate::tables::compute(&program);
+        let bytes = encode(&program, 2, &jumptab, &fntab).unwrap();
+        let result = decode(&bytes).unwrap();
+
+        assert_eq!(result.program, program);
+        assert_eq!(result.decls, program[..2].to_vec());
+        assert_eq!(result.code, program[2..].to_vec());
+        assert_eq!(result.code_start, 2);
+        assert_eq!(result.jumptab, jumptab);
+        assert_eq!(result.fntab, fntab);
+    }
+
+    #[test]
+    fn dedup_consts() {
+        let program = vec![
+            Instr::new(
+                0,
+                Op::Ldc {
+                    dest: 0,
+                    val: SifVal::Str(String::from("x")),
+                },
+                1,
+            ),
+            Instr::new(
+                0,
+                Op::Stc {
+                    val: SifVal::Str(String::from("x")),
+                    name: String::from("y"),
+                },
+                2,
+            ),
+        ];
+        let bytes = encode(&program, 0, &HashMap::new(), &HashMap::new()).unwrap();
+
+        // The constant count follows the magic, the version and the code start.
+        let const_count = u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]);
+        assert_eq!(const_count, 1);
+        assert_eq!(decode(&bytes).unwrap().program, program);
+    }
+
+    #[test]
+    fn invalid_magic() {
+        let result = decode(b"SIFX\x01\x00");
+        assert_eq!(result.err().unwrap().ty, SifbErrTy::InvalidMagic);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut bytes = encode(&Vec::new(), 0, &HashMap::new(), &HashMap::new()).unwrap();
+        bytes[4] = 99;
+        let result = decode(&bytes);
+        assert_eq!(result.err().unwrap().ty, SifbErrTy::UnsupportedVersion(99));
+    }
+
+    #[test]
+    fn truncated() {
+        let program = build_program();
+        let bytes = encode(&program, 2, &HashMap::new(), &HashMap::new()).unwrap();
+        let result = decode(&bytes[..bytes.len() - 3]);
+        assert_eq!(result.err().unwrap().ty, SifbErrTy::UnexpectedEof);
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        let program = vec![Instr::new(0, Op::Nop, 1)];
+        let mut bytes = encode(&program, 0, &HashMap::new(), &HashMap::new()).unwrap();
+
+        // The only instruction is after the header, the empty constant section, the
+        // instruction count, the label index, the line and the span.
+        bytes[34] = 200;
+        let result = decode(&bytes);
+        assert_eq!(result.err().unwrap().ty, SifbErrTy::InvalidOpcode(200));
+    }
+
+    #[test]
+    fn invalid_code_start() {
+        let program = vec![Instr::new(0, Op::Nop, 1)];
+        let bytes = encode(&program, 5, &HashMap::new(), &HashMap::new()).unwrap();
+        let err = decode(&bytes).err().unwrap();
+        assert_eq!(err.ty, SifbErrTy::InvalidCodeStart(5));
+
+        // The code start follows the magic and the version.
+        assert_eq!(err.pos, 6);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn int_too_large() {
+        let reg = u32::MAX as usize + 1;
+        let program = vec![Instr::new(0, Op::Mv { src: 0, dest: reg }, 1)];
+        let result = encode(&program, 0, &HashMap::new(), &HashMap::new());
+        assert_eq!(result.err().unwrap().ty, SifbErrTy::IntTooLarge(reg));
+    }
+
+    #[test]
+    fn val_too_deep() {
+        // A constant that is an array nested far deeper than the limit.
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(SIFB_MAGIC);
+        bytes.extend_from_slice(&SIFB_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        for _ in 0..100_000 {
+            bytes.push(VAL_ARR);
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+        }
+        bytes.push(VAL_NULL);
+
+        let result = decode(&bytes);
+        assert_eq!(
+            result.err().unwrap().ty,
+            SifbErrTy::ValTooDeep(MAX_VAL_DEPTH)
+        );
+    }
+
+    // Builds the following program, with a decl section of 2 instructions:
+    // lbl0:
+    //  fn @f ["x"]
+    //  ret
+    // lbl1:
+    //  ldc [1, "a", true, null] r0
+    //  fstpush r0
+    //  call f
+    //  fstpop r1
+    //  jmpf r1 lbl1
+    fn build_program() -> Vec<Instr> {
+        vec![
+            Instr::new(
+                0,
+                Op::Fn {
+                    name: String::from("f"),
+                    params: vec![String::from("x")],
+                    locals: Vec::new(),
+                },
+                1,
+            ),
+            Instr::new(0, Op::FnRet, 2),
+            Instr::new(
+                1,
+                Op::Ldc {
+                    dest: 0,
+                    val: SifVal::new_arr(vec![
+                        SifVal::Num(1.0),
+                        SifVal::Str(String::from("a")),
+                        SifVal::Bl(true),
+                        SifVal::Null,
+                    ]),
+                },
+                1,
+            ),
+            Instr::new(1, Op::FnStackPush { src: 0 }, 2),
+            Instr::new(
+                1,
+                Op::Call {
+                    name: String::from("f"),
+                    param_count: 1,
+                },
+                3,
+            ),
+            Instr::new(1, Op::FnStackPop { dest: 1 }, 4),
+            Instr::new(
+                1,
+                Op::JmpCnd {
+                    kind: JmpOpKind::Jmpf,
+                    src: 1,
+                    lblidx: 1,
+                },
+                5,
+            ),
+        ]
+    }
+}
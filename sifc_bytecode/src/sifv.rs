@@ -1,16 +1,109 @@
-use std::{collections::HashMap, fmt};
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
 
+/// Shared handle to the items of an array.
+pub type ArrRef = Rc<RefCell<Vec<SifVal>>>;
+
+/// Shared handle to the entries of a table.
+pub type TabRef = Rc<RefCell<HashMap<String, SifVal>>>;
+
+/// SifVal is a value that can be stored in a register or in memory.
+///
+/// Arrays and tables have reference semantics. Copying one of these values copies the
+/// handle, not the collection, so assigning an array to another name, passing it to a
+/// function, returning it or capturing it in a closure all refer to the same array.
+/// Updating the array through any of these names is visible through all of them. An
+/// array or table literal creates a new collection each time it is evaluated.
+///
+/// A collection can never contain itself, at any depth. The vm refuses stores that would
+/// make one, so that printing, comparing and copying values always terminates, and
+/// collections are freed once nothing refers to them.
+///
+/// Equality compares arrays and tables by their contents.
 #[derive(Debug, Clone, PartialEq)]
 pub enum SifVal {
     Num(f64),
     Str(String),
     Bl(bool),
-    Arr(Vec<SifVal>),
-    Tab(HashMap<String, SifVal>),
+    Arr(ArrRef),
+    Tab(TabRef),
+
+    /// A function value. The name refers to the fn declaration to call, and the env
+    /// contains any values captured from the scope the function was created in.
+    Fn {
+        name: String,
+        env: HashMap<String, SifVal>,
+    },
     Null,
 }
 
 impl SifVal {
+    /// Creates a new array containing the given items.
+    pub fn new_arr(items: Vec<SifVal>) -> SifVal {
+        SifVal::Arr(Rc::new(RefCell::new(items)))
+    }
+
+    /// Creates a new table containing the given entries.
+    pub fn new_tab(entries: HashMap<String, SifVal>) -> SifVal {
+        SifVal::Tab(Rc::new(RefCell::new(entries)))
+    }
+
+    /// Returns a copy of the value that doesn't share any arrays or tables with it.
+    /// Constants in the program are copied like this when loaded, so that mutating a
+    /// collection never changes the constant it was created from.
+    pub fn deep_copy(&self) -> SifVal {
+        match self {
+            SifVal::Arr(items) => {
+                SifVal::new_arr(items.borrow().iter().map(SifVal::deep_copy).collect())
+            }
+            SifVal::Tab(entries) => SifVal::new_tab(
+                entries
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.deep_copy()))
+                    .collect(),
+            ),
+            SifVal::Fn { name, env } => SifVal::Fn {
+                name: name.clone(),
+                env: env
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.deep_copy()))
+                    .collect(),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Returns true if the value is the array or table that target refers to, or contains
+    /// it at any depth, including in the env of a function value. Storing the value in
+    /// target would then make target contain itself.
+    pub fn refers_to(&self, target: &SifVal) -> bool {
+        let same = match (self, target) {
+            (SifVal::Arr(a), SifVal::Arr(b)) => Rc::ptr_eq(a, b),
+            (SifVal::Tab(a), SifVal::Tab(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        };
+
+        same || match self {
+            SifVal::Arr(items) => items.borrow().iter().any(|v| v.refers_to(target)),
+            SifVal::Tab(entries) => entries.borrow().values().any(|v| v.refers_to(target)),
+            SifVal::Fn { env, .. } => env.values().any(|v| v.refers_to(target)),
+            _ => false,
+        }
+    }
+
+    /// Returns the name of the kind of value, as used in error messages.
+    pub fn ty_name(&self) -> &'static str {
+        match self {
+            SifVal::Num(_) => "number",
+            SifVal::Str(_) => "string",
+            SifVal::Bl(_) => "bool",
+            SifVal::Arr(_) => "array",
+            SifVal::Tab(_) => "table",
+            SifVal::Fn { .. } => "fn",
+            SifVal::Null => "null",
+        }
+    }
+
     pub fn extract_num(&self) -> f64 {
         match self {
             SifVal::Num(f) => *f,
@@ -26,6 +119,7 @@ impl fmt::Display for SifVal {
             SifVal::Str(strval) => write!(f, "{}", strval),
             SifVal::Bl(blval) => write!(f, "{}", blval),
             SifVal::Arr(valvec) => {
+                let valvec = valvec.borrow();
                 let mut contents = String::from("[");
                 for (i, val) in valvec.iter().enumerate() {
                     contents.push_str(&format!("{:#}", val));
@@ -33,21 +127,28 @@ impl fmt::Display for SifVal {
                         contents.push_str(", ");
                     }
                 }
-                contents.push_str("]");
+                contents.push(']');
                 write!(f, "{}", contents)
             }
             SifVal::Tab(map) => {
+                // Entries are printed in key order, so that printing a table always gives
+                // the same output.
+                let map = map.borrow();
+                let mut entries: Vec<(&String, &SifVal)> = map.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+
                 let mut contents = String::from("{");
-                for (key, val) in map.iter() {
+                for (key, val) in entries {
                     contents.push_str(&format!("{:#}: ", key));
                     contents.push_str(&format!("{:#}, ", val));
                 }
                 contents.pop();
                 contents.pop();
-                contents.push_str("}");
+                contents.push('}');
                 write!(f, "{:?}", contents)
             }
-            SifVal::Null => write!(f, "{}", "null"),
+            SifVal::Fn { name, .. } => write!(f, "<fn {}>", name),
+            SifVal::Null => write!(f, "null"),
         }
     }
 }
@@ -6,7 +6,19 @@ use crate::{
 
 use sifc_parse::ast::AstNode;
 
-/// Contains compiler functions for if-stmts and for-stmts.
+// Contains compiler functions for if-stmts, loops, and loop control statements.
+
+/// LoopCtx records the jumps generated by break and continue statements inside of a loop
+/// body. The jump targets aren't known until the whole loop is compiled, so the jumps are
+/// pushed as placeholders and updated once the loop is finished.
+#[derive(Debug, Default)]
+pub struct LoopCtx {
+    /// Instruction indices of jumps that exit the loop.
+    break_idxs: Vec<usize>,
+
+    /// Instruction indices of jumps that start the next loop iteration.
+    cont_idxs: Vec<usize>,
+}
 
 impl<'c> Compiler<'c> {
     pub fn ifstmt(
@@ -42,7 +54,7 @@ impl<'c> Compiler<'c> {
         let jmpa_op_placeholder = Op::Jmpa { lblidx: usize::MAX };
         self.push_op(jmpa_op_placeholder);
 
-        let has_elifs = elif_exprs.len() != 0;
+        let has_elifs = !elif_exprs.is_empty();
         let mut jmpcnd_lbl = self.lblcnt() + 1; // first elif label, if exists
 
         // Generate statements for elif nodes. Each elif generation returns the jump index
@@ -59,7 +71,7 @@ impl<'c> Compiler<'c> {
 
         // Generate statements for else nodes. No additional labeling is needed here,
         // as the else will fall through to subsequent instructions after being evaluated.
-        let has_else = else_stmts.len() != 0;
+        let has_else = !else_stmts.is_empty();
         if has_else && !has_elifs {
             jmpcnd_lbl = self.lblcnt() + 1;
         }
@@ -156,6 +168,8 @@ impl<'c> Compiler<'c> {
         // Load the index register and set it to 0 initially.
         let idx_reg = self.nextreg();
         let (idx_name, local_name) = self.names_from_identpair(var_list);
+        self.declare_local(&idx_name);
+        self.declare_local(&local_name);
 
         // Determine the name of the array being looped over. We make a temp name if the
         // loop expression is not a primary.
@@ -163,6 +177,7 @@ impl<'c> Compiler<'c> {
             AstNode::PrimaryExpr { tkn, .. } => tkn.get_name(),
             AstNode::FnCallExpr { .. } => {
                 let temp_name = String::from("fortmp");
+                self.declare_local(&temp_name);
                 self.expr(in_expr_list);
                 // store temp var which is value of function call
                 let op = Op::Str {
@@ -207,7 +222,7 @@ impl<'c> Compiler<'c> {
         let local_reg = self.nextreg();
         self.push_op(Op::Ldav {
             name: loop_var_name.clone(),
-            idx_reg: idx_reg,
+            idx_reg,
             dest: local_reg,
         });
         self.push_op(Op::Str {
@@ -216,7 +231,16 @@ impl<'c> Compiler<'c> {
         });
 
         // Generate instructions for the actual loop statements.
+        self.loops().push(LoopCtx::default());
         self.block(stmts);
+        let ctx = self.loops().pop().unwrap();
+
+        // Continue statements jump to the index increment. This only needs its own label
+        // if the loop contains any continue statements.
+        if !ctx.cont_idxs.is_empty() {
+            self.newlbl();
+            self.patch_jmps(&ctx.cont_idxs, self.lblcnt());
+        }
 
         // Increment index register and store it again.
         self.push_op(Op::Incrr { src: idx_reg });
@@ -242,6 +266,84 @@ impl<'c> Compiler<'c> {
             lblidx: loop_lbl,
         };
         self.push_op(idx_jmp);
+
+        // Break statements jump to an exit label after the loop, which again is only needed
+        // if the loop contains any break statements.
+        if !ctx.break_idxs.is_empty() {
+            self.newlbl();
+            self.patch_jmps(&ctx.break_idxs, self.lblcnt());
+            self.push_op(Op::Nop);
+        }
+    }
+
+    pub fn whilestmt(&mut self, cond_expr: &AstNode, stmts: &AstNode) {
+        // The condition is evaluated under its own label, so we can jump back to it
+        // at the end of each iteration.
+        self.newlbl();
+        let cond_lbl = self.lblcnt();
+        self.expr(cond_expr);
+
+        // Placeholder for the jump out of the loop when the condition is false. This
+        // is updated once the loop body is generated and we know the exit label.
+        let jmpcnd_idx = self.instr_count_in_scope();
+        let jmpcnd_reg = self.prevreg();
+        let jmpcnd_op_placeholder = Op::JmpCnd {
+            kind: JmpOpKind::Jmpf,
+            src: usize::MAX,
+            lblidx: usize::MAX,
+        };
+        self.push_op(jmpcnd_op_placeholder);
+
+        self.newlbl();
+        self.loops().push(LoopCtx::default());
+        self.block(stmts);
+        let ctx = self.loops().pop().unwrap();
+        self.push_op(Op::Jmpa { lblidx: cond_lbl });
+
+        // Push a Nop to the exit label, in case there are no instructions following the loop.
+        self.newlbl();
+        let exit_lbl = self.lblcnt();
+        self.push_op(Op::Nop);
+
+        let jmp_op_real = Op::JmpCnd {
+            kind: JmpOpKind::Jmpf,
+            src: jmpcnd_reg,
+            lblidx: exit_lbl,
+        };
+        self.update_op_at(jmpcnd_idx, jmp_op_real);
+        self.patch_jmps(&ctx.break_idxs, exit_lbl);
+        self.patch_jmps(&ctx.cont_idxs, cond_lbl);
+    }
+
+    pub fn breakstmt(&mut self) {
+        let idx = self.loop_ctrl_jmp();
+        match self.loops().last_mut() {
+            Some(ctx) => ctx.break_idxs.push(idx),
+            None => panic!("Compiling break statement outside of a loop!"),
+        };
+    }
+
+    pub fn contstmt(&mut self) {
+        let idx = self.loop_ctrl_jmp();
+        match self.loops().last_mut() {
+            Some(ctx) => ctx.cont_idxs.push(idx),
+            None => panic!("Compiling continue statement outside of a loop!"),
+        };
+    }
+
+    /// Pushes a placeholder jump for a break or continue statement, and returns the index
+    /// of the jump so it can be updated when the enclosing loop is finished.
+    fn loop_ctrl_jmp(&mut self) -> usize {
+        let idx = self.instr_count_in_scope();
+        self.push_op(Op::Jmpa { lblidx: usize::MAX });
+        idx
+    }
+
+    /// Updates each placeholder jump at the given indices to jump to the given label.
+    fn patch_jmps(&mut self, idxs: &Vec<usize>, lblidx: usize) {
+        for idx in idxs {
+            self.update_op_at(*idx, Op::Jmpa { lblidx });
+        }
     }
 
     /// Processes AstNode::IdentPair and returns a tuple of the names inside the node
@@ -252,13 +354,11 @@ impl<'c> Compiler<'c> {
         match var_list {
             AstNode::IdentPair { idents } => {
                 // the vector of idents should contain no more than 2 items
-                match &idents[0] {
-                    AstNode::PrimaryExpr { tkn } => n1 = tkn.get_name(),
-                    _ => {}
+                if let AstNode::PrimaryExpr { tkn } = &idents[0] {
+                    n1 = tkn.get_name()
                 };
-                match &idents[1] {
-                    AstNode::PrimaryExpr { tkn } => n2 = tkn.get_name(),
-                    _ => {}
+                if let AstNode::PrimaryExpr { tkn } = &idents[1] {
+                    n2 = tkn.get_name()
                 };
             }
             _ => panic!("invalid ident pair ast found!"),
@@ -6,29 +6,29 @@ use std::collections::HashMap;
 ///    jmp instructions.
 /// 2. The second table in the tuple contains addresses of fn decls to jump to using
 ///    call instructions.
+///
 /// Two tables are used because the addresses of functions are in a different program
 /// section (declaration) than the jump addresses.
-pub fn compute(program: &Vec<Instr>) -> (HashMap<usize, usize>, HashMap<String, usize>) {
+pub fn compute(program: &[Instr]) -> (HashMap<usize, usize>, HashMap<String, usize>) {
     let mut jt = HashMap::new();
     let mut ft = HashMap::new();
-    if program.len() == 0 {
+    if program.is_empty() {
         return (jt, ft);
     }
 
-    jt.insert(0, 0);
+    // Each label points at the first instr under it. The first instr always starts a
+    // label, which is where loops at the start of a program jump back to.
+    jt.insert(program[0].lblidx, 0);
     let mut curridx = program[0].lblidx;
 
     for (i, instr) in program.iter().enumerate() {
         if instr.lblidx != curridx {
             curridx = instr.lblidx;
-            jt.insert(instr.lblidx, i);
+            jt.entry(instr.lblidx).or_insert(i);
         }
 
-        match &instr.op {
-            Op::Fn { name, .. } => {
-                ft.insert(name.clone(), i);
-            }
-            _ => {}
+        if let Op::Fn { name, .. } = &instr.op {
+            ft.insert(name.clone(), i);
         };
     }
 
@@ -47,18 +47,17 @@ mod tests {
     fn test_compute_empty() {
         let prog = Vec::new();
         let (jt, ft) = compute(&prog);
-        assert!(jt.len() == 0);
-        assert!(ft.len() == 0);
+        assert!(jt.is_empty());
+        assert!(ft.is_empty());
     }
 
     #[test]
     fn single_instr() {
-        let mut code = Vec::new();
-        code.push(Instr::new(0, Op::Nop, 1));
+        let code = vec![Instr::new(0, Op::Nop, 1)];
         let (jt, ft) = compute(&code);
 
         assert!(jt.len() == 1);
-        assert!(ft.len() == 0); // no fn decls
+        assert!(ft.is_empty()); // no fn decls
 
         let g = jt.get(&0).unwrap();
         assert_eq!(*g, 0);
@@ -71,7 +70,7 @@ mod tests {
         let (jt, ft) = compute(&code);
 
         assert!(jt.len() == 3);
-        assert!(ft.len() == 0);
+        assert!(ft.is_empty());
 
         let first = jt.get(&0).unwrap();
         assert_eq!(*first, 0);
@@ -91,7 +90,7 @@ mod tests {
         let (jt, ft) = compute(&code);
         println!("{:#?}", jt);
 
-        assert!(jt.len() == 1); // we always insert (0, 0)
+        assert!(jt.len() == 1); // the label of the first instr
         assert!(ft.len() == 1);
 
         let decl = ft.get("test");
@@ -99,20 +98,35 @@ mod tests {
         assert_eq!(*decl.unwrap(), 0);
     }
 
+    #[test]
+    fn leading_label() {
+        // lbl1 starts at the first instr, as it does for a program starting with a loop.
+        let code = vec![
+            Instr::new(1, Op::Nop, 1),
+            Instr::new(1, Op::Jmpa { lblidx: 1 }, 1),
+            Instr::new(2, Op::Nop, 2),
+        ];
+        let (jt, _) = compute(&code);
+
+        assert_eq!(jt.len(), 2);
+        assert_eq!(jt.get(&1), Some(&0));
+        assert_eq!(jt.get(&2), Some(&2));
+        assert_eq!(jt.get(&0), None);
+    }
+
     fn build_fn_code() -> Vec<Instr> {
-        let mut code = Vec::new();
-
-        code.push(Instr::new(
-            0,
-            Op::Fn {
-                name: String::from("test"),
-                params: Vec::new(),
-            },
-            1,
-        ));
-
-        code.push(Instr::new(0, Op::Nop, 2));
-        code
+        vec![
+            Instr::new(
+                0,
+                Op::Fn {
+                    name: String::from("test"),
+                    params: Vec::new(),
+                    locals: Vec::new(),
+                },
+                1,
+            ),
+            Instr::new(0, Op::Nop, 2),
+        ]
     }
 
     // Builds the following instructions:
@@ -126,50 +140,44 @@ mod tests {
     // lbl2:
     // 	nop
     fn build_branch_code() -> Vec<Instr> {
-        let mut code = Vec::new();
-
-        code.push(Instr::new(
-            0,
-            Op::Ldc {
-                dest: 0,
-                val: SifVal::Num(1.0),
-            },
-            1,
-        ));
-
-        code.push(Instr::new(
-            0,
-            Op::Ldc {
-                dest: 1,
-                val: SifVal::Num(2.0),
-            },
-            2,
-        ));
-
-        code.push(Instr::new(
-            0,
-            Op::Binary {
-                kind: BinOpKind::Lt,
-                src1: 0,
-                src2: 1,
-                dest: 2,
-            },
-            3,
-        ));
-
-        code.push(Instr::new(
-            0,
-            Op::JmpCnd {
-                kind: JmpOpKind::Jmpf,
-                src: 2,
-                lblidx: 2,
-            },
-            4,
-        ));
-
-        code.push(Instr::new(1, Op::Jmpa { lblidx: 2 }, 5));
-        code.push(Instr::new(2, Op::Nop, 6));
-
-        code
+        vec![
+            Instr::new(
+                0,
+                Op::Ldc {
+                    dest: 0,
+                    val: SifVal::Num(1.0),
+                },
+                1,
+            ),
+            Instr::new(
+                0,
+                Op::Ldc {
+                    dest: 1,
+                    val: SifVal::Num(2.0),
+                },
+                2,
+            ),
+            Instr::new(
+                0,
+                Op::Binary {
+                    kind: BinOpKind::Lt,
+                    src1: 0,
+                    src2: 1,
+                    dest: 2,
+                },
+                3,
+            ),
+            Instr::new(
+                0,
+                Op::JmpCnd {
+                    kind: JmpOpKind::Jmpf,
+                    src: 2,
+                    lblidx: 2,
+                },
+                4,
+            ),
+            Instr::new(1, Op::Jmpa { lblidx: 2 }, 5),
+            Instr::new(2, Op::Nop, 6),
+        ]
     }
 }
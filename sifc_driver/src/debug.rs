@@ -0,0 +1,258 @@
+use sifc_err::{
+    diag::{ErrorFormat, Renderer},
+    err::SifErr,
+    runtime_err::RuntimeErr,
+    span::Span,
+};
+use sifc_vm::{
+    debug::{Debugger, Stop},
+    vm::VM,
+};
+use std::io::{self, BufRead, Write};
+
+const PROMPT: &str = "(sif) ";
+
+const HELP: &str = "\
+break <line>, b      Sets a breakpoint on a source line
+delete <line>, d     Removes the breakpoint on a source line
+breakpoints          Lists the breakpoints
+continue, c          Runs until a breakpoint or the end of the program
+step, s              Runs to the next line, stepping into calls
+next, n              Runs to the next line, stepping over calls
+finish, f            Runs until the current function returns
+locals               Prints the params and locals of the current call
+globals              Prints the globals and their values
+regs                 Prints the registers of the current call
+reg <n>, r           Prints a single register
+backtrace, bt        Prints the active calls
+list, l              Prints the source around the current line
+help, h              Prints this message
+quit, q              Exits the debugger";
+
+/// Number of lines printed before and after the current line by the list command.
+const LIST_CONTEXT: usize = 3;
+
+/// DebugSession reads debugger commands from stdin and applies them to a program that
+/// is paused in the vm. The program starts paused at its first line.
+pub struct DebugSession {
+    debugger: Debugger,
+
+    /// Lines of the source being debugged. This is empty if the program was loaded from
+    /// a bytecode file, in which case only line numbers are printed.
+    src: Vec<String>,
+
+    /// False once the program has finished or stopped with an error. The program can't
+    /// be resumed after this, but it can still be inspected.
+    running: bool,
+
+    /// Format that errors are emitted in.
+    format: ErrorFormat,
+}
+
+impl DebugSession {
+    pub fn new(vm: VM, src: &str, format: ErrorFormat) -> DebugSession {
+        DebugSession {
+            debugger: Debugger::new(vm),
+            src: src.lines().map(|l| l.to_string()).collect(),
+            running: true,
+            format,
+        }
+    }
+
+    /// Reads and runs commands until stdin is closed or quit is entered.
+    pub fn run(&mut self) {
+        println!("sif debugger, type 'help' for a list of commands");
+        match self.debugger.vm().is_finished() {
+            true => self.stopped(Ok(Stop::Finished)),
+            false => self.print_line(self.debugger.line()),
+        };
+
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("{}", PROMPT);
+            let _ = io::stdout().flush();
+
+            let line = match lines.next() {
+                Some(Ok(l)) => l,
+                _ => break,
+            };
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["quit"] | ["q"] => break,
+                [cmd, args @ ..] => self.command(cmd, args),
+            };
+        }
+    }
+
+    fn command(&mut self, cmd: &str, args: &[&str]) {
+        match (cmd, args) {
+            ("break", [line]) | ("b", [line]) => match parse_line(line) {
+                Some(l) if self.debugger.add_breakpoint(l) => {
+                    println!("Breakpoint set at line {}", l)
+                }
+                Some(l) => println!("Line {} already has a breakpoint", l),
+                None => {}
+            },
+            ("delete", [line]) | ("d", [line]) => match parse_line(line) {
+                Some(l) if self.debugger.remove_breakpoint(l) => {
+                    println!("Breakpoint removed from line {}", l)
+                }
+                Some(l) => println!("Line {} has no breakpoint", l),
+                None => {}
+            },
+            ("breakpoints", []) => {
+                for line in self.debugger.breakpoints() {
+                    println!("line {}", line);
+                }
+            }
+            ("continue", []) | ("c", []) => self.resume(Debugger::cont),
+            ("step", []) | ("s", []) => self.resume(Debugger::step_into),
+            ("next", []) | ("n", []) => self.resume(Debugger::step_over),
+            ("finish", []) | ("f", []) => self.resume(Debugger::step_out),
+            ("locals", []) => self.print_locals(),
+            ("globals", []) => self.print_globals(),
+            ("regs", []) => self.print_regs(),
+            ("reg", [reg]) | ("r", [reg]) => self.print_reg(reg),
+            ("backtrace", []) | ("bt", []) => self.print_backtrace(),
+            ("list", []) | ("l", []) => self.print_list(),
+            ("help", []) | ("h", []) => println!("{}", HELP),
+            _ => eprintln!("sif: invalid command '{}', try 'help'", cmd),
+        };
+    }
+
+    /// Resumes the program with one of the debugger run methods, and reports where it
+    /// stopped.
+    fn resume(&mut self, f: fn(&mut Debugger) -> Result<Stop, RuntimeErr>) {
+        if !self.running {
+            eprintln!("sif: the program is not running");
+            return;
+        }
+
+        let result = f(&mut self.debugger);
+        self.stopped(result);
+    }
+
+    fn stopped(&mut self, result: Result<Stop, RuntimeErr>) {
+        match result {
+            Ok(Stop::Breakpoint(line)) => {
+                println!("Breakpoint at {}", self.location(self.debugger.span()));
+                self.print_line(line);
+            }
+            Ok(Stop::Step(line)) => self.print_line(line),
+            Ok(Stop::Finished) => {
+                println!("Program finished");
+                self.running = false;
+            }
+            Err(e) => {
+                let src = self.src.join("\n");
+                Renderer::new(&src, None)
+                    .with_format(self.format)
+                    .emit(&e.diagnostic());
+                println!("Program stopped due to an error, and can still be inspected");
+                self.running = false;
+            }
+        };
+    }
+
+    fn print_line(&self, line: usize) {
+        match self.src.get(line.wrapping_sub(1)) {
+            Some(text) => println!("{}\t{}", line, text),
+            None => println!("line {}", line),
+        };
+    }
+
+    fn print_list(&self) {
+        let line = self.debugger.line();
+        if line == 0 || self.src.is_empty() {
+            return;
+        }
+
+        let first = line.saturating_sub(LIST_CONTEXT).max(1);
+        let last = (line + LIST_CONTEXT).min(self.src.len());
+        for l in first..last + 1 {
+            let marker = match l == line {
+                true => ">",
+                false => " ",
+            };
+            println!("{} {}\t{}", marker, l, self.src[l - 1]);
+        }
+    }
+
+    fn print_locals(&self) {
+        let locals = match self.debugger.vm().locals() {
+            Some(l) => l,
+            None => {
+                println!("Not inside of a function");
+                return;
+            }
+        };
+
+        let mut names: Vec<&String> = locals.keys().collect();
+        names.sort();
+        for name in names {
+            println!("{} = {:#}", name, locals[name]);
+        }
+    }
+
+    fn print_globals(&self) {
+        let vm = self.debugger.vm();
+        for name in vm.globals().names() {
+            if let Some(val) = vm.inspect_heap(name) {
+                println!("{} = {:#}", name, val)
+            };
+        }
+    }
+
+    fn print_regs(&self) {
+        let vm = self.debugger.vm();
+        for idx in 0..vm.dreg_count() {
+            if let Some(val) = vm.inspect_dreg(idx) {
+                println!("r{} = {:#}", idx, val)
+            };
+        }
+    }
+
+    fn print_reg(&self, reg: &str) {
+        let idx = match reg.trim_start_matches('r').parse::<usize>() {
+            Ok(i) => i,
+            Err(_) => {
+                eprintln!("sif: invalid register '{}'", reg);
+                return;
+            }
+        };
+
+        match self.debugger.vm().inspect_dreg(idx) {
+            Some(val) => println!("r{} = {:#}", idx, val),
+            None => println!("r{} is empty", idx),
+        };
+    }
+
+    fn print_backtrace(&self) {
+        for (depth, site) in self.debugger.vm().backtrace().iter().enumerate() {
+            let name = match &site.name {
+                Some(n) => n.as_str(),
+                None => "<code>",
+            };
+            println!("#{} {} at {}", depth, name, self.location(site.span));
+        }
+    }
+
+    /// Formats a span as file:line:col, or as line:col if the vm wasn't given a file name.
+    fn location(&self, span: Span) -> String {
+        span.with_file(self.debugger.vm().file())
+    }
+}
+
+/// Parses a source line number given to a command. Lines start at 1.
+fn parse_line(arg: &str) -> Option<usize> {
+    match arg.parse::<usize>() {
+        Ok(l) if l > 0 => Some(l),
+        _ => {
+            eprintln!("sif: invalid line number '{}'", arg);
+            None
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use sifc_bytecode::{compiler::Compiler, instr::Instr, printer, sifv::SifVal};
+use sifc_err::{
+    diag::{ErrorFormat, Renderer},
+    err::SifErr,
+    runtime_err::RuntimeErr,
+};
+use sifc_parse::{
+    ast::AstNode,
+    lex::Lexer,
+    parser::Parser,
+    symtab::SymTab,
+    token::{Token, TokenTy},
+};
+use sifc_std::Std;
+use sifc_vm::{config::VMConfig, vm::VM};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+const PROMPT: &str = "sif> ";
+const CONT_PROMPT: &str = "...> ";
+
+/// Name of the std function that prints the value of an expression statement. Identifiers
+/// can't contain '$', so this can't be called from sif code.
+const SHOW_FN: &str = "$show";
+
+const HELP: &str = "\
+:ir      Prints the bytecode for the last input
+:heap    Prints the globals and their values
+:help    Prints this message
+:quit    Exits the repl";
+
+/// Repl reads sif code from stdin and runs it, one input at a time. The symbol table, the
+/// globals and the declared fns are kept between inputs, so later inputs can use anything
+/// declared by earlier ones. If the last statement of an input is an expression, its value
+/// is printed.
+///
+/// An input continues over several lines until all of its brackets are closed, so blocks can
+/// be written in the same way as in a file. Lines starting with ':' are meta-commands, which
+/// are listed by :help.
+pub struct Repl {
+    /// Symbol table shared by the parser across inputs.
+    symtab: SymTab,
+
+    /// Vm that every input is loaded into and run by.
+    vm: VM,
+
+    /// Number of anonymous fns compiled so far, so that each input names them uniquely.
+    anoncnt: usize,
+
+    /// Declaration and code sections compiled from the last input.
+    last_ir: Option<(Vec<Instr>, Vec<Instr>)>,
+
+    /// Format that errors are emitted in.
+    format: ErrorFormat,
+}
+
+impl Repl {
+    pub fn new(conf: VMConfig, format: ErrorFormat) -> Repl {
+        let mut vm = VM::init(Vec::new(), 0, HashMap::new(), HashMap::new(), conf);
+        let mut stdlib = Std::new();
+        stdlib.register(SHOW_FN, 1, show);
+        vm.set_stdlib(stdlib);
+
+        Repl {
+            symtab: SymTab::new(),
+            vm,
+            anoncnt: 0,
+            last_ir: None,
+            format,
+        }
+    }
+
+    /// Reads and runs inputs until stdin is closed or :quit is entered.
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        let mut input = String::new();
+
+        loop {
+            match input.is_empty() {
+                true => print!("{}", PROMPT),
+                false => print!("{}", CONT_PROMPT),
+            };
+            let _ = io::stdout().flush();
+
+            let line = match lines.next() {
+                Some(Ok(l)) => l,
+                _ => break,
+            };
+
+            if input.is_empty() {
+                match line.trim() {
+                    "" => continue,
+                    ":quit" | ":q" => break,
+                    cmd if cmd.starts_with(':') => {
+                        self.command(cmd);
+                        continue;
+                    }
+                    _ => {}
+                };
+            }
+
+            input.push_str(&line);
+            input.push('\n');
+            if is_complete(&input) {
+                self.eval(&input);
+                input.clear();
+            }
+        }
+
+        println!();
+    }
+
+    /// Parses, compiles and runs a single input. Errors are emitted, and an input that
+    /// fails to parse or compile leaves the symbol table as it was before the input.
+    pub fn eval(&mut self, src: &str) {
+        let input = with_semicolon(src);
+        let saved_symtab = self.symtab.clone();
+
+        let stdlib = Std::new();
+        let mut lexer = Lexer::new(input.as_bytes());
+        let mut parser = Parser::new(&mut lexer, &mut self.symtab, &stdlib);
+        let parse_result = parser.parse();
+
+        if parse_result.has_err {
+            let renderer = Renderer::new(&input, None).with_format(self.format);
+            for e in &parse_result.errors {
+                renderer.emit(&e.diagnostic());
+            }
+            self.symtab = saved_symtab;
+            return;
+        }
+        let ast = show_last_expr(parse_result.ast.unwrap());
+
+        let mut comp = Compiler::new(&ast);
+        comp.resume_anon(self.anoncnt);
+        let comp_result = comp.compile();
+        if let Some(e) = comp_result.err {
+            Renderer::new(&input, None)
+                .with_format(self.format)
+                .emit(&e.diagnostic());
+            self.symtab = saved_symtab;
+            return;
+        };
+        self.anoncnt = comp.anon_count();
+        self.last_ir = Some((comp_result.decls, comp_result.code));
+
+        self.vm.load(
+            comp_result.program,
+            comp_result.code_start,
+            comp_result.jumptab,
+            comp_result.fntab,
+        );
+        match self.vm.run() {
+            Ok(()) => {}
+            Err(e) => Renderer::without_src()
+                .with_format(self.format)
+                .emit(&e.diagnostic()),
+        };
+    }
+
+    fn command(&self, cmd: &str) {
+        match cmd {
+            ":ir" => {
+                if let Some((decls, code)) = &self.last_ir {
+                    printer::dump_decls(decls.clone());
+                    printer::dump_code(code.clone());
+                }
+            }
+            ":heap" => {
+                for name in self.vm.globals().names() {
+                    if let Some(val) = self.vm.inspect_heap(name) {
+                        println!("{} = {:#}", name, val)
+                    };
+                }
+            }
+            ":help" => println!("{}", HELP),
+            _ => eprintln!("sif: unknown command '{}', try :help", cmd),
+        };
+    }
+}
+
+/// Checks if an input has closed all of its brackets. Brackets inside strings and
+/// comments aren't counted.
+fn is_complete(src: &str) -> bool {
+    let mut depth: i64 = 0;
+    let mut in_str = false;
+    let mut in_comment = false;
+
+    for ch in src.chars() {
+        match (ch, in_str, in_comment) {
+            ('\n', _, true) => in_comment = false,
+            (_, _, true) => {}
+            ('"', _, false) => in_str = !in_str,
+            (_, true, false) => {}
+            ('#', false, false) => in_comment = true,
+            ('{', false, false) | ('[', false, false) | ('(', false, false) => depth += 1,
+            ('}', false, false) | (']', false, false) | (')', false, false) => depth -= 1,
+            _ => {}
+        };
+    }
+
+    depth <= 0 && !in_str
+}
+
+/// Adds the semicolon that ends an expression statement, if the input doesn't already end
+/// with one or with a block. This lets expressions be entered without a trailing semicolon.
+fn with_semicolon(src: &str) -> String {
+    let trimmed = src.trim_end();
+    match trimmed.ends_with(';') || trimmed.ends_with('}') {
+        true => trimmed.to_string(),
+        false => format!("{}\n;", trimmed),
+    }
+}
+
+/// Wraps the last statement of a program in a call to the show fn, if it's an expression
+/// statement. Assignments aren't shown, since their value is already known.
+fn show_last_expr(ast: AstNode) -> AstNode {
+    let mut blocks = match ast {
+        AstNode::Program { blocks } => blocks,
+        _ => return ast,
+    };
+
+    match blocks.pop() {
+        Some(AstNode::ExprStmt { expr }) => {
+            let stmt = match *expr {
+                AstNode::VarAssignExpr { .. } | AstNode::ArrayMutExpr { .. } => {
+                    AstNode::ExprStmt { expr }
+                }
+                _ => AstNode::ExprStmt {
+                    expr: Box::new(AstNode::FnCallExpr {
+                        fn_ident_tkn: Token::new(TokenTy::Ident(SHOW_FN.to_string()), 0, 0),
+                        fn_params: vec![*expr],
+                        is_std: true,
+                        is_var: false,
+                    }),
+                },
+            };
+            blocks.push(stmt);
+        }
+        Some(block) => blocks.push(block),
+        None => {}
+    };
+
+    AstNode::Program { blocks }
+}
+
+/// Prints the value of an expression statement. Null values, such as the result of calling
+/// a fn that doesn't return anything, aren't printed.
+fn show(params: Vec<SifVal>) -> Result<SifVal, RuntimeErr> {
+    match &params[0] {
+        SifVal::Null => {}
+        val => println!("{:#}", val),
+    };
+    Ok(SifVal::Null)
+}
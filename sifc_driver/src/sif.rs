@@ -3,26 +3,37 @@ extern crate sifc_bytecode;
 extern crate sifc_parse;
 extern crate sifc_vm;
 
+mod debug;
+mod repl;
 mod timings;
 
-use crate::timings::Timings;
+use crate::{debug::DebugSession, repl::Repl, timings::Timings};
 
-use clap::{Arg, ArgMatches, Command};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
 use sifc_analysis::analyzer::Analyzer;
 use sifc_bytecode::{
     compiler::{CompileResult, Compiler},
+    instr::Instr,
     optimize::bco::{BytecodeOptimizer, OptimizeResult},
-    printer,
+    printer, sifb,
+};
+use sifc_err::{
+    diag::{ErrorFormat, Renderer},
+    err::SifErr,
 };
-use sifc_err::err::SifErr;
 use sifc_parse::{
     ast::AstNode,
     lex::Lexer,
     parser::{Parser, ParserResult},
     symtab::SymTab,
 };
-use sifc_vm::{config::VMConfig, vm::VM};
-use std::{fs::File, time::Instant};
+use sifc_std::{io::Io, Std};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    verify,
+    vm::VM,
+};
+use std::{collections::HashMap, fs, path::Path, process, time::Instant};
 
 // Default size of heap, in number of items, NOT bytes.
 const DEFAULT_HEAP: &str = "100";
@@ -40,69 +51,75 @@ const ARG_REG_COUNT: &str = "reg-count";
 const ARG_DUR: &str = "timings";
 const ARG_BC_OPT: &str = "bco";
 const ARG_ANALYSIS: &str = "analysis";
+const ARG_OUTPUT: &str = "output";
+const ARG_ERROR_FORMAT: &str = "error-format";
+
+const CMD_BUILD: &str = "build";
+const CMD_REPL: &str = "repl";
+const CMD_DEBUG: &str = "debug";
+
+// Extension of sif bytecode files.
+const SIFB_EXT: &str = "sifb";
 
 fn main() {
     let matches = parse_cl();
-    from_file(matches);
+    match matches.subcommand() {
+        Some((CMD_BUILD, build_opts)) => build(build_opts),
+        Some((CMD_REPL, repl_opts)) => repl(repl_opts),
+        Some((CMD_DEBUG, debug_opts)) => debug(debug_opts),
+        _ if !matches.contains_id(ARG_FILENAME) => repl(&matches),
+        _ => from_file(matches),
+    };
 }
 
 fn from_file(opts: ArgMatches) {
     let exec_start = Instant::now();
 
     let mut timings: Timings = Default::default();
-    let show_duration = opts.get_one::<String>(ARG_DUR).is_some();
-    let mut symtab = SymTab::new();
+    let show_duration = opts.get_flag(ARG_DUR);
     let path = opts.get_one::<String>(ARG_FILENAME).unwrap();
+    let format = error_format(&opts);
+
+    // The input is either sif source or a bytecode file built from source. Bytecode files
+    // are already compiled, so they skip straight to the vm.
+    let input = read_file(path);
+    let (comp_result, src) = match sifb::is_sifb(&input) {
+        true => (load(&input, format, &mut timings), String::new()),
+        false => (
+            compile_src(
+                &input,
+                path,
+                opts.get_flag(ARG_EMIT_AST),
+                format,
+                &mut timings,
+            ),
+            String::from_utf8_lossy(&input).to_string(),
+        ),
+    };
 
-    let parse_result = parse(&path, &mut symtab);
-    timings.parse_time = exec_start.elapsed();
-
-    // Any errors should already have been emitted by the
-    // parser, whether or not they are continuable.
-    if parse_result.has_err {
-        eprintln!("sif: Exiting due to parser errors");
-        return;
-    }
-    let ast = parse_result.ast.unwrap();
-
-    if opts.get_one::<String>(ARG_EMIT_AST).is_some() {
-        println!("{:#?}", ast);
-    }
-
-    let compile_start = Instant::now();
-    let comp_result = compile(&ast);
-    timings.compile_time = compile_start.elapsed();
-
-    let maybe_err = &comp_result.err;
-    if maybe_err.is_some() {
-        maybe_err.as_ref().unwrap().emit();
-        eprintln!("sif: exiting due to errors");
-        return;
-    }
-
-    if opts.get_one::<String>(ARG_EMIT_IR).is_some() {
+    if opts.get_flag(ARG_EMIT_IR) {
         printer::dump_decls(comp_result.decls.clone());
         printer::dump_code(comp_result.code.clone());
     }
 
-    if opts.get_one::<String>(ARG_ANALYSIS).is_some() {
+    if opts.get_flag(ARG_ANALYSIS) {
         let analyzer = Analyzer::new(comp_result.program.clone());
         // TODO: should be more fine grained eventually
         analyzer.perform();
     }
 
-    if opts.get_one::<String>(ARG_BC_OPT).is_some() {
+    if opts.get_flag(ARG_BC_OPT) {
         let opt_start = Instant::now();
         let opt_result = run_optimizer(&comp_result);
         timings.optimize_time = opt_start.elapsed();
 
         let vm_start = Instant::now();
         // TODO: need to provide better params/options to run_vm method
-        run_vm_optimized(opts, opt_result);
+        run_vm_optimized(opts, opt_result, &src);
         timings.vm_time = vm_start.elapsed();
     } else {
         let vm_start = Instant::now();
-        run_vm_raw(opts, comp_result);
+        run_vm_raw(opts, comp_result, &src);
         timings.vm_time = vm_start.elapsed();
     }
 
@@ -113,20 +130,195 @@ fn from_file(opts: ArgMatches) {
     }
 }
 
-/// Opens the file from the filename provided, creates a lexer for that file
-/// and a parser for that lexer. Fully parses the input file, and returns
-/// the result from the parser. This result will contain any errors, as well
-/// as the AST from parsing (which will be None if there are errors).
-fn parse(filename: &str, symtab: &mut SymTab) -> ParserResult {
-    let infile = match File::open(filename) {
-        Ok(file) => file,
+/// Compiles a sif source file into a bytecode file, without running it. The output
+/// path defaults to the input path with the sifb extension.
+fn build(opts: &ArgMatches) {
+    let mut timings: Timings = Default::default();
+    let path = opts.get_one::<String>(ARG_FILENAME).unwrap();
+    let input = read_file(path);
+    let format = error_format(opts);
+
+    let comp_result = compile_src(
+        &input,
+        path,
+        opts.get_flag(ARG_EMIT_AST),
+        format,
+        &mut timings,
+    );
+
+    let encoded = match opts.get_flag(ARG_BC_OPT) {
+        true => {
+            let opt_result = run_optimizer(&comp_result);
+            sifb::encode(
+                &opt_result.optimized,
+                opt_result.new_code_start,
+                &opt_result.jumptab,
+                &opt_result.fntab,
+            )
+        }
+        false => sifb::encode(
+            &comp_result.program,
+            comp_result.code_start,
+            &comp_result.jumptab,
+            &comp_result.fntab,
+        ),
+    };
+    let bytes = match encoded {
+        Ok(bytes) => bytes,
         Err(e) => {
-            panic!("sif: could not open file '{}': {:?}", filename, e.kind());
+            Renderer::without_src()
+                .with_format(format)
+                .emit(&e.diagnostic());
+            exiting("sif: exiting due to errors", format)
+        }
+    };
+
+    let outpath = match opts.get_one::<String>(ARG_OUTPUT) {
+        Some(out) => out.clone(),
+        None => Path::new(path)
+            .with_extension(SIFB_EXT)
+            .to_string_lossy()
+            .to_string(),
+    };
+
+    match fs::write(&outpath, bytes) {
+        Ok(()) => {}
+        Err(e) => {
+            eprintln!("sif: could not write file '{}': {:?}", outpath, e.kind());
+            process::exit(1);
         }
     };
+}
+
+/// Runs the interactive repl. This is used when sif is run without a file.
+fn repl(opts: &ArgMatches) {
+    let conf = VMConfig {
+        trace: opts.get_flag(ARG_TRACE_EXEC),
+        initial_heap_size: *opts.get_one::<usize>(ARG_HEAP_SIZE).unwrap(),
+        initial_dreg_count: *opts.get_one::<usize>(ARG_REG_COUNT).unwrap(),
+        limits: Limits::default(),
+        io: Io::stdio(),
+    };
 
-    let mut lexer = Lexer::new(infile);
-    let mut parser = Parser::new(&mut lexer, symtab);
+    let mut repl = Repl::new(conf, error_format(opts));
+    repl.run();
+}
+
+/// Runs a program in the interactive debugger. The program is paused before its first
+/// line, and the optimizer is never run so that every instr keeps its source line.
+fn debug(opts: &ArgMatches) {
+    let mut timings: Timings = Default::default();
+    let path = opts.get_one::<String>(ARG_FILENAME).unwrap();
+    let input = read_file(path);
+    let format = error_format(opts);
+
+    let (comp_result, src) = match sifb::is_sifb(&input) {
+        true => (load(&input, format, &mut timings), String::new()),
+        false => (
+            compile_src(&input, path, false, format, &mut timings),
+            String::from_utf8_lossy(&input).to_string(),
+        ),
+    };
+
+    let program = comp_result.program;
+    let code_start = comp_result.code_start;
+    let jumptab = comp_result.jumptab;
+    let fntab = comp_result.fntab;
+    verify_program(&program, code_start, &jumptab, &fntab, format);
+
+    let conf = VMConfig {
+        trace: false,
+        initial_heap_size: *opts.get_one::<usize>(ARG_HEAP_SIZE).unwrap(),
+        initial_dreg_count: *opts.get_one::<usize>(ARG_REG_COUNT).unwrap(),
+        limits: Limits::default(),
+        io: Io::stdio(),
+    };
+    let mut vm = VM::init(program, code_start, jumptab, fntab, conf);
+    vm.set_file(path);
+
+    let mut session = DebugSession::new(vm, &src, format);
+    session.run();
+}
+
+/// Reads the full contents of the file from the filename provided.
+fn read_file(filename: &str) -> Vec<u8> {
+    match fs::read(filename) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            panic!("sif: could not open file '{}': {:?}", filename, e.kind());
+        }
+    }
+}
+
+/// Parses and compiles sif source read from path. Any errors are emitted in the given
+/// format, and sif exits if there were any.
+fn compile_src(
+    input: &[u8],
+    path: &str,
+    emit_ast: bool,
+    format: ErrorFormat,
+    timings: &mut Timings,
+) -> CompileResult {
+    let parse_start = Instant::now();
+    let mut symtab = SymTab::new();
+    let parse_result = parse(input, &mut symtab);
+    timings.parse_time = parse_start.elapsed();
+
+    if parse_result.has_err {
+        let src = String::from_utf8_lossy(input);
+        let renderer = Renderer::new(&src, Some(path)).with_format(format);
+        for e in &parse_result.errors {
+            renderer.emit(&e.diagnostic());
+        }
+        exiting("sif: Exiting due to parser errors", format);
+    }
+    let ast = parse_result.ast.unwrap();
+
+    if emit_ast {
+        println!("{:#?}", ast);
+    }
+
+    let compile_start = Instant::now();
+    let comp_result = compile(&ast);
+    timings.compile_time = compile_start.elapsed();
+
+    let maybe_err = &comp_result.err;
+    if maybe_err.is_some() {
+        let src = String::from_utf8_lossy(input);
+        Renderer::new(&src, Some(path))
+            .with_format(format)
+            .emit(&maybe_err.as_ref().unwrap().diagnostic());
+        exiting("sif: exiting due to errors", format);
+    }
+
+    comp_result
+}
+
+/// Loads a compiled program from the contents of a bytecode file. Load time is
+/// counted as compile time.
+fn load(input: &[u8], format: ErrorFormat, timings: &mut Timings) -> CompileResult {
+    let load_start = Instant::now();
+    let result = sifb::decode(input);
+    timings.compile_time = load_start.elapsed();
+
+    match result {
+        Ok(comp_result) => comp_result,
+        Err(e) => {
+            Renderer::without_src()
+                .with_format(format)
+                .emit(&e.diagnostic());
+            exiting("sif: exiting due to errors", format)
+        }
+    }
+}
+
+/// Creates a lexer for the input and a parser for that lexer. Fully parses the input,
+/// and returns the result from the parser. This result will contain any errors, as well
+/// as the AST from parsing (which will be None if there are errors).
+fn parse(input: &[u8], symtab: &mut SymTab) -> ParserResult {
+    let mut lexer = Lexer::new(input);
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lexer, symtab, &stdlib);
     parser.parse()
 }
 
@@ -145,7 +337,9 @@ fn run_optimizer(comp_result: &CompileResult) -> OptimizeResult {
     opt.run_passes()
 }
 
-fn run_vm_optimized(opts: ArgMatches, opt_result: OptimizeResult) {
+/// Runs an optimized program. Runtime errors are emitted with a snippet of src, which is
+/// empty if the program was loaded from a bytecode file.
+fn run_vm_optimized(opts: ArgMatches, opt_result: OptimizeResult, src: &str) {
     let program = opt_result.optimized;
 
     let code_start = opt_result.new_code_start;
@@ -153,78 +347,143 @@ fn run_vm_optimized(opts: ArgMatches, opt_result: OptimizeResult) {
     let fntab = opt_result.fntab;
     let heap_size: usize = *opts.get_one::<usize>(ARG_HEAP_SIZE).unwrap();
     let dreg_count: usize = *opts.get_one::<usize>(ARG_REG_COUNT).unwrap();
+    let format = error_format(&opts);
 
     let conf = VMConfig {
-        trace: opts.get_one::<String>(ARG_TRACE_EXEC).is_some(),
+        trace: opts.get_flag(ARG_TRACE_EXEC),
         initial_heap_size: heap_size,
         initial_dreg_count: dreg_count,
+        limits: Limits::default(),
+        io: Io::stdio(),
     };
 
+    verify_program(&program, code_start, &jumptab, &fntab, format);
+
     // TODO: use a param struct for this? A builder?
     let mut vm = VM::init(program, code_start, jumptab, fntab, conf);
+    vm.set_file(opts.get_one::<String>(ARG_FILENAME).unwrap());
     let vm_result = vm.run();
 
     match vm_result {
         Ok(()) => {}
         Err(e) => {
-            e.emit();
-            eprintln!("sif: exiting due to errors");
+            Renderer::new(src, None)
+                .with_format(format)
+                .emit(&e.diagnostic());
+            exiting("sif: exiting due to errors", format);
         }
     }
 }
 
-fn run_vm_raw(opts: ArgMatches, comp_result: CompileResult) {
+/// Runs a program without optimizing it. Runtime errors are emitted in the same way as
+/// run_vm_optimized().
+fn run_vm_raw(opts: ArgMatches, comp_result: CompileResult, src: &str) {
     let program = comp_result.program;
     let code_start = comp_result.code_start;
     let jumptab = comp_result.jumptab;
     let fntab = comp_result.fntab;
     let heap_size: usize = *opts.get_one::<usize>(ARG_HEAP_SIZE).unwrap();
     let dreg_count: usize = *opts.get_one::<usize>(ARG_REG_COUNT).unwrap();
+    let format = error_format(&opts);
 
     let conf = VMConfig {
-        trace: opts.get_one::<String>(ARG_TRACE_EXEC).is_some(),
+        trace: opts.get_flag(ARG_TRACE_EXEC),
         initial_heap_size: heap_size,
         initial_dreg_count: dreg_count,
+        limits: Limits::default(),
+        io: Io::stdio(),
     };
 
+    verify_program(&program, code_start, &jumptab, &fntab, format);
+
     // TODO: use a param struct for this? A builder?
     let mut vm = VM::init(program, code_start, jumptab, fntab, conf);
+    vm.set_file(opts.get_one::<String>(ARG_FILENAME).unwrap());
     let vm_result = vm.run();
 
     match vm_result {
         Ok(()) => {}
         Err(e) => {
-            e.emit();
-            eprintln!("sif: exiting due to errors");
+            Renderer::new(src, None)
+                .with_format(format)
+                .emit(&e.diagnostic());
+            exiting("sif: exiting due to errors", format);
         }
     }
 }
 
+/// Returns the format errors should be emitted in, which is set by the error-format arg.
+fn error_format(opts: &ArgMatches) -> ErrorFormat {
+    match opts.get_one::<String>(ARG_ERROR_FORMAT).map(|f| f.as_str()) {
+        Some("json") => ErrorFormat::Json,
+        _ => ErrorFormat::Human,
+    }
+}
+
+/// Prints a message saying why sif is stopping and exits with a failure status. The
+/// message is left out of json output, so that stderr only contains diagnostics.
+fn exiting(msg: &str, format: ErrorFormat) -> ! {
+    if format == ErrorFormat::Human {
+        eprintln!("{}", msg);
+    }
+    process::exit(1);
+}
+
+/// Runs the bytecode verifier on a program before it is passed to the vm. Any errors
+/// found are emitted, and sif exits if there were any.
+fn verify_program(
+    program: &Vec<Instr>,
+    code_start: usize,
+    jumptab: &HashMap<usize, usize>,
+    fntab: &HashMap<String, usize>,
+    format: ErrorFormat,
+) {
+    if let Err(errs) = verify::verify(program, code_start, jumptab, fntab) {
+        let renderer = Renderer::without_src().with_format(format);
+        for e in errs {
+            renderer.emit(&e.diagnostic());
+        }
+        exiting("sif: exiting due to errors", format);
+    }
+}
+
 fn parse_cl() -> ArgMatches {
     Command::new("sif")
         .version("0.1")
         .author("cjkenn")
         .about("sif interpreter and vm")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .arg(
+            Arg::new(ARG_ERROR_FORMAT)
+                .long(ARG_ERROR_FORMAT)
+                .global(true)
+                .default_value("human")
+                .value_parser(["human", "json"])
+                .help("Prints errors for people to read, or as one json object per line for tools"),
+        )
         .arg(
             Arg::new(ARG_FILENAME)
-                .help("sif file to parse and run")
-                .required(true)
+                .help("sif source or bytecode file to run. Starts the repl if no file is given")
                 .index(1),
         )
         .arg(
             Arg::new(ARG_EMIT_AST)
                 .long(ARG_EMIT_AST)
+                .action(ArgAction::SetTrue)
                 .help("Prints the syntax tree to stdout"),
         )
         .arg(
             Arg::new(ARG_EMIT_IR)
                 .long(ARG_EMIT_IR)
+                .action(ArgAction::SetTrue)
                 .help("Prints sif bytecode to stdout"),
         )
         .arg(
             Arg::new(ARG_TRACE_EXEC)
                 .short('t')
                 .long(ARG_TRACE_EXEC)
+                .action(ArgAction::SetTrue)
                 .help("Traces VM execution by printing running instructions to stdout"),
         )
         .arg(
@@ -232,6 +491,7 @@ fn parse_cl() -> ArgMatches {
                 .short('H')
                 .long(ARG_HEAP_SIZE)
                 .default_value(DEFAULT_HEAP)
+                .value_parser(value_parser!(usize))
                 .help("Sets initial heap size"),
         )
         .arg(
@@ -239,23 +499,108 @@ fn parse_cl() -> ArgMatches {
                 .short('R')
                 .long(ARG_REG_COUNT)
                 .default_value(DEFAULT_DREG)
+                .value_parser(value_parser!(usize))
                 .help("Sets the default virtual register count"),
         )
         .arg(
             Arg::new(ARG_DUR)
                 .long(ARG_DUR)
+                .action(ArgAction::SetTrue)
                 .help("Display basic durations for phases of sif"),
         )
         .arg(
             Arg::new(ARG_BC_OPT)
                 .long(ARG_BC_OPT)
+                .action(ArgAction::SetTrue)
                 .help("Runs the bytecode optimizer before executing in vm"),
         )
         .arg(
             Arg::new(ARG_ANALYSIS)
                 .short('a')
                 .long(ARG_ANALYSIS)
+                .action(ArgAction::SetTrue)
                 .help("Performs analysis on the CFG and IR before starting the vm"),
         )
+        .subcommand(
+            Command::new(CMD_BUILD)
+                .about("Compiles a sif file to a bytecode file, which can be run by sif")
+                .arg(
+                    Arg::new(ARG_FILENAME)
+                        .help("sif file to compile")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new(ARG_OUTPUT)
+                        .short('o')
+                        .long(ARG_OUTPUT)
+                        .help("Path of the bytecode file to write. Defaults to the input path with a .sifb extension"),
+                )
+                .arg(
+                    Arg::new(ARG_EMIT_AST)
+                        .long(ARG_EMIT_AST)
+                        .action(ArgAction::SetTrue)
+                        .help("Prints the syntax tree to stdout"),
+                )
+                .arg(
+                    Arg::new(ARG_BC_OPT)
+                        .long(ARG_BC_OPT)
+                        .action(ArgAction::SetTrue)
+                        .help("Runs the bytecode optimizer before writing the bytecode file"),
+                ),
+        )
+        .subcommand(
+            Command::new(CMD_DEBUG)
+                .about("Runs a sif file in an interactive debugger, with breakpoints and stepping")
+                .arg(
+                    Arg::new(ARG_FILENAME)
+                        .help("sif source or bytecode file to debug")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new(ARG_HEAP_SIZE)
+                        .short('H')
+                        .long(ARG_HEAP_SIZE)
+                        .default_value(DEFAULT_HEAP)
+                        .value_parser(value_parser!(usize))
+                        .help("Sets initial heap size"),
+                )
+                .arg(
+                    Arg::new(ARG_REG_COUNT)
+                        .short('R')
+                        .long(ARG_REG_COUNT)
+                        .default_value(DEFAULT_DREG)
+                        .value_parser(value_parser!(usize))
+                        .help("Sets the default virtual register count"),
+                ),
+        )
+        .subcommand(
+            Command::new(CMD_REPL)
+                .about("Starts an interactive sif session. This is the same as running sif without a file")
+                .arg(
+                    Arg::new(ARG_TRACE_EXEC)
+                        .short('t')
+                        .long(ARG_TRACE_EXEC)
+                        .action(ArgAction::SetTrue)
+                        .help("Traces VM execution by printing running instructions to stdout"),
+                )
+                .arg(
+                    Arg::new(ARG_HEAP_SIZE)
+                        .short('H')
+                        .long(ARG_HEAP_SIZE)
+                        .default_value(DEFAULT_HEAP)
+                        .value_parser(value_parser!(usize))
+                        .help("Sets initial heap size"),
+                )
+                .arg(
+                    Arg::new(ARG_REG_COUNT)
+                        .short('R')
+                        .long(ARG_REG_COUNT)
+                        .default_value(DEFAULT_DREG)
+                        .value_parser(value_parser!(usize))
+                        .help("Sets the default virtual register count"),
+                ),
+        )
         .get_matches()
 }
@@ -0,0 +1,210 @@
+use sifc_bytecode::sifv::SifVal;
+use sifc_err::engine_err::ConvertErr;
+use std::collections::HashMap;
+
+/// FromSif converts a sif value into a Rust value. This is used to read globals and the
+/// results of calls, and can be used by host functions to read their params.
+pub trait FromSif: Sized {
+    fn from_sif(val: SifVal) -> Result<Self, ConvertErr>;
+}
+
+/// IntoSif converts a Rust value into a sif value. This is used for values returned by
+/// host functions.
+pub trait IntoSif {
+    fn into_sif(self) -> SifVal;
+}
+
+impl FromSif for SifVal {
+    fn from_sif(val: SifVal) -> Result<SifVal, ConvertErr> {
+        Ok(val)
+    }
+}
+
+impl IntoSif for SifVal {
+    fn into_sif(self) -> SifVal {
+        self
+    }
+}
+
+impl FromSif for f64 {
+    fn from_sif(val: SifVal) -> Result<f64, ConvertErr> {
+        match val {
+            SifVal::Num(n) => Ok(n),
+            v => Err(ConvertErr::new("number", v.ty_name())),
+        }
+    }
+}
+
+impl IntoSif for f64 {
+    fn into_sif(self) -> SifVal {
+        SifVal::Num(self)
+    }
+}
+
+/// Numbers are stored as floats, so integers can only be read from numbers without a
+/// fraction that are in range of the integer type.
+macro_rules! int_conversions {
+    ($($ty:ty),*) => {
+        $(
+            impl FromSif for $ty {
+                fn from_sif(val: SifVal) -> Result<$ty, ConvertErr> {
+                    let n = f64::from_sif(val)?;
+                    let in_range = n >= <$ty>::MIN as f64 && n <= <$ty>::MAX as f64;
+                    match n.fract() == 0.0 && in_range {
+                        true => Ok(n as $ty),
+                        false => Err(ConvertErr::new(stringify!($ty), &n.to_string())),
+                    }
+                }
+            }
+
+            impl IntoSif for $ty {
+                fn into_sif(self) -> SifVal {
+                    SifVal::Num(self as f64)
+                }
+            }
+        )*
+    };
+}
+
+int_conversions!(i32, i64, u32, u64, usize);
+
+impl FromSif for bool {
+    fn from_sif(val: SifVal) -> Result<bool, ConvertErr> {
+        match val {
+            SifVal::Bl(b) => Ok(b),
+            v => Err(ConvertErr::new("bool", v.ty_name())),
+        }
+    }
+}
+
+impl IntoSif for bool {
+    fn into_sif(self) -> SifVal {
+        SifVal::Bl(self)
+    }
+}
+
+impl FromSif for String {
+    fn from_sif(val: SifVal) -> Result<String, ConvertErr> {
+        match val {
+            SifVal::Str(s) => Ok(s),
+            v => Err(ConvertErr::new("string", v.ty_name())),
+        }
+    }
+}
+
+impl IntoSif for String {
+    fn into_sif(self) -> SifVal {
+        SifVal::Str(self)
+    }
+}
+
+impl IntoSif for &str {
+    fn into_sif(self) -> SifVal {
+        SifVal::Str(self.to_string())
+    }
+}
+
+/// Null converts to the unit type, which lets a host function without a return value
+/// return `Ok(())`.
+impl FromSif for () {
+    fn from_sif(val: SifVal) -> Result<(), ConvertErr> {
+        match val {
+            SifVal::Null => Ok(()),
+            v => Err(ConvertErr::new("null", v.ty_name())),
+        }
+    }
+}
+
+impl IntoSif for () {
+    fn into_sif(self) -> SifVal {
+        SifVal::Null
+    }
+}
+
+/// Null converts to None, and any other value is converted to T.
+impl<T: FromSif> FromSif for Option<T> {
+    fn from_sif(val: SifVal) -> Result<Option<T>, ConvertErr> {
+        match val {
+            SifVal::Null => Ok(None),
+            v => Ok(Some(T::from_sif(v)?)),
+        }
+    }
+}
+
+impl<T: IntoSif> IntoSif for Option<T> {
+    fn into_sif(self) -> SifVal {
+        match self {
+            Some(v) => v.into_sif(),
+            None => SifVal::Null,
+        }
+    }
+}
+
+/// Arrays are copied into the Vec, so changes made to it aren't seen by the program.
+impl<T: FromSif> FromSif for Vec<T> {
+    fn from_sif(val: SifVal) -> Result<Vec<T>, ConvertErr> {
+        match val {
+            SifVal::Arr(items) => items.borrow().iter().cloned().map(T::from_sif).collect(),
+            v => Err(ConvertErr::new("array", v.ty_name())),
+        }
+    }
+}
+
+impl<T: IntoSif> IntoSif for Vec<T> {
+    fn into_sif(self) -> SifVal {
+        SifVal::new_arr(self.into_iter().map(IntoSif::into_sif).collect())
+    }
+}
+
+/// Tables are copied into the map, so changes made to it aren't seen by the program.
+impl<T: FromSif> FromSif for HashMap<String, T> {
+    fn from_sif(val: SifVal) -> Result<HashMap<String, T>, ConvertErr> {
+        match val {
+            SifVal::Tab(entries) => entries
+                .borrow()
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), T::from_sif(v.clone())?)))
+                .collect(),
+            v => Err(ConvertErr::new("table", v.ty_name())),
+        }
+    }
+}
+
+impl<T: IntoSif> IntoSif for HashMap<String, T> {
+    fn into_sif(self) -> SifVal {
+        SifVal::new_tab(self.into_iter().map(|(k, v)| (k, v.into_sif())).collect())
+    }
+}
+
+/// IntoSifArgs converts the params of a call from the host into sif values. It's
+/// implemented for tuples of values that implement IntoSif, such as `(user, 3.0)`, and for
+/// vecs of them. A call without params takes `()`.
+pub trait IntoSifArgs {
+    fn into_sif_args(self) -> Vec<SifVal>;
+}
+
+impl<T: IntoSif> IntoSifArgs for Vec<T> {
+    fn into_sif_args(self) -> Vec<SifVal> {
+        self.into_iter().map(IntoSif::into_sif).collect()
+    }
+}
+
+macro_rules! tuple_args {
+    ($($name:ident),*) => {
+        impl<$($name: IntoSif),*> IntoSifArgs for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn into_sif_args(self) -> Vec<SifVal> {
+                let ($($name,)*) = self;
+                vec![$($name.into_sif()),*]
+            }
+        }
+    };
+}
+
+tuple_args!();
+tuple_args!(A);
+tuple_args!(A, B);
+tuple_args!(A, B, C);
+tuple_args!(A, B, C, D);
+tuple_args!(A, B, C, D, E);
+tuple_args!(A, B, C, D, E, F);
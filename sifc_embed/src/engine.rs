@@ -0,0 +1,202 @@
+use crate::{
+    convert::{FromSif, IntoSif, IntoSifArgs},
+    program::Program,
+};
+use sifc_bytecode::{compiler::Compiler, sifv::SifVal};
+use sifc_err::{
+    engine_err::EngineErr,
+    runtime_err::{RuntimeErr, RuntimeErrTy},
+};
+use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::{io::Io, Std};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    vm::VM,
+};
+use std::rc::Rc;
+
+/// Signature of the functions that hosts register with an engine, after their return
+/// value has been converted to a SifVal.
+type HostFnPtr = Rc<dyn Fn(Vec<SifVal>) -> Result<SifVal, RuntimeErr>>;
+
+/// HostFn is a function registered by the host, which sif programs call like the std
+/// library functions, ie. `@name(params)`.
+struct HostFn {
+    name: String,
+    arity: usize,
+    f: HostFnPtr,
+}
+
+/// Engine compiles and runs sif programs for a host. It's configured with the builder
+/// methods, which set the vm config and register host functions, and is then used to
+/// compile source into programs that can be run many times.
+///
+/// The vm of the last program run is kept after it finishes, so the host can read the
+/// globals it left behind with `get()` and call the fns it declares with `call()`.
+pub struct Engine {
+    /// Config for the vm each program is run in.
+    config: VMConfig,
+
+    /// Functions added to the std library of every program.
+    host_fns: Vec<HostFn>,
+
+    /// Vm of the last program run, or None if no program has been run.
+    vm: Option<VM>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine {
+            config: VMConfig {
+                trace: false,
+                initial_heap_size: 32,
+                initial_dreg_count: 64,
+                limits: Limits::default(),
+                io: Io::stdio(),
+            },
+            host_fns: Vec::new(),
+            vm: None,
+        }
+    }
+
+    /// Sets the config of the vm that programs are run in.
+    pub fn with_config(self, config: VMConfig) -> Engine {
+        Engine { config, ..self }
+    }
+
+    /// Sets the limits of the vm that programs are run in, keeping the rest of its config.
+    /// Programs that go over a limit stop with a runtime error, which lets a host run
+    /// scripts that might loop forever or use too much memory.
+    pub fn with_limits(self, limits: Limits) -> Engine {
+        Engine {
+            config: VMConfig {
+                limits,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Sets the handles that programs print to and read input from, keeping the rest of
+    /// the vm config. Giving the io a Buffer captures what programs print, eg.
+    /// `Io::stdio().with_out(buf.clone())`.
+    pub fn with_io(self, io: Io) -> Engine {
+        Engine {
+            config: VMConfig { io, ..self.config },
+            ..self
+        }
+    }
+
+    /// Registers a host function that programs can call as `@name(params)`. Calls are
+    /// checked against arity when programs are compiled. The function's return value is
+    /// converted to a sif value, and an error returned from it stops the program.
+    pub fn with_fn<F, R>(self, name: &str, arity: usize, f: F) -> Engine
+    where
+        F: Fn(Vec<SifVal>) -> Result<R, RuntimeErr> + 'static,
+        R: IntoSif,
+    {
+        let mut host_fns = self.host_fns;
+        host_fns.push(HostFn {
+            name: name.to_string(),
+            arity,
+            f: Rc::new(move |params| f(params).map(IntoSif::into_sif)),
+        });
+
+        Engine { host_fns, ..self }
+    }
+
+    /// Parses and compiles source into a program. Every parse error found is returned,
+    /// not only the first.
+    pub fn compile(&self, src: &str) -> Result<Program, EngineErr> {
+        let stdlib = self.stdlib();
+        let mut symtab = SymTab::new();
+        let mut lexer = Lexer::new(src.as_bytes());
+        let mut parser = Parser::new(&mut lexer, &mut symtab, &stdlib);
+        let parse_result = parser.parse();
+        if parse_result.has_err {
+            return Err(EngineErr::Parse(parse_result.errors));
+        }
+
+        let ast = parse_result.ast.unwrap();
+        let mut compiler = Compiler::new(&ast);
+        let comp_result = compiler.compile();
+        if let Some(e) = comp_result.err {
+            return Err(EngineErr::Compile(e));
+        };
+
+        Ok(Program {
+            instrs: comp_result.program,
+            code_start: comp_result.code_start,
+            jumptab: comp_result.jumptab,
+            fntab: comp_result.fntab,
+            file: None,
+        })
+    }
+
+    /// Runs a program in a new vm. The vm is kept once the program finishes, whether it
+    /// succeeds or fails, so that its globals can be read.
+    pub fn run(&mut self, program: &Program) -> Result<(), EngineErr> {
+        let mut vm = VM::init(
+            program.instrs.clone(),
+            program.code_start,
+            program.jumptab.clone(),
+            program.fntab.clone(),
+            self.config.clone(),
+        );
+        vm.set_stdlib(self.stdlib());
+        if let Some(name) = &program.file {
+            vm.set_file(name)
+        };
+
+        let result = vm.run();
+        self.vm = Some(vm);
+        result.map_err(EngineErr::Runtime)
+    }
+
+    /// Compiles and runs source. This is a shortcut for programs that are only run once.
+    pub fn eval(&mut self, src: &str) -> Result<(), EngineErr> {
+        let program = self.compile(src)?;
+        self.run(&program)
+    }
+
+    /// Reads a global left by the last program run, converted to T.
+    pub fn get<T: FromSif>(&self, name: &str) -> Result<T, EngineErr> {
+        let val = match &self.vm {
+            Some(vm) => vm.inspect_heap(name),
+            None => None,
+        };
+
+        match val {
+            Some(v) => Ok(T::from_sif(v.clone())?),
+            None => Err(EngineErr::UnknownGlobal(name.to_string())),
+        }
+    }
+
+    /// Calls a fn declared by the last program run, and returns the value it returns. The
+    /// fn sees the globals left by the program, so programs can be loaded once and then
+    /// used as modules of callbacks, eg. `engine.call("score", (name, 3.0))`.
+    pub fn call<A: IntoSifArgs>(&mut self, name: &str, args: A) -> Result<SifVal, EngineErr> {
+        match &mut self.vm {
+            Some(vm) => Ok(vm.call_fn(name, args.into_sif_args())?),
+            None => Err(EngineErr::Runtime(RuntimeErr::new(
+                RuntimeErrTy::InvalidFnSym(name.to_string()),
+            ))),
+        }
+    }
+
+    /// Builds the std library for a program, with the host functions added to it.
+    fn stdlib(&self) -> Std {
+        let mut stdlib = Std::new();
+        for host_fn in &self.host_fns {
+            let f = Rc::clone(&host_fn.f);
+            stdlib.register(&host_fn.name, host_fn.arity, move |params| f(params));
+        }
+        stdlib
+    }
+}
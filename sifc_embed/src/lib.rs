@@ -0,0 +1,11 @@
+pub mod convert;
+pub mod engine;
+pub mod program;
+
+pub use convert::{FromSif, IntoSif, IntoSifArgs};
+pub use engine::Engine;
+pub use program::Program;
+pub use sifc_bytecode::sifv::SifVal;
+pub use sifc_err::engine_err::{ConvertErr, EngineErr};
+pub use sifc_std::io::{Buffer, Io};
+pub use sifc_vm::config::{Limits, VMConfig};
@@ -0,0 +1,43 @@
+use sifc_bytecode::instr::Instr;
+use std::collections::HashMap;
+
+/// Program is sif source that has been compiled by an Engine. Compiling is done once, and
+/// the program can then be run any number of times. Each run starts from a fresh vm, so
+/// globals set by one run aren't seen by the next.
+#[derive(Clone)]
+pub struct Program {
+    /// Decl and code sections of the compiled program.
+    pub(crate) instrs: Vec<Instr>,
+
+    /// Index of the start of the code section in instrs.
+    pub(crate) code_start: usize,
+
+    /// Label indices mapped to instr indices.
+    pub(crate) jumptab: HashMap<usize, usize>,
+
+    /// Fn names mapped to the instr indices of their declarations.
+    pub(crate) fntab: HashMap<String, usize>,
+
+    /// Name of the file the source was read from, reported in runtime errors.
+    pub(crate) file: Option<String>,
+}
+
+impl Program {
+    /// Sets the name of the file the program was compiled from, which is reported along
+    /// with the source span in runtime errors.
+    pub fn with_file(self, name: &str) -> Program {
+        Program {
+            file: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Returns the compiled instrs, with the decl section followed by the code section.
+    pub fn instrs(&self) -> &[Instr] {
+        &self.instrs
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{diag::Diagnostic, err::SifErr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsmErrTy {
+    UnknownSection(String),
+    UnknownOp(String),
+    InvalidLbl(String),
+    InvalidReg(String),
+    InvalidSlot(String),
+    InvalidNum(String),
+    InvalidVal(String),
+    InvalidComment(String),
+    UnexpectedEol,
+    UnterminatedStr,
+    InvalidEscape(char),
+    TrailingInput(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct AsmErr {
+    pub ty: AsmErrTy,
+
+    /// Line of the assembly text where the error was found.
+    pub line: usize,
+}
+
+impl AsmErr {
+    pub fn new(ty: AsmErrTy, line: usize) -> AsmErr {
+        AsmErr { ty, line }
+    }
+
+    /// Returns the stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self.ty {
+            AsmErrTy::UnknownSection(_) => "E0701",
+            AsmErrTy::UnknownOp(_) => "E0702",
+            AsmErrTy::InvalidLbl(_) => "E0703",
+            AsmErrTy::InvalidReg(_) => "E0704",
+            AsmErrTy::InvalidSlot(_) => "E0705",
+            AsmErrTy::InvalidNum(_) => "E0706",
+            AsmErrTy::InvalidVal(_) => "E0707",
+            AsmErrTy::InvalidComment(_) => "E0708",
+            AsmErrTy::UnexpectedEol => "E0709",
+            AsmErrTy::UnterminatedStr => "E0710",
+            AsmErrTy::InvalidEscape(_) => "E0711",
+            AsmErrTy::TrailingInput(_) => "E0712",
+        }
+    }
+}
+
+impl SifErr for AsmErr {
+    fn to_msg(&self) -> String {
+        let str_pos = format!("[Line {}]", self.line);
+
+        match &self.ty {
+            AsmErrTy::UnknownSection(s) => format!("{} Unknown section '{}'", str_pos, s),
+            AsmErrTy::UnknownOp(op) => format!("{} Unknown instruction '{}'", str_pos, op),
+            AsmErrTy::InvalidLbl(l) => format!("{} Invalid label '{}'", str_pos, l),
+            AsmErrTy::InvalidReg(r) => format!("{} Invalid register '{}'", str_pos, r),
+            AsmErrTy::InvalidSlot(g) => format!("{} Invalid global slot '{}'", str_pos, g),
+            AsmErrTy::InvalidNum(n) => format!("{} Invalid number '{}'", str_pos, n),
+            AsmErrTy::InvalidVal(v) => format!("{} Invalid constant value '{}'", str_pos, v),
+            AsmErrTy::InvalidComment(c) => {
+                format!(
+                    "{} Invalid instruction comment '{}', expected '; line, label'",
+                    str_pos, c
+                )
+            }
+            AsmErrTy::UnexpectedEol => format!("{} Unexpected end of line", str_pos),
+            AsmErrTy::UnterminatedStr => format!("{} Unterminated string", str_pos),
+            AsmErrTy::InvalidEscape(c) => {
+                format!("{} Invalid escape sequence '\\{}' in string", str_pos, c)
+            }
+            AsmErrTy::TrailingInput(s) => format!("{} Unexpected input '{}'", str_pos, s),
+        }
+    }
+
+    fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(self.code(), &self.to_msg())
+    }
+}
@@ -1,29 +1,64 @@
-use crate::err::SifErr;
+use crate::{diag::Diagnostic, err::SifErr, span::Span};
 
 #[derive(Debug, Clone)]
 pub enum CompileErrTy {
     InvalidAst,
+
+    /// A closure assigns to a variable it captured from the enclosing function.
+    CapturedAssign(String),
 }
 
 #[derive(Debug, Clone)]
 pub struct CompileErr {
     pub ty: CompileErrTy,
+
+    /// Source span the error points to. This is unknown for errors that aren't tied to a
+    /// location in the source.
+    pub span: Span,
 }
 
 impl CompileErr {
     pub fn new(t: CompileErrTy) -> CompileErr {
-        CompileErr { ty: t }
+        CompileErr {
+            ty: t,
+            span: Span::default(),
+        }
     }
-}
 
-impl SifErr for CompileErr {
-    fn emit(&self) {
-        eprintln!("sif: Compile error - {}", self.to_msg());
+    pub fn with_span(self, span: Span) -> CompileErr {
+        CompileErr { span, ..self }
     }
 
-    fn to_msg(&self) -> String {
+    /// Returns the stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
         match self.ty {
+            CompileErrTy::InvalidAst => "E0301",
+            CompileErrTy::CapturedAssign(_) => "E0302",
+        }
+    }
+}
+
+impl SifErr for CompileErr {
+    fn to_msg(&self) -> String {
+        match &self.ty {
             CompileErrTy::InvalidAst => String::from("invalid or unknown ast format provided"),
+            CompileErrTy::CapturedAssign(name) => format!(
+                "Cannot assign to '{}': closures capture variables by value",
+                name
+            ),
+        }
+    }
+
+    fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.code(), &self.to_msg());
+        match &self.ty {
+            CompileErrTy::InvalidAst => diag,
+            CompileErrTy::CapturedAssign(name) => diag
+                .with_span(self.span, name.len())
+                .with_label("captured from the enclosing function")
+                .with_help(
+                    "to share state with the enclosing function, store it in an array or table",
+                ),
         }
     }
 }
@@ -0,0 +1,586 @@
+use crate::{
+    runtime_err::{self, TraceFrame},
+    span::Span,
+};
+use std::{
+    fmt,
+    io::{self, IsTerminal},
+};
+
+const STYLE_ERROR: &str = "\x1b[1;31m";
+const STYLE_WARNING: &str = "\x1b[1;33m";
+const STYLE_GUTTER: &str = "\x1b[1;34m";
+const STYLE_BOLD: &str = "\x1b[1m";
+const STYLE_RESET: &str = "\x1b[0m";
+
+/// ErrorFormat is how emitted diagnostics are printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ErrorFormat {
+    /// Rendered for a person reading the terminal, with source snippets.
+    #[default]
+    Human,
+
+    /// One json object per line, for tools that consume sif errors.
+    Json,
+}
+
+/// Severity of a diagnostic.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Diagnostic is the structured form of an error, which is rendered for the user by a
+/// Renderer. Errors return one from SifErr::diagnostic(), so that the same error can be
+/// printed with or without the source that it refers to.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    /// Code identifying the kind of error, such as 'E0210'. Codes are stable, so tools can
+    /// match on them: each error variant has its own code, and codes are never reused. The
+    /// second digit is the phase that found the error: 1 for lexing, 2 for parsing, 3 for
+    /// compiling, 4 for running, 5 for verifying bytecode, 6 for loading bytecode files and
+    /// 7 for assembling.
+    pub code: &'static str,
+
+    pub severity: Severity,
+
+    /// Main message, which doesn't include the location of the error.
+    pub msg: String,
+
+    /// Source span that the diagnostic points to. This is unknown for errors that aren't
+    /// tied to a location in the source.
+    pub span: Span,
+
+    /// Number of columns underlined, starting at the span. This is at least 1.
+    pub len: usize,
+
+    /// Short label printed next to the underline.
+    pub label: Option<String>,
+
+    /// Name of the file the span is in. Renderers that were given a file use it instead.
+    pub file: Option<String>,
+
+    /// Extra context printed after the source snippet.
+    pub notes: Vec<String>,
+
+    /// Suggestions for fixing the error, such as a likely misspelled name.
+    pub help: Vec<String>,
+
+    /// Calls that were active when a runtime error occurred, starting with the innermost
+    /// one. This is empty for other errors.
+    pub trace: Vec<TraceFrame>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, msg: &str) -> Diagnostic {
+        Diagnostic {
+            code,
+            severity: Severity::Error,
+            msg: msg.to_string(),
+            span: Span::default(),
+            len: 1,
+            label: None,
+            file: None,
+            notes: Vec::new(),
+            help: Vec::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    /// Points the diagnostic at a span, underlining len columns.
+    pub fn with_span(self, span: Span, len: usize) -> Diagnostic {
+        Diagnostic {
+            span,
+            len: len.max(1),
+            ..self
+        }
+    }
+
+    pub fn with_label(self, label: &str) -> Diagnostic {
+        Diagnostic {
+            label: Some(label.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_file(self, file: Option<String>) -> Diagnostic {
+        Diagnostic { file, ..self }
+    }
+
+    pub fn with_note(mut self, note: &str) -> Diagnostic {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Diagnostic {
+        self.help.push(help.to_string());
+        self
+    }
+
+    pub fn with_trace(self, trace: Vec<TraceFrame>) -> Diagnostic {
+        Diagnostic { trace, ..self }
+    }
+}
+
+/// Renderer formats diagnostics for the terminal. When the source is available, the line
+/// a diagnostic points to is printed below the message with its span underlined:
+///
+/// ```text
+/// error[E0210]: Undeclared symbol 'elsif' found
+///  --> foo.sif:4:3
+///   |
+/// 4 | } elsif x == 2 {
+///   |   ^^^^^ not found in this scope
+///   |
+///   = help: did you mean 'elif'?
+/// ```
+///
+/// Without the source, only the message, location, notes and help are printed. Colors are
+/// used when stderr is a terminal, unless the NO_COLOR environment variable is set.
+pub struct Renderer<'a> {
+    /// Lines of the source that diagnostics refer to. This is empty if the source isn't
+    /// available.
+    lines: Vec<&'a str>,
+
+    /// Name of the source file, used in the location of each diagnostic.
+    file: Option<&'a str>,
+
+    /// True if the output should contain ansi color codes.
+    color: bool,
+
+    /// Format that emit() prints diagnostics in.
+    format: ErrorFormat,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(src: &'a str, file: Option<&'a str>) -> Renderer<'a> {
+        Renderer {
+            lines: src.lines().collect(),
+            file,
+            color: io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            format: ErrorFormat::Human,
+        }
+    }
+
+    /// Creates a renderer for diagnostics whose source isn't available.
+    pub fn without_src() -> Renderer<'static> {
+        Renderer::new("", None)
+    }
+
+    pub fn with_color(self, color: bool) -> Renderer<'a> {
+        Renderer { color, ..self }
+    }
+
+    pub fn with_format(self, format: ErrorFormat) -> Renderer<'a> {
+        Renderer { format, ..self }
+    }
+
+    /// Prints a diagnostic to stderr, in the format set by with_format().
+    pub fn emit(&self, diag: &Diagnostic) {
+        match self.format {
+            ErrorFormat::Human => eprint!("{}", self.render(diag)),
+            ErrorFormat::Json => eprintln!("{}", self.json(diag)),
+        };
+    }
+
+    /// Formats a diagnostic as a single line json object:
+    ///
+    /// ```text
+    /// {"code":"E0210","severity":"error","file":"foo.sif","span":{"line":4,"col":3,"len":5},
+    ///  "message":"Undeclared symbol 'elsif' found","label":"not found in this scope",
+    ///  "notes":[],"help":["did you mean 'elif'?"],"trace":[]}
+    /// ```
+    ///
+    /// The file, span and label are null when they aren't known. Columns start at 1. Each
+    /// frame of the trace is an object holding the fn name and the span being executed in
+    /// it, along with the span of the fn declaration:
+    ///
+    /// ```text
+    /// {"name":"f","span":{"line":2,"col":12},"decl_span":{"line":1,"col":1}}
+    /// ```
+    ///
+    /// The name is null for the code section, and spans are null when they aren't known.
+    pub fn json(&self, diag: &Diagnostic) -> String {
+        let file = match self.file(diag) {
+            Some(f) => json_str(f),
+            None => String::from("null"),
+        };
+        let span = match diag.span.is_known() {
+            true => format!(
+                "{{\"line\":{},\"col\":{},\"len\":{}}}",
+                diag.span.line, diag.span.col, diag.len
+            ),
+            false => String::from("null"),
+        };
+        let label = match &diag.label {
+            Some(l) => json_str(l),
+            None => String::from("null"),
+        };
+        let notes: Vec<String> = diag.notes.iter().map(|n| json_str(n)).collect();
+        let help: Vec<String> = diag.help.iter().map(|h| json_str(h)).collect();
+        let trace: Vec<String> = diag.trace.iter().map(json_frame).collect();
+
+        format!(
+            "{{\"code\":{},\"severity\":{},\"file\":{},\"span\":{},\"message\":{},\"label\":{},\"notes\":[{}],\"help\":[{}],\"trace\":[{}]}}",
+            json_str(diag.code),
+            json_str(&diag.severity.to_string()),
+            file,
+            span,
+            json_str(&diag.msg),
+            label,
+            notes.join(","),
+            help.join(","),
+            trace.join(",")
+        )
+    }
+
+    /// Returns the file a diagnostic is in. The file given to the renderer is used over the
+    /// one in the diagnostic.
+    fn file<'d>(&'d self, diag: &'d Diagnostic) -> Option<&'d str> {
+        match self.file {
+            Some(f) => Some(f),
+            None => diag.file.as_deref(),
+        }
+    }
+
+    /// Formats a diagnostic. The result always ends with a newline.
+    pub fn render(&self, diag: &Diagnostic) -> String {
+        let sev_style = match diag.severity {
+            Severity::Error => STYLE_ERROR,
+            Severity::Warning => STYLE_WARNING,
+        };
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(&format!("{}[{}]", diag.severity, diag.code), sev_style),
+            self.paint(&format!(": {}", diag.msg), STYLE_BOLD)
+        );
+
+        let src_line = match diag.span.is_known() {
+            true => self.lines.get(diag.span.line - 1),
+            false => None,
+        };
+
+        // The gutter is wide enough to fit the line number of the snippet.
+        let width = match src_line {
+            Some(_) => diag.span.line.to_string().len(),
+            None => 0,
+        };
+        let pad = " ".repeat(width);
+        let bar = self.paint("|", STYLE_GUTTER);
+
+        if diag.span.is_known() {
+            out.push_str(&format!(
+                "{}{} {}\n",
+                pad,
+                self.paint("-->", STYLE_GUTTER),
+                diag.span.with_file(self.file(diag))
+            ));
+        }
+
+        if let Some(text) = src_line {
+            let line_num = self.paint(&diag.span.line.to_string(), STYLE_GUTTER);
+            out.push_str(&format!("{} {}\n", pad, bar));
+            out.push_str(&format!("{} {} {}\n", line_num, bar, text));
+            out.push_str(&format!(
+                "{} {} {}\n",
+                pad,
+                bar,
+                self.underline(text, diag, sev_style)
+            ));
+        };
+
+        if !diag.trace.is_empty() || diag.notes.len() + diag.help.len() > 0 {
+            if src_line.is_some() {
+                out.push_str(&format!("{} {}\n", pad, bar));
+            }
+            if !diag.trace.is_empty() {
+                let trace = runtime_err::format_trace(&diag.trace, self.file(diag));
+                out.push_str(&self.footer(&pad, "note", &trace));
+            }
+            for note in &diag.notes {
+                out.push_str(&self.footer(&pad, "note", note));
+            }
+            for help in &diag.help {
+                out.push_str(&self.footer(&pad, "help", help));
+            }
+        }
+
+        out
+    }
+
+    /// Builds the line under a source snippet, with carets under the span and the label
+    /// after them. Tabs before the span are kept so that the carets line up with the text.
+    fn underline(&self, text: &str, diag: &Diagnostic, style: &str) -> String {
+        let start = diag.span.col.saturating_sub(1);
+        let indent: String = text
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(start)
+            .map(|ch| match ch {
+                '\t' => '\t',
+                _ => ' ',
+            })
+            .collect();
+
+        // The underline stops at the end of the line, but is always at least one caret so
+        // that errors at the end of a line can still be seen.
+        let remaining = text.chars().count().saturating_sub(start);
+        let len = diag.len.min(remaining).max(1);
+
+        let mut marker = "^".repeat(len);
+        if let Some(l) = &diag.label {
+            marker = format!("{} {}", marker, l)
+        };
+        format!("{}{}", indent, self.paint(&marker, style))
+    }
+
+    /// Formats a note or help message. Lines after the first are indented to line up with
+    /// the start of the message.
+    fn footer(&self, pad: &str, kind: &str, msg: &str) -> String {
+        let prefix = format!("{} = {}: ", pad, kind);
+        let indent = " ".repeat(prefix.len());
+        let body = msg.trim_end().replace('\n', &format!("\n{}", indent));
+        format!(
+            "{} {} {}\n",
+            pad,
+            self.paint("=", STYLE_GUTTER),
+            self.paint(&format!("{}: ", kind), STYLE_BOLD) + &body
+        )
+    }
+
+    fn paint(&self, text: &str, style: &str) -> String {
+        match self.color {
+            true => format!("{}{}{}", style, text, STYLE_RESET),
+            false => text.to_string(),
+        }
+    }
+}
+
+/// Formats a frame of a stack trace as a json object.
+fn json_frame(frame: &TraceFrame) -> String {
+    let name = match &frame.name {
+        Some(n) => json_str(n),
+        None => String::from("null"),
+    };
+    format!(
+        "{{\"name\":{},\"span\":{},\"decl_span\":{}}}",
+        name,
+        json_pos(frame.span),
+        json_pos(frame.decl_span)
+    )
+}
+
+/// Formats the line and column of a span as a json object, or null if it isn't known.
+fn json_pos(span: Span) -> String {
+    match span.is_known() {
+        true => format!("{{\"line\":{},\"col\":{}}}", span.line, span.col),
+        false => String::from("null"),
+    }
+}
+
+/// Quotes and escapes a string for json.
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        };
+    }
+    out.push('"');
+    out
+}
+
+/// Returns the candidate that is closest to a name, if it is close enough that the name
+/// is likely a misspelling of it. This is used to suggest names in help messages. Every
+/// character of a short name can be replaced within the allowed distance, so a candidate
+/// must also keep at least one character of the name.
+pub fn closest<'c, I>(name: &str, candidates: I) -> Option<&'c str>
+where
+    I: IntoIterator<Item = &'c str>,
+{
+    let len = name.chars().count();
+    let max_dist = (len / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|c| *c != name)
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(dist, _)| *dist <= max_dist && *dist < len)
+        .min()
+        .map(|(_, c)| c)
+}
+
+/// Computes the levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..b.len() + 1).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = match ca == *cb {
+                true => 0,
+                false => 1,
+            };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_snippet() {
+        let src = "var x = 1;\nif x == 1 {\n} elsif x == 2 {\n}\n";
+        let diag = Diagnostic::error("E0210", "undeclared symbol 'elsif' found")
+            .with_span(Span::new(3, 3), 5)
+            .with_label("not found in this scope")
+            .with_help("did you mean 'elif'?");
+
+        let out = Renderer::new(src, Some("foo.sif"))
+            .with_color(false)
+            .render(&diag);
+        assert_eq!(
+            out,
+            "error[E0210]: undeclared symbol 'elsif' found\n \
+             --> foo.sif:3:3\n  \
+             |\n\
+             3 | } elsif x == 2 {\n  \
+             |   ^^^^^ not found in this scope\n  \
+             |\n  \
+             = help: did you mean 'elif'?\n"
+        );
+    }
+
+    #[test]
+    fn render_without_src() {
+        let diag = Diagnostic::error("E0409", "operator cannot be applied")
+            .with_span(Span::new(2, 12), 1)
+            .with_file(Some(String::from("foo.sif")))
+            .with_note("stack backtrace:\n  0: g");
+
+        let out = Renderer::without_src().with_color(false).render(&diag);
+        assert_eq!(
+            out,
+            "error[E0409]: operator cannot be applied\n\
+             --> foo.sif:2:12\n \
+             = note: stack backtrace:\n           \
+             0: g\n"
+        );
+    }
+
+    #[test]
+    fn render_underline_clamped() {
+        let src = "\tvar x = ";
+        let diag =
+            Diagnostic::error("E0207", "unexpected end of file").with_span(Span::new(1, 10), 3);
+
+        let out = Renderer::new(src, None).with_color(false).render(&diag);
+        assert_eq!(
+            out,
+            "error[E0207]: unexpected end of file\n \
+             --> 1:10\n  \
+             |\n\
+             1 | \tvar x = \n  \
+             | \t        ^\n"
+        );
+    }
+
+    #[test]
+    fn render_json() {
+        let diag = Diagnostic::error("E0102", "Unterminated string literal 'a\"b'")
+            .with_span(Span::new(2, 9), 1)
+            .with_label("expected a closing '\"'")
+            .with_note("line one\nline two");
+
+        let out = Renderer::new("", Some("dir\\foo.sif")).json(&diag);
+        assert_eq!(
+            out,
+            "{\"code\":\"E0102\",\"severity\":\"error\",\"file\":\"dir\\\\foo.sif\",\
+             \"span\":{\"line\":2,\"col\":9,\"len\":1},\
+             \"message\":\"Unterminated string literal 'a\\\"b'\",\
+             \"label\":\"expected a closing '\\\"'\",\
+             \"notes\":[\"line one\\nline two\"],\"help\":[],\"trace\":[]}"
+        );
+
+        let diag = Diagnostic::error("E0301", "invalid ast");
+        assert_eq!(
+            Renderer::without_src().json(&diag),
+            "{\"code\":\"E0301\",\"severity\":\"error\",\"file\":null,\"span\":null,\
+             \"message\":\"invalid ast\",\"label\":null,\"notes\":[],\"help\":[],\"trace\":[]}"
+        );
+    }
+
+    #[test]
+    fn render_trace() {
+        let trace = vec![
+            TraceFrame {
+                name: Some(String::from("g")),
+                span: Span::new(2, 12),
+                decl_span: Span::new(1, 1),
+            },
+            TraceFrame {
+                name: None,
+                span: Span::new(4, 1),
+                decl_span: Span::default(),
+            },
+        ];
+        let diag = Diagnostic::error("E0409", "operator cannot be applied")
+            .with_span(Span::new(2, 12), 1)
+            .with_trace(trace);
+
+        let renderer = Renderer::without_src().with_color(false);
+        assert_eq!(
+            renderer.render(&diag),
+            "error[E0409]: operator cannot be applied\n\
+             --> 2:12\n \
+             = note: stack backtrace:\n           \
+             0: g at 2:12 (declared at 1:1)\n           \
+             1: <code> at 4:1\n"
+        );
+        assert_eq!(
+            renderer.json(&diag),
+            "{\"code\":\"E0409\",\"severity\":\"error\",\"file\":null,\
+             \"span\":{\"line\":2,\"col\":12,\"len\":1},\
+             \"message\":\"operator cannot be applied\",\"label\":null,\"notes\":[],\"help\":[],\
+             \"trace\":[{\"name\":\"g\",\"span\":{\"line\":2,\"col\":12},\"decl_span\":{\"line\":1,\"col\":1}},\
+             {\"name\":null,\"span\":{\"line\":4,\"col\":1},\"decl_span\":null}]}"
+        );
+    }
+
+    #[test]
+    fn closest_name() {
+        let words = ["if", "elif", "else", "while"];
+        assert_eq!(closest("elsif", words.iter().copied()), Some("elif"));
+        assert_eq!(closest("whle", words.iter().copied()), Some("while"));
+        assert_eq!(closest("count", words.iter().copied()), None);
+        assert_eq!(closest("if", words.iter().copied()), None);
+
+        // Single character names don't share anything with other single character names.
+        let names = ["a", "b", "ab"];
+        assert_eq!(closest("z", names.iter().copied()), None);
+        assert_eq!(closest("a", names.iter().copied()), None);
+        assert_eq!(closest("ac", names.iter().copied()), Some("a"));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{
+    compile_err::CompileErr,
+    diag::{Diagnostic, Renderer},
+    err::SifErr,
+    parse_err::ParseErr,
+    runtime_err::{RuntimeErr, RuntimeErrTy},
+};
+use std::fmt;
+
+/// ConvertErr is returned when a sif value passed to the host doesn't have the type
+/// that the host asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertErr {
+    /// Kind of value the host expected, such as "number" or "array".
+    pub expected: String,
+
+    /// Kind of value that was found instead.
+    pub found: String,
+}
+
+impl ConvertErr {
+    pub fn new(expected: &str, found: &str) -> ConvertErr {
+        ConvertErr {
+            expected: expected.to_string(),
+            found: found.to_string(),
+        }
+    }
+
+    /// Returns the stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        "E0801"
+    }
+}
+
+impl SifErr for ConvertErr {
+    fn to_msg(&self) -> String {
+        format!(
+            "Cannot convert value: expected {}, found {}",
+            self.expected, self.found
+        )
+    }
+
+    fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(self.code(), &self.to_msg())
+    }
+}
+
+/// Host functions can convert their params and return a ConvertErr with `?`, which stops
+/// the vm like any other error from a host function.
+impl From<ConvertErr> for RuntimeErr {
+    fn from(err: ConvertErr) -> RuntimeErr {
+        RuntimeErr::new(RuntimeErrTy::HostFnErr(err.to_msg()))
+    }
+}
+
+/// EngineErr is the error returned by the embedding api. It holds the error from whichever
+/// phase of compiling or running a program failed, so that hosts can handle all of them
+/// in one place.
+#[derive(Debug, Clone)]
+pub enum EngineErr {
+    /// Errors found while parsing the source, including errors found by the lexer, in the
+    /// order they were found.
+    Parse(Vec<ParseErr>),
+
+    Compile(CompileErr),
+
+    Runtime(RuntimeErr),
+
+    /// A value read from the program couldn't be converted to the requested type.
+    Convert(ConvertErr),
+
+    /// The host read a global that the last program run didn't define, or no program
+    /// has been run yet.
+    UnknownGlobal(String),
+}
+
+impl EngineErr {
+    /// Returns the stable code identifying the kind of error. For parse errors, this is
+    /// the code of the first error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EngineErr::Parse(errs) => match errs.first() {
+                Some(e) => e.code(),
+                None => "E0200",
+            },
+            EngineErr::Compile(e) => e.code(),
+            EngineErr::Runtime(e) => e.code(),
+            EngineErr::Convert(e) => e.code(),
+            EngineErr::UnknownGlobal(_) => "E0802",
+        }
+    }
+
+    /// Returns a diagnostic for each error, which can be rendered with the source of the
+    /// program to show where they occurred.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            EngineErr::Parse(errs) => errs.iter().map(|e| e.diagnostic()).collect(),
+            _ => vec![self.diagnostic()],
+        }
+    }
+}
+
+impl SifErr for EngineErr {
+    /// Prints every error to stderr. Like the other errors, this can't show the source,
+    /// so hosts that have it should render `diagnostics()` themselves.
+    fn emit(&self) {
+        let renderer = Renderer::without_src();
+        for diag in self.diagnostics() {
+            renderer.emit(&diag);
+        }
+    }
+
+    fn to_msg(&self) -> String {
+        match self {
+            EngineErr::Parse(errs) => errs
+                .iter()
+                .map(|e| e.to_msg())
+                .collect::<Vec<String>>()
+                .join("\n"),
+            EngineErr::Compile(e) => e.to_msg(),
+            EngineErr::Runtime(e) => e.to_msg(),
+            EngineErr::Convert(e) => e.to_msg(),
+            EngineErr::UnknownGlobal(name) => format!("Global '{}' is not defined", name),
+        }
+    }
+
+    fn diagnostic(&self) -> Diagnostic {
+        match self {
+            EngineErr::Parse(errs) => match errs.first() {
+                Some(e) => e.diagnostic(),
+                None => Diagnostic::error(self.code(), "Parsing failed"),
+            },
+            EngineErr::Compile(e) => e.diagnostic(),
+            EngineErr::Runtime(e) => e.diagnostic(),
+            EngineErr::Convert(e) => e.diagnostic(),
+            EngineErr::UnknownGlobal(_) => Diagnostic::error(self.code(), &self.to_msg()),
+        }
+    }
+}
+
+impl fmt::Display for EngineErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_msg())
+    }
+}
+
+impl std::error::Error for EngineErr {}
+
+impl From<CompileErr> for EngineErr {
+    fn from(err: CompileErr) -> EngineErr {
+        EngineErr::Compile(err)
+    }
+}
+
+impl From<RuntimeErr> for EngineErr {
+    fn from(err: RuntimeErr) -> EngineErr {
+        EngineErr::Runtime(err)
+    }
+}
+
+impl From<ConvertErr> for EngineErr {
+    fn from(err: ConvertErr) -> EngineErr {
+        EngineErr::Convert(err)
+    }
+}
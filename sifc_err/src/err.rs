@@ -1,4 +1,16 @@
+use crate::diag::{Diagnostic, Renderer};
+
 pub trait SifErr {
-    fn emit(&self);
+    /// Prints the error to stderr. The source isn't available here, so only the message
+    /// and location are printed. Callers that have the source should render the
+    /// diagnostic themselves, so that the offending line is shown.
+    fn emit(&self) {
+        Renderer::without_src().emit(&self.diagnostic());
+    }
+
     fn to_msg(&self) -> String;
+
+    /// Returns the structured form of the error, which can be rendered with a snippet of
+    /// the source it refers to.
+    fn diagnostic(&self) -> Diagnostic;
 }
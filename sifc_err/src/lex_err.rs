@@ -1,11 +1,35 @@
-use crate::err::SifErr;
+use crate::{diag::Diagnostic, err::SifErr, span::Span};
 
 #[derive(Debug, Clone)]
 pub enum LexErrTy {
     UnknownChar(char),
     UnterminatedString(String),
+
+    /// Escape sequence in a string that isn't recognized, such as `\q`.
+    InvalidEscape(char),
+
+    /// Malformed `\u{...}` escape, holding the text of the escape after the '\'.
+    InvalidUnicodeEscape(String),
+
+    /// Number literal that ends before its digits, such as `0x` or `1e+`. Holds the text
+    /// of the literal so far.
+    MissingDigits(String),
+
+    /// Digit that isn't valid in the base of the number literal, such as the 2 in
+    /// `0b102`. Holds the digit and the base.
+    InvalidDigit(char, u32),
+
+    /// A `_` digit separator that isn't between two digits.
+    InvalidSeparator,
+
+    /// Hex, binary or octal literal that doesn't fit in 64 bits.
+    IntTooLarge(String),
+
+    /// Block comment that is still open at the end of the input.
+    UnterminatedComment,
 }
 
+#[derive(Debug, Clone)]
 pub struct LexErr {
     pub line: usize,
     pub pos: usize,
@@ -14,27 +38,76 @@ pub struct LexErr {
 
 impl LexErr {
     pub fn new(line: usize, pos: usize, ty: LexErrTy) -> LexErr {
-        LexErr {
-            line: line,
-            pos: pos,
-            ty: ty,
-        }
+        LexErr { line, pos, ty }
     }
-}
 
-impl SifErr for LexErr {
-    fn emit(&self) {
-        eprintln!("sif: Parse error - {}", self.to_msg());
+    /// Returns the stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self.ty {
+            LexErrTy::UnknownChar(_) => "E0101",
+            LexErrTy::UnterminatedString(_) => "E0102",
+            LexErrTy::InvalidEscape(_) => "E0103",
+            LexErrTy::InvalidUnicodeEscape(_) => "E0104",
+            LexErrTy::MissingDigits(_) => "E0105",
+            LexErrTy::InvalidDigit(_, _) => "E0106",
+            LexErrTy::InvalidSeparator => "E0107",
+            LexErrTy::IntTooLarge(_) => "E0108",
+            LexErrTy::UnterminatedComment => "E0109",
+        }
     }
 
-    fn to_msg(&self) -> String {
-        let str_pos = format!("[Line {}:{}]", self.line, self.pos);
-
+    /// Describes the error, without its location.
+    pub(crate) fn describe(&self) -> String {
         match self.ty {
-            LexErrTy::UnknownChar(ref ch) => format!("{} Unrecognized character '{}'", str_pos, ch),
+            LexErrTy::UnknownChar(ref ch) => format!("Unrecognized character '{}'", ch),
             LexErrTy::UnterminatedString(ref found) => {
-                format!("{} Unterminated string literal '{}'", str_pos, found)
+                // The rest of the input is part of the string, so only its first line is
+                // shown.
+                let first_line = found.lines().next().unwrap_or("");
+                format!("Unterminated string literal '{}'", first_line)
+            }
+            LexErrTy::InvalidEscape(ref ch) => {
+                format!("Invalid escape sequence '\\{}'", ch.escape_default())
+            }
+            LexErrTy::InvalidUnicodeEscape(ref found) => {
+                format!("Invalid unicode escape '\\{}'", found)
+            }
+            LexErrTy::MissingDigits(ref found) => {
+                format!("Expected digits after '{}' in number literal", found)
+            }
+            LexErrTy::InvalidDigit(ref ch, ref radix) => {
+                format!("Invalid digit '{}' in base {} literal", ch, radix)
+            }
+            LexErrTy::InvalidSeparator => {
+                String::from("Digit separator '_' must be placed between two digits")
+            }
+            LexErrTy::IntTooLarge(ref found) => {
+                format!("Integer literal '{}' does not fit in 64 bits", found)
             }
+            LexErrTy::UnterminatedComment => String::from("Unterminated block comment"),
         }
     }
 }
+
+impl SifErr for LexErr {
+    fn to_msg(&self) -> String {
+        format!("[Line {}:{}] {}", self.line, self.pos, self.describe())
+    }
+
+    fn diagnostic(&self) -> Diagnostic {
+        let label = match self.ty {
+            LexErrTy::UnknownChar(_) => "not valid in sif source",
+            LexErrTy::UnterminatedString(_) => "string is never closed",
+            LexErrTy::InvalidEscape(_) => "unknown escape",
+            LexErrTy::InvalidUnicodeEscape(_) => "expected 1 to 6 hex digits in braces",
+            LexErrTy::MissingDigits(_) => "expected a digit here",
+            LexErrTy::InvalidDigit(_, _) => "not a digit in this base",
+            LexErrTy::InvalidSeparator => "misplaced separator",
+            LexErrTy::IntTooLarge(_) => "literal is too large",
+            LexErrTy::UnterminatedComment => "comment is never closed",
+        };
+        Diagnostic::error(self.code(), &self.describe())
+            .with_span(Span::new(self.line, self.pos + 1), 1)
+            .with_label(label)
+    }
+}
@@ -1,5 +1,11 @@
+pub mod asm_err;
 pub mod compile_err;
+pub mod diag;
+pub mod engine_err;
 pub mod err;
 pub mod lex_err;
 pub mod parse_err;
 pub mod runtime_err;
+pub mod sifb_err;
+pub mod span;
+pub mod verify_err;
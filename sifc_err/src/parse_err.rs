@@ -1,4 +1,4 @@
-use crate::err::SifErr;
+use crate::{diag::Diagnostic, err::SifErr, lex_err::LexErr, span::Span};
 
 #[derive(Debug, Clone)]
 pub enum ParseErrTy {
@@ -7,12 +7,17 @@ pub enum ParseErrTy {
     InvalidAssign(String),
     InvalidForStmt,
     InvalidIfStmt,
+    InvalidLoopCtrl(String),
     TknMismatch(String, String),
     FnParmCntExceeded(usize),
     WrongFnParmCnt(usize, usize),
     UndeclSym(String),
     UnassignedVar(String),
     ExpectedIdent(String),
+
+    /// Error found by the lexer. These are reported along with parse errors, in the
+    /// order they were found.
+    Lex(LexErr),
 }
 
 #[derive(Debug, Clone)]
@@ -20,74 +25,138 @@ pub struct ParseErr {
     pub line: usize,
     pub pos: usize,
     pub ty: ParseErrTy,
+
+    /// Suggestion for fixing the error, such as the name that a misspelled one was likely
+    /// meant to be.
+    pub help: Option<String>,
 }
 
 impl ParseErr {
+    /// Wraps an error from the lexer, keeping its location.
+    pub fn from_lex(err: LexErr) -> ParseErr {
+        ParseErr::new(err.line, err.pos, ParseErrTy::Lex(err))
+    }
+
     pub fn new(line: usize, pos: usize, ty: ParseErrTy) -> ParseErr {
         ParseErr {
-            line: line,
-            pos: pos,
-            ty: ty,
+            line,
+            pos,
+            ty,
+            help: None,
         }
     }
 
-    pub fn continuable(self) -> bool {
+    /// Returns the stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
         match self.ty {
-            ParseErrTy::TknMismatch(_, _) => false,
-            ParseErrTy::FnParmCntExceeded(_) => false,
-            _ => true,
+            ParseErrTy::InvalidIdent(_) => "E0201",
+            ParseErrTy::InvalidTkn(_) => "E0202",
+            ParseErrTy::InvalidAssign(_) => "E0203",
+            ParseErrTy::InvalidForStmt => "E0204",
+            ParseErrTy::InvalidIfStmt => "E0205",
+            ParseErrTy::InvalidLoopCtrl(_) => "E0206",
+            ParseErrTy::TknMismatch(_, _) => "E0207",
+            ParseErrTy::FnParmCntExceeded(_) => "E0208",
+            ParseErrTy::WrongFnParmCnt(_, _) => "E0209",
+            ParseErrTy::UndeclSym(_) => "E0210",
+            ParseErrTy::UnassignedVar(_) => "E0211",
+            ParseErrTy::ExpectedIdent(_) => "E0212",
+            ParseErrTy::Lex(ref e) => e.code(),
         }
     }
-}
 
-impl SifErr for ParseErr {
-    fn emit(&self) {
-        eprintln!("sif: Parse error - {}", self.to_msg());
+    pub fn with_help(self, help: String) -> ParseErr {
+        ParseErr {
+            help: Some(help),
+            ..self
+        }
     }
 
-    fn to_msg(&self) -> String {
-        let str_pos = format!("[Line {}:{}]", self.line, self.pos);
-
+    /// Describes the error, without its location.
+    fn describe(&self) -> String {
         match self.ty {
-            ParseErrTy::InvalidIdent(ref found) => {
-                format!("{} Invalid identifier '{}' found", str_pos, found)
+            ParseErrTy::InvalidIdent(ref found) => format!("Invalid identifier '{}' found", found),
+            ParseErrTy::InvalidTkn(ref found) => format!("Invalid token '{}' found", found),
+            ParseErrTy::InvalidAssign(ref found) => {
+                format!("'{}' is not a valid assignment value", found)
             }
-            ParseErrTy::InvalidTkn(ref found) => {
-                format!("{} Invalid token '{}' found", str_pos, found)
+            ParseErrTy::InvalidForStmt => {
+                String::from("Invalid for loop: must start with a variable declaration")
             }
-            ParseErrTy::InvalidAssign(ref found) => {
-                format!("{} '{}' is not a valid assignment value", str_pos, found)
+            ParseErrTy::InvalidIfStmt => {
+                String::from("Invalid if statement: cannot contain more than one else condition")
             }
-            ParseErrTy::InvalidForStmt => format!(
-                "{} Invalid for loop: must start with a variable declaration",
-                str_pos
-            ),
-            ParseErrTy::InvalidIfStmt => format!(
-                "{} Invalid if statement: cannot contain more than one else condition",
-                str_pos
-            ),
-            ParseErrTy::TknMismatch(ref expected, ref found) => format!(
-                "{} Expected token '{}', but found '{}'",
-                str_pos, expected, found
+            ParseErrTy::InvalidLoopCtrl(ref found) => format!(
+                "Invalid '{}' statement: must be inside of a loop body",
+                found
             ),
+            ParseErrTy::TknMismatch(ref expected, ref found) => {
+                format!("Expected token '{}', but found '{}'", expected, found)
+            }
             ParseErrTy::FnParmCntExceeded(ref expected) => {
-                format!("{} Parameter count exceeds limit of {}", str_pos, expected)
+                format!("Parameter count exceeds limit of {}", expected)
             }
-            ParseErrTy::WrongFnParmCnt(ref expected, ref found) => format!(
-                "{} Expected {} parameters, but found {}",
-                str_pos, expected, found
-            ),
-            ParseErrTy::UnassignedVar(ref found) => format!(
-                "{} Cannot reference un-assigned variable '{}'",
-                str_pos, found
-            ),
-            ParseErrTy::UndeclSym(ref found) => {
-                format!("{} Undeclared symbol '{}' found", str_pos, found)
+            ParseErrTy::WrongFnParmCnt(ref expected, ref found) => {
+                format!("Expected {} parameters, but found {}", expected, found)
+            }
+            ParseErrTy::UnassignedVar(ref found) => {
+                format!("Cannot reference un-assigned variable '{}'", found)
             }
+            ParseErrTy::UndeclSym(ref found) => format!("Undeclared symbol '{}' found", found),
+            ParseErrTy::Lex(ref e) => e.describe(),
             ParseErrTy::ExpectedIdent(ref found) => format!(
-                "{} Identifier expected, found '{}'. Is this a reserved word?",
-                str_pos, found
+                "Identifier expected, found '{}'. Is this a reserved word?",
+                found
             ),
         }
     }
+
+    /// Returns the label printed under the token that caused the error, and the number of
+    /// columns it covers. Errors that don't refer to a single token only mark its start.
+    fn label(&self) -> (Option<String>, usize) {
+        match self.ty {
+            ParseErrTy::InvalidIdent(ref found) | ParseErrTy::ExpectedIdent(ref found) => {
+                (Some(String::from("expected an identifier")), found.len())
+            }
+            ParseErrTy::InvalidTkn(ref found) => {
+                (Some(String::from("unexpected token")), found.len())
+            }
+            ParseErrTy::InvalidAssign(ref found) => (None, found.len()),
+            ParseErrTy::TknMismatch(ref expected, ref found) => {
+                (Some(format!("expected '{}'", expected)), found.len())
+            }
+            ParseErrTy::UndeclSym(ref found) => {
+                (Some(String::from("not found in this scope")), found.len())
+            }
+            ParseErrTy::UnassignedVar(ref found) => (None, found.len()),
+            ParseErrTy::InvalidLoopCtrl(ref found) => {
+                (Some(String::from("not inside of a loop")), found.len())
+            }
+            ParseErrTy::InvalidIfStmt => (Some(String::from("extra else")), "else".len()),
+            _ => (None, 1),
+        }
+    }
+}
+
+impl SifErr for ParseErr {
+    fn to_msg(&self) -> String {
+        format!("[Line {}:{}] {}", self.line, self.pos, self.describe())
+    }
+
+    fn diagnostic(&self) -> Diagnostic {
+        if let ParseErrTy::Lex(ref e) = self.ty {
+            return e.diagnostic();
+        };
+
+        let (label, len) = self.label();
+        let mut diag = Diagnostic::error(self.code(), &self.describe())
+            .with_span(Span::new(self.line, self.pos + 1), len);
+        if let Some(l) = label {
+            diag = diag.with_label(&l)
+        };
+        if let Some(h) = &self.help {
+            diag = diag.with_help(h)
+        };
+        diag
+    }
 }
@@ -1,4 +1,5 @@
-use crate::err::SifErr;
+use crate::{diag::Diagnostic, err::SifErr, span::Span};
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub enum RuntimeErrTy {
@@ -13,29 +14,181 @@ pub enum RuntimeErrTy {
     TyMismatch,
     NotAnArray(String),
     InvalidFnSym(String),
+    NotCallable(String),
+    WrongFnParamCnt(String, usize, usize),
     EmptyCallStack,
     IndexOutOfBounds(String, usize, usize),
+    EmptyFnStack,
+    HostFnErr(String),
+
+    /// The program went over one of the limits set in the vm config.
+    LimitExceeded(Limit),
+
+    /// Reading from or writing to one of the vm io handles failed.
+    IoErr(String),
+
+    /// Storing a value in the named collection would make the collection contain itself.
+    CyclicStore(String),
+
+    /// A table was read at a key that it doesn't contain. Holds the table and key names.
+    MissingKey(String, String),
+
+    /// A key was read from a value that isn't a table.
+    NotATable(String),
+}
+
+/// Limit is one of the resource limits that a program can be run with, holding the value
+/// the limit was set to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Limit {
+    /// Number of instrs executed.
+    Fuel(u64),
+
+    /// Wall clock time.
+    Timeout(Duration),
+
+    /// Number of data registers in a call.
+    Dregs(usize),
+
+    /// Number of globals in the heap.
+    Heap(usize),
+
+    /// Number of items in an array or table.
+    CollectionLen(usize),
+}
+
+/// TraceFrame is one of the calls that was active when a runtime error occurred.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    /// Name of the function being executed, or None for the code section.
+    pub name: Option<String>,
+
+    /// Source span of the instr being executed in this call. For every frame except the
+    /// innermost, this is the call site of the frame before it.
+    pub span: Span,
+
+    /// Source span of the function declaration. This is unknown for the code section.
+    pub decl_span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct RuntimeErr {
     pub ty: RuntimeErrTy,
-    pub line: usize,
+
+    /// Source span of the instr that failed. This is unknown for errors created outside of
+    /// the vm, such as by std functions, until the vm adds the span of the call.
+    pub span: Span,
+
+    /// Name of the file the program was loaded from, if the vm was given one.
+    pub file: Option<String>,
+
+    /// Active calls when the error occurred, starting with the innermost one and ending
+    /// with the code section. This is empty for errors that weren't raised by the vm.
+    pub trace: Vec<TraceFrame>,
 }
 
 impl RuntimeErr {
-    pub fn new(t: RuntimeErrTy, l: usize) -> RuntimeErr {
-        RuntimeErr { ty: t, line: l }
+    pub fn new(t: RuntimeErrTy) -> RuntimeErr {
+        RuntimeErr {
+            ty: t,
+            span: Span::default(),
+            file: None,
+            trace: Vec::new(),
+        }
     }
+
+    /// Returns the stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self.ty {
+            RuntimeErrTy::InvalidName(_) => "E0401",
+            RuntimeErrTy::InvalidIncr => "E0402",
+            RuntimeErrTy::InvalidIncrTy => "E0403",
+            RuntimeErrTy::InvalidDecr => "E0404",
+            RuntimeErrTy::InvalidDecrTy => "E0405",
+            RuntimeErrTy::InvalidOp => "E0406",
+            RuntimeErrTy::InvalidJump => "E0407",
+            RuntimeErrTy::RegNoContents(_) => "E0408",
+            RuntimeErrTy::TyMismatch => "E0409",
+            RuntimeErrTy::NotAnArray(_) => "E0410",
+            RuntimeErrTy::InvalidFnSym(_) => "E0411",
+            RuntimeErrTy::NotCallable(_) => "E0412",
+            RuntimeErrTy::WrongFnParamCnt(_, _, _) => "E0413",
+            RuntimeErrTy::EmptyCallStack => "E0414",
+            RuntimeErrTy::IndexOutOfBounds(_, _, _) => "E0415",
+            RuntimeErrTy::EmptyFnStack => "E0416",
+            RuntimeErrTy::HostFnErr(_) => "E0417",
+            RuntimeErrTy::LimitExceeded(_) => "E0418",
+            RuntimeErrTy::IoErr(_) => "E0419",
+            RuntimeErrTy::CyclicStore(_) => "E0420",
+            RuntimeErrTy::MissingKey(_, _) => "E0421",
+            RuntimeErrTy::NotATable(_) => "E0422",
+        }
+    }
+
+    /// Sets the location the error occurred at.
+    pub fn at(self, span: Span, file: Option<String>) -> RuntimeErr {
+        RuntimeErr {
+            ty: self.ty,
+            span,
+            file,
+            trace: self.trace,
+        }
+    }
+
+    /// Sets the calls that were active when the error occurred.
+    pub fn with_trace(self, trace: Vec<TraceFrame>) -> RuntimeErr {
+        RuntimeErr {
+            ty: self.ty,
+            span: self.span,
+            file: self.file,
+            trace,
+        }
+    }
+
+    /// Formats the stack trace, one call per line with the innermost call first. Each
+    /// line holds the name of the function, the location being executed in it and where
+    /// the function was declared. Returns an empty string if the error wasn't raised
+    /// inside of a function, since the location of the error already covers that.
+    pub fn backtrace(&self) -> String {
+        match self.trace.len() < 2 {
+            true => String::new(),
+            false => format_trace(&self.trace, self.file.as_deref()),
+        }
+    }
+}
+
+/// Formats the frames of a stack trace in the way described by RuntimeErr::backtrace().
+pub(crate) fn format_trace(trace: &[TraceFrame], file: Option<&str>) -> String {
+    let mut result = String::from("stack backtrace:\n");
+    for (depth, frame) in trace.iter().enumerate() {
+        let name = match &frame.name {
+            Some(n) => n.as_str(),
+            None => "<code>",
+        };
+        let loc = match frame.span.is_known() {
+            true => format!(" at {}", frame.span.with_file(file)),
+            false => String::new(),
+        };
+        let decl = match frame.decl_span.is_known() {
+            true => format!(" (declared at {})", frame.decl_span.with_file(file)),
+            false => String::new(),
+        };
+        result.push_str(&format!("  {}: {}{}{}\n", depth, name, loc, decl));
+    }
+    result
 }
 
 impl SifErr for RuntimeErr {
-    fn emit(&self) {
-        eprintln!(
-            "sif: runtime error at instruction #{} : {}",
-            self.line,
-            self.to_msg()
-        );
+    /// The trace is only included for errors raised inside of a function, since the
+    /// location of the error already covers the code section.
+    fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.code(), &self.to_msg())
+            .with_span(self.span, 1)
+            .with_file(self.file.clone());
+        match self.trace.len() < 2 {
+            true => diag,
+            false => diag.with_trace(self.trace.clone()),
+        }
     }
 
     fn to_msg(&self) -> String {
@@ -65,6 +218,13 @@ impl SifErr for RuntimeErr {
             RuntimeErrTy::InvalidFnSym(s) => {
                 format!("Cannot call function {}, declaration not found", s)
             }
+            RuntimeErrTy::NotCallable(v) => {
+                format!("Cannot call value '{}', it is not a function", v)
+            }
+            RuntimeErrTy::WrongFnParamCnt(s, expected, found) => format!(
+                "Function {} expects {} params, but was called with {}",
+                s, expected, found
+            ),
             RuntimeErrTy::EmptyCallStack => {
                 String::from("invalid function call access: no return address specified")
             }
@@ -72,6 +232,41 @@ impl SifErr for RuntimeErr {
                 "Array index out of bounds! Value {} has len {}, but requested index {}",
                 n, len, idx
             ),
+            RuntimeErrTy::EmptyFnStack => {
+                String::from("cannot pop function param: function stack is empty")
+            }
+            RuntimeErrTy::HostFnErr(msg) => msg.to_string(),
+            RuntimeErrTy::IoErr(msg) => format!("I/O error: {}", msg),
+            RuntimeErrTy::CyclicStore(n) => format!(
+                "Cannot store value in '{}': the value contains '{}', and a collection can't contain itself",
+                n, n
+            ),
+            RuntimeErrTy::MissingKey(n, key) => {
+                format!("Cannot load value: table '{}' has no key '{}'", n, key)
+            }
+            RuntimeErrTy::NotATable(n) => format!("Cannot load value: '{}' is not a table", n),
+            RuntimeErrTy::LimitExceeded(Limit::Fuel(n)) => {
+                format!(
+                    "Instruction limit exceeded: executed more than {} instrs",
+                    n
+                )
+            }
+            RuntimeErrTy::LimitExceeded(Limit::Timeout(d)) => {
+                format!("Time limit exceeded: ran for more than {:?}", d)
+            }
+            RuntimeErrTy::LimitExceeded(Limit::Dregs(n)) => {
+                format!(
+                    "Register limit exceeded: a call can use at most {} registers",
+                    n
+                )
+            }
+            RuntimeErrTy::LimitExceeded(Limit::Heap(n)) => {
+                format!("Heap limit exceeded: at most {} globals can be stored", n)
+            }
+            RuntimeErrTy::LimitExceeded(Limit::CollectionLen(n)) => format!(
+                "Collection limit exceeded: arrays and tables can hold at most {} items",
+                n
+            ),
         }
     }
 }
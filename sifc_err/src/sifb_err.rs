@@ -0,0 +1,93 @@
+use crate::{diag::Diagnostic, err::SifErr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SifbErrTy {
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    UnexpectedEof,
+    InvalidOpcode(u8),
+    InvalidOpKind(u8),
+    InvalidValTag(u8),
+    InvalidConstIdx(usize),
+    InvalidStr,
+
+    /// A constant has arrays, tables or fn envs nested deeper than the given limit.
+    ValTooDeep(usize),
+
+    /// An index, register or length is too large to be encoded as a u32.
+    IntTooLarge(usize),
+
+    /// The code section starts past the end of the program.
+    InvalidCodeStart(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct SifbErr {
+    pub ty: SifbErrTy,
+
+    /// Byte offset in the file where the error was found.
+    pub pos: usize,
+}
+
+impl SifbErr {
+    pub fn new(ty: SifbErrTy, pos: usize) -> SifbErr {
+        SifbErr { ty, pos }
+    }
+
+    /// Returns the stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self.ty {
+            SifbErrTy::InvalidMagic => "E0601",
+            SifbErrTy::UnsupportedVersion(_) => "E0602",
+            SifbErrTy::UnexpectedEof => "E0603",
+            SifbErrTy::InvalidOpcode(_) => "E0604",
+            SifbErrTy::InvalidOpKind(_) => "E0605",
+            SifbErrTy::InvalidValTag(_) => "E0606",
+            SifbErrTy::InvalidConstIdx(_) => "E0607",
+            SifbErrTy::InvalidStr => "E0608",
+            SifbErrTy::ValTooDeep(_) => "E0609",
+            SifbErrTy::IntTooLarge(_) => "E0610",
+            SifbErrTy::InvalidCodeStart(_) => "E0611",
+        }
+    }
+}
+
+impl SifErr for SifbErr {
+    fn to_msg(&self) -> String {
+        let str_pos = format!("[Byte {}]", self.pos);
+
+        match self.ty {
+            SifbErrTy::InvalidMagic => format!("{} Not a sif bytecode file", str_pos),
+            SifbErrTy::UnsupportedVersion(v) => {
+                format!("{} Unsupported bytecode format version {}", str_pos, v)
+            }
+            SifbErrTy::UnexpectedEof => format!("{} Unexpected end of file", str_pos),
+            SifbErrTy::InvalidOpcode(op) => format!("{} Unknown opcode {}", str_pos, op),
+            SifbErrTy::InvalidOpKind(k) => format!("{} Unknown operator kind {}", str_pos, k),
+            SifbErrTy::InvalidValTag(t) => format!("{} Unknown value tag {}", str_pos, t),
+            SifbErrTy::InvalidConstIdx(idx) => {
+                format!("{} Constant index {} is out of bounds", str_pos, idx)
+            }
+            SifbErrTy::InvalidStr => format!("{} String is not valid utf-8", str_pos),
+            SifbErrTy::ValTooDeep(max) => {
+                format!("{} Value is nested more than {} levels deep", str_pos, max)
+            }
+            SifbErrTy::IntTooLarge(v) => {
+                format!(
+                    "{} Integer {} is too large to be encoded as a u32",
+                    str_pos, v
+                )
+            }
+            SifbErrTy::InvalidCodeStart(idx) => {
+                format!(
+                    "{} Code section start {} is past the end of the program",
+                    str_pos, idx
+                )
+            }
+        }
+    }
+
+    fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(self.code(), &self.to_msg())
+    }
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+
+/// Span is a position in sif source. Spans are carried from tokens through the ast into
+/// compiled instrs, so that errors and tools can refer back to the source that code came
+/// from. Lines and columns both start at 1, and a span with line 0 is unknown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, col: usize) -> Span {
+        Span { line, col }
+    }
+
+    /// True if the span refers to a position in the source.
+    pub fn is_known(&self) -> bool {
+        self.line != 0
+    }
+
+    /// Formats the span as 'file:line:col', or 'line:col' if the file isn't known.
+    pub fn with_file(&self, file: Option<&str>) -> String {
+        match file {
+            Some(f) => format!("{}:{}", f, self),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
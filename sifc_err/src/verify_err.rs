@@ -0,0 +1,94 @@
+use crate::{diag::Diagnostic, err::SifErr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrTy {
+    InvalidCodeStart(usize),
+    InvalidJumpTarget(usize),
+    InvalidFnEntry(String),
+    InvalidFnSym(String),
+    WrongFnParamCnt(String, usize, usize),
+    InvalidStdFn(String),
+    WrongStdParamCnt(String, usize, usize),
+    RegUndefined(usize),
+
+    /// A register is above the largest one allowed. Holds the register and the maximum.
+    RegOutOfRange(usize, usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyErr {
+    pub ty: VerifyErrTy,
+
+    /// Index of the instruction in the program where the error was found.
+    pub idx: usize,
+
+    /// Line of the instruction where the error was found.
+    pub line: usize,
+}
+
+impl VerifyErr {
+    pub fn new(ty: VerifyErrTy, idx: usize, line: usize) -> VerifyErr {
+        VerifyErr { ty, idx, line }
+    }
+
+    /// Returns the stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self.ty {
+            VerifyErrTy::InvalidCodeStart(_) => "E0501",
+            VerifyErrTy::InvalidJumpTarget(_) => "E0502",
+            VerifyErrTy::InvalidFnEntry(_) => "E0503",
+            VerifyErrTy::InvalidFnSym(_) => "E0504",
+            VerifyErrTy::WrongFnParamCnt(_, _, _) => "E0505",
+            VerifyErrTy::InvalidStdFn(_) => "E0506",
+            VerifyErrTy::WrongStdParamCnt(_, _, _) => "E0507",
+            VerifyErrTy::RegUndefined(_) => "E0508",
+            VerifyErrTy::RegOutOfRange(_, _) => "E0509",
+        }
+    }
+}
+
+impl SifErr for VerifyErr {
+    fn to_msg(&self) -> String {
+        let str_pos = format!("[Instr {}, Line {}]", self.idx, self.line);
+
+        match &self.ty {
+            VerifyErrTy::InvalidCodeStart(start) => format!(
+                "{} Code section starts at {}, which is past the end of the program",
+                str_pos, start
+            ),
+            VerifyErrTy::InvalidJumpTarget(lbl) => {
+                format!("{} Jump to label lbl{} which does not exist", str_pos, lbl)
+            }
+            VerifyErrTy::InvalidFnEntry(name) => format!(
+                "{} Fn table entry for '{}' does not point to its declaration",
+                str_pos, name
+            ),
+            VerifyErrTy::InvalidFnSym(name) => {
+                format!("{} Function '{}' is not declared", str_pos, name)
+            }
+            VerifyErrTy::WrongFnParamCnt(name, expected, found) => format!(
+                "{} Function '{}' expects {} params, but is called with {}",
+                str_pos, name, expected, found
+            ),
+            VerifyErrTy::InvalidStdFn(name) => {
+                format!("{} '{}' is not a std library function", str_pos, name)
+            }
+            VerifyErrTy::WrongStdParamCnt(name, expected, found) => format!(
+                "{} Std library function '{}' expects {} params, but is called with {}",
+                str_pos, name, expected, found
+            ),
+            VerifyErrTy::RegUndefined(reg) => format!(
+                "{} Register r{} may be used before it is defined",
+                str_pos, reg
+            ),
+            VerifyErrTy::RegOutOfRange(reg, max) => format!(
+                "{} Register r{} is above the largest allowed register r{}",
+                str_pos, reg, max
+            ),
+        }
+    }
+
+    fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(self.code(), &self.to_msg())
+    }
+}
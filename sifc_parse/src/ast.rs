@@ -1,4 +1,5 @@
 use crate::token::Token;
+use sifc_err::span::Span;
 use std::collections::HashMap;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -30,6 +31,15 @@ pub enum AstNode {
         stmts: Box<AstNode>,
     },
 
+    WhileStmt {
+        cond_expr: Box<AstNode>,
+        stmts: Box<AstNode>,
+    },
+
+    BreakStmt,
+
+    ContinueStmt,
+
     ReturnStmt {
         ret_expr: Option<Box<AstNode>>,
     },
@@ -51,6 +61,12 @@ pub enum AstNode {
         scope: usize,
     },
 
+    FnExpr {
+        fn_tkn: Token,
+        fn_params: Box<AstNode>,
+        fn_body: Box<AstNode>,
+    },
+
     FnParams {
         params: Vec<AstNode>,
     },
@@ -103,6 +119,7 @@ pub enum AstNode {
         fn_ident_tkn: Token,
         fn_params: Vec<AstNode>,
         is_std: bool,
+        is_var: bool,
     },
 
     VarAssignExpr {
@@ -130,13 +147,49 @@ pub enum AstNode {
 }
 
 impl AstNode {
-    pub fn is_primary_expr(&self) -> bool {
+    /// Returns the span of the main token of the node: the operator of an operation, the
+    /// name in a declaration, access or call, or the value of a literal. Statements and
+    /// lists use the span of their first node. Returns None if the node doesn't contain
+    /// any tokens.
+    pub fn span(&self) -> Option<Span> {
         match self {
-            AstNode::PrimaryExpr { .. } => true,
-            _ => false,
+            AstNode::VarDecl { ident_tkn, .. }
+            | AstNode::FnDecl { ident_tkn, .. }
+            | AstNode::Table { ident_tkn, .. }
+            | AstNode::Array { ident_tkn, .. }
+            | AstNode::VarAssignExpr { ident_tkn, .. } => Some(ident_tkn.span()),
+            AstNode::FnExpr { fn_tkn, .. } => Some(fn_tkn.span()),
+            AstNode::TableAccess { table_tkn, .. } => Some(table_tkn.span()),
+            AstNode::ArrayAccess { array_tkn, .. } | AstNode::ArrayMutExpr { array_tkn, .. } => {
+                Some(array_tkn.span())
+            }
+            AstNode::FnCallExpr { fn_ident_tkn, .. } => Some(fn_ident_tkn.span()),
+            AstNode::UnaryExpr { op_tkn, .. } | AstNode::BinaryExpr { op_tkn, .. } => {
+                Some(op_tkn.span())
+            }
+            AstNode::PrimaryExpr { tkn } => Some(tkn.span()),
+            AstNode::IfStmt { cond_expr, .. }
+            | AstNode::ElifStmt { cond_expr, .. }
+            | AstNode::WhileStmt { cond_expr, .. } => cond_expr.span(),
+            AstNode::ForStmt { var_list, .. } => var_list.span(),
+            AstNode::ExprStmt { expr } => expr.span(),
+            AstNode::ReturnStmt {
+                ret_expr: Some(expr),
+            } => expr.span(),
+            AstNode::TableItem { key, .. } => key.span(),
+            AstNode::Program { blocks: nodes }
+            | AstNode::Block { decls: nodes, .. }
+            | AstNode::FnParams { params: nodes }
+            | AstNode::IdentPair { idents: nodes }
+            | AstNode::ArrayItems { items: nodes } => nodes.iter().find_map(|n| n.span()),
+            _ => None,
         }
     }
 
+    pub fn is_primary_expr(&self) -> bool {
+        matches!(self, AstNode::PrimaryExpr { .. })
+    }
+
     pub fn get_fn_params(&self) -> Vec<AstNode> {
         match self {
             AstNode::FnCallExpr {
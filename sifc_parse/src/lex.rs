@@ -2,10 +2,7 @@ use crate::{
     reserved::{get_reserved_words, is_reserved_word},
     token::{Token, TokenTy},
 };
-use sifc_err::{
-    err::SifErr,
-    lex_err::{LexErr, LexErrTy},
-};
+use sifc_err::lex_err::{LexErr, LexErrTy};
 use std::io::Read;
 use std::{
     collections::HashMap,
@@ -38,6 +35,10 @@ where
 
     /// Number of cumulative bytes read by the reader in this lexer
     bytes_read: usize,
+
+    /// Errors found since they were last taken. Lexing continues after an error, so
+    /// that the parser can report it along with its own errors.
+    errors: Vec<LexErr>,
 }
 
 impl<T> Lexer<T>
@@ -52,7 +53,7 @@ where
             .expect("reading from lex buffer won't fail");
 
         let buffer: Vec<char> = buf.chars().collect();
-        let curr_ch = if buffer.len() == 0 {
+        let curr_ch = if buffer.is_empty() {
             None
         } else {
             Some(buffer[0])
@@ -62,15 +63,24 @@ where
             curr: curr_ch,
             line_num: 1,
             line_pos: 0,
-            reader: reader,
-            buffer: buffer,
+            reader,
+            buffer,
             reserved: get_reserved_words(),
             bytes_read: init_bytes,
+            errors: Vec::new(),
         }
     }
 
-    /// Get the next token from the input stream. If this returns None, it means we're either
-    /// at the end of the input, or we've encountered a character we don't recognize.
+    /// Returns the errors found since the last call, and clears them.
+    pub fn take_errors(&mut self) -> Vec<LexErr> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Get the next token from the input stream. Characters that aren't recognized are
+    /// recorded as errors and skipped, and an unterminated string or block comment is
+    /// recorded as an error and ends the input. Malformed escapes and number literals are
+    /// recorded as errors, and still produce a token so the parser can carry on. Errors
+    /// can be retrieved with take_errors().
     pub fn lex(&mut self) -> Token {
         if self.curr.is_none() {
             return self.eof_tkn();
@@ -98,7 +108,7 @@ where
             '[' => {
                 let nextch = self.peek_char();
                 match nextch {
-                    Some(ch) if ch == '[' => {
+                    Some('[') => {
                         let tkn = self.consume(TokenTy::DoubleLeftBracket);
                         self.advance();
                         tkn
@@ -109,7 +119,7 @@ where
             ']' => {
                 let nextch = self.peek_char();
                 match nextch {
-                    Some(ch) if ch == ']' => {
+                    Some(']') => {
                         let tkn = self.consume(TokenTy::DoubleRightBracket);
                         self.advance();
                         tkn
@@ -129,11 +139,15 @@ where
             '/' => {
                 let nextch = self.peek_char();
                 match nextch {
-                    Some(ch) if ch == '/' => {
+                    Some('/') => {
                         while self.curr.unwrap() != '\n' {
                             self.advance();
                         }
-                        return self.lex();
+                        self.lex()
+                    }
+                    Some('*') => {
+                        self.skip_block_comment();
+                        self.lex()
                     }
                     _ => self.consume(TokenTy::Slash),
                 }
@@ -141,12 +155,12 @@ where
             '=' => {
                 let nextch = self.peek_char();
                 match nextch {
-                    Some(ch) if ch == '=' => {
+                    Some('=') => {
                         let tkn = self.consume(TokenTy::EqEq);
                         self.advance();
                         tkn
                     }
-                    Some(ch) if ch == '>' => {
+                    Some('>') => {
                         let tkn = self.consume(TokenTy::EqArrow);
                         self.advance();
                         tkn
@@ -157,7 +171,7 @@ where
             '<' => {
                 let nextch = self.peek_char();
                 match nextch {
-                    Some(ch) if ch == '=' => {
+                    Some('=') => {
                         let tkn = self.consume(TokenTy::LtEq);
                         self.advance();
                         tkn
@@ -168,7 +182,7 @@ where
             '>' => {
                 let nextch = self.peek_char();
                 match nextch {
-                    Some(ch) if ch == '=' => {
+                    Some('=') => {
                         let tkn = self.consume(TokenTy::GtEq);
                         self.advance();
                         tkn
@@ -179,7 +193,7 @@ where
             '!' => {
                 let nextch = self.peek_char();
                 match nextch {
-                    Some(ch) if ch == '=' => {
+                    Some('=') => {
                         let tkn = self.consume(TokenTy::BangEq);
                         self.advance();
                         tkn
@@ -190,7 +204,7 @@ where
             '&' => {
                 let nextch = self.peek_char();
                 match nextch {
-                    Some(ch) if ch == '&' => {
+                    Some('&') => {
                         let tkn = self.consume(TokenTy::AmpAmp);
                         self.advance();
                         tkn
@@ -201,7 +215,7 @@ where
             '|' => {
                 let nextch = self.peek_char();
                 match nextch {
-                    Some(ch) if ch == '|' => {
+                    Some('|') => {
                         let tkn = self.consume(TokenTy::PipePipe);
                         self.advance();
                         tkn
@@ -209,18 +223,25 @@ where
                     _ => self.consume(TokenTy::Pipe),
                 }
             }
-            _ if ch.is_digit(10) => self.lex_num(),
-            _ if ch.is_alphabetic() => self.lex_ident(),
+            'r' if self.starts_raw_str() => self.lex_raw_str(),
+            _ if ch.is_ascii_digit() => self.lex_num(),
+            _ if ch.is_alphabetic() || ch == '_' => self.lex_ident(),
             _ => {
-                LexErr::new(self.line_num, self.line_pos, LexErrTy::UnknownChar(ch)).emit();
-                self.eof_tkn()
+                self.errors.push(LexErr::new(
+                    self.line_num,
+                    self.line_pos,
+                    LexErrTy::UnknownChar(ch),
+                ));
+                self.advance();
+                self.lex()
             }
         }
     }
 
     /// Lex a string literal. We expect to have a " character when this
     /// function is called, and we consume the last " character during
-    /// this call.
+    /// this call. Strings can span lines, and may contain the escapes
+    /// `\n \r \t \0 \\ \" \'` and `\u{...}`, which takes 1 to 6 hex digits.
     fn lex_str(&mut self) -> Token {
         let mut lit = String::new();
         let startpos = self.line_pos;
@@ -234,73 +255,339 @@ where
                 Some(ch) => {
                     if ch == '"' {
                         return self.consume_w_pos(TokenTy::Str(lit), startline, startpos);
+                    } else if ch == '\\' {
+                        if let Some(esc) = self.lex_escape() {
+                            lit.push(esc)
+                        };
                     } else {
                         lit.push(ch);
                         self.advance();
                     }
                 }
-                None => {
-                    LexErr::new(
-                        self.line_num,
-                        self.line_pos,
-                        LexErrTy::UnterminatedString(lit),
-                    )
-                    .emit();
-                    return self.eof_tkn();
-                }
+                None => break,
             }
         }
 
         // If we finished lexing here without returning, the file
-        // is fully lexed without a string termination occurring.
-        LexErr::new(
-            self.line_num,
-            self.line_pos,
+        // is fully lexed without a string termination occurring. The error
+        // points at the start of the string.
+        self.errors.push(LexErr::new(
+            startline,
+            startpos,
             LexErrTy::UnterminatedString(lit),
-        )
-        .emit();
+        ));
         self.eof_tkn()
     }
 
-    /// Lex a floating point or integer literal.
-    fn lex_num(&mut self) -> Token {
+    /// Lex an escape sequence in a string literal, starting at the '\' character.
+    /// Returns the escaped char, or None if the escape is invalid, in which case an
+    /// error is recorded at the '\' and the rest of the string is still lexed.
+    fn lex_escape(&mut self) -> Option<char> {
+        let line = self.line_num;
+        let pos = self.line_pos;
+
+        // Consume '\'
+        self.advance();
+
+        let ch = self.curr?;
+        let escaped = match ch {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            '\\' | '"' | '\'' => ch,
+            'u' => return self.lex_unicode_escape(line, pos),
+            _ => {
+                self.errors
+                    .push(LexErr::new(line, pos, LexErrTy::InvalidEscape(ch)));
+                self.advance();
+                return None;
+            }
+        };
+
+        self.advance();
+        Some(escaped)
+    }
+
+    /// Lex a `\u{...}` escape, starting at the 'u' character. The closing '}' is only
+    /// consumed if the escape is well formed up to it, so that a missing brace doesn't
+    /// swallow the end of the string.
+    fn lex_unicode_escape(&mut self, line: usize, pos: usize) -> Option<char> {
+        let mut text = String::from("u");
+
+        // Consume 'u'
+        self.advance();
+
+        if self.curr != Some('{') {
+            self.errors
+                .push(LexErr::new(line, pos, LexErrTy::InvalidUnicodeEscape(text)));
+            return None;
+        }
+        text.push('{');
+        self.advance();
+
+        let mut hex = String::new();
+        while let Some(ch) = self.curr {
+            if !ch.is_ascii_hexdigit() {
+                break;
+            }
+            hex.push(ch);
+            text.push(ch);
+            self.advance();
+        }
+
+        if self.curr != Some('}') {
+            self.errors
+                .push(LexErr::new(line, pos, LexErrTy::InvalidUnicodeEscape(text)));
+            return None;
+        }
+        text.push('}');
+        self.advance();
+
+        let escaped = match hex.len() {
+            1..=6 => u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32),
+            _ => None,
+        };
+        if escaped.is_none() {
+            self.errors
+                .push(LexErr::new(line, pos, LexErrTy::InvalidUnicodeEscape(text)));
+        }
+        escaped
+    }
+
+    /// Checks if the current 'r' starts a raw string, which is an 'r' followed by any
+    /// number of '#' characters and a '"'.
+    fn starts_raw_str(&self) -> bool {
+        let mut idx = self.line_pos + 1;
+        while idx < self.buffer.len() && self.buffer[idx] == '#' {
+            idx += 1;
+        }
+        idx < self.buffer.len() && self.buffer[idx] == '"'
+    }
+
+    /// Lex a raw string literal, such as `r"C:\path"` or `r#"say "hi""#`. Raw strings
+    /// have no escapes and can span lines. They end at the first '"' followed by as many
+    /// '#' characters as they started with, so adding '#'s lets them contain quotes.
+    fn lex_raw_str(&mut self) -> Token {
         let mut lit = String::new();
         let startpos = self.line_pos;
         let startline = self.line_num;
 
-        let mut currch = self.curr;
+        // Consume 'r' and count the '#'s before the opening '"'.
+        self.advance();
+        let mut hashes = 0;
+        while self.curr == Some('#') {
+            hashes += 1;
+            self.advance();
+        }
+        self.advance();
 
-        while let Some(ch) = currch {
-            if ch.is_digit(10) {
-                lit.push(ch);
-                self.advance();
-                currch = self.curr;
-            } else if ch == '.' {
-                lit.push(ch);
-                self.advance();
-                let mut innerch = self.curr;
+        while let Some(ch) = self.curr {
+            if ch == '"' && self.closes_raw_str(hashes) {
+                for _ in 0..hashes {
+                    self.advance();
+                }
+                return self.consume_w_pos(TokenTy::Str(lit), startline, startpos);
+            }
+            lit.push(ch);
+            self.advance();
+        }
 
-                while let Some(ch) = innerch {
-                    if ch.is_digit(10) {
-                        lit.push(ch);
+        self.errors.push(LexErr::new(
+            startline,
+            startpos,
+            LexErrTy::UnterminatedString(lit),
+        ));
+        self.eof_tkn()
+    }
+
+    /// Checks if the current '"' is followed by the given number of '#' characters.
+    fn closes_raw_str(&self, hashes: usize) -> bool {
+        (1..hashes + 1).all(|n| self.buffer.get(self.line_pos + n) == Some(&'#'))
+    }
+
+    /// Lex a floating point or integer literal. Decimal literals can have a fraction
+    /// and an exponent, such as `1.5e-3`. Integers can also be written in hex, binary or
+    /// octal with a `0x`, `0b` or `0o` prefix. Digits can be separated with `_`, which
+    /// must be placed between two digits.
+    fn lex_num(&mut self) -> Token {
+        let startpos = self.line_pos;
+        let startline = self.line_num;
+
+        let radix = match (self.curr, self.peek_char()) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('b')) => 2,
+            (Some('0'), Some('o')) => 8,
+            _ => 10,
+        };
+        let numval = match radix {
+            10 => self.lex_decimal(startline, startpos),
+            _ => self.lex_radix_int(radix, startline, startpos),
+        };
+
+        Token::new(TokenTy::Val(numval), startline, startpos)
+    }
+
+    /// Lex a decimal number, with an optional fraction and exponent, that starts at the
+    /// given line and position. Returns 0 if the literal is malformed.
+    fn lex_decimal(&mut self, line: usize, pos: usize) -> f64 {
+        let mut lit = String::new();
+        let mut valid = self.lex_digits(10, &mut lit);
+
+        if self.curr == Some('.') {
+            lit.push('.');
+            self.advance();
+            valid = self.lex_digits(10, &mut lit) && valid;
+        }
+
+        match self.curr {
+            Some('e') | Some('E') => {
+                lit.push('e');
+                self.advance();
+                match self.curr {
+                    Some(sign) if sign == '+' || sign == '-' => {
+                        lit.push(sign);
                         self.advance();
-                        innerch = self.curr;
-                    } else {
-                        innerch = None;
-                        currch = None;
                     }
+                    _ => (),
+                };
+
+                let mut exp = String::new();
+                valid = self.lex_digits(10, &mut exp) && valid;
+                if exp.is_empty() {
+                    self.errors
+                        .push(LexErr::new(line, pos, LexErrTy::MissingDigits(lit.clone())));
+                    valid = false;
+                }
+                lit.push_str(&exp);
+            }
+            _ => (),
+        };
+
+        match valid {
+            true => lit.parse::<f64>().unwrap(),
+            false => 0.0,
+        }
+    }
+
+    /// Lex an integer written in hex, binary or octal, starting at its prefix on the
+    /// given line and position. Returns 0 if the literal is malformed.
+    fn lex_radix_int(&mut self, radix: u32, line: usize, pos: usize) -> f64 {
+        let mut prefix = String::new();
+        for _ in 0..2 {
+            prefix.push(self.curr.unwrap());
+            self.advance();
+        }
+
+        let mut digits = String::new();
+        if !self.lex_digits(radix, &mut digits) {
+            return 0.0;
+        }
+        if digits.is_empty() {
+            self.errors
+                .push(LexErr::new(line, pos, LexErrTy::MissingDigits(prefix)));
+            return 0.0;
+        }
+
+        match u64::from_str_radix(&digits, radix) {
+            Ok(val) => val as f64,
+            Err(_) => {
+                self.errors.push(LexErr::new(
+                    line,
+                    pos,
+                    LexErrTy::IntTooLarge(format!("{}{}", prefix, digits)),
+                ));
+                0.0
+            }
+        }
+    }
+
+    /// Lex a run of digits into `digits`, dropping `_` separators. Letters directly after
+    /// hex, binary or octal digits are lexed as part of the number and reported as invalid
+    /// digits. Returns false if an error was recorded.
+    fn lex_digits(&mut self, radix: u32, digits: &mut String) -> bool {
+        let mut valid = true;
+        let mut after_digit = false;
+        let mut after_sep = false;
+
+        while let Some(ch) = self.curr {
+            let in_num = match radix {
+                10 => ch.is_ascii_digit() || ch == '_',
+                _ => ch.is_alphanumeric() || ch == '_',
+            };
+            if !in_num {
+                break;
+            }
+
+            if ch == '_' {
+                let before_digit = match self.peek_char() {
+                    Some(next) => next.is_digit(radix),
+                    None => false,
+                };
+                // A run of separators is only reported once.
+                if !after_sep && (!after_digit || !before_digit) {
+                    self.errors.push(LexErr::new(
+                        self.line_num,
+                        self.line_pos,
+                        LexErrTy::InvalidSeparator,
+                    ));
+                    valid = false;
                 }
+            } else if ch.is_digit(radix) {
+                digits.push(ch);
             } else {
-                currch = None;
+                self.errors.push(LexErr::new(
+                    self.line_num,
+                    self.line_pos,
+                    LexErrTy::InvalidDigit(ch, radix),
+                ));
+                valid = false;
+            }
+
+            after_digit = ch != '_';
+            after_sep = ch == '_';
+            self.advance();
+        }
+
+        valid
+    }
+
+    /// Skip a block comment, starting at its opening "/*". Block comments nest, so each
+    /// "/*" inside of the comment needs its own "*/". An unterminated comment is recorded
+    /// as an error at its opening, and runs to the end of the input.
+    fn skip_block_comment(&mut self) {
+        let startpos = self.line_pos;
+        let startline = self.line_num;
+        let mut depth = 0;
+
+        while let Some(ch) = self.curr {
+            let nextch = self.peek_char();
+            match (ch, nextch) {
+                ('/', Some('*')) => depth += 1,
+                ('*', Some('/')) => depth -= 1,
+                _ => {
+                    self.advance();
+                    continue;
+                }
+            };
+
+            // Consume both chars of the "/*" or "*/".
+            self.advance();
+            self.advance();
+            if depth == 0 {
+                return;
             }
         }
 
-        let numval = lit.parse::<f64>().unwrap();
-        Token::new(TokenTy::Val(numval), startline, startpos)
+        self.errors.push(LexErr::new(
+            startline,
+            startpos,
+            LexErrTy::UnterminatedComment,
+        ));
     }
 
     /// Lex an identifier. This is not a string literal and does not
-    /// contain quotations around it.
+    /// contain quotations around it. Identifiers can contain and start with '_'.
     fn lex_ident(&mut self) -> Token {
         let mut lit = String::new();
         let startpos = self.line_pos;
@@ -309,7 +596,7 @@ where
         let mut currch = self.curr;
 
         while let Some(ch) = currch {
-            if ch.is_alphanumeric() {
+            if ch.is_alphanumeric() || ch == '_' {
                 lit.push(ch);
                 self.advance();
                 currch = self.curr;
@@ -356,15 +643,12 @@ where
     /// buffer, reads the next line of the file into the buffer and sets
     /// the position to 0.
     fn advance(&mut self) {
-        let on_new_line = match self.curr {
-            Some(ch) if ch == '\n' => true,
-            _ => false,
-        };
+        let on_new_line = matches!(self.curr, Some('\n'));
 
         if self.line_pos == self.buffer.len() - 1 || on_new_line {
             self.next_line();
         } else {
-            self.line_pos = self.line_pos + 1;
+            self.line_pos += 1;
         }
 
         if self.finished() {
@@ -401,16 +685,16 @@ where
             .read_line(&mut buf)
             .expect("file reader should not fail");
         self.buffer = buf.chars().collect();
-        self.bytes_read = self.bytes_read + line_bytes;
+        self.bytes_read += line_bytes;
 
         self.line_pos = 0;
-        self.line_num = self.line_num + 1;
+        self.line_num += 1;
     }
 
     /// When the input buffer is empty, that means read_line has indicated
     /// we're at the end of the file.
     fn finished(&self) -> bool {
-        self.buffer.len() == 0
+        self.buffer.is_empty()
     }
 
     fn eof_tkn(&self) -> Token {
@@ -2,5 +2,6 @@ pub mod ast;
 pub mod lex;
 pub mod parser;
 pub mod reserved;
+pub mod stdfns;
 pub mod symtab;
 pub mod token;
@@ -1,13 +1,16 @@
 use crate::{
     ast::AstNode,
     lex::Lexer,
+    reserved::get_reserved_words,
+    stdfns::StdFns,
     symtab::SymTab,
     token::{Token, TokenTy},
 };
 
 use sifc_err::{
-    err::SifErr,
+    diag,
     parse_err::{ParseErr, ParseErrTy},
+    span::Span,
 };
 
 use std::collections::HashMap;
@@ -17,21 +20,21 @@ use std::io::Read;
 const FN_PARAM_MAX_LEN: usize = 64;
 
 /// [`ParserResult`] handles the result from parsing a file. This contains an optional
-/// AST structure, as well as a flag indicating whether or not continuable errors
+/// AST structure, as well as a flag indicating whether or not errors
 /// were encountered during the parsing phase. This is returned from the parse()
 /// method, and should be checked for errors before continuing further phases
 /// of the compiler.
 #[derive(Default)]
 pub struct ParserResult {
-    /// The resulting AST from parsing. This will be None if parsing failed with
-    /// non-continuable errors.
+    /// The resulting AST from parsing. When errors were found, this holds the declarations
+    /// that parsed successfully, so it can still be used by tooling but should not be compiled.
     pub ast: Option<AstNode>,
 
     /// Flag indicating if errors have ocurred during parsing.
     pub has_err: bool,
 
-    /// Vec of errors that have been parsed. It is possible to encounter
-    /// several continuable errors.
+    /// Vec of errors that have been parsed, including the errors found by the lexer, in
+    /// the order they were found.
     pub errors: Vec<ParseErr>,
 }
 
@@ -40,15 +43,18 @@ pub struct ParserResult {
 /// pretty clearly from it. There are a couple of things to note:
 ///
 /// 1. Operator precedence is encoded into the grammar itself. This means that parsing expressions
-/// begins at the most general precedence (expr), and ends at the most specific (primary expressions
-/// which are usually primitive values).
+///    begins at the most general precedence (expr), and ends at the most specific (primary
+///    expressions which are usually primitive values).
 ///
 /// 2. The parser contains a flag to enabel/disable symbol table checking. This allows some control
-/// over when to emit parsing errors for undefined or poorly defined symbols, in cases where
-/// the parser may know that the symbols do not need to be defined when parsing certain constructs.
+///    over when to emit parsing errors for undefined or poorly defined symbols, in cases where
+///    the parser may know that the symbols do not need to be defined when parsing certain
+///    constructs.
 ///
-/// 3. Some errors encountered during parsing can be marked continuable, which indicates that
-/// parsing can continue after the error is recorded.
+/// 3. Errors don't stop parsing. Each error is recorded, and the parser skips ahead to the
+///    next `;`, `}` or declaration keyword and resumes in `decl()`, so a single run reports
+///    every independent syntax error and still produces an AST of the declarations that
+///    parsed.
 ///
 /// In general, parsing methods will match on tokens using `expect()` or `optional()`, and recurse
 /// to the correct production based on the current available token, `curr_tkn`. Each method makes use
@@ -64,17 +70,19 @@ where
     /// Reference to a symbol table, used to store symbols defined in this file.
     sym_tab: &'s mut SymTab,
 
+    /// Registry of std library functions that can be called. Calls to names in this registry
+    /// don't need to be declared in the symbol table.
+    std_fns: &'s dyn StdFns,
+
     /// The current token from the lexer.
     curr_tkn: Token,
 
     /// Vec of errors parsed so far.
     errors: Vec<ParseErr>,
 
-    /// Flag indicating whether or not potential identifiers should be looked up
-    /// the symbol table before attempting to access or assign them. This is true
-    /// almost all the time, but parsing table access is simplified for now
-    /// by disabling checking the table definition.
-    should_check_sym_tab: bool,
+    /// Number of loops enclosing the current token. Break and continue statements
+    /// are only valid when this is greater than 0.
+    loop_depth: usize,
 }
 
 impl<'l, 's, T> Parser<'l, 's, T>
@@ -83,15 +91,25 @@ where
 {
     /// Creates a new parser. Makes an initial call to `lex()` in the supplied lexer,
     /// in order to fill the `curr_tkn` field.
-    pub fn new(lex: &'l mut Lexer<T>, symt: &'s mut SymTab) -> Parser<'l, 's, T> {
+    pub fn new(
+        lex: &'l mut Lexer<T>,
+        symt: &'s mut SymTab,
+        stdf: &'s dyn StdFns,
+    ) -> Parser<'l, 's, T> {
         let firsttkn = lex.lex();
+        let errors = lex
+            .take_errors()
+            .into_iter()
+            .map(ParseErr::from_lex)
+            .collect();
 
         Parser {
             lexer: lex,
             sym_tab: symt,
+            std_fns: stdf,
             curr_tkn: firsttkn,
-            errors: Vec::new(),
-            should_check_sym_tab: true,
+            errors,
+            loop_depth: 0,
         }
     }
 
@@ -104,24 +122,21 @@ where
         let mut found_err = false;
 
         while self.curr_tkn.ty != TokenTy::Eof {
+            let start = self.curr_tkn.span();
             match self.decl() {
                 Ok(a) => blocks.push(a),
-                Err(e) => {
+                Err(_) => {
                     found_err = true;
-                    e.emit();
-                    match e.continuable() {
-                        true => (),
-                        false => break,
-                    };
+                    self.synchronize(start);
                 }
             }
         }
 
-        let head = AstNode::Program { blocks: blocks };
+        let head = AstNode::Program { blocks };
 
         ParserResult {
             ast: Some(head),
-            has_err: found_err,
+            has_err: found_err || !self.errors.is_empty(),
             errors: self.errors.clone(),
         }
     }
@@ -137,18 +152,23 @@ where
         }
     }
 
-    /// Parses a statement. There are currently 5 kinds of statements, including the
+    /// Parses a statement. There are currently 8 kinds of statements, including the
     /// Block statement, which is a brace delimited list of other declarations.
     ///
-    /// stmt ::= ifstmt   |
-    ///          forstmt  |
-    ///          exprstmt |
-    ///          retstmt  |
-    ///          block    ;
+    /// stmt ::= ifstmt       |
+    ///          forstmt      |
+    ///          whilestmt    |
+    ///          breakstmt    |
+    ///          continuestmt |
+    ///          exprstmt     |
+    ///          retstmt      |
+    ///          block        ;
     fn stmt(&mut self) -> Result<AstNode, ParseErr> {
         match self.curr_tkn.ty {
             TokenTy::If => self.if_stmt(),
             TokenTy::For => self.for_stmt(),
+            TokenTy::While => self.while_stmt(),
+            TokenTy::Break | TokenTy::Continue => self.loop_ctrl_stmt(),
             TokenTy::Return => self.ret_stmt(),
             TokenTy::LeftBrace => self.block(None),
             _ => self.expr_stmt(),
@@ -168,19 +188,13 @@ where
         // Make a new scope for the block, and insert optional block scope bindings
         // into the new scope.
         self.sym_tab.init_scope();
-        match bindings {
-            Some(nodes) => {
-                for node in nodes {
-                    match &node {
-                        AstNode::PrimaryExpr { ref tkn } => {
-                            let name = tkn.get_name();
-                            self.sym_tab.store(&name, node.clone());
-                        }
-                        _ => (),
-                    };
-                }
+        if let Some(nodes) = bindings {
+            for node in nodes {
+                if let AstNode::PrimaryExpr { ref tkn } = &node {
+                    let name = tkn.get_name();
+                    self.sym_tab.store(&name, node.clone());
+                };
             }
-            None => (),
         };
 
         // Match on declarations until we reach the closing brace.
@@ -188,8 +202,11 @@ where
             match self.curr_tkn.ty {
                 TokenTy::RightBrace | TokenTy::Eof => break,
                 _ => {
-                    let result = self.decl()?;
-                    decls.push(result);
+                    let start = self.curr_tkn.span();
+                    match self.decl() {
+                        Ok(result) => decls.push(result),
+                        Err(_) => self.synchronize(start),
+                    };
                 }
             };
         }
@@ -199,10 +216,7 @@ where
         let lvl = self.sym_tab.level();
         self.sym_tab.close_scope();
 
-        Ok(AstNode::Block {
-            decls: decls,
-            scope: lvl,
-        })
+        Ok(AstNode::Block { decls, scope: lvl })
     }
 
     /// Parses a variable declaration, including optional assignment.
@@ -225,13 +239,18 @@ where
             TokenTy::Eq => {
                 self.expect(TokenTy::Eq)?;
 
-                let rhs = match self.curr_tkn.ty {
-                    TokenTy::LeftBracket => self.array_decl(ident_tkn.clone())?,
-                    TokenTy::DoubleLeftBracket => self.table_decl(ident_tkn.clone())?,
-                    _ => {
-                        let res = self.expr()?;
-                        self.expect(TokenTy::Semicolon)?;
-                        res
+                let rhs = match self.var_rhs(&ident_tkn) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        // Declare the name even though its value didn't parse, so that uses
+                        // of it after recovering aren't also reported as undeclared.
+                        let node = AstNode::VarDecl {
+                            ident_tkn: ident_tkn.clone(),
+                            is_global: self.sym_tab.is_global(),
+                            rhs: None,
+                        };
+                        self.sym_tab.store(&ident_tkn.get_name(), node);
+                        return Err(e);
                     }
                 };
 
@@ -262,6 +281,19 @@ where
         }
     }
 
+    /// Parses the value assigned in a variable declaration, after the "=".
+    fn var_rhs(&mut self, ident_tkn: &Token) -> Result<AstNode, ParseErr> {
+        match self.curr_tkn.ty {
+            TokenTy::LeftBracket => self.array_decl(ident_tkn.clone()),
+            TokenTy::DoubleLeftBracket => self.table_decl(ident_tkn.clone()),
+            _ => {
+                let res = self.expr()?;
+                self.expect(TokenTy::Semicolon)?;
+                Ok(res)
+            }
+        }
+    }
+
     /// Parse a function declaration. This function does some symbol table dancing in
     /// order to properly parse recursive function definitions.
     ///
@@ -284,10 +316,52 @@ where
         let params = self.param_list(false)?;
         self.expect(TokenTy::RightParen)?;
 
+        let body = self.fn_body(&params)?;
+
+        let node = AstNode::FnDecl {
+            ident_tkn: ident_tkn.clone(),
+            fn_params: Box::new(params),
+            fn_body: Box::new(body),
+            scope: self.sym_tab.level(),
+        };
+
+        self.sym_tab.store(&ident_tkn.get_name(), node.clone());
+
+        Ok(node)
+    }
+
+    /// Parse an anonymous function expression. The resulting function is a value, which
+    /// can be stored in a variable, passed as a param or returned from another function.
+    ///
+    /// fnexpr ::= "fn" "(" [ paramlist ] ")" block ;
+    fn fn_expr(&mut self) -> Result<AstNode, ParseErr> {
+        let fn_tkn = self.curr_tkn.clone();
+        self.expect(TokenTy::Fn)?;
+
+        self.expect(TokenTy::LeftParen)?;
+        let params = self.param_list(false)?;
+        self.expect(TokenTy::RightParen)?;
+
+        let body = self.fn_body(&params)?;
+
+        Ok(AstNode::FnExpr {
+            fn_tkn,
+            fn_params: Box::new(params),
+            fn_body: Box::new(body),
+        })
+    }
+
+    /// Parse the body of a function declaration or expression, binding the given params
+    /// in the body scope.
+    fn fn_body(&mut self, params: &AstNode) -> Result<AstNode, ParseErr> {
+        // Loops outside of the function body can't be controlled from inside of it.
+        let outer_loop_depth = self.loop_depth;
+        self.loop_depth = 0;
+
         // Insert function params into symtab for block parsing.
-        let bindings = match &params {
+        let bindings = match params {
             AstNode::FnParams { ref params } => {
-                if params.len() > 0 {
+                if !params.is_empty() {
                     Some(params.clone())
                 } else {
                     None
@@ -296,7 +370,9 @@ where
             _ => None,
         };
 
-        let body = self.block(bindings)?;
+        let body = self.block(bindings);
+        self.loop_depth = outer_loop_depth;
+        let body = body?;
 
         // We don't need to write a return statement in every function, but we need
         // to insert the return ast if we don't have one so we can generate the right code
@@ -309,7 +385,7 @@ where
                     new_decls.push(AstNode::ReturnStmt { ret_expr: None });
                     AstNode::Block {
                         decls: new_decls,
-                        scope: scope,
+                        scope,
                     }
                 }
                 _ => {
@@ -321,7 +397,7 @@ where
                             new_decls.push(AstNode::ReturnStmt { ret_expr: None });
                             AstNode::Block {
                                 decls: new_decls,
-                                scope: scope,
+                                scope,
                             }
                         }
                     }
@@ -330,16 +406,7 @@ where
             _ => body,
         };
 
-        let node = AstNode::FnDecl {
-            ident_tkn: ident_tkn.clone(),
-            fn_params: Box::new(params),
-            fn_body: Box::new(body_w_ret),
-            scope: self.sym_tab.level(),
-        };
-
-        self.sym_tab.store(&ident_tkn.get_name(), node.clone());
-
-        Ok(node)
+        Ok(body_w_ret)
     }
 
     /// Processes param lists when we declare a function and when we call a function.
@@ -446,20 +513,17 @@ where
             // Parse the value for this entry using expr().
             self.expect(TokenTy::EqArrow)?;
             let val = self.expr()?;
-            items.insert(String::from(ident_tkn.get_name()), val);
+            items.insert(ident_tkn.get_name(), val);
 
             // Entries are separated by comma tokens. The final comma in the list is
             // optional: if we expect it but don't find it we must break. This is ok to do
             // here, as the table_decl() method will match the ending bracket token for us.
-            match self.optional(TokenTy::Comma) {
-                false => {
-                    break;
-                }
-                _ => {}
+            if !self.optional(TokenTy::Comma) {
+                break;
             };
         }
 
-        Ok(AstNode::ItemList { items: items })
+        Ok(AstNode::ItemList { items })
     }
 
     /// Parses an array declaration.
@@ -481,11 +545,8 @@ where
 
             // If we have a comma here, consume and continue. If we don't
             // have one, break the loop
-            match self.optional(TokenTy::Comma) {
-                false => {
-                    break;
-                }
-                _ => {}
+            if !self.optional(TokenTy::Comma) {
+                break;
             };
         }
 
@@ -501,7 +562,7 @@ where
         let node = AstNode::Array {
             ident_tkn: ident_tkn.clone(),
             body: box_body,
-            len: len,
+            len,
         };
         self.sym_tab.store(&ident_tkn.get_name(), node.clone());
 
@@ -538,7 +599,12 @@ where
                     else_ifs.push(stmt_ast);
                 }
                 TokenTy::Else => {
-                    else_cnt = else_cnt + 1;
+                    // Don't allow multiple else statements. The error is reported at the
+                    // extra else, and the rest of the statement is still parsed.
+                    else_cnt += 1;
+                    if else_cnt > 1 {
+                        self.add_error(ParseErrTy::InvalidIfStmt);
+                    }
                     self.expect(TokenTy::Else)?;
                     let blck = self.block(None)?;
                     else_blck.push(blck);
@@ -547,11 +613,6 @@ where
             };
         }
 
-        // Don't allow multiple else statements.
-        if else_cnt > 1 {
-            self.add_error(ParseErrTy::InvalidIfStmt);
-        }
-
         Ok(AstNode::IfStmt {
             cond_expr: Box::new(if_cond),
             if_stmts: Box::new(if_blck),
@@ -577,7 +638,7 @@ where
             _ => None,
         };
 
-        let stmts = self.block(bindings)?;
+        let stmts = self.loop_body(bindings)?;
 
         Ok(AstNode::ForStmt {
             var_list: Box::new(var_list),
@@ -586,6 +647,55 @@ where
         })
     }
 
+    /// Parses a while loop statement.
+    ///
+    /// whilestmt ::= "while" expr block ;
+    fn while_stmt(&mut self) -> Result<AstNode, ParseErr> {
+        self.expect(TokenTy::While)?;
+
+        let cond_expr = self.expr()?;
+        let stmts = self.loop_body(None)?;
+
+        Ok(AstNode::WhileStmt {
+            cond_expr: Box::new(cond_expr),
+            stmts: Box::new(stmts),
+        })
+    }
+
+    /// Parses the block of a loop, tracking the loop depth so that break and continue
+    /// statements inside of the block can be checked.
+    fn loop_body(&mut self, bindings: Option<Vec<AstNode>>) -> Result<AstNode, ParseErr> {
+        self.loop_depth += 1;
+        let result = self.block(bindings);
+        self.loop_depth -= 1;
+        result
+    }
+
+    /// Parses a break or continue statement. These are only valid inside of a loop body.
+    ///
+    /// breakstmt    ::= "break" ";" ;
+    /// continuestmt ::= "continue" ";" ;
+    fn loop_ctrl_stmt(&mut self) -> Result<AstNode, ParseErr> {
+        let tkn = self.curr_tkn.clone();
+        let node = match tkn.ty {
+            TokenTy::Break => AstNode::BreakStmt,
+            _ => AstNode::ContinueStmt,
+        };
+
+        self.consume();
+        if self.loop_depth == 0 {
+            let err = self.add_error_w_pos(
+                tkn.line,
+                tkn.pos,
+                ParseErrTy::InvalidLoopCtrl(tkn.ty.to_string()),
+            );
+            return Err(err);
+        }
+
+        self.expect(TokenTy::Semicolon)?;
+        Ok(node)
+    }
+
     /// Parses a pair of identifiers. This is intended to be used inside the for loop, but
     /// could also be re-used if any tuple structures need to be parsed.
     ///
@@ -611,7 +721,7 @@ where
         let second_ident = maybe_sec_ident.unwrap();
         idents.push(AstNode::PrimaryExpr { tkn: second_ident });
 
-        Ok(AstNode::IdentPair { idents: idents })
+        Ok(AstNode::IdentPair { idents })
     }
 
     /// Parses a return statement.
@@ -682,70 +792,64 @@ where
     fn assign_expr(&mut self) -> Result<AstNode, ParseErr> {
         let ast = self.or_expr()?;
 
-        match self.curr_tkn.ty {
-            TokenTy::Eq => {
-                let op = self.curr_tkn.clone();
-                self.expect(TokenTy::Eq)?;
-
-                // Get the assignment value
-                let rhs = self.assign_expr()?;
-
-                // Check the lhs of the expression. If it's an ident, we have a variable assignment.
-                // We check the symbol table for that variable, and error if we can't find it.
-                // If the lhs is an array access, we're mutating an array value.
-                // If it's neither of those, we have an invalid assignment.
-                match ast.clone() {
-                    AstNode::PrimaryExpr { tkn } => {
-                        match tkn.ty {
-                            TokenTy::Ident(name) => {
-                                let maybe_sym = self.sym_tab.retrieve(&name);
-                                if maybe_sym.is_none() {
-                                    return Err(self.add_error(ParseErrTy::UndeclSym(name)));
-                                }
+        if self.curr_tkn.ty == TokenTy::Eq {
+            let op = self.curr_tkn.clone();
+            self.expect(TokenTy::Eq)?;
+
+            // Get the assignment value
+            let rhs = self.assign_expr()?;
+
+            // Check the lhs of the expression. If it's an ident, we have a variable assignment.
+            // We check the symbol table for that variable, and error if we can't find it.
+            // If the lhs is an array access, we're mutating an array value.
+            // If it's neither of those, we have an invalid assignment.
+            match ast.clone() {
+                AstNode::PrimaryExpr { tkn } => {
+                    match &tkn.ty {
+                        TokenTy::Ident(name) => {
+                            let maybe_sym = self.sym_tab.retrieve(name);
+                            if maybe_sym.is_none() {
+                                return Err(self.undecl_sym_error(name));
+                            }
 
-                                // Check symbol table for var name.
-                                let var_node = maybe_sym.unwrap();
-                                match var_node {
-                                    AstNode::VarDecl {
-                                        ident_tkn,
+                            // Check symbol table for var name. The assignment keeps the
+                            // token of the lhs, so that it has the line of the assignment
+                            // rather than the declaration.
+                            let var_node = maybe_sym.unwrap();
+                            match var_node {
+                                AstNode::VarDecl { is_global, .. } => {
+                                    return Ok(AstNode::VarAssignExpr {
+                                        ident_tkn: tkn.clone(),
                                         is_global,
-                                        ..
-                                    } => {
-                                        return Ok(AstNode::VarAssignExpr {
-                                            ident_tkn: ident_tkn.clone(),
-                                            is_global: is_global,
-                                            rhs: Box::new(rhs),
-                                        });
-                                    }
-                                    _ => {
-                                        return Err(self.add_error(ParseErrTy::UndeclSym(name)));
-                                    }
+                                        rhs: Box::new(rhs),
+                                    });
+                                }
+                                _ => {
+                                    return Err(self.undecl_sym_error(name));
                                 }
                             }
-                            _ => {
-                                return Err(self.add_error(ParseErrTy::InvalidAssign(
-                                    tkn.ty.clone().to_string(),
-                                )));
-                            }
-                        };
-                    }
-                    AstNode::ArrayAccess { array_tkn, index } => {
-                        return Ok(AstNode::ArrayMutExpr {
-                            array_tkn: array_tkn,
-                            index: index,
-                            rhs: Box::new(rhs),
-                        });
-                    }
-                    _ => {
-                        return Err(self.add_error_w_pos(
-                            op.line,
-                            op.pos,
-                            ParseErrTy::InvalidAssign(op.ty.to_string()),
-                        ));
-                    }
+                        }
+                        _ => {
+                            return Err(self
+                                .add_error(ParseErrTy::InvalidAssign(tkn.ty.clone().to_string())));
+                        }
+                    };
+                }
+                AstNode::ArrayAccess { array_tkn, index } => {
+                    return Ok(AstNode::ArrayMutExpr {
+                        array_tkn,
+                        index,
+                        rhs: Box::new(rhs),
+                    });
+                }
+                _ => {
+                    return Err(self.add_error_w_pos(
+                        op.line,
+                        op.pos,
+                        ParseErrTy::InvalidAssign(op.ty.to_string()),
+                    ));
                 }
             }
-            _ => (),
         };
 
         Ok(ast)
@@ -758,21 +862,16 @@ where
         let mut ast = self.and_expr()?;
 
         // Continue parsing as long as the rhs contains expressions.
-        loop {
-            match self.curr_tkn.ty {
-                TokenTy::PipePipe => {
-                    let op = self.curr_tkn.clone();
-                    self.consume();
-                    let rhs = self.and_expr()?;
+        while let TokenTy::PipePipe = self.curr_tkn.ty {
+            let op = self.curr_tkn.clone();
+            self.consume();
+            let rhs = self.and_expr()?;
 
-                    ast = AstNode::BinaryExpr {
-                        op_tkn: op,
-                        lhs: Box::new(ast),
-                        rhs: Box::new(rhs),
-                    };
-                }
-                _ => break,
-            }
+            ast = AstNode::BinaryExpr {
+                op_tkn: op,
+                lhs: Box::new(ast),
+                rhs: Box::new(rhs),
+            };
         }
 
         Ok(ast)
@@ -784,21 +883,16 @@ where
     fn and_expr(&mut self) -> Result<AstNode, ParseErr> {
         let mut ast = self.equality_expr()?;
 
-        loop {
-            match self.curr_tkn.ty {
-                TokenTy::AmpAmp => {
-                    let op = self.curr_tkn.clone();
-                    self.consume();
-                    let rhs = self.equality_expr()?;
+        while let TokenTy::AmpAmp = self.curr_tkn.ty {
+            let op = self.curr_tkn.clone();
+            self.consume();
+            let rhs = self.equality_expr()?;
 
-                    ast = AstNode::BinaryExpr {
-                        op_tkn: op,
-                        lhs: Box::new(ast),
-                        rhs: Box::new(rhs),
-                    };
-                }
-                _ => break,
-            }
+            ast = AstNode::BinaryExpr {
+                op_tkn: op,
+                lhs: Box::new(ast),
+                rhs: Box::new(rhs),
+            };
         }
 
         Ok(ast)
@@ -810,21 +904,16 @@ where
     fn equality_expr(&mut self) -> Result<AstNode, ParseErr> {
         let mut ast = self.compr_expr()?;
 
-        loop {
-            match self.curr_tkn.ty {
-                TokenTy::BangEq | TokenTy::EqEq => {
-                    let op = self.curr_tkn.clone();
-                    self.consume();
-                    let rhs = self.compr_expr()?;
+        while let TokenTy::BangEq | TokenTy::EqEq = self.curr_tkn.ty {
+            let op = self.curr_tkn.clone();
+            self.consume();
+            let rhs = self.compr_expr()?;
 
-                    ast = AstNode::BinaryExpr {
-                        op_tkn: op,
-                        lhs: Box::new(ast),
-                        rhs: Box::new(rhs),
-                    };
-                }
-                _ => break,
-            }
+            ast = AstNode::BinaryExpr {
+                op_tkn: op,
+                lhs: Box::new(ast),
+                rhs: Box::new(rhs),
+            };
         }
 
         Ok(ast)
@@ -836,21 +925,16 @@ where
     fn compr_expr(&mut self) -> Result<AstNode, ParseErr> {
         let mut ast = self.add_or_sub_expr()?;
 
-        loop {
-            match self.curr_tkn.ty {
-                TokenTy::Lt | TokenTy::LtEq | TokenTy::Gt | TokenTy::GtEq => {
-                    let op = self.curr_tkn.clone();
-                    self.consume();
-                    let rhs = self.add_or_sub_expr()?;
+        while let TokenTy::Lt | TokenTy::LtEq | TokenTy::Gt | TokenTy::GtEq = self.curr_tkn.ty {
+            let op = self.curr_tkn.clone();
+            self.consume();
+            let rhs = self.add_or_sub_expr()?;
 
-                    ast = AstNode::BinaryExpr {
-                        op_tkn: op,
-                        lhs: Box::new(ast),
-                        rhs: Box::new(rhs),
-                    };
-                }
-                _ => break,
-            }
+            ast = AstNode::BinaryExpr {
+                op_tkn: op,
+                lhs: Box::new(ast),
+                rhs: Box::new(rhs),
+            };
         }
 
         Ok(ast)
@@ -862,21 +946,16 @@ where
     fn add_or_sub_expr(&mut self) -> Result<AstNode, ParseErr> {
         let mut ast = self.mul_or_div_expr()?;
 
-        loop {
-            match self.curr_tkn.ty {
-                TokenTy::Plus | TokenTy::Minus => {
-                    let op = self.curr_tkn.clone();
-                    self.consume();
-                    let rhs = self.mul_or_div_expr()?;
+        while let TokenTy::Plus | TokenTy::Minus = self.curr_tkn.ty {
+            let op = self.curr_tkn.clone();
+            self.consume();
+            let rhs = self.mul_or_div_expr()?;
 
-                    ast = AstNode::BinaryExpr {
-                        op_tkn: op,
-                        lhs: Box::new(ast),
-                        rhs: Box::new(rhs),
-                    };
-                }
-                _ => break,
-            }
+            ast = AstNode::BinaryExpr {
+                op_tkn: op,
+                lhs: Box::new(ast),
+                rhs: Box::new(rhs),
+            };
         }
 
         Ok(ast)
@@ -888,21 +967,16 @@ where
     fn mul_or_div_expr(&mut self) -> Result<AstNode, ParseErr> {
         let mut ast = self.mod_expr()?;
 
-        loop {
-            match self.curr_tkn.ty {
-                TokenTy::Star | TokenTy::Slash => {
-                    let op = self.curr_tkn.clone();
-                    self.consume();
-                    let rhs = self.mod_expr()?;
+        while let TokenTy::Star | TokenTy::Slash = self.curr_tkn.ty {
+            let op = self.curr_tkn.clone();
+            self.consume();
+            let rhs = self.mod_expr()?;
 
-                    ast = AstNode::BinaryExpr {
-                        op_tkn: op,
-                        lhs: Box::new(ast),
-                        rhs: Box::new(rhs),
-                    };
-                }
-                _ => break,
-            }
+            ast = AstNode::BinaryExpr {
+                op_tkn: op,
+                lhs: Box::new(ast),
+                rhs: Box::new(rhs),
+            };
         }
 
         Ok(ast)
@@ -914,21 +988,16 @@ where
     fn mod_expr(&mut self) -> Result<AstNode, ParseErr> {
         let mut ast = self.unary_expr()?;
 
-        loop {
-            match self.curr_tkn.ty {
-                TokenTy::Percent => {
-                    let op = self.curr_tkn.clone();
-                    self.consume();
-                    let rhs = self.unary_expr()?;
+        while let TokenTy::Percent = self.curr_tkn.ty {
+            let op = self.curr_tkn.clone();
+            self.consume();
+            let rhs = self.unary_expr()?;
 
-                    ast = AstNode::BinaryExpr {
-                        op_tkn: op,
-                        lhs: Box::new(ast),
-                        rhs: Box::new(rhs),
-                    };
-                }
-                _ => break,
-            }
+            ast = AstNode::BinaryExpr {
+                op_tkn: op,
+                lhs: Box::new(ast),
+                rhs: Box::new(rhs),
+            };
         }
 
         Ok(ast)
@@ -944,10 +1013,10 @@ where
                 self.consume();
                 let rhs = self.unary_expr()?;
 
-                return Ok(AstNode::UnaryExpr {
+                Ok(AstNode::UnaryExpr {
                     op_tkn: op,
                     rhs: Box::new(rhs),
-                });
+                })
             }
             _ => self.fn_call_expr(),
         }
@@ -960,8 +1029,8 @@ where
     ///
     /// funccall ::= [ "@" ] primary "(" [ paramlist ] ")" |
     ///              tableaccess |
-    ///		     arrayaccess |
-    ///		     primary ;
+    ///              arrayaccess |
+    ///              primary ;
     fn fn_call_expr(&mut self) -> Result<AstNode, ParseErr> {
         let ast = self.primary_expr()?;
         let mut params = Vec::new();
@@ -971,48 +1040,54 @@ where
         };
 
         match self.curr_tkn.ty {
-            TokenTy::LeftParen => {
+            TokenTy::LeftParen if ident_tkn.is_some() => {
                 self.expect(TokenTy::LeftParen)?;
                 // This is a function call, so pass true into param_list(), indicating we
                 // need to parse the params as possible expressions.
                 let params_list = self.param_list(true)?;
                 self.expect(TokenTy::RightParen)?;
-                match params_list {
-                    AstNode::FnParams {
-                        params: inner_params,
-                    } => {
-                        params = inner_params;
-                    }
-                    _ => (),
+                if let AstNode::FnParams {
+                    params: inner_params,
+                } = params_list
+                {
+                    params = inner_params;
                 };
 
                 let ident_name = ident_tkn.clone().unwrap().get_name();
                 let maybe_ast = self.sym_tab.retrieve(&ident_name);
-                let is_std = crate::reserved::is_std_lib_fn(&ident_name);
+                let std_arity = self.std_fns.arity(&ident_name);
+                let is_std = std_arity.is_some();
 
                 // If we can't find the function name in the ast, we assume it's undeclared,
                 // GIVEN that the symbol is not a standard lib function.
-                if maybe_ast.is_none() {
-                    if !is_std {
-                        let err = self.add_error(ParseErrTy::UndeclSym(ident_name.to_string()));
-                        self.consume();
-                        return Err(err);
-                    }
+                if maybe_ast.is_none() && !is_std {
+                    let err = self.undecl_sym_error(&ident_name);
+                    self.consume();
+                    return Err(err);
                 }
 
                 // Used to check if this may be a recursive call. If it is, we skip some
                 // further checks and assume the function will be defined properly in
                 // the symbol table after further parsing. If there are any errors they will get
                 // raised at runtime or possibly compile time.
-                let is_null = (!is_std)
-                    && match maybe_ast.clone().unwrap() {
-                        AstNode::Null => true,
-                        _ => false,
-                    };
+                let is_null = (!is_std) && matches!(maybe_ast.clone().unwrap(), AstNode::Null);
+
+                // If the name isn't a declared function, we're calling through a variable
+                // holding a function value. We can't know the param count of the value until
+                // runtime, so we skip checking it here.
+                let is_var = (!is_std)
+                    && !matches!(
+                        maybe_ast.clone().unwrap(),
+                        AstNode::FnDecl { .. } | AstNode::Null
+                    );
+
+                if is_std && params.len() != std_arity.unwrap() {
+                    let err = self
+                        .add_error(ParseErrTy::WrongFnParmCnt(std_arity.unwrap(), params.len()));
+                    return Err(err);
+                }
 
-                // HACK: if not standard, we match the params and error on the wrong param
-                // count. If it is standard lib, just skip for now
-                if !is_std && !is_null {
+                if !is_std && !is_null && !is_var {
                     let expected_param_len = match maybe_ast.unwrap() {
                         AstNode::FnDecl {
                             ident_tkn: _,
@@ -1037,17 +1112,25 @@ where
                 return Ok(AstNode::FnCallExpr {
                     fn_ident_tkn: ident_tkn.unwrap(),
                     fn_params: params,
-                    is_std: is_std,
+                    is_std,
+                    is_var,
                 });
             }
             TokenTy::Period => {
                 self.expect(TokenTy::Period)?;
-                self.should_check_sym_tab = false;
-                let val = self.expr()?;
-                self.should_check_sym_tab = true;
+
+                // Table keys are names, which don't need to be declared.
+                let maybe_key_tkn = self.match_ident();
+                if maybe_key_tkn.is_none() {
+                    let ty_str = self.curr_tkn.ty.to_string();
+                    return Err(self.add_error(ParseErrTy::ExpectedIdent(ty_str)));
+                }
+
                 return Ok(AstNode::TableAccess {
                     table_tkn: ident_tkn.unwrap(),
-                    index: Box::new(val),
+                    index: Box::new(AstNode::PrimaryExpr {
+                        tkn: maybe_key_tkn.unwrap(),
+                    }),
                 });
             }
             TokenTy::LeftBracket => {
@@ -1072,13 +1155,15 @@ where
     /// 3. Boolean literals
     /// 4. Identifiers
     /// 5. Parens, indicating a grouped expression.
+    /// 6. Anonymous functions.
     ///
     /// primary  ::= NUMBER |
     ///              STRING |
     ///              TRUE   |
     ///              FALSE  |
     ///              IDENT  |
-    ///              groupexpr ;
+    ///              groupexpr |
+    ///              fnexpr ;
     fn primary_expr(&mut self) -> Result<AstNode, ParseErr> {
         match self.curr_tkn.ty.clone() {
             TokenTy::Str(_) | TokenTy::Val(_) | TokenTy::True | TokenTy::False => {
@@ -1090,18 +1175,13 @@ where
                 ast
             }
             TokenTy::Ident(ref ident_name) => {
-                // Identifier. If required, check the symbol table to see if this identifier
-                // exists. It may not be necessary if we're declaring a table.
+                // Identifier. Check the symbol table to see if this identifier exists.
                 let ident_tkn = self.curr_tkn.clone();
 
-                if self.should_check_sym_tab {
-                    if !self.sym_exists(ident_name) {
-                        if !crate::reserved::is_std_lib_fn(ident_name) {
-                            let err = self.add_error(ParseErrTy::UndeclSym(ident_name.to_string()));
-                            self.consume();
-                            return Err(err);
-                        }
-                    }
+                if !self.sym_exists(ident_name) && self.std_fns.arity(ident_name).is_none() {
+                    let err = self.undecl_sym_error(ident_name);
+                    self.consume();
+                    return Err(err);
                 }
 
                 let ast = Ok(AstNode::PrimaryExpr {
@@ -1112,10 +1192,11 @@ where
                 ast
             }
             TokenTy::LeftParen => self.group_expr(),
+            TokenTy::Fn => self.fn_expr(),
             TokenTy::At => {
                 // Stdlib function call.
                 self.expect(TokenTy::At)?;
-                return self.fn_call_expr();
+                self.fn_call_expr()
             }
             _ => {
                 let ty_str = self.curr_tkn.ty.to_string();
@@ -1144,8 +1225,7 @@ where
             Ok(())
         } else {
             let ty_str = self.curr_tkn.ty.to_string();
-            let err_ty = ParseErrTy::TknMismatch(tknty.to_string(), ty_str);
-            Err(ParseErr::new(self.curr_tkn.line, self.curr_tkn.pos, err_ty))
+            Err(self.add_error(ParseErrTy::TknMismatch(tknty.to_string(), ty_str)))
         }
     }
 
@@ -1175,8 +1255,8 @@ where
     }
 
     /// Expects an identifier token to be passed in. If it is, returns a token that matches
-    /// the identifier that we've parsed. If it's not, we return None and add an error
-    /// to the error vec.
+    /// the identifier that we've parsed. If it's not, we return None, and the caller
+    /// reports the error.
     fn match_ident(&mut self) -> Option<Token> {
         match self.curr_tkn.ty {
             TokenTy::Ident(_) => {
@@ -1184,17 +1264,73 @@ where
                 self.consume();
                 tkn
             }
-            _ => {
-                let ty_str = self.curr_tkn.ty.to_string();
-                self.add_error(ParseErrTy::InvalidIdent(ty_str));
-                None
-            }
+            _ => None,
         }
     }
 
-    /// Advance to the next token, discarding the previously read token.
+    /// Advance to the next token, discarding the previously read token. Any errors the
+    /// lexer found while reading the token are added to the error vec.
     fn consume(&mut self) {
         self.curr_tkn = self.lexer.lex();
+        for err in self.lexer.take_errors() {
+            self.errors.push(ParseErr::from_lex(err));
+        }
+    }
+
+    /// Recovers from a parsing error by skipping tokens until a point where a new
+    /// declaration can begin: just past a ";", or at a "}", a keyword that starts a
+    /// declaration or statement, or the end of the file. A "{" found while skipping is
+    /// skipped along with everything up to its matching "}", so the body of a broken
+    /// function or loop isn't parsed as if it were outside of it. The error has already
+    /// been recorded, so skipping the rest of the broken declaration keeps it from causing
+    /// more errors. If the declaration failed without consuming anything since `start`,
+    /// the current token is skipped first so that parsing always makes progress.
+    fn synchronize(&mut self, start: Span) {
+        let stuck = self.curr_tkn.span() == start;
+        if stuck && self.curr_tkn.ty != TokenTy::Eof && self.curr_tkn.ty != TokenTy::LeftBrace {
+            self.consume();
+        }
+
+        loop {
+            match self.curr_tkn.ty {
+                TokenTy::Semicolon => {
+                    self.consume();
+                    break;
+                }
+                TokenTy::LeftBrace => {
+                    self.skip_braces();
+                    break;
+                }
+                TokenTy::RightBrace
+                | TokenTy::Eof
+                | TokenTy::Var
+                | TokenTy::Fn
+                | TokenTy::If
+                | TokenTy::For
+                | TokenTy::While
+                | TokenTy::Break
+                | TokenTy::Continue
+                | TokenTy::Return => break,
+                _ => self.consume(),
+            };
+        }
+    }
+
+    /// Skips a "{" and all tokens up to and including its matching "}".
+    fn skip_braces(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.curr_tkn.ty {
+                TokenTy::LeftBrace => depth += 1,
+                TokenTy::RightBrace => depth -= 1,
+                TokenTy::Eof => break,
+                _ => (),
+            };
+            self.consume();
+            if depth == 0 {
+                break;
+            }
+        }
     }
 
     /// Push a parsing error onto the error vector.
@@ -1211,6 +1347,26 @@ where
         err
     }
 
+    /// Reports an undeclared symbol at the current token. If the name looks like a
+    /// misspelled reserved word or a declared symbol, the error suggests it.
+    fn undecl_sym_error(&mut self, name: &str) -> ParseErr {
+        let names = self.sym_tab.names();
+        let reserved = get_reserved_words();
+        let candidates = names.iter().chain(reserved.keys()).map(|n| n.as_str());
+
+        let err = ParseErr::new(
+            self.curr_tkn.line,
+            self.curr_tkn.pos,
+            ParseErrTy::UndeclSym(name.to_string()),
+        );
+        let err = match diag::closest(name, candidates) {
+            Some(c) => err.with_help(format!("did you mean '{}'?", c)),
+            None => err,
+        };
+        self.errors.push(err.clone());
+        err
+    }
+
     fn sym_exists(&self, key: &str) -> bool {
         self.sym_tab.contains(key)
     }
@@ -1,6 +1,6 @@
 use crate::token::TokenTy;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 pub fn get_reserved_words() -> HashMap<String, TokenTy> {
     [
@@ -13,6 +13,9 @@ pub fn get_reserved_words() -> HashMap<String, TokenTy> {
         (String::from("elif"), TokenTy::Elif),
         (String::from("else"), TokenTy::Else),
         (String::from("for"), TokenTy::For),
+        (String::from("while"), TokenTy::While),
+        (String::from("break"), TokenTy::Break),
+        (String::from("continue"), TokenTy::Continue),
         (String::from("in"), TokenTy::In),
         (String::from("true"), TokenTy::True),
         (String::from("false"), TokenTy::False),
@@ -22,19 +25,7 @@ pub fn get_reserved_words() -> HashMap<String, TokenTy> {
     .collect()
 }
 
-pub fn get_reserved_fns() -> HashSet<String> {
-    [(String::from("print")), (String::from("range"))]
-        .iter()
-        .cloned()
-        .collect()
-}
-
 pub fn is_reserved_word(word: &str) -> bool {
     let words = get_reserved_words();
     words.contains_key(word)
 }
-
-pub fn is_std_lib_fn(word: &str) -> bool {
-    let fns = get_reserved_fns();
-    fns.contains(word)
-}
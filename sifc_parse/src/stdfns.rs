@@ -0,0 +1,9 @@
+/// StdFns is implemented by registries of std library functions. These are the functions
+/// called with '@name(...)', and include any functions registered by a host program. The
+/// parser uses this to check that std calls name a known function with the right number
+/// of params, so it should be the same registry the vm calls into.
+pub trait StdFns {
+    /// Returns the number of params the named function expects, or None if there is no
+    /// function with that name.
+    fn arity(&self, name: &str) -> Option<usize>;
+}
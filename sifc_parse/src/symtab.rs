@@ -9,7 +9,7 @@ pub enum SymTy {
 /// Scope is a map of ident names to their declaration AST.
 type Scope = HashMap<String, AstNode>;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SymTab {
     /// Current scope level in the symbol table. 0 is the global scope,
     /// and when the table is created we allocate a new hashmap to hold that scope.
@@ -21,6 +21,12 @@ pub struct SymTab {
     tab: Vec<Scope>,
 }
 
+impl Default for SymTab {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SymTab {
     pub fn new() -> SymTab {
         SymTab {
@@ -33,14 +39,14 @@ impl SymTab {
     /// This should be called at the entry of each block in order to properly
     /// block scope statements.
     pub fn init_scope(&mut self) {
-        self.curr_lvl = self.curr_lvl + 1;
+        self.curr_lvl += 1;
         self.tab.push(HashMap::new());
     }
 
     /// Close the current scope block, moving back up into a higher
     /// (previous) scope.
     pub fn close_scope(&mut self) {
-        self.curr_lvl = self.curr_lvl - 1;
+        self.curr_lvl -= 1;
     }
 
     /// True if the current scope is the global scope, false otherwise.
@@ -79,12 +85,20 @@ impl SymTab {
                 }
                 None => (),
             };
-            curr = curr - 1;
+            curr -= 1;
         }
 
         None
     }
 
+    /// Returns the names of every symbol visible from the current scope.
+    pub fn names(&self) -> Vec<String> {
+        self.tab[..self.curr_lvl + 1]
+            .iter()
+            .flat_map(|scope| scope.keys().cloned())
+            .collect()
+    }
+
     pub fn contains(&self, key: &str) -> bool {
         self.retrieve(key).is_some()
     }
@@ -1,3 +1,4 @@
+use sifc_err::span::Span;
 use std::fmt;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -50,6 +51,9 @@ pub enum TokenTy {
     Elif,
     Else,
     For,
+    While,
+    Break,
+    Continue,
     In,
     True,
     False,
@@ -62,59 +66,51 @@ pub enum TokenTy {
 impl TokenTy {
     /// True if the TokenTy is a binary operator.
     pub fn is_bin_op(&self) -> bool {
-        match self {
+        matches!(
+            self,
             TokenTy::Plus
-            | TokenTy::Minus
-            | TokenTy::Star
-            | TokenTy::Slash
-            | TokenTy::Percent
-            | TokenTy::EqEq
-            | TokenTy::BangEq
-            | TokenTy::Gt
-            | TokenTy::Lt
-            | TokenTy::GtEq
-            | TokenTy::LtEq => true,
-            _ => false,
-        }
+                | TokenTy::Minus
+                | TokenTy::Star
+                | TokenTy::Slash
+                | TokenTy::Percent
+                | TokenTy::EqEq
+                | TokenTy::BangEq
+                | TokenTy::Gt
+                | TokenTy::Lt
+                | TokenTy::GtEq
+                | TokenTy::LtEq
+        )
     }
 
     /// True if the TokenTy is an operator that is expected to work on
     /// numerical values.
     pub fn is_numerical_op(&self) -> bool {
-        match self {
-            TokenTy::Plus | TokenTy::Minus | TokenTy::Star | TokenTy::Slash | TokenTy::Percent => {
-                true
-            }
-            _ => false,
-        }
+        matches!(
+            self,
+            TokenTy::Plus | TokenTy::Minus | TokenTy::Star | TokenTy::Slash | TokenTy::Percent
+        )
     }
 
     /// True if the TokenTy is a numerical comparison operator.
     pub fn is_comp_op(&self) -> bool {
-        match self {
+        matches!(
+            self,
             TokenTy::EqEq
-            | TokenTy::BangEq
-            | TokenTy::Gt
-            | TokenTy::Lt
-            | TokenTy::GtEq
-            | TokenTy::LtEq => true,
-            _ => false,
-        }
+                | TokenTy::BangEq
+                | TokenTy::Gt
+                | TokenTy::Lt
+                | TokenTy::GtEq
+                | TokenTy::LtEq
+        )
     }
 
     /// True if the TokenTy is a logical operator.
     pub fn is_logical_op(&self) -> bool {
-        match self {
-            TokenTy::AmpAmp | TokenTy::PipePipe => true,
-            _ => false,
-        }
+        matches!(self, TokenTy::AmpAmp | TokenTy::PipePipe)
     }
 
     pub fn is_unary_op(&self) -> bool {
-        match self {
-            TokenTy::Minus | TokenTy::Bang => true,
-            _ => false,
-        }
+        matches!(self, TokenTy::Minus | TokenTy::Bang)
     }
 }
 
@@ -153,8 +149,8 @@ impl fmt::Display for TokenTy {
             TokenTy::DoubleLeftBracket => "[[".to_string(),
             TokenTy::DoubleRightBracket => "]]".to_string(),
 
-            TokenTy::Ident(name) => format!("{}", name),
-            TokenTy::Str(name) => format!("{}", name),
+            TokenTy::Ident(name) => name.to_string(),
+            TokenTy::Str(name) => name.to_string(),
             TokenTy::Val(val) => format!("{}", val),
 
             TokenTy::Var => "var".to_string(),
@@ -166,6 +162,9 @@ impl fmt::Display for TokenTy {
             TokenTy::Elif => "elif".to_string(),
             TokenTy::Else => "else".to_string(),
             TokenTy::For => "for".to_string(),
+            TokenTy::While => "while".to_string(),
+            TokenTy::Break => "break".to_string(),
+            TokenTy::Continue => "continue".to_string(),
             TokenTy::In => "in".to_string(),
             TokenTy::True => "true".to_string(),
             TokenTy::False => "false".to_string(),
@@ -187,11 +186,13 @@ pub struct Token {
 
 impl Token {
     pub fn new(ty: TokenTy, line: usize, pos: usize) -> Token {
-        Token {
-            ty: ty,
-            line: line,
-            pos: pos,
-        }
+        Token { ty, line, pos }
+    }
+
+    /// Returns the position of the token in the source. Token positions count columns from
+    /// 0, while spans count them from 1.
+    pub fn span(&self) -> Span {
+        Span::new(self.line, self.pos + 1)
     }
 
     pub fn get_name(&self) -> String {
@@ -210,9 +211,6 @@ impl Token {
     }
 
     pub fn is_ident(&self) -> bool {
-        match self.ty {
-            TokenTy::Ident(_) => true,
-            _ => false,
-        }
+        matches!(self.ty, TokenTy::Ident(_))
     }
 }
@@ -1,3 +1,4 @@
+use sifc_err::lex_err::LexErrTy;
 use sifc_parse::{lex::Lexer, token::TokenTy};
 use std::fs::File;
 
@@ -5,7 +6,7 @@ use std::fs::File;
 fn test_lex_single_char() {
     let path = "./tests/lex_input/single_char";
 
-    let infile = File::open(&path).unwrap();
+    let infile = File::open(path).unwrap();
     let mut lexer = Lexer::new(infile);
 
     let mut nexttkn = lexer.lex();
@@ -76,7 +77,7 @@ fn test_lex_single_char() {
 fn test_lex_multi_char() {
     let path = "./tests/lex_input/multi_char";
 
-    let infile = File::open(&path).unwrap();
+    let infile = File::open(path).unwrap();
     let mut lexer = Lexer::new(infile);
 
     let mut nexttkn = lexer.lex();
@@ -117,7 +118,7 @@ fn test_lex_multi_char() {
 fn test_lex_reserved_words() {
     let path = "./tests/lex_input/reserved_words";
 
-    let infile = File::open(&path).unwrap();
+    let infile = File::open(path).unwrap();
     let mut lexer = Lexer::new(infile);
 
     let mut nexttkn = lexer.lex();
@@ -155,4 +156,184 @@ fn test_lex_reserved_words() {
 
     nexttkn = lexer.lex();
     assert_eq!(nexttkn.ty, TokenTy::False);
+
+    nexttkn = lexer.lex();
+    assert_eq!(nexttkn.ty, TokenTy::While);
+
+    nexttkn = lexer.lex();
+    assert_eq!(nexttkn.ty, TokenTy::Break);
+
+    nexttkn = lexer.lex();
+    assert_eq!(nexttkn.ty, TokenTy::Continue);
+}
+
+#[test]
+fn test_lex_unknown_char() {
+    let mut lexer = Lexer::new("var x = $1;".as_bytes());
+
+    assert_eq!(lexer.lex().ty, TokenTy::Var);
+    assert_eq!(lexer.lex().ty, TokenTy::Ident(String::from("x")));
+    assert_eq!(lexer.lex().ty, TokenTy::Eq);
+    assert_eq!(lexer.take_errors().len(), 0);
+
+    // The unknown character is recorded and skipped.
+    assert_eq!(lexer.lex().ty, TokenTy::Val(1.0));
+    let errors = lexer.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].line, errors[0].pos), (1, 8));
+    match errors[0].ty {
+        LexErrTy::UnknownChar(ch) => assert_eq!(ch, '$'),
+        _ => panic!("expected an unknown char error"),
+    };
+
+    assert_eq!(lexer.lex().ty, TokenTy::Semicolon);
+    assert_eq!(lexer.lex().ty, TokenTy::Eof);
+    assert_eq!(lexer.take_errors().len(), 0);
+}
+
+#[test]
+fn test_lex_unterminated_str() {
+    let mut lexer = Lexer::new("var x = 1;\nvar s = \"abc;\n".as_bytes());
+    for _ in 0..8 {
+        lexer.lex();
+    }
+
+    assert_eq!(lexer.lex().ty, TokenTy::Eof);
+    let errors = lexer.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].line, errors[0].pos), (2, 8));
+}
+
+#[test]
+fn test_lex_str_escapes() {
+    let mut lexer = Lexer::new(r#""a\n\t\\\"\u{1F600}\u{e9}""#.as_bytes());
+    assert_eq!(
+        lexer.lex().ty,
+        TokenTy::Str(String::from("a\n\t\\\"\u{1F600}\u{e9}"))
+    );
+    assert_eq!(lexer.lex().ty, TokenTy::Eof);
+    assert_eq!(lexer.take_errors().len(), 0);
+}
+
+#[test]
+fn test_lex_invalid_escapes() {
+    let mut lexer = Lexer::new(r#""a\qb\u{110000}c\u41" 1"#.as_bytes());
+    assert_eq!(lexer.lex().ty, TokenTy::Str(String::from("abc41")));
+    assert_eq!(lexer.lex().ty, TokenTy::Val(1.0));
+
+    let errors = lexer.take_errors();
+    assert_eq!(errors.len(), 3);
+    match &errors[0].ty {
+        LexErrTy::InvalidEscape('q') => assert_eq!(errors[0].pos, 2),
+        ty => panic!("unexpected error {:?}", ty),
+    };
+    match &errors[1].ty {
+        LexErrTy::InvalidUnicodeEscape(text) => assert_eq!(text, "u{110000}"),
+        ty => panic!("unexpected error {:?}", ty),
+    };
+    match &errors[2].ty {
+        LexErrTy::InvalidUnicodeEscape(text) => assert_eq!(text, "u"),
+        ty => panic!("unexpected error {:?}", ty),
+    };
+}
+
+#[test]
+fn test_lex_raw_str() {
+    let src = "r\"C:\\dir\\n\" r#\"say \"hi\"\nok\"# x";
+    let mut lexer = Lexer::new(src.as_bytes());
+    assert_eq!(lexer.lex().ty, TokenTy::Str(String::from("C:\\dir\\n")));
+
+    let tkn = lexer.lex();
+    assert_eq!(tkn.ty, TokenTy::Str(String::from("say \"hi\"\nok")));
+    assert_eq!((tkn.line, tkn.pos), (1, 12));
+    assert_eq!(lexer.lex().ty, TokenTy::Ident(String::from("x")));
+    assert_eq!(lexer.take_errors().len(), 0);
+}
+
+#[test]
+fn test_lex_numbers() {
+    let src = "1_000 2.5 1e3 1.5E-2 2e+1 0xff 0XFF 0b1010 0o17 0x_1";
+    let mut lexer = Lexer::new(src.as_bytes());
+    let expected = [1000.0, 2.5, 1000.0, 0.015, 20.0, 255.0];
+    for val in expected.iter() {
+        assert_eq!(lexer.lex().ty, TokenTy::Val(*val));
+    }
+
+    // Prefixes are lowercase, so 0XFF is the number 0 followed by an identifier.
+    assert_eq!(lexer.lex().ty, TokenTy::Val(0.0));
+    assert_eq!(lexer.lex().ty, TokenTy::Ident(String::from("XFF")));
+
+    assert_eq!(lexer.lex().ty, TokenTy::Val(10.0));
+    assert_eq!(lexer.lex().ty, TokenTy::Val(15.0));
+    assert_eq!(lexer.take_errors().len(), 0);
+
+    // Separators must come between digits.
+    assert_eq!(lexer.lex().ty, TokenTy::Val(0.0));
+    let errors = lexer.take_errors();
+    assert_eq!(errors.len(), 1);
+    match errors[0].ty {
+        LexErrTy::InvalidSeparator => assert_eq!(errors[0].pos, 50),
+        ref ty => panic!("unexpected error {:?}", ty),
+    };
+}
+
+#[test]
+fn test_lex_malformed_numbers() {
+    let mut lexer = Lexer::new("0b102 0x 1e+ 1__0 0xffffffffffffffffff;".as_bytes());
+    for _ in 0..5 {
+        assert_eq!(lexer.lex().ty, TokenTy::Val(0.0));
+    }
+    assert_eq!(lexer.lex().ty, TokenTy::Semicolon);
+
+    let errors = lexer.take_errors();
+    let codes: Vec<&str> = errors.iter().map(|e| e.code()).collect();
+    assert_eq!(codes, vec!["E0106", "E0105", "E0105", "E0107", "E0108"]);
+    match errors[0].ty {
+        LexErrTy::InvalidDigit('2', 2) => assert_eq!(errors[0].pos, 4),
+        ref ty => panic!("unexpected error {:?}", ty),
+    };
+    match errors[2].ty {
+        LexErrTy::MissingDigits(ref lit) => assert_eq!(lit, "1e+"),
+        ref ty => panic!("unexpected error {:?}", ty),
+    };
+}
+
+#[test]
+fn test_lex_block_comments() {
+    let src = "var /* one\n/* nested */ still a comment\n*/ x = 1; /**/ y";
+    let mut lexer = Lexer::new(src.as_bytes());
+    assert_eq!(lexer.lex().ty, TokenTy::Var);
+
+    let tkn = lexer.lex();
+    assert_eq!(tkn.ty, TokenTy::Ident(String::from("x")));
+    assert_eq!((tkn.line, tkn.pos), (3, 3));
+    assert_eq!(lexer.lex().ty, TokenTy::Eq);
+    assert_eq!(lexer.lex().ty, TokenTy::Val(1.0));
+    assert_eq!(lexer.lex().ty, TokenTy::Semicolon);
+    assert_eq!(lexer.lex().ty, TokenTy::Ident(String::from("y")));
+    assert_eq!(lexer.take_errors().len(), 0);
+}
+
+#[test]
+fn test_lex_unterminated_comment() {
+    let mut lexer = Lexer::new("x /* a /* b */\ny;".as_bytes());
+    assert_eq!(lexer.lex().ty, TokenTy::Ident(String::from("x")));
+    assert_eq!(lexer.lex().ty, TokenTy::Eof);
+
+    let errors = lexer.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].line, errors[0].pos), (1, 2));
+    match errors[0].ty {
+        LexErrTy::UnterminatedComment => (),
+        ref ty => panic!("unexpected error {:?}", ty),
+    };
+}
+
+#[test]
+fn test_lex_underscore_idents() {
+    let mut lexer = Lexer::new("snake_case _private __x2 _ var_1".as_bytes());
+    for name in ["snake_case", "_private", "__x2", "_", "var_1"].iter() {
+        assert_eq!(lexer.lex().ty, TokenTy::Ident(name.to_string()));
+    }
+    assert_eq!(lexer.lex().ty, TokenTy::Eof);
 }
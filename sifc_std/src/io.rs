@@ -0,0 +1,148 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+/// Io holds the handles that the std library and the vm read from and write to. By
+/// default these are stdin, stdout and stderr, but hosts can replace any of them with
+/// their own readers and writers, such as a Buffer, to capture what a program prints or
+/// to feed it input.
+///
+/// Handles are shared, so cloning an Io gives a copy that reads from and writes to the
+/// same handles.
+#[derive(Clone)]
+pub struct Io {
+    /// Handle that @print and traced instrs are written to.
+    out: Rc<RefCell<dyn Write>>,
+
+    /// Handle that warnings from the vm are written to.
+    err: Rc<RefCell<dyn Write>>,
+
+    /// Handle that @readln reads from, or None to read from stdin. Stdin is read through
+    /// the process wide handle rather than a buffered reader, so that the repl and the
+    /// debugger can keep reading commands from it after a program has read input.
+    input: Option<Rc<RefCell<dyn BufRead>>>,
+}
+
+impl Io {
+    /// Returns an Io that uses stdin, stdout and stderr.
+    pub fn stdio() -> Io {
+        Io {
+            out: Rc::new(RefCell::new(io::stdout())),
+            err: Rc::new(RefCell::new(io::stderr())),
+            input: None,
+        }
+    }
+
+    /// Replaces the output handle.
+    pub fn with_out<W: Write + 'static>(self, out: W) -> Io {
+        Io {
+            out: Rc::new(RefCell::new(out)),
+            ..self
+        }
+    }
+
+    /// Replaces the error handle.
+    pub fn with_err<W: Write + 'static>(self, err: W) -> Io {
+        Io {
+            err: Rc::new(RefCell::new(err)),
+            ..self
+        }
+    }
+
+    /// Replaces the input handle. Any BufRead can be used, such as a byte slice or a
+    /// Cursor for input held in memory.
+    pub fn with_input<R: BufRead + 'static>(self, input: R) -> Io {
+        Io {
+            input: Some(Rc::new(RefCell::new(input))),
+            ..self
+        }
+    }
+
+    /// Writes a line to the output handle.
+    pub fn println(&self, line: &str) -> io::Result<()> {
+        writeln!(self.out.borrow_mut(), "{}", line)
+    }
+
+    /// Writes a line to the error handle.
+    pub fn eprintln(&self, line: &str) -> io::Result<()> {
+        writeln!(self.err.borrow_mut(), "{}", line)
+    }
+
+    /// Reads a line from the input handle, without its line ending. Returns None once the
+    /// input has no more lines.
+    pub fn read_line(&self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        let read = match &self.input {
+            Some(input) => input.borrow_mut().read_line(&mut line)?,
+            None => io::stdin().read_line(&mut line)?,
+        };
+
+        if read == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    /// Flushes the output and error handles.
+    pub fn flush(&self) -> io::Result<()> {
+        self.out.borrow_mut().flush()?;
+        self.err.borrow_mut().flush()
+    }
+}
+
+impl Default for Io {
+    fn default() -> Io {
+        Io::stdio()
+    }
+}
+
+impl fmt::Debug for Io {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Io").finish_non_exhaustive()
+    }
+}
+
+/// Buffer is an in-memory handle that can be written to in place of stdout or stderr.
+/// Clones share the same bytes, so one clone can be given to an Io and another used to
+/// read what was written.
+#[derive(Clone, Debug, Default)]
+pub struct Buffer {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Buffer {
+    pub fn new() -> Buffer {
+        Buffer::default()
+    }
+
+    /// Returns everything written to the buffer so far. Bytes that aren't valid utf8 are
+    /// replaced, like in String::from_utf8_lossy.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.borrow()).into_owned()
+    }
+
+    /// Removes everything written to the buffer.
+    pub fn clear(&self) {
+        self.bytes.borrow_mut().clear();
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,58 +1,160 @@
+pub mod io;
+
+use io::Io;
 use sifc_bytecode::sifv::SifVal;
+use sifc_err::runtime_err::{Limit, RuntimeErr, RuntimeErrTy};
+use sifc_parse::stdfns::StdFns;
 use std::collections::HashMap;
 
+/// Signature of every function in the std library. Params are passed in the order
+/// they appear in the call, along with the context of the vm making the call.
+type StdFnPtr = Box<dyn Fn(Vec<SifVal>, &Context) -> Result<SifVal, RuntimeErr>>;
+
+/// Context is what the vm making a call gives to the std library functions.
+pub struct Context<'a> {
+    /// Handles that functions print to and read input from.
+    pub io: &'a Io,
+
+    /// Maximum length of the arrays and tables that functions can create, if the vm
+    /// limits it. Functions check this before building a collection, so that a limited
+    /// program can't make them allocate more than the limit.
+    pub max_collection_len: Option<usize>,
+}
+
 /// Std contains the sif std library. This is a small amount of
 /// core functions contained in a single map of strings (function names)
 /// to function pointers. The implementations are not contained
 /// within this struct, but it is responsible for loading the library
 /// into memory so a vm can use it.
-pub struct Std<'s> {
-    lib: HashMap<String, &'s fn(Vec<SifVal>) -> SifVal>,
+///
+/// Host programs can add their own functions to the library with `register()`. These
+/// are called from sif in the same way as the core functions, ie. `@name(params)`.
+pub struct Std {
+    lib: HashMap<String, StdFn>,
+}
+
+/// StdFn is a single function in the std library, along with the
+/// number of params it must be called with.
+struct StdFn {
+    arity: usize,
+    f: StdFnPtr,
+}
+
+impl Default for Std {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<'s> Std<'s> {
-    pub fn new() -> Std<'s> {
-        Std { lib: load() }
+impl Std {
+    pub fn new() -> Std {
+        let mut std = Std {
+            lib: HashMap::new(),
+        };
+        std.insert("print", 1, std_print);
+        std.insert("range", 2, std_range);
+        std.insert("readln", 0, std_readln);
+        std
+    }
+
+    /// Adds a function to the library, replacing any existing function with the same name.
+    /// The parser checks that calls to the function pass exactly arity params, and
+    /// any error returned from the function stops the vm like other runtime errors.
+    pub fn register<F>(&mut self, name: &str, arity: usize, f: F)
+    where
+        F: Fn(Vec<SifVal>) -> Result<SifVal, RuntimeErr> + 'static,
+    {
+        self.insert(name, arity, move |params, _| f(params));
     }
 
-    pub fn call(&self, name: &String, params: Vec<SifVal>) -> SifVal {
-        let to_call = self.lib.get(name);
-        let f = to_call.unwrap();
-        f(params)
+    fn insert<F>(&mut self, name: &str, arity: usize, f: F)
+    where
+        F: Fn(Vec<SifVal>, &Context) -> Result<SifVal, RuntimeErr> + 'static,
+    {
+        let stdfn = StdFn {
+            arity,
+            f: Box::new(f),
+        };
+        self.lib.insert(name.to_string(), stdfn);
+    }
+
+    /// Calls the named function with params. Functions that print or read input use the
+    /// handles in the context. If the function doesn't have a return value, SifVal::Null
+    /// is returned.
+    pub fn call(
+        &self,
+        name: &str,
+        params: Vec<SifVal>,
+        ctx: &Context,
+    ) -> Result<SifVal, RuntimeErr> {
+        match self.lib.get(name) {
+            Some(stdfn) => (stdfn.f)(params, ctx),
+            None => Err(RuntimeErr::new(RuntimeErrTy::InvalidFnSym(
+                name.to_string(),
+            ))),
+        }
+    }
+
+    /// Returns the number of params the named function expects, or None if
+    /// there is no function in the library with that name.
+    pub fn arity(&self, name: &str) -> Option<usize> {
+        self.lib.get(name).map(|stdfn| stdfn.arity)
     }
 }
 
-fn load<'a>() -> HashMap<String, &'a fn(Vec<SifVal>) -> SifVal> {
-    let mut std_map = HashMap::new();
-    std_map.insert(
-        String::from("print"),
-        &(std_print as fn(Vec<SifVal>) -> SifVal),
-    );
-    std_map.insert(
-        String::from("range"),
-        &(std_range as fn(Vec<SifVal>) -> SifVal),
-    );
-    std_map
+impl StdFns for Std {
+    fn arity(&self, name: &str) -> Option<usize> {
+        Std::arity(self, name)
+    }
 }
 
 /// Implements the print function inside the std lib. This uses the
-/// fmt::Display formatter implemented by SifVal.
+/// fmt::Display formatter implemented by SifVal, and writes to the output handle.
 /// @print(value)
-fn std_print(params: Vec<SifVal>) -> SifVal {
-    // TODO: How to handle wrong params here? Do we print any amount?
-    assert!(params.len() == 1);
+fn std_print(params: Vec<SifVal>, ctx: &Context) -> Result<SifVal, RuntimeErr> {
     let val = &params[0];
-    println!("{:#}", val);
-    SifVal::Null
+    match ctx.io.println(&format!("{:#}", val)) {
+        Ok(()) => Ok(SifVal::Null),
+        Err(e) => Err(RuntimeErr::new(RuntimeErrTy::IoErr(e.to_string()))),
+    }
 }
 
-fn std_range(params: Vec<SifVal>) -> SifVal {
-    let start = params[1].extract_num() as i64;
-    let end = params[0].extract_num() as i64;
-    let mut range = Vec::new();
+/// Implements the readln function inside the std lib. This returns the next line of the
+/// input handle as a string, without its line ending, or null at the end of the input.
+/// @readln()
+fn std_readln(_params: Vec<SifVal>, ctx: &Context) -> Result<SifVal, RuntimeErr> {
+    match ctx.io.read_line() {
+        Ok(Some(line)) => Ok(SifVal::Str(line)),
+        Ok(None) => Ok(SifVal::Null),
+        Err(e) => Err(RuntimeErr::new(RuntimeErrTy::IoErr(e.to_string()))),
+    }
+}
 
-    for i in start..end + 1 {
+/// Implements the range function inside the std lib. This returns an array
+/// containing each number from start to end, inclusive. Bounds are truncated to
+/// integers, and the length of the range is checked against the collection limit
+/// before the array is built.
+/// @range(start, end)
+fn std_range(params: Vec<SifVal>, ctx: &Context) -> Result<SifVal, RuntimeErr> {
+    // Float to int casts saturate, so bounds outside of i64 are clamped to it.
+    let (start, end) = match (&params[0], &params[1]) {
+        (SifVal::Num(s), SifVal::Num(e)) => (*s as i64, *e as i64),
+        _ => return Err(RuntimeErr::new(RuntimeErrTy::TyMismatch)),
+    };
+
+    let len = (end as i128 - start as i128 + 1).max(0);
+    match ctx.max_collection_len {
+        Some(max) if len > max as i128 => {
+            return Err(RuntimeErr::new(RuntimeErrTy::LimitExceeded(
+                Limit::CollectionLen(max),
+            )))
+        }
+        _ => (),
+    };
+
+    let mut range = Vec::new();
+    for i in start..=end {
         range.push(SifVal::Num(i as f64));
     }
-    SifVal::Arr(range)
+    Ok(SifVal::new_arr(range))
 }
@@ -1,6 +1,7 @@
 use sifc_analysis::analyzer::Analyzer;
 use sifc_bytecode::compiler::Compiler;
 use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::Std;
 
 #[test]
 fn build_ssa_if_stmt() {
@@ -19,10 +20,11 @@ var z = x + y;
 
     let mut symtab = SymTab::new();
     let mut lex = Lexer::new(program.as_bytes());
-    let mut parser = Parser::new(&mut lex, &mut symtab);
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
 
     let parse_result = parser.parse();
-    assert_eq!(parse_result.has_err, false);
+    assert!(!parse_result.has_err);
 
     let ast = parse_result.ast.unwrap();
     let mut compiler = Compiler::new(&ast);
@@ -110,10 +112,11 @@ for x,i in @range(0,3) {
 ";
     let mut symtab = SymTab::new();
     let mut lex = Lexer::new(program.as_bytes());
-    let mut parser = Parser::new(&mut lex, &mut symtab);
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
 
     let parse_result = parser.parse();
-    assert_eq!(parse_result.has_err, false);
+    assert!(!parse_result.has_err);
 
     let ast = parse_result.ast.unwrap();
     let mut compiler = Compiler::new(&ast);
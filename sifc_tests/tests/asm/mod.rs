@@ -0,0 +1,163 @@
+use sifc_bytecode::{asm, compiler::Compiler, printer, sifv::SifVal};
+use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::{io::Io, Std};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    vm::VM,
+};
+use std::fs::File;
+
+// Inputs are shared with the exec_pass tests, since the printed form of every program
+// there should assemble back into the same program.
+const INPUT_PATH: &str = "./tests/exec_pass/inputs";
+
+macro_rules! asm_roundtrip_test {
+    ($test_name:ident) => {
+        #[test]
+        fn $test_name() {
+            let path = format!("{}/{}.sif", INPUT_PATH, stringify!($test_name));
+
+            let infile = File::open(path).unwrap();
+            let mut symtab = SymTab::new();
+            let mut lex = Lexer::new(infile);
+            let stdlib = Std::new();
+            let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+            let parse_result = parser.parse();
+            assert_eq!(parse_result.has_err, false);
+
+            let ast = parse_result.ast.unwrap();
+            let mut compiler = Compiler::new(&ast);
+            let compile_result = compiler.compile();
+            assert!(compile_result.err.is_none());
+
+            // Print the program in the same form as --emit-ir, and assemble it back.
+            let text = format!(
+                "{}{}",
+                printer::decls_str(compile_result.decls.clone()),
+                printer::code_str(compile_result.code.clone())
+            );
+            let asm_result = match asm::assemble(&text) {
+                Ok(result) => result,
+                Err(e) => panic!("{:?}", e),
+            };
+
+            assert_eq!(asm_result.program, compile_result.program);
+            assert_eq!(asm_result.decls, compile_result.decls);
+            assert_eq!(asm_result.code, compile_result.code);
+            assert_eq!(asm_result.code_start, compile_result.code_start);
+            assert_eq!(asm_result.jumptab, compile_result.jumptab);
+            assert_eq!(asm_result.fntab, compile_result.fntab);
+        }
+    };
+}
+
+// Expects a program in assembly text as input, returns a vm after running and asserting
+// the run completes successfully.
+macro_rules! asm_run {
+    ($input:expr) => {{
+        let asm_result = match asm::assemble($input) {
+            Ok(result) => result,
+            Err(e) => panic!("{:?}", e),
+        };
+
+        let conf = VMConfig {
+            trace: false,
+            initial_heap_size: 32,
+            initial_dreg_count: 64,
+            limits: Limits::default(),
+            io: Io::stdio(),
+        };
+
+        let mut vm = VM::init(
+            asm_result.program,
+            asm_result.code_start,
+            asm_result.jumptab,
+            asm_result.fntab,
+            conf,
+        );
+        let vm_result = vm.run();
+        assert!(vm_result.is_ok());
+
+        vm
+    }};
+}
+
+asm_roundtrip_test!(var_decl);
+asm_roundtrip_test!(array_decl);
+asm_roundtrip_test!(array_mut_assign);
+asm_roundtrip_test!(exprs);
+asm_roundtrip_test!(fn_call);
+asm_roundtrip_test!(fn_w_ret_stmt);
+asm_roundtrip_test!(for_stmt);
+asm_roundtrip_test!(if_stmt);
+asm_roundtrip_test!(table_decl);
+asm_roundtrip_test!(std_lib_calls);
+asm_roundtrip_test!(recursion);
+asm_roundtrip_test!(while_stmt);
+asm_roundtrip_test!(closures);
+
+#[test]
+fn asm_bin_op() {
+    let vm = asm_run!(
+        "
+ldc 2 r0
+ldc 3 r1
+mul r0 r1 r2
+str r2 x
+"
+    );
+    assert_eq!(vm.inspect_dreg(2), Some(SifVal::Num(6.0)));
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(6.0)));
+}
+
+#[test]
+fn asm_loop() {
+    let vm = asm_run!(
+        "
+lbl0:
+    stc 0 i
+    ldc 0 r0
+    ldc 5 r1
+lbl1:
+    incrr r0
+    lt r0 r1 r2
+    jmpt r2 lbl1
+lbl2:
+    str r0 i
+"
+    );
+    assert_eq!(vm.inspect_heap("i"), Some(&SifVal::Num(5.0)));
+}
+
+#[test]
+fn asm_fn_call() {
+    let vm = asm_run!(
+        r#"
+SECTION_ decls.
+lbl0:
+fn @double ["n"]
+    fstpop r0
+    add r0 r0 r1
+    fstpush r1
+    ret
+SECTION_ code.
+lbl1:
+    ldc 21 r2
+    fstpush r2
+    call double 1
+    fstpop r3
+    str r3 x
+"#
+    );
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(42.0)));
+}
+
+#[test]
+fn asm_str_const() {
+    let vm = asm_run!(r#"stc "a; b" s"#);
+    assert_eq!(
+        vm.inspect_heap("s"),
+        Some(&SifVal::Str(String::from("a; b")))
+    );
+}
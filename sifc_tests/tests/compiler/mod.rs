@@ -1,5 +1,12 @@
-use sifc_bytecode::{compiler::Compiler, instr::Instr};
+use sifc_bytecode::{
+    compiler::{CompileResult, Compiler},
+    instr::Instr,
+    opc::Op,
+    optimize::bco::BytecodeOptimizer,
+};
+use sifc_err::{compile_err::CompileErrTy, span::Span};
 use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::Std;
 use std::fs::File;
 
 const INPUT_PATH: &str = "./tests/compiler/inputs";
@@ -16,6 +23,34 @@ fn prog_to_string(prog: Vec<Instr>) -> String {
     result
 }
 
+/// Compiles source code, ensuring there are no parse errors. Compile errors are returned
+/// in the result.
+fn compile_parsed(src: &str) -> CompileResult {
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new(src.as_bytes());
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+    let parse_result = parser.parse();
+    assert!(!parse_result.has_err);
+
+    let ast = parse_result.ast.unwrap();
+    let mut compiler = Compiler::new(&ast);
+    compiler.compile()
+}
+
+/// Compiles source code, ensuring there are no errors.
+fn compile_src(src: &str) -> CompileResult {
+    let compile_result = compile_parsed(src);
+    assert!(compile_result.err.is_none());
+    compile_result
+}
+
+/// Returns the span of each instr in a program.
+fn spans(prog: &[Instr]) -> Vec<Span> {
+    prog.iter().map(|i| i.span).collect()
+}
+
 // Expects a test/file name as first arg, and then the expected output as a second arg.
 macro_rules! compile_test {
     ($test_name:ident, $expected:expr) => {
@@ -27,7 +62,8 @@ macro_rules! compile_test {
             let infile = File::open(path).unwrap();
             let mut symtab = SymTab::new();
             let mut lex = Lexer::new(infile);
-            let mut parser = Parser::new(&mut lex, &mut symtab);
+            let stdlib = Std::new();
+            let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
 
             let parse_result = parser.parse();
             assert_eq!(parse_result.has_err, false);
@@ -53,11 +89,11 @@ lbl0: stc 0 g
 
 compile_test! {
     multi_var,
-    r"
+    r#"
 lbl0: stc 0 g
-lbl0: stc hello t
+lbl0: stc "hello" t
 lbl0: stc false r
-"
+"#
 }
 
 compile_test! {
@@ -91,44 +127,44 @@ lbl0: str r1 g
 compile_test! {
     empty_fn,
     r"
-lbl0: fn @x []
-lbl0: ret
+lbl1: fn @x []
+lbl1: ret
 "
 }
 
 compile_test! {
     fn_w_params,
     r#"
-lbl0: fn @x ["y"]
-lbl0: fstpop r0
-lbl0: str r0 y
-lbl0: ldn y r1
-lbl0: fstpush r1
-lbl0: ret
+lbl1: fn @x ["y"]
+lbl1: fstpop r0
+lbl1: str r0 y
+lbl1: ldn y r1
+lbl1: fstpush r1
+lbl1: ret
 "#
 }
 
 compile_test! {
     fn_call,
     r#"
-lbl0: fn @x ["y"]
-lbl0: fstpop r0
-lbl0: str r0 y
-lbl0: ldn y r1
-lbl0: fstpush r1
-lbl0: ret
-lbl1: ldc 1 r2
-lbl1: fstpush r2
-lbl1: call x
-lbl1: fstpop r3
-lbl1: str r3 g
+lbl1: fn @x ["y"]
+lbl1: fstpop r0
+lbl1: str r0 y
+lbl1: ldn y r1
+lbl1: fstpush r1
+lbl1: ret
+lbl2: ldc 1 r2
+lbl2: fstpush r2
+lbl2: call x 1
+lbl2: fstpop r3
+lbl2: str r3 g
 "#
 }
 
 compile_test! {
     for_stmt,
     r"
-lbl0: stc [Num(1.0), Num(2.0), Num(3.0)] g
+lbl0: stc [1, 2, 3] g
 lbl0: stc 0 x
 lbl0: stc 0 idx
 lbl0: ldas g r1
@@ -148,12 +184,12 @@ lbl1: jmpt r6 lbl1
 
 compile_test! {
     std_lib_call,
-    r"
-lbl0: ldc hello world r0
+    r#"
+lbl0: ldc "hello world" r0
 lbl0: fstpush r0
-lbl0: stdcall print
+lbl0: stdcall print 1
 lbl0: fstpop r1
-"
+"#
 }
 
 compile_test! {
@@ -290,116 +326,116 @@ lbl8: nop
 compile_test! {
     fn_decl_if,
     r#"
-lbl0: fn @t ["x"]
-lbl0: fstpop r0
-lbl0: str r0 x
-lbl0: ldc true r1
-lbl0: ldc false r2
-lbl0: or r1 r2 r3
-lbl0: jmpf r3 lbl2
-lbl1: stc 0 t
-lbl1: jmpa lbl2
-lbl2: nop
-lbl2: ret
+lbl1: fn @t ["x"] ["t"]
+lbl1: fstpop r0
+lbl1: str r0 x
+lbl1: ldc true r1
+lbl1: ldc false r2
+lbl1: or r1 r2 r3
+lbl1: jmpf r3 lbl3
+lbl2: stc 0 t
+lbl2: jmpa lbl3
+lbl3: nop
+lbl3: ret
 "#
 }
 
 compile_test! {
     fn_decl_if_else,
     r#"
-lbl0: fn @t ["x"]
-lbl0: fstpop r0
-lbl0: str r0 x
-lbl0: ldc true r1
-lbl0: ldc false r2
-lbl0: or r1 r2 r3
-lbl0: jmpf r3 lbl2
-lbl1: stc 0 t
-lbl1: jmpa lbl3
-lbl2: stc 1 t
-lbl3: nop
-lbl3: ret
+lbl1: fn @t ["x"] ["t"]
+lbl1: fstpop r0
+lbl1: str r0 x
+lbl1: ldc true r1
+lbl1: ldc false r2
+lbl1: or r1 r2 r3
+lbl1: jmpf r3 lbl3
+lbl2: stc 0 t
+lbl2: jmpa lbl4
+lbl3: stc 1 t
+lbl4: nop
+lbl4: ret
 "#
 }
 
 compile_test! {
     fn_decl_if_elif,
     r#"
-lbl0: fn @t ["x"]
-lbl0: fstpop r0
-lbl0: str r0 x
-lbl0: ldc true r1
-lbl0: ldc false r2
-lbl0: or r1 r2 r3
-lbl0: jmpf r3 lbl2
-lbl1: stc 0 t
-lbl1: jmpa lbl4
-lbl2: ldc false r4
-lbl2: ldc false r5
-lbl2: and r4 r5 r6
-lbl2: jmpf r6 lbl4
-lbl3: stc 1 t
-lbl3: jmpa lbl4
-lbl4: nop
-lbl4: ret
+lbl1: fn @t ["x"] ["t"]
+lbl1: fstpop r0
+lbl1: str r0 x
+lbl1: ldc true r1
+lbl1: ldc false r2
+lbl1: or r1 r2 r3
+lbl1: jmpf r3 lbl3
+lbl2: stc 0 t
+lbl2: jmpa lbl5
+lbl3: ldc false r4
+lbl3: ldc false r5
+lbl3: and r4 r5 r6
+lbl3: jmpf r6 lbl5
+lbl4: stc 1 t
+lbl4: jmpa lbl5
+lbl5: nop
+lbl5: ret
 "#
 }
 
 compile_test! {
     fn_decl_if_elif_else,
     r#"
-lbl0: fn @t ["x"]
-lbl0: fstpop r0
-lbl0: str r0 x
-lbl0: ldc true r1
-lbl0: ldc false r2
-lbl0: or r1 r2 r3
-lbl0: jmpf r3 lbl2
-lbl1: stc 0 t
-lbl1: jmpa lbl5
-lbl2: ldc false r4
-lbl2: ldc false r5
-lbl2: and r4 r5 r6
-lbl2: jmpf r6 lbl4
-lbl3: stc 1 t
-lbl3: jmpa lbl5
-lbl4: stc 2 t
-lbl5: nop
-lbl5: ret
+lbl1: fn @t ["x"] ["t"]
+lbl1: fstpop r0
+lbl1: str r0 x
+lbl1: ldc true r1
+lbl1: ldc false r2
+lbl1: or r1 r2 r3
+lbl1: jmpf r3 lbl3
+lbl2: stc 0 t
+lbl2: jmpa lbl6
+lbl3: ldc false r4
+lbl3: ldc false r5
+lbl3: and r4 r5 r6
+lbl3: jmpf r6 lbl5
+lbl4: stc 1 t
+lbl4: jmpa lbl6
+lbl5: stc 2 t
+lbl6: nop
+lbl6: ret
 "#
 }
 
 compile_test! {
     fn_decl_for_stmt,
     r#"
-lbl0: fn @t ["x"]
-lbl0: fstpop r0
-lbl0: str r0 x
-lbl0: stc [Num(1.0), Num(2.0), Num(3.0)] arr
-lbl0: stc 0 i
-lbl0: ldas arr r2
-lbl1: ldn i r1
-lbl1: ldav arr r1 r3
-lbl1: str r3 v
-lbl1: ldn v r4
-lbl1: fstpush r4
-lbl1: stdcall print
-lbl1: fstpop r5
-lbl1: incrr r1
-lbl1: str r1 i
-lbl1: lt r1 r2 r6
-lbl1: jmpt r6 lbl1
-lbl1: ret
+lbl1: fn @t ["x"] ["arr", "i", "v"]
+lbl1: fstpop r0
+lbl1: str r0 x
+lbl1: stc [1, 2, 3] arr
+lbl1: stc 0 i
+lbl1: ldas arr r2
+lbl2: ldn i r1
+lbl2: ldav arr r1 r3
+lbl2: str r3 v
+lbl2: ldn v r4
+lbl2: fstpush r4
+lbl2: stdcall print 1
+lbl2: fstpop r5
+lbl2: incrr r1
+lbl2: str r1 i
+lbl2: lt r1 r2 r6
+lbl2: jmpt r6 lbl2
+lbl2: ret
 "#
 }
 
 compile_test! {
     array_mut,
     r"
-lbl0: stc [Num(1.0), Num(2.0)] g
+lbl0: stc [1, 2] g
 lbl0: ldc 3 r0
 lbl0: ldc 1 r1
-lbl0: upda g r0 r1
+lbl0: upda g r1 r0
 "
 }
 
@@ -410,7 +446,7 @@ lbl0: ldc 0 r1
 lbl0: fstpush r1
 lbl0: ldc 1 r2
 lbl0: fstpush r2
-lbl0: stdcall range
+lbl0: stdcall range 2
 lbl0: fstpop r3
 lbl0: str r3 fortmp
 lbl0: stc 0 i
@@ -425,3 +461,186 @@ lbl1: lt r0 r4 r6
 lbl1: jmpt r6 lbl1
 "
 }
+
+compile_test! {
+    while_stmt,
+    r"
+lbl0: stc 0 x
+lbl1: ldn x r0
+lbl1: ldc 3 r1
+lbl1: lt r0 r1 r2
+lbl1: jmpf r2 lbl3
+lbl2: ldn x r3
+lbl2: ldc 1 r4
+lbl2: add r3 r4 r5
+lbl2: str r5 x
+lbl2: jmpa lbl1
+lbl3: nop
+"
+}
+
+compile_test! {
+    while_break_continue,
+    r"
+lbl0: stc 0 x
+lbl1: ldc true r0
+lbl1: jmpf r0 lbl5
+lbl2: ldn x r1
+lbl2: ldc 1 r2
+lbl2: add r1 r2 r3
+lbl2: str r3 x
+lbl2: ldn x r4
+lbl2: ldc 3 r5
+lbl2: lt r4 r5 r6
+lbl2: jmpf r6 lbl4
+lbl3: jmpa lbl1
+lbl3: jmpa lbl4
+lbl4: nop
+lbl4: jmpa lbl5
+lbl4: jmpa lbl1
+lbl5: nop
+"
+}
+
+compile_test! {
+    for_stmt_break_continue,
+    r"
+lbl0: stc [1, 2, 3] g
+lbl0: stc 0 idx
+lbl0: ldas g r1
+lbl1: ldn idx r0
+lbl1: ldav g r0 r2
+lbl1: str r2 val
+lbl1: ldn val r3
+lbl1: ldc 1 r4
+lbl1: eq r3 r4 r5
+lbl1: jmpf r5 lbl3
+lbl2: jmpa lbl4
+lbl2: jmpa lbl3
+lbl3: nop
+lbl3: jmpa lbl5
+lbl4: incrr r0
+lbl4: str r0 idx
+lbl4: lt r0 r1 r6
+lbl4: jmpt r6 lbl1
+lbl5: nop
+"
+}
+
+compile_test! {
+    fn_expr,
+    r#"
+lbl1: fn @anon$0 ["x"]
+lbl1: fstpop r0
+lbl1: str r0 x
+lbl1: ldn x r1
+lbl1: ldc 1 r2
+lbl1: add r1 r2 r3
+lbl1: fstpush r3
+lbl1: ret
+lbl2: ldf @anon$0 [] r4
+lbl2: str r4 f
+lbl2: ldc 2 r5
+lbl2: fstpush r5
+lbl2: ldn f r6
+lbl2: callr r6 1
+lbl2: fstpop r7
+lbl2: str r7 y
+"#
+}
+
+compile_test! {
+    fn_expr_capture,
+    r#"
+lbl1: fn @adder ["n"]
+lbl1: fstpop r0
+lbl1: str r0 n
+lbl1: jmpa lbl3
+lbl2: fn @anon$0 ["x"] ["n"]
+lbl2: fstpop r1
+lbl2: str r1 x
+lbl2: ldn x r2
+lbl2: ldn n r3
+lbl2: add r2 r3 r4
+lbl2: fstpush r4
+lbl2: ret
+lbl3: nop
+lbl3: ldf @anon$0 ["n"] r5
+lbl3: fstpush r5
+lbl3: ret
+"#
+}
+
+#[test]
+fn instr_spans() {
+    let result = compile_src("var a = 1;\nvar b = a * 2 + 3;\n");
+    assert_eq!(
+        spans(&result.program),
+        vec![
+            Span::new(1, 5),
+            Span::new(2, 9),
+            Span::new(2, 13),
+            Span::new(2, 11),
+            Span::new(2, 17),
+            Span::new(2, 15),
+            Span::new(2, 5),
+        ]
+    );
+}
+
+#[test]
+fn instr_spans_in_fn() {
+    let result = compile_src("fn f(x) {\n  return x + 1;\n}\nf(2);\n");
+    let add = result
+        .program
+        .iter()
+        .find(|i| format!("{:#}", i).contains("add"))
+        .unwrap();
+    assert_eq!(add.span, Span::new(2, 12));
+
+    let call = result
+        .program
+        .iter()
+        .find(|i| format!("{:#}", i).contains("call"))
+        .unwrap();
+    assert_eq!(call.span, Span::new(4, 1));
+}
+
+#[test]
+fn optimizer_keeps_spans() {
+    let result = compile_src("var a = 1;\nvar b = a * 2 + 3;\nif b > 4 {\n  b = b - 1;\n}\n");
+    let mut opt = BytecodeOptimizer::new(result.decls, result.code.clone(), result.code_start);
+    let opt_result = opt.run_passes();
+
+    // Each optimized instr should come from a source line, and keep the span of the instr
+    // it was copied from.
+    let known = spans(&result.code);
+    for instr in &opt_result.optimized {
+        match instr.op {
+            Op::Nop => {}
+            _ => assert!(known.contains(&instr.span), "{:?}", instr),
+        };
+    }
+}
+
+#[test]
+fn captured_assign() {
+    let src =
+        "fn counter() {\n  var c = 0;\n  return fn() {\n    c = c + 1;\n    return c;\n  };\n}\n";
+    let err = compile_parsed(src).err.unwrap();
+    match &err.ty {
+        CompileErrTy::CapturedAssign(name) => assert_eq!(name, "c"),
+        ty => panic!("unexpected error {:?}", ty),
+    };
+    assert_eq!(err.span, Span::new(4, 5));
+
+    // Nested closures capture through the closure enclosing them.
+    let src =
+        "fn f() {\n  var c = 0;\n  return fn() {\n    return fn() { c = 1; return c; };\n  };\n}\n";
+    let err = compile_parsed(src).err.unwrap();
+    assert_eq!(err.span, Span::new(4, 19));
+
+    // Reading a capture, or assigning to a name the closure declared itself, is fine.
+    compile_src("fn f() {\n  var c = 0;\n  return fn() { var d = c; d = d + 1; return d; };\n}\n");
+    compile_src("fn f() {\n  var c = 0;\n  return fn() { var c = 5; c = c + 1; return c; };\n}\n");
+}
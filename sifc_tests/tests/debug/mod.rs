@@ -0,0 +1,134 @@
+use sifc_bytecode::{compiler::Compiler, sifv::SifVal};
+use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::{io::Io, Std};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    debug::{Debugger, Stop},
+    vm::VM,
+};
+
+const PROG: &str = "var x = 1;
+fn f(a) {
+  var b = a + 1;
+  return b;
+}
+while x < 3 {
+  x = x + 1;
+}
+var y = f(x);
+var z = y;
+";
+
+// Expects a sif program str as input, returns a debugger paused before the first line
+// of the program.
+fn debugger(input: &str) -> Debugger {
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new(input.as_bytes());
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+    let parse_result = parser.parse();
+    assert!(!parse_result.has_err);
+
+    let ast = parse_result.ast.unwrap();
+    let mut compiler = Compiler::new(&ast);
+    let compile_result = compiler.compile();
+    assert!(compile_result.err.is_none());
+
+    let conf = VMConfig {
+        trace: false,
+        initial_heap_size: 32,
+        initial_dreg_count: 64,
+        limits: Limits::default(),
+        io: Io::stdio(),
+    };
+    let vm = VM::init(
+        compile_result.program,
+        compile_result.code_start,
+        compile_result.jumptab,
+        compile_result.fntab,
+        conf,
+    );
+
+    Debugger::new(vm)
+}
+
+#[test]
+fn debug_starts_at_first_line() {
+    let dbg = debugger(PROG);
+    assert_eq!(dbg.line(), 1);
+    assert_eq!(dbg.vm().call_depth(), 0);
+}
+
+#[test]
+fn debug_step_over() {
+    let mut dbg = debugger(PROG);
+    let mut lines = Vec::new();
+    loop {
+        match dbg.step_over() {
+            Ok(Stop::Step(line)) => lines.push(line),
+            Ok(Stop::Finished) => break,
+            other => panic!("unexpected stop {:?}", other),
+        };
+    }
+
+    assert_eq!(lines, vec![6, 7, 6, 7, 6, 9, 10]);
+    assert_eq!(dbg.vm().inspect_heap("z"), Some(&SifVal::Num(4.0)));
+}
+
+#[test]
+fn debug_step_into_and_out() {
+    let mut dbg = debugger(PROG);
+    dbg.add_breakpoint(9);
+    assert_eq!(dbg.cont().ok(), Some(Stop::Breakpoint(9)));
+
+    assert_eq!(dbg.step_into().ok(), Some(Stop::Step(2)));
+    assert_eq!(dbg.vm().call_depth(), 1);
+    assert_eq!(dbg.step_into().ok(), Some(Stop::Step(3)));
+    assert_eq!(dbg.step_out().ok(), Some(Stop::Step(9)));
+    assert_eq!(dbg.vm().call_depth(), 0);
+    assert_eq!(dbg.step_over().ok(), Some(Stop::Step(10)));
+}
+
+#[test]
+fn debug_breakpoint_in_fn() {
+    let mut dbg = debugger(PROG);
+    assert!(dbg.add_breakpoint(4));
+    assert!(!dbg.add_breakpoint(4));
+    assert_eq!(dbg.cont().ok(), Some(Stop::Breakpoint(4)));
+
+    let locals = dbg.vm().locals().unwrap();
+    assert_eq!(locals.get("a"), Some(&SifVal::Num(3.0)));
+    assert_eq!(locals.get("b"), Some(&SifVal::Num(4.0)));
+
+    let trace = dbg.vm().backtrace();
+    assert_eq!(trace.len(), 2);
+    assert_eq!(trace[0].name, Some(String::from("f")));
+    assert_eq!(trace[0].span.line, 4);
+    assert_eq!(trace[1].name, None);
+    assert_eq!(trace[1].span.line, 9);
+
+    assert!(dbg.remove_breakpoint(4));
+    assert_eq!(dbg.cont().ok(), Some(Stop::Finished));
+}
+
+#[test]
+fn debug_breakpoint_step_over() {
+    // Breakpoints are also checked while stepping over calls.
+    let mut dbg = debugger(PROG);
+    dbg.add_breakpoint(3);
+    dbg.add_breakpoint(9);
+    assert_eq!(dbg.cont().ok(), Some(Stop::Breakpoint(9)));
+    assert_eq!(dbg.step_over().ok(), Some(Stop::Breakpoint(3)));
+    assert_eq!(dbg.cont().ok(), Some(Stop::Finished));
+}
+
+#[test]
+fn debug_runtime_err() {
+    let mut dbg = debugger("fn bad(a) {\n  return a * \"s\";\n}\nbad(1);\n");
+    assert!(dbg.cont().is_err());
+
+    // The failed call is still on the call stack, so it can be inspected.
+    assert_eq!(dbg.line(), 2);
+    assert_eq!(dbg.vm().call_depth(), 1);
+    assert_eq!(dbg.vm().locals().unwrap().get("a"), Some(&SifVal::Num(1.0)));
+}
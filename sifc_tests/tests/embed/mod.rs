@@ -0,0 +1,227 @@
+use sifc_embed::{Buffer, ConvertErr, Engine, EngineErr, FromSif, Io, Limits, SifVal};
+use sifc_err::{
+    err::SifErr,
+    runtime_err::{Limit, RuntimeErrTy},
+};
+use std::{cell::Cell, collections::HashMap, rc::Rc};
+
+#[test]
+fn program_runs_many_times() {
+    let runs = Rc::new(Cell::new(0.0));
+    let counter = Rc::clone(&runs);
+    let mut engine = Engine::new().with_fn("next", 0, move |_| {
+        counter.set(counter.get() + 1.0);
+        Ok(counter.get())
+    });
+
+    let program = engine.compile("var n = @next();").unwrap();
+    engine.run(&program).unwrap();
+    assert_eq!(engine.get::<f64>("n").unwrap(), 1.0);
+    engine.run(&program).unwrap();
+    assert_eq!(engine.get::<f64>("n").unwrap(), 2.0);
+    assert_eq!(runs.get(), 2.0);
+}
+
+#[test]
+fn values_convert_to_rust() {
+    let mut engine = Engine::new();
+    engine
+        .eval(
+            "var n = 3;
+             var s = \"hi\";
+             var b = false;
+             var a = [1, 2, 3];
+             var t = [[x => 1, y => 2]];
+             var none;",
+        )
+        .unwrap();
+
+    assert_eq!(engine.get::<i64>("n").unwrap(), 3);
+    assert_eq!(engine.get::<String>("s").unwrap(), "hi");
+    assert!(!engine.get::<bool>("b").unwrap());
+    assert_eq!(engine.get::<Vec<usize>>("a").unwrap(), vec![1, 2, 3]);
+
+    let t = engine.get::<HashMap<String, f64>>("t").unwrap();
+    assert_eq!(t["x"], 1.0);
+    assert_eq!(t["y"], 2.0);
+
+    assert_eq!(engine.get::<Option<f64>>("none").unwrap(), None);
+    assert_eq!(engine.get::<Option<f64>>("n").unwrap(), Some(3.0));
+}
+
+#[test]
+fn host_fn_values_convert_to_sif() {
+    let mut engine = Engine::new()
+        .with_fn("pair", 2, |params| {
+            let a = f64::from_sif(params[0].clone())?;
+            let b = f64::from_sif(params[1].clone())?;
+            Ok(vec![a, b])
+        })
+        .with_fn("greet", 1, |params| {
+            let name = String::from_sif(params[0].clone())?;
+            Ok(format!("hello {}", name))
+        })
+        .with_fn("nothing", 0, |_| Ok(()));
+
+    engine
+        .eval("var p = @pair(1, 2); var g = @greet(\"sif\"); var x = @nothing();")
+        .unwrap();
+    assert_eq!(engine.get::<Vec<f64>>("p").unwrap(), vec![1.0, 2.0]);
+    assert_eq!(engine.get::<String>("g").unwrap(), "hello sif");
+    assert_eq!(engine.get::<SifVal>("x").unwrap().ty_name(), "null");
+}
+
+#[test]
+fn parse_errors_are_all_returned() {
+    let engine = Engine::new();
+    let err = engine.compile("var a = 1 +;\nvar b = ;\n").err().unwrap();
+    match &err {
+        EngineErr::Parse(errs) => assert_eq!(errs.len(), 2),
+        e => panic!("unexpected error {:?}", e),
+    };
+    assert_eq!(err.diagnostics().len(), 2);
+}
+
+#[test]
+fn host_fn_convert_errors_stop_the_program() {
+    let mut engine =
+        Engine::new().with_fn("num", 1, |params| Ok(f64::from_sif(params[0].clone())?));
+
+    let program = engine.compile("var x = 1;\nvar y = @num(\"s\");").unwrap();
+    let err = engine.run(&program.with_file("host.sif")).err().unwrap();
+    match &err {
+        EngineErr::Runtime(e) => {
+            match &e.ty {
+                RuntimeErrTy::HostFnErr(msg) => {
+                    assert_eq!(msg, "Cannot convert value: expected number, found string")
+                }
+                ty => panic!("unexpected error {:?}", ty),
+            };
+            assert_eq!(e.span.line, 2);
+            assert_eq!(e.file.as_deref(), Some("host.sif"));
+        }
+        e => panic!("unexpected error {:?}", e),
+    };
+
+    // Globals stored before the error can still be read.
+    assert_eq!(engine.get::<f64>("x").unwrap(), 1.0);
+}
+
+#[test]
+fn get_errors() {
+    let mut engine = Engine::new();
+    match engine.get::<f64>("x") {
+        Err(EngineErr::UnknownGlobal(name)) => assert_eq!(name, "x"),
+        r => panic!("unexpected result {:?}", r),
+    };
+
+    engine.eval("var x = \"s\"; var y = 1.5;").unwrap();
+    match engine.get::<f64>("x") {
+        Err(EngineErr::Convert(e)) => assert_eq!(e, ConvertErr::new("number", "string")),
+        r => panic!("unexpected result {:?}", r),
+    };
+    match engine.get::<i32>("y") {
+        Err(e) => assert_eq!(e.code(), "E0801"),
+        r => panic!("unexpected result {:?}", r),
+    };
+    assert_eq!(
+        engine.get::<bool>("y").err().unwrap().to_msg(),
+        "Cannot convert value: expected bool, found number"
+    );
+}
+
+#[test]
+fn call_sif_fns() {
+    let mut engine = Engine::new();
+    engine
+        .eval(
+            "var weight = 2;
+             fn score(points, mult) {
+               return points * weight * mult;
+             }
+             fn same(val) {
+               return val;
+             }
+             fn total(items) {
+               var sum = 0;
+               for i, v in items {
+                 sum = sum + v;
+               }
+               return sum;
+             }
+             fn ping() {}",
+        )
+        .unwrap();
+
+    let score = engine.call("score", (5, 3.0)).unwrap();
+    assert_eq!(f64::from_sif(score).unwrap(), 30.0);
+
+    let mut user = HashMap::new();
+    user.insert(String::from("name"), String::from("sif"));
+    let same = engine.call("same", (user.clone(),)).unwrap();
+    assert_eq!(HashMap::<String, String>::from_sif(same).unwrap(), user);
+
+    let total = engine.call("total", (vec![1, 2, 3],)).unwrap();
+    assert_eq!(i64::from_sif(total).unwrap(), 6);
+    assert_eq!(engine.call("ping", ()).unwrap(), SifVal::Null);
+}
+
+#[test]
+fn call_errors() {
+    let mut engine = Engine::new();
+    match engine.call("f", ()) {
+        Err(EngineErr::Runtime(e)) => assert_eq!(e.code(), "E0411"),
+        r => panic!("unexpected result {:?}", r),
+    };
+
+    engine.eval("fn f(x) { return x + 1; }").unwrap();
+    match engine.call("f", ("s",)) {
+        Err(EngineErr::Runtime(e)) => assert_eq!(e.code(), "E0409"),
+        r => panic!("unexpected result {:?}", r),
+    };
+    assert_eq!(engine.call("f", (1,)).unwrap(), SifVal::Num(2.0));
+}
+
+#[test]
+fn limits_stop_runaway_programs() {
+    let mut engine = Engine::new().with_limits(Limits {
+        fuel: Some(10_000),
+        ..Limits::default()
+    });
+    let program = engine
+        .compile("fn spin() { while true { var x = 1; } }\nvar done = true;")
+        .unwrap();
+    engine.run(&program).unwrap();
+
+    match engine.call("spin", ()) {
+        Err(EngineErr::Runtime(e)) => match e.ty {
+            RuntimeErrTy::LimitExceeded(Limit::Fuel(10_000)) => assert_eq!(e.code(), "E0418"),
+            ty => panic!("unexpected error {:?}", ty),
+        },
+        r => panic!("unexpected result {:?}", r),
+    };
+
+    // The program can still be run and called after going over a limit.
+    engine.run(&program).unwrap();
+    assert!(engine.get::<bool>("done").is_ok());
+}
+
+#[test]
+fn output_is_captured() {
+    let out = Buffer::new();
+    let io = Io::stdio()
+        .with_out(out.clone())
+        .with_input("sif\n".as_bytes());
+    let mut engine = Engine::new().with_io(io);
+
+    engine
+        .eval("var name = @readln();\n@print(name);\nfn greet() { @print(\"hi\"); }")
+        .unwrap();
+    assert_eq!(out.contents(), "sif\n");
+
+    // Host calls print to the same handle.
+    engine.call("greet", ()).unwrap();
+    assert_eq!(out.contents(), "sif\nhi\n");
+    out.clear();
+    assert_eq!(out.contents(), "");
+}
@@ -1,7 +1,16 @@
+use crate::golden;
 use sifc_bytecode::compiler::Compiler;
+use sifc_err::{diag::Renderer, err::SifErr};
 use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
-use sifc_vm::{config::VMConfig, vm::VM};
-use std::fs::File;
+use sifc_std::{
+    io::{Buffer, Io},
+    Std,
+};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    vm::VM,
+};
+use std::fs::{self, File};
 
 const INPUT_PATH: &str = "./tests/exec_fail/inputs";
 
@@ -10,13 +19,15 @@ macro_rules! exec_fail_test {
         #[test]
         fn $test_name() {
             // Open input file, which is a sif program.
-            let path = format!("{}/{}.sif", INPUT_PATH, stringify!($test_name));
+            let name = stringify!($test_name);
+            let path = format!("{}/{}.sif", INPUT_PATH, name);
 
             // Lex and parse the file, ensuring no errors.
-            let infile = File::open(path).unwrap();
+            let infile = File::open(&path).unwrap();
             let mut symtab = SymTab::new();
             let mut lex = Lexer::new(infile);
-            let mut parser = Parser::new(&mut lex, &mut symtab);
+            let stdlib = Std::new();
+            let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
 
             let parse_result = parser.parse();
             assert_eq!(parse_result.has_err, false);
@@ -27,11 +38,20 @@ macro_rules! exec_fail_test {
             let compile_result = compiler.compile();
             assert!(compile_result.err.is_none());
 
-            // Execute bytecode, ensuring a runtime error occurs
+            // Execute bytecode, ensuring a runtime error occurs. The error is rendered
+            // after anything the program printed to stderr, and both are compared to the
+            // expected output.
+            let stdout = Buffer::new();
+            let stderr = Buffer::new();
             let conf = VMConfig {
                 trace: false,
                 initial_heap_size: 10,
                 initial_dreg_count: 32,
+                limits: Limits::default(),
+                io: Io::stdio()
+                    .with_out(stdout.clone())
+                    .with_err(stderr.clone())
+                    .with_input("".as_bytes()),
             };
             let mut vm = VM::init(
                 compile_result.program,
@@ -40,8 +60,16 @@ macro_rules! exec_fail_test {
                 compile_result.fntab,
                 conf,
             );
-            let vm_result = vm.run();
-            assert!(vm_result.is_err());
+            vm.set_file(&format!("{}.sif", name));
+            let err = vm.run().err().unwrap();
+            let src = fs::read_to_string(&path).unwrap();
+            let file = format!("{}.sif", name);
+            let rendered = Renderer::new(&src, Some(&file))
+                .with_color(false)
+                .render(&err.diagnostic());
+            let errors = format!("{}{}", stderr.contents(), rendered);
+            let out = format!("{}{}", stdout.contents(), golden::globals(&vm));
+            golden::check(INPUT_PATH, name, &out, &errors);
         }
     };
 }
@@ -53,3 +81,8 @@ exec_fail_test!(binop_str_num);
 exec_fail_test!(binop_str_bool);
 exec_fail_test!(binop_num_bool);
 exec_fail_test!(array_index_bounds);
+exec_fail_test!(call_non_fn);
+exec_fail_test!(fn_val_wrong_params);
+exec_fail_test!(cyclic_array);
+exec_fail_test!(table_missing_key);
+exec_fail_test!(table_access_not_table);
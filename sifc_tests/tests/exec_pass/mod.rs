@@ -1,6 +1,14 @@
+use crate::golden;
 use sifc_bytecode::compiler::Compiler;
 use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
-use sifc_vm::{config::VMConfig, vm::VM};
+use sifc_std::{
+    io::{Buffer, Io},
+    Std,
+};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    vm::VM,
+};
 use std::fs::File;
 
 const INPUT_PATH: &str = "./tests/exec_pass/inputs";
@@ -10,13 +18,15 @@ macro_rules! exec_pass_test {
         #[test]
         fn $test_name() {
             // Open input file, which is a sif program.
-            let path = format!("{}/{}.sif", INPUT_PATH, stringify!($test_name));
+            let name = stringify!($test_name);
+            let path = format!("{}/{}.sif", INPUT_PATH, name);
 
             // Lex and parse the file, ensuring no errors.
-            let infile = File::open(path).unwrap();
+            let infile = File::open(&path).unwrap();
             let mut symtab = SymTab::new();
             let mut lex = Lexer::new(infile);
-            let mut parser = Parser::new(&mut lex, &mut symtab);
+            let stdlib = Std::new();
+            let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
 
             let parse_result = parser.parse();
             assert_eq!(parse_result.has_err, false);
@@ -27,11 +37,19 @@ macro_rules! exec_pass_test {
             let compile_result = compiler.compile();
             assert!(compile_result.err.is_none());
 
-            // Execute bytecode, ensuring no panics/runtime errors.
+            // Execute bytecode, ensuring no panics/runtime errors, and compare what the
+            // program printed to the expected output.
+            let stdout = Buffer::new();
+            let stderr = Buffer::new();
             let conf = VMConfig {
                 trace: false,
                 initial_heap_size: 10,
                 initial_dreg_count: 32,
+                limits: Limits::default(),
+                io: Io::stdio()
+                    .with_out(stdout.clone())
+                    .with_err(stderr.clone())
+                    .with_input("".as_bytes()),
             };
             let mut vm = VM::init(
                 compile_result.program,
@@ -40,8 +58,11 @@ macro_rules! exec_pass_test {
                 compile_result.fntab,
                 conf,
             );
+            vm.set_file(&format!("{}.sif", name));
             let vm_result = vm.run();
             assert!(vm_result.is_ok());
+            let out = format!("{}{}", stdout.contents(), golden::globals(&vm));
+            golden::check(INPUT_PATH, name, &out, &stderr.contents());
         }
     };
 }
@@ -59,3 +80,15 @@ exec_pass_test!(std_lib_calls);
 exec_pass_test!(scopes);
 exec_pass_test!(array_mut_assign);
 exec_pass_test!(for_stmt_fn_call);
+exec_pass_test!(recursion);
+exec_pass_test!(while_stmt);
+exec_pass_test!(closures);
+exec_pass_test!(literals);
+exec_pass_test!(snake_case);
+exec_pass_test!(leading_while);
+exec_pass_test!(closure_shared_state);
+exec_pass_test!(closure_capture_by_value);
+exec_pass_test!(fn_ret_vals);
+exec_pass_test!(fn_decl_after_if);
+exec_pass_test!(fn_decl_after_while);
+exec_pass_test!(table_access);
@@ -0,0 +1,145 @@
+use sifc_vm::vm::VM;
+use std::{env, fs, path::Path};
+
+/// Environment variable that switches the harness to bless mode. Instead of comparing, the
+/// output of each program is written to its expected output files, which can then be
+/// reviewed and committed with the change that caused them.
+const BLESS_VAR: &str = "SIF_BLESS";
+
+/// Compares what a program printed to the expected output files next to it. The stdout of
+/// `inputs/name.sif`, followed by its [`globals`], is expected in `inputs/name.out`, and
+/// its stderr in `inputs/name.err`.
+/// The .err file is optional, and leaving it out expects nothing on stderr. Mismatches
+/// panic with a diff of the expected and actual output.
+pub fn check(dir: &str, name: &str, stdout: &str, stderr: &str) {
+    let out_path = format!("{}/{}.out", dir, name);
+    let err_path = format!("{}/{}.err", dir, name);
+
+    match env::var_os(BLESS_VAR) {
+        Some(_) => {
+            bless(&out_path, stdout, true);
+            bless(&err_path, stderr, false);
+        }
+        None => {
+            let expected_out = match fs::read_to_string(&out_path) {
+                Ok(s) => s,
+                Err(_) => panic!(
+                    "{} is missing, rerun with {}=1 to create it",
+                    out_path, BLESS_VAR
+                ),
+            };
+            let expected_err = fs::read_to_string(&err_path).unwrap_or_default();
+
+            let mut report = String::new();
+            report.push_str(&compare(&out_path, &expected_out, stdout));
+            report.push_str(&compare(&err_path, &expected_err, stderr));
+            if !report.is_empty() {
+                panic!(
+                    "output of {}/{}.sif doesn't match:\n{}rerun with {}=1 to update the expected output",
+                    dir, name, report, BLESS_VAR
+                );
+            }
+        }
+    };
+}
+
+/// Formats the globals of a program after it has run, in the order they were declared.
+/// This is appended to what the program printed, so that programs which don't print
+/// anything are still checked against the values they computed. Returns an empty string
+/// if the program has no globals.
+pub fn globals(vm: &VM) -> String {
+    let names = vm.globals().names();
+    if names.is_empty() {
+        return String::new();
+    }
+
+    let mut result = String::from("-- globals --\n");
+    for name in names {
+        match vm.inspect_heap(name) {
+            Some(val) => result.push_str(&format!("{} = {}\n", name, val)),
+            None => result.push_str(&format!("{} = <unset>\n", name)),
+        };
+    }
+    result
+}
+
+/// Writes the expected output of a program. Optional files are only kept while the
+/// program produces output for them.
+fn bless(path: &str, contents: &str, required: bool) {
+    match required || !contents.is_empty() {
+        true => fs::write(path, contents).unwrap(),
+        false if Path::new(path).exists() => fs::remove_file(path).unwrap(),
+        false => {}
+    };
+}
+
+/// Returns a report containing the diff of the expected and actual contents of a file,
+/// or an empty string if they're the same.
+fn compare(path: &str, expected: &str, actual: &str) -> String {
+    if expected == actual {
+        return String::new();
+    }
+
+    let mut report = format!("--- {}\n+++ actual\n", path);
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let actual_lines: Vec<&str> = actual.lines().collect();
+    for line in diff(&expected_lines, &actual_lines) {
+        report.push_str(&line);
+        report.push('\n');
+    }
+
+    // Contents that only differ by the final line ending have identical lines.
+    if expected.ends_with('\n') != actual.ends_with('\n') {
+        report.push_str("(the final line ending differs)\n");
+    }
+    report
+}
+
+/// Builds a line diff from the longest common subsequence of the expected and actual
+/// lines. Lines only in expected are prefixed with '-', lines only in actual with '+', and
+/// lines in both with a space.
+fn diff(expected: &[&str], actual: &[&str]) -> Vec<String> {
+    // lcs[i][j] is the length of the longest common subsequence of expected[i..] and
+    // actual[j..].
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = match expected[i] == actual[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[test]
+fn diff_marks_changed_lines() {
+    let expected = ["a", "b", "c"];
+    let actual = ["a", "x", "c", "d"];
+    assert_eq!(
+        diff(&expected, &actual),
+        vec!["  a", "- b", "+ x", "  c", "+ d"]
+    );
+    assert_eq!(compare("f.out", "a\n", "a\n"), "");
+    assert_eq!(
+        compare("f.out", "a\n", "a"),
+        "--- f.out\n+++ actual\n  a\n(the final line ending differs)\n"
+    );
+}
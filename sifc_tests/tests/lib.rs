@@ -1,13 +1,20 @@
 extern crate sifc_analysis;
 extern crate sifc_bytecode;
+extern crate sifc_embed;
 extern crate sifc_err;
 extern crate sifc_parse;
 extern crate sifc_vm;
 
 mod analysis;
+mod asm;
 mod compiler;
+mod debug;
+mod embed;
 mod exec_fail;
 mod exec_pass;
+mod golden;
 mod parse_fail;
 mod parse_pass;
+mod sifb;
+mod verify;
 mod vm;
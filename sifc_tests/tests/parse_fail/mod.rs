@@ -1,4 +1,6 @@
-use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_err::{diag::Renderer, err::SifErr, parse_err::ParseErrTy};
+use sifc_parse::{ast::AstNode, lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::Std;
 use std::fs::File;
 
 const INPUT_PATH: &str = "./tests/parse_fail/inputs";
@@ -11,7 +13,8 @@ macro_rules! parse_fail_test {
             let infile = File::open(&path).unwrap();
             let mut symtab = SymTab::new();
             let mut lex = Lexer::new(infile);
-            let mut parser = Parser::new(&mut lex, &mut symtab);
+            let stdlib = Std::new();
+            let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
 
             let result = parser.parse();
             assert_eq!(true, result.has_err);
@@ -33,3 +36,118 @@ parse_fail_test!(fn_decl_eof);
 parse_fail_test!(fn_param_count_exceeded);
 parse_fail_test!(fn_decl_no_ident);
 parse_fail_test!(var_decl_not_ident);
+parse_fail_test!(break_outside_loop);
+parse_fail_test!(continue_in_fn_in_loop);
+parse_fail_test!(fn_expr_no_parens);
+parse_fail_test!(std_fn_wrong_params);
+parse_fail_test!(undecl_std_fn);
+parse_fail_test!(if_multiple_else);
+parse_fail_test!(stray_char);
+parse_fail_test!(unterminated_str);
+parse_fail_test!(multiple_errors);
+parse_fail_test!(table_access_not_ident);
+
+#[test]
+fn errors_are_collected() {
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new("var x = 1\nvar y = 2;".as_bytes());
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+    let result = parser.parse();
+    assert_eq!(result.errors.len(), 1);
+    match &result.errors[0].ty {
+        ParseErrTy::TknMismatch(expected, found) => {
+            assert_eq!(expected, ";");
+            assert_eq!(found, "var");
+        }
+        ty => panic!("unexpected error {:?}", ty),
+    };
+}
+
+#[test]
+fn parser_recovers_after_errors() {
+    let path = format!("{}/multiple_errors.sif", INPUT_PATH);
+    let infile = File::open(&path).unwrap();
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new(infile);
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+    let result = parser.parse();
+    assert!(result.has_err);
+    let lines: Vec<usize> = result.errors.iter().map(|e| e.line).collect();
+    assert_eq!(lines, vec![2, 6, 9, 14]);
+
+    // Declarations that parsed are kept in the AST, including the function whose body
+    // had an error in it.
+    let blocks = match result.ast {
+        Some(AstNode::Program { blocks }) => blocks,
+        _ => panic!("expected a program"),
+    };
+    assert_eq!(blocks.len(), 4);
+    match &blocks[1] {
+        AstNode::FnDecl { ident_tkn, .. } => assert_eq!(ident_tkn.get_name(), "f"),
+        node => panic!("unexpected node {:?}", node),
+    };
+}
+
+#[test]
+fn stray_brace_is_skipped() {
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new(
+        "var x = 1;
+}
+var y = x;"
+            .as_bytes(),
+    );
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+    let result = parser.parse();
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].line, 2);
+}
+
+#[test]
+fn lex_errors_are_collected() {
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new("var x = 1;\nvar y = x $ 2;".as_bytes());
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+    let result = parser.parse();
+    assert!(result.has_err);
+    assert_eq!(result.errors[0].code(), "E0101");
+    assert_eq!((result.errors[0].line, result.errors[0].pos), (2, 10));
+}
+
+#[test]
+fn undecl_sym_diagnostic() {
+    let src = "var count = 1;\nvar y = cont + 1;\n";
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new(src.as_bytes());
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+    let result = parser.parse();
+    assert!(result.has_err);
+    assert_eq!(
+        result.errors[0].help,
+        Some(String::from("did you mean 'count'?"))
+    );
+
+    let out = Renderer::new(src, Some("test.sif"))
+        .with_color(false)
+        .render(&result.errors[0].diagnostic());
+    assert_eq!(
+        out,
+        "error[E0210]: Undeclared symbol 'cont' found\n \
+         --> test.sif:2:9\n  \
+         |\n\
+         2 | var y = cont + 1;\n  \
+         |         ^^^^ not found in this scope\n  \
+         |\n  \
+         = help: did you mean 'count'?\n"
+    );
+}
@@ -1,4 +1,5 @@
 use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::Std;
 use std::fs::File;
 
 const INPUT_PATH: &str = "./tests/parse_pass/inputs";
@@ -11,7 +12,8 @@ macro_rules! parse_pass_test {
             let infile = File::open(&path).unwrap();
             let mut symtab = SymTab::new();
             let mut lex = Lexer::new(infile);
-            let mut parser = Parser::new(&mut lex, &mut symtab);
+            let stdlib = Std::new();
+            let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
 
             let result = parser.parse();
             assert_eq!(false, result.has_err);
@@ -29,3 +31,5 @@ parse_pass_test!(exprs);
 parse_pass_test!(table_decl);
 parse_pass_test!(array_decl);
 parse_pass_test!(array_mut_assign);
+parse_pass_test!(while_stmt);
+parse_pass_test!(fn_expr);
@@ -0,0 +1,83 @@
+use sifc_bytecode::{compiler::Compiler, sifb};
+use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::{io::Io, Std};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    vm::VM,
+};
+use std::fs::File;
+
+// Inputs are shared with the exec_pass tests, since every program there should
+// be able to run from a bytecode file as well.
+const INPUT_PATH: &str = "./tests/exec_pass/inputs";
+
+macro_rules! sifb_test {
+    ($test_name:ident) => {
+        #[test]
+        fn $test_name() {
+            let path = format!("{}/{}.sif", INPUT_PATH, stringify!($test_name));
+
+            let infile = File::open(path).unwrap();
+            let mut symtab = SymTab::new();
+            let mut lex = Lexer::new(infile);
+            let stdlib = Std::new();
+            let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+            let parse_result = parser.parse();
+            assert_eq!(parse_result.has_err, false);
+
+            let ast = parse_result.ast.unwrap();
+            let mut compiler = Compiler::new(&ast);
+            let compile_result = compiler.compile();
+            assert!(compile_result.err.is_none());
+
+            // Encode to the bytecode format and load it back, ensuring the program
+            // and its tables are unchanged.
+            let bytes = sifb::encode(
+                &compile_result.program,
+                compile_result.code_start,
+                &compile_result.jumptab,
+                &compile_result.fntab,
+            )
+            .unwrap();
+            assert!(sifb::is_sifb(&bytes));
+
+            let loaded = sifb::decode(&bytes).unwrap();
+            assert_eq!(loaded.program, compile_result.program);
+            assert_eq!(loaded.decls, compile_result.decls);
+            assert_eq!(loaded.code, compile_result.code);
+            assert_eq!(loaded.code_start, compile_result.code_start);
+            assert_eq!(loaded.jumptab, compile_result.jumptab);
+            assert_eq!(loaded.fntab, compile_result.fntab);
+
+            // Execute the loaded bytecode, ensuring no panics/runtime errors.
+            let conf = VMConfig {
+                trace: false,
+                initial_heap_size: 10,
+                initial_dreg_count: 32,
+                limits: Limits::default(),
+                io: Io::stdio(),
+            };
+            let mut vm = VM::init(
+                loaded.program,
+                loaded.code_start,
+                loaded.jumptab,
+                loaded.fntab,
+                conf,
+            );
+            let vm_result = vm.run();
+            assert!(vm_result.is_ok());
+        }
+    };
+}
+
+sifb_test!(var_decl);
+sifb_test!(array_decl);
+sifb_test!(exprs);
+sifb_test!(fn_call);
+sifb_test!(for_stmt);
+sifb_test!(if_stmt);
+sifb_test!(table_decl);
+sifb_test!(std_lib_calls);
+sifb_test!(while_stmt);
+sifb_test!(closures);
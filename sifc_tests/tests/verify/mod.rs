@@ -0,0 +1,188 @@
+use sifc_bytecode::{asm, compiler::Compiler, optimize::bco::BytecodeOptimizer, sifb};
+use sifc_err::{
+    runtime_err::{Limit, RuntimeErrTy},
+    verify_err::VerifyErrTy,
+};
+use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::{io::Io, Std};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    verify,
+    vm::VM,
+};
+use std::fs::File;
+
+// Inputs are shared with the exec_pass tests. Everything the compiler generates for them
+// should pass verification, both before and after optimizing.
+const INPUT_PATH: &str = "./tests/exec_pass/inputs";
+
+macro_rules! verify_pass_test {
+    ($test_name:ident) => {
+        #[test]
+        fn $test_name() {
+            let path = format!("{}/{}.sif", INPUT_PATH, stringify!($test_name));
+
+            let infile = File::open(path).unwrap();
+            let mut symtab = SymTab::new();
+            let mut lex = Lexer::new(infile);
+            let stdlib = Std::new();
+            let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+            let parse_result = parser.parse();
+            assert_eq!(parse_result.has_err, false);
+
+            let ast = parse_result.ast.unwrap();
+            let mut compiler = Compiler::new(&ast);
+            let compile_result = compiler.compile();
+            assert!(compile_result.err.is_none());
+
+            let result = verify::verify(
+                &compile_result.program,
+                compile_result.code_start,
+                &compile_result.jumptab,
+                &compile_result.fntab,
+            );
+            assert!(result.is_ok(), "{:?}", result);
+
+            let mut opt = BytecodeOptimizer::new(
+                compile_result.decls,
+                compile_result.code,
+                compile_result.code_start,
+            );
+            let opt_result = opt.run_passes();
+            let result = verify::verify(
+                &opt_result.optimized,
+                opt_result.new_code_start,
+                &opt_result.jumptab,
+                &opt_result.fntab,
+            );
+            assert!(result.is_ok(), "{:?}", result);
+        }
+    };
+}
+
+verify_pass_test!(var_decl);
+verify_pass_test!(array_decl);
+verify_pass_test!(array_mut_assign);
+verify_pass_test!(exprs);
+verify_pass_test!(fn_call);
+verify_pass_test!(fn_decl_valid);
+verify_pass_test!(fn_w_ret_stmt);
+verify_pass_test!(for_stmt);
+verify_pass_test!(for_stmt_fn_call);
+verify_pass_test!(if_stmt);
+verify_pass_test!(table_decl);
+verify_pass_test!(std_lib_calls);
+verify_pass_test!(scopes);
+verify_pass_test!(recursion);
+verify_pass_test!(while_stmt);
+verify_pass_test!(closures);
+
+#[test]
+fn verified_jump_to_start_runs() {
+    // A loop back to the first instr of the program passes verification, and runs until
+    // the vm stops it.
+    let prog = asm::assemble("lbl0:\n    ldc 1 r0\n    jmpa lbl0").unwrap();
+    let result = verify::verify(&prog.program, prog.code_start, &prog.jumptab, &prog.fntab);
+    assert!(result.is_ok(), "{:?}", result);
+
+    let conf = VMConfig {
+        trace: false,
+        initial_heap_size: 32,
+        initial_dreg_count: 64,
+        limits: Limits {
+            fuel: Some(100),
+            ..Limits::default()
+        },
+        io: Io::stdio(),
+    };
+    let mut vm = VM::init(
+        prog.program,
+        prog.code_start,
+        prog.jumptab,
+        prog.fntab,
+        conf,
+    );
+    match vm.run().err().unwrap().ty {
+        RuntimeErrTy::LimitExceeded(Limit::Fuel(100)) => {}
+        ty => panic!("unexpected error {:?}", ty),
+    };
+}
+verify_pass_test!(leading_while);
+
+#[test]
+fn large_program() {
+    // Registers are never reused, so a long program has as many registers as it has
+    // instrs, and many blocks. Verifying it should take about as long as compiling it.
+    let mut src = String::new();
+    for i in 0..5000 {
+        src.push_str(&format!(
+            "var a{} = {};\nif a{} > 5 {{ a{} = 1; }}\n",
+            i, i, i, i
+        ));
+    }
+
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new(src.as_bytes());
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+    let ast = parser.parse().ast.unwrap();
+    let compile_result = Compiler::new(&ast).compile();
+    assert!(compile_result.program.len() >= 40000);
+
+    let result = verify::verify(
+        &compile_result.program,
+        compile_result.code_start,
+        &compile_result.jumptab,
+        &compile_result.fntab,
+    );
+    assert!(result.is_ok(), "{:?}", result);
+}
+
+#[test]
+fn crafted_sifb_reg_out_of_range() {
+    // A register this high would make the vm try to allocate billions of registers, so
+    // a file using it loads but is rejected by the verifier.
+    let prog = asm::assemble("ldc 1 r4000000000\nfstpush r4000000000").unwrap();
+    let bytes = sifb::encode(&prog.program, prog.code_start, &prog.jumptab, &prog.fntab).unwrap();
+    let loaded = sifb::decode(&bytes).unwrap();
+
+    let errs = verify::verify(
+        &loaded.program,
+        loaded.code_start,
+        &loaded.jumptab,
+        &loaded.fntab,
+    )
+    .unwrap_err();
+    let tys: Vec<VerifyErrTy> = errs.into_iter().map(|e| e.ty).collect();
+    let err = VerifyErrTy::RegOutOfRange(4000000000, verify::DEFAULT_MAX_REG);
+    assert_eq!(tys, vec![err.clone(), err]);
+}
+
+#[test]
+fn verified_missing_table_key_errors() {
+    // Verified bytecode can still read a key that a table doesn't have, which is an error
+    // when it runs.
+    let prog = asm::assemble("stc {\"a\": 1} t\ntblg t b r0\nfstpush r0").unwrap();
+    let result = verify::verify(&prog.program, prog.code_start, &prog.jumptab, &prog.fntab);
+    assert!(result.is_ok(), "{:?}", result);
+
+    let conf = VMConfig {
+        trace: false,
+        initial_heap_size: 32,
+        initial_dreg_count: 64,
+        limits: Limits::default(),
+        io: Io::stdio(),
+    };
+    let mut vm = VM::init(
+        prog.program,
+        prog.code_start,
+        prog.jumptab,
+        prog.fntab,
+        conf,
+    );
+    match vm.run().err().unwrap().ty {
+        RuntimeErrTy::MissingKey(tab, key) => assert_eq!((tab.as_str(), key.as_str()), ("t", "b")),
+        ty => panic!("unexpected error {:?}", ty),
+    };
+}
@@ -1,14 +1,32 @@
 use sifc_bytecode::{compiler::Compiler, sifv::SifVal};
+use sifc_err::{
+    err::SifErr,
+    runtime_err::{Limit, RuntimeErr, RuntimeErrTy},
+    span::Span,
+};
 use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
-use sifc_vm::{config::VMConfig, vm::VM};
+use sifc_std::{
+    io::{Buffer, Io},
+    Std,
+};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    vm::VM,
+};
+use std::{collections::HashMap, time::Duration};
 
 // Expects a sif program str as input, returns a vm after running and asserting the
-// run completes successfully.
+// run completes successfully. A std library can be passed as a second arg, to run
+// programs that call host functions.
 macro_rules! vm_run {
     ($input:expr) => {{
+        vm_run!($input, Std::new())
+    }};
+    ($input:expr, $stdlib:expr) => {{
         let mut symtab = SymTab::new();
         let mut lex = Lexer::new($input.as_bytes());
-        let mut parser = Parser::new(&mut lex, &mut symtab);
+        let stdlib = $stdlib;
+        let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
 
         let parse_result = parser.parse();
         assert_eq!(parse_result.has_err, false);
@@ -22,6 +40,8 @@ macro_rules! vm_run {
             trace: false,
             initial_heap_size: 32,
             initial_dreg_count: 64,
+            limits: Limits::default(),
+            io: Io::stdio(),
         };
 
         let program = compile_result.program;
@@ -30,6 +50,7 @@ macro_rules! vm_run {
         let fntab = compile_result.fntab;
 
         let mut vm = VM::init(program, code_start, jumptab, fntab, conf);
+        vm.set_stdlib(stdlib);
         let vm_result = vm.run();
         assert!(vm_result.is_ok());
 
@@ -52,7 +73,7 @@ fn stn() {
 
 #[test]
 fn ldc() {
-    let mut vm = vm_run!("var y = 1 + 2;");
+    let vm = vm_run!("var y = 1 + 2;");
     assert_eq!(vm.inspect_dreg(0), Some(SifVal::Num(1.0)));
     assert_eq!(vm.inspect_dreg(1), Some(SifVal::Num(2.0)));
     assert_eq!(vm.inspect_dreg(2), Some(SifVal::Num(3.0)));
@@ -61,7 +82,7 @@ fn ldc() {
 
 #[test]
 fn ldn() {
-    let mut vm = vm_run!("var y = 1; var x = 2 + y;");
+    let vm = vm_run!("var y = 1; var x = 2 + y;");
     assert_eq!(vm.inspect_dreg(0), Some(SifVal::Num(2.0)));
     assert_eq!(vm.inspect_dreg(1), Some(SifVal::Num(1.0)));
     assert_eq!(vm.inspect_dreg(2), Some(SifVal::Num(3.0)));
@@ -71,7 +92,7 @@ fn ldn() {
 
 #[test]
 fn nneg() {
-    let mut vm = vm_run!("var y = -1;");
+    let vm = vm_run!("var y = -1;");
     assert_eq!(vm.inspect_dreg(0), Some(SifVal::Num(1.0)));
     assert_eq!(vm.inspect_dreg(1), Some(SifVal::Num(-1.0)));
     assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(-1.0)));
@@ -79,7 +100,7 @@ fn nneg() {
 
 #[test]
 fn lneg() {
-    let mut vm = vm_run!("var y = !true;");
+    let vm = vm_run!("var y = !true;");
     assert_eq!(vm.inspect_dreg(0), Some(SifVal::Bl(true)));
     assert_eq!(vm.inspect_dreg(1), Some(SifVal::Bl(false)));
     assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Bl(false)));
@@ -87,18 +108,777 @@ fn lneg() {
 
 #[test]
 fn stdcall_range() {
-    let mut vm = vm_run!("var y = @range(0,1);");
+    let vm = vm_run!("var y = @range(0,1);");
     let expected_vec = vec![SifVal::Num(0.0), SifVal::Num(1.0)];
     assert_eq!(vm.inspect_dreg(0), Some(SifVal::Num(0.0)));
     assert_eq!(vm.inspect_dreg(1), Some(SifVal::Num(1.0)));
-    assert_eq!(vm.inspect_dreg(2), Some(SifVal::Arr(expected_vec.clone())));
-    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Arr(expected_vec)));
+    assert_eq!(
+        vm.inspect_dreg(2),
+        Some(SifVal::new_arr(expected_vec.clone()))
+    );
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::new_arr(expected_vec)));
 }
 
 #[test]
 fn fncall() {
-    let mut vm = vm_run!("fn x() { return 1; } var y = x();");
-    assert_eq!(vm.inspect_dreg(0), Some(SifVal::Num(1.0)));
+    let vm = vm_run!("fn x() { return 1; } var y = x();");
+    // r0 is written in the frame of x, and is not visible after the call returns.
+    assert_eq!(vm.inspect_dreg(0), None);
     assert_eq!(vm.inspect_dreg(1), Some(SifVal::Num(1.0)));
     assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(1.0)));
 }
+
+#[test]
+fn fncall_param_order() {
+    let vm = vm_run!("fn sub(a, b) { return a - b; } var y = sub(10, 4);");
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(6.0)));
+}
+
+#[test]
+fn fncall_no_ret_val() {
+    let vm = vm_run!("fn x() {} fn y(a, b) { return b; } var z = y(1, x());");
+    assert_eq!(vm.inspect_heap("z"), Some(&SifVal::Null));
+}
+
+#[test]
+fn fncall_recursive() {
+    let vm = vm_run!(
+        "fn fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); } var y = fib(15);"
+    );
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(610.0)));
+    assert_eq!(vm.inspect_heap("n"), None);
+}
+
+#[test]
+fn fncall_locals() {
+    let vm = vm_run!("var x = 1; var g = 0; fn f() { var x = 5; g = x; } f(); var h = x;");
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(1.0)));
+    assert_eq!(vm.inspect_heap("g"), Some(&SifVal::Num(5.0)));
+    assert_eq!(vm.inspect_heap("h"), Some(&SifVal::Num(1.0)));
+}
+
+#[test]
+fn while_loop() {
+    let vm = vm_run!("var x = 0; var y = 1; while x < 5 { x = x + 1; y = y * 2; }");
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(5.0)));
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(32.0)));
+}
+
+#[test]
+fn while_break_continue() {
+    let vm = vm_run!(
+        "var x = 0; var y = 0; while true { x = x + 1; if x == 2 { continue; } if x > 4 { break; } y = y + x; }"
+    );
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(5.0)));
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(8.0)));
+}
+
+#[test]
+fn for_break_continue() {
+    let vm = vm_run!(
+        "var a = [1,2,3,4,5]; var y = 0; for i, v in a { if v == 2 { continue; } if v == 4 { break; } y = y + v; }"
+    );
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(4.0)));
+}
+
+#[test]
+fn nested_loop_break() {
+    let vm = vm_run!(
+        "var a = [1,2,3]; var y = 0; var x = 0; while x < 3 { x = x + 1; for i, v in a { if v == 2 { break; } y = y + v; } }"
+    );
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(3.0)));
+}
+
+#[test]
+fn while_in_fn() {
+    let vm = vm_run!("fn f(n) { var i = 0; while i < n { i = i + 1; } return i; } var y = f(7);");
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(7.0)));
+}
+
+#[test]
+fn fn_expr_call() {
+    let vm = vm_run!("var f = fn(x, y) { return x - y; }; var z = f(10, 4);");
+    assert_eq!(vm.inspect_heap("z"), Some(&SifVal::Num(6.0)));
+}
+
+#[test]
+fn fn_decl_as_val() {
+    let vm = vm_run!(
+        "fn double(x) { return x * 2; } fn apply(f, x) { return f(x); } var y = apply(double, 4);"
+    );
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(8.0)));
+}
+
+#[test]
+fn closure_capture() {
+    let vm = vm_run!(
+        "fn adder(n) { return fn(x) { return x + n; }; } var a = adder(1); var b = adder(10); var y = a(5); var z = b(5);"
+    );
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(6.0)));
+    assert_eq!(vm.inspect_heap("z"), Some(&SifVal::Num(15.0)));
+    assert_eq!(vm.inspect_heap("n"), None);
+}
+
+#[test]
+fn fn_global_array() {
+    let vm = vm_run!("var g = [1, 2]; var i = 0; fn f() { g[i] = 5; i = 1; } f();");
+    let expected = vec![SifVal::Num(5.0), SifVal::Num(2.0)];
+    assert_eq!(vm.inspect_heap("g"), Some(&SifVal::new_arr(expected)));
+    assert_eq!(vm.inspect_heap("i"), Some(&SifVal::Num(1.0)));
+}
+
+#[test]
+fn closure_capture_by_val() {
+    let vm = vm_run!(
+        "fn f() { var c = 1; var g = fn() { return c; }; c = 2; return g(); } var y = f();"
+    );
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(1.0)));
+}
+
+#[test]
+fn closure_nested_capture() {
+    let vm = vm_run!(
+        "fn f(n) { var g = fn() { var h = fn() { return n * 3; }; return h(); }; return g(); } var y = f(3);"
+    );
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(9.0)));
+}
+
+#[test]
+fn array_alias() {
+    let vm = vm_run!("var a = [1, 2]; var b = a; b[0] = 9;");
+    let expected = SifVal::new_arr(vec![SifVal::Num(9.0), SifVal::Num(2.0)]);
+    assert_eq!(vm.inspect_heap("a"), Some(&expected));
+    assert_eq!(vm.inspect_heap("b"), Some(&expected));
+}
+
+#[test]
+fn array_fn_param_alias() {
+    let vm = vm_run!("var a = [1, 2]; fn set(arr, v) { arr[1] = v; } set(a, 7);");
+    let expected = SifVal::new_arr(vec![SifVal::Num(1.0), SifVal::Num(7.0)]);
+    assert_eq!(vm.inspect_heap("a"), Some(&expected));
+}
+
+#[test]
+fn array_literal_new_each_eval() {
+    let vm = vm_run!(
+        "fn mk() { var x = [0, 0]; return x; } var m = mk(); var n = mk(); m[1] = 3; var i = 0; var keep = 0; while i < 2 { var c = [0]; if i == 0 { keep = c; } c[0] = i + 10; i = i + 1; }"
+    );
+    assert_eq!(
+        vm.inspect_heap("m"),
+        Some(&SifVal::new_arr(vec![SifVal::Num(0.0), SifVal::Num(3.0)]))
+    );
+    assert_eq!(
+        vm.inspect_heap("n"),
+        Some(&SifVal::new_arr(vec![SifVal::Num(0.0), SifVal::Num(0.0)]))
+    );
+    assert_eq!(
+        vm.inspect_heap("keep"),
+        Some(&SifVal::new_arr(vec![SifVal::Num(10.0)]))
+    );
+}
+
+#[test]
+fn std_range() {
+    let vm = vm_run!("var r = @range(2, 4);");
+    assert_eq!(
+        vm.inspect_heap("r"),
+        Some(&SifVal::new_arr(vec![
+            SifVal::Num(2.0),
+            SifVal::Num(3.0),
+            SifVal::Num(4.0)
+        ]))
+    );
+}
+
+#[test]
+fn host_fn() {
+    let mut stdlib = Std::new();
+    stdlib.register("lookup", 1, |params| match &params[0] {
+        SifVal::Num(id) => Ok(SifVal::Str(format!("user{}", id))),
+        _ => Ok(SifVal::Null),
+    });
+
+    let vm = vm_run!("var u = @lookup(7);", stdlib);
+    assert_eq!(
+        vm.inspect_heap("u"),
+        Some(&SifVal::Str(String::from("user7")))
+    );
+}
+
+#[test]
+fn host_fn_param_order() {
+    let mut stdlib = Std::new();
+    stdlib.register("sub", 2, |params| {
+        Ok(SifVal::Num(
+            params[0].extract_num() - params[1].extract_num(),
+        ))
+    });
+
+    let vm = vm_run!("var x = @sub(10, 4);", stdlib);
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(6.0)));
+}
+
+#[test]
+fn host_fn_replaces_std() {
+    let mut stdlib = Std::new();
+    stdlib.register("print", 1, |_| Ok(SifVal::Bl(true)));
+
+    let vm = vm_run!("var p = @print(1);", stdlib);
+    assert_eq!(vm.inspect_heap("p"), Some(&SifVal::Bl(true)));
+}
+
+#[test]
+fn host_fn_err() {
+    let mut stdlib = Std::new();
+    stdlib.register("fail", 0, |_| {
+        Err(RuntimeErr::new(RuntimeErrTy::HostFnErr(String::from(
+            "lookup failed",
+        ))))
+    });
+
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new("var x = 1;\n@fail();".as_bytes());
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+    let parse_result = parser.parse();
+    assert!(!parse_result.has_err);
+
+    let ast = parse_result.ast.unwrap();
+    let mut compiler = Compiler::new(&ast);
+    let compile_result = compiler.compile();
+
+    let conf = VMConfig {
+        trace: false,
+        initial_heap_size: 32,
+        initial_dreg_count: 64,
+        limits: Limits::default(),
+        io: Io::stdio(),
+    };
+    let mut vm = VM::init(
+        compile_result.program,
+        compile_result.code_start,
+        compile_result.jumptab,
+        compile_result.fntab,
+        conf,
+    );
+    vm.set_stdlib(stdlib);
+
+    match vm.run() {
+        Ok(()) => panic!("expected host fn error"),
+        Err(e) => {
+            assert_eq!(e.to_msg(), "lookup failed");
+            assert_eq!(e.span.line, 2);
+        }
+    };
+}
+
+// Compiles a sif program str against a symbol table kept between programs, and loads
+// it into the vm in the same way as the repl. Returns the result of running it.
+fn load_run(
+    vm: &mut VM,
+    symtab: &mut SymTab,
+    anoncnt: &mut usize,
+    input: &str,
+) -> Result<(), RuntimeErr> {
+    let stdlib = Std::new();
+    let mut lex = Lexer::new(input.as_bytes());
+    let mut parser = Parser::new(&mut lex, symtab, &stdlib);
+    let parse_result = parser.parse();
+    assert!(!parse_result.has_err);
+
+    let ast = parse_result.ast.unwrap();
+    let mut compiler = Compiler::new(&ast);
+    compiler.resume_anon(*anoncnt);
+    let compile_result = compiler.compile();
+    assert!(compile_result.err.is_none());
+    *anoncnt = compiler.anon_count();
+
+    vm.load(
+        compile_result.program,
+        compile_result.code_start,
+        compile_result.jumptab,
+        compile_result.fntab,
+    );
+    vm.run()
+}
+
+fn empty_vm() -> VM {
+    let conf = VMConfig {
+        trace: false,
+        initial_heap_size: 32,
+        initial_dreg_count: 64,
+        limits: Limits::default(),
+        io: Io::stdio(),
+    };
+    VM::init(Vec::new(), 0, HashMap::new(), HashMap::new(), conf)
+}
+
+#[test]
+fn load_keeps_globals() {
+    let mut vm = empty_vm();
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "var x = 1;").is_ok());
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "var y = x + 1; x = 5;").is_ok());
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(5.0)));
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(2.0)));
+}
+
+#[test]
+fn load_keeps_fns() {
+    let mut vm = empty_vm();
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    let decl = "fn double(a) { var b = a * 2; return b; }";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, decl).is_ok());
+    let loop_src = "var total = 0; for i, v in @range(1, 3) { total = total + double(v); }";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, loop_src).is_ok());
+    assert_eq!(vm.inspect_heap("total"), Some(&SifVal::Num(12.0)));
+
+    // A new declaration replaces the old fn.
+    let redecl = "fn double(a) { return a + a + a; }";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, redecl).is_ok());
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "var x = double(2);").is_ok());
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(6.0)));
+}
+
+#[test]
+fn load_anon_fns() {
+    let mut vm = empty_vm();
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    let first = "var f = fn(a) { return a * 2; };";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, first).is_ok());
+    let second = "var g = fn(a) { return a + 1; };";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, second).is_ok());
+    assert_eq!(anoncnt, 2);
+
+    let calls = "var x = f(5); var y = g(5);";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, calls).is_ok());
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(10.0)));
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(6.0)));
+}
+
+#[test]
+fn load_after_err() {
+    let mut vm = empty_vm();
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    let decl = "fn bad(a) { var b = a * \"s\"; return b; }";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, decl).is_ok());
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "var x = bad(1);").is_err());
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "var y = 1;").is_ok());
+    assert_eq!(vm.inspect_heap("y"), Some(&SifVal::Num(1.0)));
+}
+
+#[test]
+fn load_leading_while() {
+    // Each repl input is compiled on its own, so a loop can be the first instr of a program.
+    let mut vm = empty_vm();
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "while false {}").is_ok());
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "var x = 0;").is_ok());
+    let src = "while x < 5 { x = x + 1; }";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, src).is_ok());
+    assert_eq!(vm.inspect_heap("x"), Some(&SifVal::Num(5.0)));
+}
+
+#[test]
+fn runtime_err_span() {
+    let mut vm = empty_vm();
+    vm.set_file("test.sif");
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    let src = "fn f(x) {\n  return x * \"s\";\n}\nvar y = f(1);";
+    let err = load_run(&mut vm, &mut symtab, &mut anoncnt, src)
+        .err()
+        .unwrap();
+    assert_eq!(err.span, Span::new(2, 12));
+    assert_eq!(err.file, Some(String::from("test.sif")));
+    assert_eq!(err.span.with_file(err.file.as_deref()), "test.sif:2:12");
+}
+
+#[test]
+fn runtime_err_trace() {
+    let mut vm = empty_vm();
+    vm.set_file("test.sif");
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    let src = "fn g(x) {\n  return x * \"s\";\n}\nfn f(x) {\n  return g(x) + 1;\n}\nvar y = f(1);";
+    let err = load_run(&mut vm, &mut symtab, &mut anoncnt, src)
+        .err()
+        .unwrap();
+    let trace: Vec<(Option<&str>, Span, Span)> = err
+        .trace
+        .iter()
+        .map(|t| (t.name.as_deref(), t.span, t.decl_span))
+        .collect();
+    assert_eq!(
+        trace,
+        vec![
+            (Some("g"), Span::new(2, 12), Span::new(1, 4)),
+            (Some("f"), Span::new(5, 10), Span::new(4, 4)),
+            (None, Span::new(7, 9), Span::default()),
+        ]
+    );
+    assert_eq!(
+        err.backtrace(),
+        "stack backtrace:\n  \
+         0: g at test.sif:2:12 (declared at test.sif:1:4)\n  \
+         1: f at test.sif:5:10 (declared at test.sif:4:4)\n  \
+         2: <code> at test.sif:7:9\n"
+    );
+}
+
+#[test]
+fn runtime_err_no_trace_in_code() {
+    let mut vm = empty_vm();
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    let err = load_run(&mut vm, &mut symtab, &mut anoncnt, "var y = 1 * \"s\";")
+        .err()
+        .unwrap();
+    assert_eq!(err.trace.len(), 1);
+    assert_eq!(err.backtrace(), "");
+}
+
+#[test]
+fn call_fn_from_host() {
+    let mut vm = vm_run!(
+        "var base = 10;
+         fn score(points, mult) {
+           var bonus = 0;
+           if points > 1 {
+             bonus = 1;
+           }
+           return base * mult + bonus;
+         }
+         fn nothing() {}"
+    );
+
+    let val = vm.call_fn("score", vec![SifVal::Num(2.0), SifVal::Num(3.0)]);
+    assert_eq!(val.unwrap(), SifVal::Num(31.0));
+    let val = vm.call_fn("score", vec![SifVal::Num(1.0), SifVal::Num(2.0)]);
+    assert_eq!(val.unwrap(), SifVal::Num(20.0));
+    assert_eq!(vm.call_fn("nothing", Vec::new()).unwrap(), SifVal::Null);
+    assert_eq!(vm.call_depth(), 0);
+}
+
+#[test]
+fn call_fn_err_unwinds() {
+    let mut vm = vm_run!(
+        "fn inner(x) {
+           return x * \"s\";
+         }
+         fn outer(x) {
+           return inner(x) + 1;
+         }
+         fn id(x) {
+           return x;
+         }"
+    );
+
+    let err = vm.call_fn("outer", vec![SifVal::Num(1.0)]).err().unwrap();
+    match err.ty {
+        RuntimeErrTy::TyMismatch => assert_eq!(err.span.line, 2),
+        ty => panic!("unexpected error {:?}", ty),
+    };
+    assert_eq!(err.trace[0].name.as_deref(), Some("inner"));
+    assert_eq!(err.trace[1].name.as_deref(), Some("outer"));
+    assert_eq!(vm.call_depth(), 0);
+
+    match vm.call_fn("id", Vec::new()).err().unwrap().ty {
+        RuntimeErrTy::WrongFnParamCnt(name, 1, 0) => assert_eq!(name, "id"),
+        ty => panic!("unexpected error {:?}", ty),
+    };
+    match vm.call_fn("missing", Vec::new()).err().unwrap().ty {
+        RuntimeErrTy::InvalidFnSym(name) => assert_eq!(name, "missing"),
+        ty => panic!("unexpected error {:?}", ty),
+    };
+
+    // The vm can still be called after errors.
+    assert_eq!(
+        vm.call_fn("id", vec![SifVal::Num(4.0)]).unwrap(),
+        SifVal::Num(4.0)
+    );
+}
+
+fn limited_vm(limits: Limits) -> VM {
+    let conf = VMConfig {
+        trace: false,
+        initial_heap_size: 32,
+        initial_dreg_count: 64,
+        limits,
+        io: Io::stdio(),
+    };
+    VM::init(Vec::new(), 0, HashMap::new(), HashMap::new(), conf)
+}
+
+// Runs a sif program str in a vm with limits, and returns the limit that stopped it.
+fn exceeded_limit(limits: Limits, input: &str) -> Limit {
+    let mut vm = limited_vm(limits);
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+    match load_run(&mut vm, &mut symtab, &mut anoncnt, input) {
+        Ok(()) => panic!("expected a limit to be exceeded"),
+        Err(e) => match e.ty {
+            RuntimeErrTy::LimitExceeded(limit) => limit,
+            ty => panic!("unexpected error {:?}", ty),
+        },
+    }
+}
+
+#[test]
+fn fuel_limit() {
+    let limits = Limits {
+        fuel: Some(1000),
+        ..Limits::default()
+    };
+    let limit = exceeded_limit(limits.clone(), "var i = 0;\nwhile true {\n  i = i + 1;\n}");
+    assert_eq!(limit, Limit::Fuel(1000));
+
+    // Fuel is refilled for each run and each host call.
+    let mut vm = limited_vm(limits);
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+    let decl = "fn count(n) { var i = 0; while i < n { i = i + 1; } return i; }";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, decl).is_ok());
+    for _ in 0..3 {
+        assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "var x = count(50);").is_ok());
+        assert_eq!(
+            vm.call_fn("count", vec![SifVal::Num(50.0)]).unwrap(),
+            SifVal::Num(50.0)
+        );
+    }
+    match vm
+        .call_fn("count", vec![SifVal::Num(1000.0)])
+        .err()
+        .unwrap()
+        .ty
+    {
+        RuntimeErrTy::LimitExceeded(Limit::Fuel(1000)) => {}
+        ty => panic!("unexpected error {:?}", ty),
+    };
+    assert_eq!(vm.call_depth(), 0);
+}
+
+#[test]
+fn timeout_limit() {
+    let limits = Limits {
+        timeout: Some(Duration::from_millis(20)),
+        ..Limits::default()
+    };
+    let limit = exceeded_limit(limits, "var i = 0;\nwhile true {\n  i = i + 1;\n}");
+    assert_eq!(limit, Limit::Timeout(Duration::from_millis(20)));
+}
+
+#[test]
+fn dreg_limit() {
+    let limits = Limits {
+        max_dregs: Some(1),
+        ..Limits::default()
+    };
+    let limit = exceeded_limit(limits, "var a = 1;\nvar b = a + a;");
+    assert_eq!(limit, Limit::Dregs(1));
+}
+
+#[test]
+fn heap_limit() {
+    let limits = Limits {
+        max_heap: Some(2),
+        ..Limits::default()
+    };
+    let mut vm = limited_vm(limits);
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "var a = 1;\nvar b = 2;").is_ok());
+    let err = load_run(&mut vm, &mut symtab, &mut anoncnt, "var c = 3;")
+        .err()
+        .unwrap();
+    match err.ty {
+        RuntimeErrTy::LimitExceeded(Limit::Heap(2)) => assert_eq!(err.span.line, 1),
+        ty => panic!("unexpected error {:?}", ty),
+    };
+    assert_eq!(err.code(), "E0418");
+}
+
+#[test]
+fn dreg_limit_counts_live_registers() {
+    // Registers are numbered across the program, so the fn declared last uses registers
+    // with high numbers, but only a few of them hold a value when it's called.
+    let mut input = String::new();
+    for i in 0..300 {
+        input.push_str(&format!(
+            "fn f{}(x) {{\n  var y = x + {};\n  return y;\n}}\n",
+            i, i
+        ));
+    }
+    input.push_str("var r = f299(1);\n");
+    let limits = Limits {
+        max_dregs: Some(16),
+        ..Limits::default()
+    };
+    let mut vm = limited_vm(limits);
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, &input).is_ok());
+    assert_eq!(vm.inspect_heap("r"), Some(&SifVal::Num(300.0)));
+}
+
+#[test]
+fn heap_limit_counts_stored_globals() {
+    // Every global in the program is given a slot, but the globals that are never stored
+    // don't count towards the limit.
+    let mut input = String::from("if false {\n");
+    for i in 0..1000 {
+        input.push_str(&format!("  var g{} = {};\n", i, i));
+    }
+    input.push_str("}\nvar a = 1;\nvar b = 2;\n");
+    let limits = Limits {
+        max_heap: Some(2),
+        ..Limits::default()
+    };
+    let mut vm = limited_vm(limits);
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, &input).is_ok());
+
+    // Storing to a global again doesn't count it twice.
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "a = 3;\nb = 4;").is_ok());
+    let err = load_run(&mut vm, &mut symtab, &mut anoncnt, "var c = 5;")
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.ty,
+        RuntimeErrTy::LimitExceeded(Limit::Heap(2))
+    ));
+}
+
+#[test]
+fn collection_limit() {
+    let limits = Limits {
+        max_collection_len: Some(3),
+        ..Limits::default()
+    };
+    let arr = exceeded_limit(limits.clone(), "var a = [1, 2, 3, 4];");
+    assert_eq!(arr, Limit::CollectionLen(3));
+    let range = exceeded_limit(limits.clone(), "var a = @range(1, 10);");
+    assert_eq!(range, Limit::CollectionLen(3));
+    let tab = exceeded_limit(
+        limits.clone(),
+        "var t = [[a => 1, b => 2, c => 3, d => 4]];",
+    );
+    assert_eq!(tab, Limit::CollectionLen(3));
+
+    // Ranges are checked before they're built, so huge ranges fail without allocating.
+    let huge = exceeded_limit(limits.clone(), "var a = @range(1, 300000000);");
+    assert_eq!(huge, Limit::CollectionLen(3));
+    let clamped = exceeded_limit(limits.clone(), "var a = @range(0, 1e19);");
+    assert_eq!(clamped, Limit::CollectionLen(3));
+
+    // Collections at the limit are fine, and a full table can still update its keys.
+    let mut vm = limited_vm(limits);
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+    let src = "var a = @range(1, 3);\nvar t = [[a => 1, b => 2, c => 3, c => 4]];";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, src).is_ok());
+}
+
+#[test]
+fn range_bounds_saturate() {
+    // Bounds past the range of integers are clamped instead of overflowing.
+    let vm = vm_run!("var a = @range(1e19, 1e19);\nvar b = @range(5, 1);");
+    let expected = SifVal::new_arr(vec![SifVal::Num(i64::MAX as f64)]);
+    assert_eq!(vm.inspect_heap("a"), Some(&expected));
+    assert_eq!(vm.inspect_heap("b"), Some(&SifVal::new_arr(Vec::new())));
+}
+
+fn io_vm(io: Io, trace: bool) -> VM {
+    let conf = VMConfig {
+        trace,
+        initial_heap_size: 32,
+        initial_dreg_count: 64,
+        limits: Limits::default(),
+        io,
+    };
+    VM::init(Vec::new(), 0, HashMap::new(), HashMap::new(), conf)
+}
+
+#[test]
+fn std_io_uses_vm_handles() {
+    let out = Buffer::new();
+    let input = "first\r\nsecond\n".as_bytes();
+    let mut vm = io_vm(Io::stdio().with_out(out.clone()).with_input(input), false);
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    let src = "var a = @readln();
+               var b = @readln();
+               var c = @readln();
+               @print(a);
+               @print(b);
+               @print(c);
+               var d = [1, 2];
+               @print(d);";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, src).is_ok());
+    assert_eq!(
+        vm.inspect_heap("a"),
+        Some(&SifVal::Str(String::from("first")))
+    );
+    assert_eq!(vm.inspect_heap("c"), Some(&SifVal::Null));
+    assert_eq!(out.contents(), "first\nsecond\nnull\n[1, 2]\n");
+}
+
+#[test]
+fn trace_uses_vm_handles() {
+    let out = Buffer::new();
+    let mut vm = io_vm(Io::stdio().with_out(out.clone()), true);
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    vm.set_file("trace.sif");
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, "var x = 1;\n@print(x);").is_ok());
+    let lines: Vec<String> = out.contents().lines().map(String::from).collect();
+    assert!(lines[0].starts_with("EXEC [trace.sif:1:"));
+    assert!(lines.iter().any(|l| l == "1"));
+    assert!(lines.last().unwrap().starts_with("EXEC [trace.sif:2:"));
+}
+
+#[test]
+fn cyclic_store() {
+    let mut vm = empty_vm();
+    let mut symtab = SymTab::new();
+    let mut anoncnt = 0;
+
+    let decl = "fn wrap(x) { var r = [0]; r[0] = x; return r; }\nvar a = [1, 2];";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, decl).is_ok());
+
+    // A collection can't be stored in itself, directly or through other collections.
+    for src in [
+        "a[0] = a;",
+        "var b = wrap(a);\na[0] = b;",
+        "var c = wrap(wrap(a));\na[1] = c;",
+        "var t = [[x => 1]];\nvar t = [[y => t]];",
+    ] {
+        let err = load_run(&mut vm, &mut symtab, &mut anoncnt, src)
+            .err()
+            .unwrap();
+        match err.ty {
+            RuntimeErrTy::CyclicStore(_) => assert_eq!(err.code(), "E0420"),
+            ty => panic!("unexpected error {:?} for {}", ty, src),
+        };
+    }
+
+    // The same collection can still be stored many times in another one.
+    let src = "var d = [0, 0];\nd[0] = a;\nd[1] = a;";
+    assert!(load_run(&mut vm, &mut symtab, &mut anoncnt, src).is_ok());
+    assert_eq!(vm.inspect_heap("a").unwrap().to_string(), "[1, 2]");
+}
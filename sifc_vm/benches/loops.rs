@@ -0,0 +1,113 @@
+//! Benchmarks for loop heavy scripts. These spend nearly all of their time in the
+//! execution loop of the vm, moving values between registers and the heap.
+//! Run with `cargo bench -p sifc_vm`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use sifc_bytecode::compiler::{CompileResult, Compiler};
+use sifc_parse::{lex::Lexer, parser::Parser, symtab::SymTab};
+use sifc_std::{io::Io, Std};
+use sifc_vm::{
+    config::{Limits, VMConfig},
+    vm::VM,
+};
+
+const COUNT_LOOP: &str = "
+var x = 0;
+var sum = 0;
+while x < 100000 {
+  sum = sum + x * 2 - 1;
+  x = x + 1;
+}
+";
+
+const NESTED_LOOP: &str = "
+var i = 0;
+var total = 0;
+while i < 300 {
+  var j = 0;
+  while j < 300 {
+    if j % 2 == 0 {
+      total = total + j;
+    } else {
+      total = total - 1;
+    }
+    j = j + 1;
+  }
+  i = i + 1;
+}
+";
+
+const CALL_LOOP: &str = "
+fn add(a, b) {
+  return a + b;
+}
+
+var x = 0;
+var acc = 0;
+while x < 20000 {
+  acc = add(acc, x);
+  x = x + 1;
+}
+";
+
+const ARRAY_LOOP: &str = "
+var arr = @range(1, 5000);
+var i = 0;
+while i < 5000 {
+  arr[i] = i * 2;
+  i = i + 1;
+}
+";
+
+fn compile(src: &str) -> CompileResult {
+    let mut symtab = SymTab::new();
+    let mut lex = Lexer::new(src.as_bytes());
+    let stdlib = Std::new();
+    let mut parser = Parser::new(&mut lex, &mut symtab, &stdlib);
+
+    let parse_result = parser.parse();
+    assert!(!parse_result.has_err);
+
+    let ast = parse_result.ast.unwrap();
+    let mut compiler = Compiler::new(&ast);
+    let result = compiler.compile();
+    assert!(result.err.is_none());
+    result
+}
+
+fn bench_script(c: &mut Criterion, name: &str, src: &str) {
+    let compiled = compile(src);
+
+    c.bench_function(name, |b| {
+        b.iter_batched(
+            || {
+                let conf = VMConfig {
+                    trace: false,
+                    initial_heap_size: 32,
+                    initial_dreg_count: 64,
+                    limits: Limits::default(),
+                    io: Io::stdio(),
+                };
+                VM::init(
+                    compiled.program.clone(),
+                    compiled.code_start,
+                    compiled.jumptab.clone(),
+                    compiled.fntab.clone(),
+                    conf,
+                )
+            },
+            |mut vm| vm.run().unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn loops(c: &mut Criterion) {
+    bench_script(c, "count_loop", COUNT_LOOP);
+    bench_script(c, "nested_loop", NESTED_LOOP);
+    bench_script(c, "call_loop", CALL_LOOP);
+    bench_script(c, "array_loop", ARRAY_LOOP);
+}
+
+criterion_group!(benches, loops);
+criterion_main!(benches);
@@ -1,3 +1,6 @@
+use sifc_std::io::Io;
+use std::time::Duration;
+
 #[derive(Clone, Debug)]
 pub struct VMConfig {
     /// Indicates whether or not the VM should trace execution and print
@@ -9,4 +12,37 @@ pub struct VMConfig {
 
     /// Starting amount of data registers to make when creating the VM.
     pub initial_dreg_count: usize,
+
+    /// Budgets for the time and memory that a program can use. A program that goes over
+    /// one of them stops with a LimitExceeded error.
+    pub limits: Limits,
+
+    /// Handles that the std library reads input from and prints to, and that traced instrs
+    /// are written to. Use Io::stdio() to read from stdin and print to stdout.
+    pub io: Io,
+}
+
+/// Limits bound the work a program can do and the memory it can use, so that a host can
+/// run scripts that might never finish or might grow without bound. Each limit is
+/// disabled when it's None, which is the default.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// Maximum number of instrs executed by each run of the program, or each call into
+    /// it from the host.
+    pub fuel: Option<u64>,
+
+    /// Maximum wall clock time of each run of the program, or each call into it from the
+    /// host. This is checked periodically, so a run can go slightly over it.
+    pub timeout: Option<Duration>,
+
+    /// Maximum number of data registers holding a value in a single call.
+    pub max_dregs: Option<usize>,
+
+    /// Maximum number of globals stored in the heap.
+    pub max_heap: Option<usize>,
+
+    /// Maximum number of items in an array, or entries in a table. Collections are
+    /// checked when they're loaded into a register, returned from the std library or
+    /// inserted into.
+    pub max_collection_len: Option<usize>,
 }
@@ -0,0 +1,163 @@
+use crate::vm::VM;
+use sifc_err::{runtime_err::RuntimeErr, span::Span};
+use std::collections::BTreeSet;
+
+/// Stop is the reason that the debugger paused the program.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    /// The program reached a source line with a breakpoint on it.
+    Breakpoint(usize),
+
+    /// A step finished at the start of a source line.
+    Step(usize),
+
+    /// There are no more instrs to execute.
+    Finished,
+}
+
+/// StepMode determines where a resumed program pauses next, if it doesn't reach a
+/// breakpoint first.
+#[derive(Clone, Copy, PartialEq)]
+enum StepMode {
+    /// Run until a breakpoint or the end of the program.
+    Continue,
+
+    /// Pause at the next source line, including lines inside of called functions.
+    Into,
+
+    /// Pause at the next source line in the current function or one of its callers.
+    Over,
+
+    /// Pause once the current function has returned to its caller.
+    Out,
+}
+
+/// Debugger runs a program in the vm one source line at a time. Programs pause at the
+/// start of a source line, which is the first instr executed with that line after an instr
+/// with a different line, or the first instr of a call. Returning from a call continues the
+/// line that made it, so the caller is only paused in when stepping out of the function
+/// that the program was paused in. Instrs without a source line never pause the program.
+///
+/// Breakpoints are set on source lines. They're checked whenever the program is resumed,
+/// whether it is continued or stepped.
+pub struct Debugger {
+    /// Vm executing the program. It can be inspected while the program is paused.
+    vm: VM,
+
+    /// Source lines that the program pauses at.
+    breakpoints: BTreeSet<usize>,
+}
+
+impl Debugger {
+    pub fn new(vm: VM) -> Debugger {
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    /// Returns the source line of the next instr to execute, or 0 if it isn't known.
+    pub fn line(&self) -> usize {
+        self.span().line
+    }
+
+    /// Returns the source span of the next instr to execute.
+    pub fn span(&self) -> Span {
+        match self.vm.current_instr() {
+            Some(instr) => instr.span,
+            None => Span::default(),
+        }
+    }
+
+    /// Adds a breakpoint, returning false if there was already one on the line.
+    pub fn add_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.insert(line)
+    }
+
+    /// Removes a breakpoint, returning false if there wasn't one on the line.
+    pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    /// Returns the lines with breakpoints on them, in order.
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    /// Runs the program until it reaches a breakpoint or finishes.
+    pub fn cont(&mut self) -> Result<Stop, RuntimeErr> {
+        self.resume(StepMode::Continue)
+    }
+
+    /// Runs the program until the start of the next source line, stepping into any
+    /// function that is called.
+    pub fn step_into(&mut self) -> Result<Stop, RuntimeErr> {
+        self.resume(StepMode::Into)
+    }
+
+    /// Runs the program until the start of the next source line, without pausing inside
+    /// any function that is called unless it reaches a breakpoint.
+    pub fn step_over(&mut self) -> Result<Stop, RuntimeErr> {
+        self.resume(StepMode::Over)
+    }
+
+    /// Runs the program until the current function returns. In the code section, this
+    /// runs until a breakpoint or the end of the program.
+    pub fn step_out(&mut self) -> Result<Stop, RuntimeErr> {
+        self.resume(StepMode::Out)
+    }
+
+    /// Executes at least one instr, and continues until the program reaches the start of
+    /// a line that it should pause at. Errors stop the program at the instr that failed.
+    fn resume(&mut self, mode: StepMode) -> Result<Stop, RuntimeErr> {
+        let start_depth = self.vm.call_depth();
+        let mut prev_line = self.line();
+        let mut prev_depth = start_depth;
+
+        loop {
+            self.vm.step()?;
+            if self.vm.is_finished() {
+                return Ok(Stop::Finished);
+            }
+
+            let line = self.line();
+            let depth = self.vm.call_depth();
+            if line == 0 {
+                continue;
+            }
+
+            let returned = depth < prev_depth;
+            let starts_line = line != prev_line || depth > prev_depth;
+            prev_line = line;
+            prev_depth = depth;
+
+            if returned {
+                match mode != StepMode::Continue && depth < start_depth {
+                    true => return Ok(Stop::Step(line)),
+                    false => continue,
+                };
+            }
+            if !starts_line {
+                continue;
+            }
+
+            if self.breakpoints.contains(&line) {
+                return Ok(Stop::Breakpoint(line));
+            }
+
+            let should_stop = match mode {
+                StepMode::Continue => false,
+                StepMode::Into => true,
+                StepMode::Over => depth <= start_depth,
+                StepMode::Out => false,
+            };
+            if should_stop {
+                return Ok(Stop::Step(line));
+            }
+        }
+    }
+}
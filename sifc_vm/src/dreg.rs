@@ -1,78 +1,84 @@
 use sifc_bytecode::sifv::SifVal;
-use std::{cell::RefCell, rc::Rc};
-
-type DRegVec = Vec<Rc<RefCell<DReg>>>;
-
-/// DReg represents a data register.
-#[derive(Clone, Debug)]
-pub struct DReg {
-    pub name: String,
-    pub cont: Option<SifVal>,
-}
-
-impl DReg {
-    pub fn new(n: String) -> DReg {
-        DReg {
-            name: n,
-            cont: None,
-        }
-    }
-}
 
+/// DataRegisterList is the register file of the vm. Registers are stored contiguously in
+/// a single vector and are indexed directly by register number. A register that has not
+/// been written to yet contains no value, and reading it returns None.
 #[derive(Clone, Debug)]
 pub struct DataRegisterList {
-    initial_size: usize,
+    /// Number of times the list has grown. Each expansion grows the list by a multiple
+    /// of this count, so that repeated writes to high registers don't expand every time.
     alloc_count: usize,
-    dregs: DRegVec,
+
+    /// Contents of each register.
+    dregs: Vec<Option<SifVal>>,
+
+    /// Number of registers that contain a value.
+    live: usize,
 }
 
 impl DataRegisterList {
     pub fn init(initial_size: usize) -> DataRegisterList {
         let mut regs = Vec::with_capacity(initial_size);
-
-        for i in 0..initial_size {
-            let reg = DReg::new(format!("r{}", i));
-            regs.push(Rc::new(RefCell::new(reg)));
-        }
+        regs.resize_with(initial_size, || None);
 
         DataRegisterList {
-            initial_size: initial_size,
             alloc_count: 0,
             dregs: regs,
+            live: 0,
         }
     }
 
-    pub fn get(&mut self, index: usize) -> Rc<RefCell<DReg>> {
-        if index >= self.dregs.len() {
-            self.expand(index);
+    /// Returns a reference to the contents of a register, or None if the register
+    /// hasn't been written to. Reading never grows the list.
+    pub fn get(&self, index: usize) -> Option<&SifVal> {
+        match self.dregs.get(index) {
+            Some(cont) => cont.as_ref(),
+            None => None,
+        }
+    }
+
+    /// Returns a mutable reference to the contents of a register, so that the value can be
+    /// updated in place.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut SifVal> {
+        match self.dregs.get_mut(index) {
+            Some(cont) => cont.as_mut(),
+            None => None,
         }
-        Rc::clone(&self.dregs[index])
+    }
+
+    pub fn set(&mut self, index: usize, val: SifVal) {
+        self.set_contents(index, Some(val));
     }
 
     pub fn set_contents(&mut self, index: usize, val: Option<SifVal>) {
         if index >= self.dregs.len() {
             self.expand(index);
         }
-        self.dregs[index].borrow_mut().cont = val;
+        match (self.dregs[index].is_some(), val.is_some()) {
+            (false, true) => self.live += 1,
+            (true, false) => self.live -= 1,
+            _ => (),
+        };
+        self.dregs[index] = val;
     }
 
     pub fn register_count(&self) -> usize {
         self.dregs.len()
     }
 
+    /// Returns the number of registers that contain a value. Unlike the register count,
+    /// this doesn't depend on the numbers of the registers that were written.
+    pub fn live_count(&self) -> usize {
+        self.live
+    }
+
     fn expand(&mut self, upto: usize) {
         self.alloc_count += 1;
 
         let max_size = self.register_count() * self.alloc_count;
         let grow_size = if max_size < upto { upto } else { max_size };
-        self.dregs.reserve(grow_size);
-
-        let mut start_idx = self.dregs.len() - 1;
-        for _i in 0..grow_size {
-            let reg = DReg::new(format!("r{}", start_idx));
-            self.dregs.push(Rc::new(RefCell::new(reg)));
-            start_idx += 1;
-        }
+        let new_len = self.dregs.len() + grow_size;
+        self.dregs.resize_with(new_len, || None);
     }
 }
 
@@ -86,20 +92,57 @@ mod tests {
         let mut initial = DataRegisterList::init(1);
         assert!(initial.register_count() == 1);
 
-        // When we get register at index 1, we must expand.
-        let _i = initial.get(1);
+        // Reading a register out of bounds doesn't expand.
+        assert!(initial.get(1).is_none());
+        assert!(initial.register_count() == 1);
+
+        // When we set register at index 1, we must expand.
+        initial.set(1, SifVal::Null);
         assert!(initial.register_count() == 2);
 
         // In this case, we expand to reg_count() * alloc_count (2 * 3)
-        let _x = initial.get(2);
+        initial.set(2, SifVal::Null);
         assert!(initial.register_count() == 6);
 
         // No expansion here, becauase register at index 3 is within bounds.
-        let _x = initial.get(3);
+        initial.set(3, SifVal::Null);
         assert!(initial.register_count() == 6);
 
         // Here we expand upto the desired register + previous memory.
-        let _i = initial.get(100);
+        initial.set(100, SifVal::Null);
         assert!(initial.register_count() == 106);
     }
+
+    #[test]
+    fn test_get_set() {
+        let mut regs = DataRegisterList::init(4);
+        assert!(regs.get(0).is_none());
+
+        regs.set(0, SifVal::Num(1.0));
+        assert_eq!(regs.get(0), Some(&SifVal::Num(1.0)));
+
+        match regs.get_mut(0) {
+            Some(SifVal::Num(n)) => *n += 1.0,
+            _ => panic!("expected a num in r0"),
+        };
+        assert_eq!(regs.get(0), Some(&SifVal::Num(2.0)));
+
+        regs.set_contents(0, None);
+        assert!(regs.get(0).is_none());
+    }
+
+    #[test]
+    fn test_live_count() {
+        let mut regs = DataRegisterList::init(4);
+        regs.set(1000, SifVal::Null);
+        assert_eq!(regs.live_count(), 1);
+
+        regs.set(1000, SifVal::Num(1.0));
+        regs.set(2, SifVal::Null);
+        assert_eq!(regs.live_count(), 2);
+
+        regs.set_contents(1000, None);
+        regs.set_contents(3, None);
+        assert_eq!(regs.live_count(), 1);
+    }
 }
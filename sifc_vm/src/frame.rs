@@ -0,0 +1,51 @@
+use crate::dreg::DataRegisterList;
+use sifc_bytecode::sifv::SifVal;
+use std::collections::HashMap;
+
+/// Frame is the activation record for a single function call. A frame is pushed onto the
+/// call stack by each call instruction and popped off by the matching return.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// Name of the called function.
+    pub name: String,
+
+    /// Program index of the call instruction. Execution resumes after this instruction
+    /// when the function returns.
+    pub ret_addr: usize,
+
+    /// Params and locals of this call, keyed by name. Every local declared in the
+    /// function is inserted when the frame is created, so that names shadowing a global
+    /// are always resolved in the frame.
+    pub locals: HashMap<String, SifVal>,
+
+    /// Registers of the caller. The callee runs with a fresh register list, and these
+    /// are swapped back in when the call returns.
+    pub caller_dregs: DataRegisterList,
+
+    /// Height of the fn stack when the call began, after params were pushed and not
+    /// including them. This is used to detect functions that return without a value.
+    pub stack_base: usize,
+}
+
+impl Frame {
+    pub fn new(
+        name: &str,
+        ret_addr: usize,
+        names: Vec<String>,
+        caller_dregs: DataRegisterList,
+        stack_base: usize,
+    ) -> Frame {
+        let mut locals = HashMap::with_capacity(names.len());
+        for name in names {
+            locals.insert(name, SifVal::Null);
+        }
+
+        Frame {
+            name: name.to_string(),
+            ret_addr,
+            locals,
+            caller_dregs,
+            stack_base,
+        }
+    }
+}
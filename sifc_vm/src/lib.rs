@@ -1,4 +1,7 @@
 pub mod config;
+pub mod debug;
+pub mod verify;
 pub mod vm;
 
 mod dreg;
+mod frame;
@@ -0,0 +1,605 @@
+use sifc_bytecode::{instr::Instr, opc::Op};
+use sifc_err::verify_err::{VerifyErr, VerifyErrTy};
+use sifc_std::Std;
+use std::collections::{HashMap, HashSet};
+
+/// Largest register index accepted by verify. The compiler numbers registers across the
+/// whole program, so this has to allow for large programs, while still rejecting indices
+/// that would make the vm allocate far more registers than any program needs.
+pub const DEFAULT_MAX_REG: usize = 1 << 20;
+
+/// Statically checks a program before it is run by the vm. The vm assumes that its input
+/// is correct, so this should be run on any bytecode that didn't come directly from the
+/// compiler (for example, bytecode loaded from a file). The following is checked:
+///
+/// 1. Every jump target is a label in the jump table, and the fn table points at
+///    the declaration of each fn.
+/// 2. Every called or loaded fn is declared, and calls by name pass the number of
+///    params the fn expects.
+/// 3. Every std call names a function in the std library, with a valid number of params.
+/// 4. Registers are defined before they are used, along every path through the code
+///    section and through each fn. Each call gets a fresh set of registers, so every
+///    fn is checked separately, starting with no defined registers.
+/// 5. No register is above the maximum register. The vm grows its registers up to the
+///    highest one written, so this bounds the memory used for them.
+///
+/// All errors found are returned, ordered by the index of the instruction.
+pub fn verify(
+    program: &Vec<Instr>,
+    code_start: usize,
+    jumptab: &HashMap<usize, usize>,
+    fntab: &HashMap<String, usize>,
+) -> Result<(), Vec<VerifyErr>> {
+    verify_with_std(
+        program,
+        code_start,
+        jumptab,
+        fntab,
+        &Std::new(),
+        DEFAULT_MAX_REG,
+    )
+}
+
+/// Verifies a program like verify, but checks std calls against the given std library
+/// instead of the default one, and rejects registers above max_reg. This should be used
+/// when the host has registered its own functions with the library, or wants to set a
+/// different bound on registers.
+pub fn verify_with_std(
+    program: &Vec<Instr>,
+    code_start: usize,
+    jumptab: &HashMap<usize, usize>,
+    fntab: &HashMap<String, usize>,
+    stdlib: &Std,
+    max_reg: usize,
+) -> Result<(), Vec<VerifyErr>> {
+    if code_start > program.len() {
+        return Err(vec![VerifyErr::new(
+            VerifyErrTy::InvalidCodeStart(code_start),
+            code_start,
+            0,
+        )]);
+    }
+
+    let mut verifier = Verifier {
+        program,
+        jumptab,
+        fntab,
+        stdlib,
+        max_reg,
+        errs: Vec::new(),
+        undef_regs: HashSet::new(),
+        block_starts: block_starts(program, code_start, jumptab),
+        reg_count: reg_count(program, max_reg),
+    };
+
+    verifier.check_fntab();
+    for idx in 0..program.len() {
+        verifier.check_instr(idx);
+    }
+
+    // Registers are checked from the start of the code section and from each fn decl.
+    if code_start < program.len() {
+        verifier.check_regs(code_start);
+    }
+    for (idx, instr) in program.iter().enumerate() {
+        if let Op::Fn { .. } = instr.op {
+            verifier.check_regs(idx)
+        };
+    }
+
+    match verifier.errs.len() {
+        0 => Ok(()),
+        _ => {
+            verifier.errs.sort_by_key(|e| e.idx);
+            Err(verifier.errs)
+        }
+    }
+}
+
+struct Verifier<'v> {
+    program: &'v Vec<Instr>,
+    jumptab: &'v HashMap<usize, usize>,
+    fntab: &'v HashMap<String, usize>,
+    stdlib: &'v Std,
+    max_reg: usize,
+    errs: Vec<VerifyErr>,
+
+    /// Instruction index and register of each undefined register error already added.
+    /// Instructions may be reachable from more than one entry, and each use should only
+    /// be reported once.
+    undef_regs: HashSet<(usize, usize)>,
+
+    /// Whether the instruction at each index starts a basic block.
+    block_starts: Vec<bool>,
+
+    /// Number of registers tracked when checking that registers are defined. This is one
+    /// more than the highest register used that isn't above the maximum.
+    reg_count: usize,
+}
+
+impl<'v> Verifier<'v> {
+    fn adderr(&mut self, ty: VerifyErrTy, idx: usize) {
+        let line = match self.program.get(idx) {
+            Some(instr) => instr.line,
+            None => 0,
+        };
+        self.errs.push(VerifyErr::new(ty, idx, line));
+    }
+
+    fn check_fntab(&mut self) {
+        let mut names: Vec<&String> = self.fntab.keys().collect();
+        names.sort();
+
+        for name in names {
+            let idx = self.fntab[name];
+            let valid = match self.program.get(idx) {
+                Some(instr) => match &instr.op {
+                    Op::Fn { name: declname, .. } => declname == name,
+                    _ => false,
+                },
+                None => false,
+            };
+
+            if !valid {
+                self.adderr(VerifyErrTy::InvalidFnEntry(name.clone()), idx);
+            }
+        }
+    }
+
+    fn check_instr(&mut self, idx: usize) {
+        match &self.program[idx].op {
+            Op::Jmpa { lblidx } | Op::JmpCnd { lblidx, .. }
+                if self.jump_target(*lblidx).is_none() =>
+            {
+                self.adderr(VerifyErrTy::InvalidJumpTarget(*lblidx), idx);
+            }
+            Op::Call { name, param_count } => match self.fn_params(name) {
+                Some(expected) if expected != *param_count => self.adderr(
+                    VerifyErrTy::WrongFnParamCnt(name.clone(), expected, *param_count),
+                    idx,
+                ),
+                Some(_) => {}
+                None => self.adderr(VerifyErrTy::InvalidFnSym(name.clone()), idx),
+            },
+            // Fn values can be called with any number of params, which is checked when
+            // they are called by the vm.
+            Op::Ldf { name, .. } if self.fn_params(name).is_none() => {
+                self.adderr(VerifyErrTy::InvalidFnSym(name.clone()), idx);
+            }
+            Op::StdCall { name, param_count } => match self.stdlib.arity(name) {
+                Some(expected) if expected != *param_count => self.adderr(
+                    VerifyErrTy::WrongStdParamCnt(name.clone(), expected, *param_count),
+                    idx,
+                ),
+                Some(_) => {}
+                None => self.adderr(VerifyErrTy::InvalidStdFn(name.clone()), idx),
+            },
+            _ => {}
+        };
+
+        let (mut regs, dest) = reg_uses(&self.program[idx].op);
+        regs.extend(dest);
+        regs.sort_unstable();
+        regs.dedup();
+        for reg in regs {
+            if reg > self.max_reg {
+                self.adderr(VerifyErrTy::RegOutOfRange(reg, self.max_reg), idx);
+            }
+        }
+    }
+
+    /// Returns the index of the instruction the label points to, if the label exists.
+    fn jump_target(&self, lblidx: usize) -> Option<usize> {
+        match self.jumptab.get(&lblidx) {
+            Some(idx) if *idx < self.program.len() => Some(*idx),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of params of the declared fn, if it exists.
+    fn fn_params(&self, name: &str) -> Option<usize> {
+        let idx = self.fntab.get(name)?;
+        match &self.program.get(*idx)?.op {
+            Op::Fn { params, .. } => Some(params.len()),
+            _ => None,
+        }
+    }
+
+    /// Returns the indices of the instructions that can execute after the one at idx.
+    /// Paths end at fn returns, the end of the program and at fn decls. Each fn decl is
+    /// checked from its own entry, so paths don't need to continue into them.
+    fn succs(&self, idx: usize) -> Vec<usize> {
+        let mut succs = Vec::new();
+        match &self.program[idx].op {
+            Op::Jmpa { lblidx } => {
+                succs.extend(self.jump_target(*lblidx));
+            }
+            Op::JmpCnd { lblidx, .. } => {
+                succs.extend(self.jump_target(*lblidx));
+                succs.push(idx + 1);
+            }
+            Op::FnRet => {}
+            _ => succs.push(idx + 1),
+        };
+
+        succs
+            .into_iter()
+            .filter(|s| match self.program.get(*s) {
+                Some(instr) => !matches!(instr.op, Op::Fn { .. }),
+                None => false,
+            })
+            .collect()
+    }
+
+    /// Returns the index of the last instruction in the basic block starting at start.
+    /// Blocks end at jumps and returns, and before the start of the next block.
+    fn block_end(&self, start: usize) -> usize {
+        let mut idx = start;
+        loop {
+            match self.program[idx].op {
+                Op::Jmpa { .. } | Op::JmpCnd { .. } | Op::FnRet => return idx,
+                _ if idx + 1 == self.program.len() || self.block_starts[idx + 1] => return idx,
+                _ => idx += 1,
+            };
+        }
+    }
+
+    /// Computes the registers that are defined on every path from entry to each basic
+    /// block, and adds an error for any register used by an instruction that isn't
+    /// defined on all paths to it. Registers are only recorded at the start of each block,
+    /// and are found for the instructions inside of it by walking the block.
+    fn check_regs(&mut self, entry: usize) {
+        let mut defined: HashMap<usize, RegSet> = HashMap::new();
+        defined.insert(entry, RegSet::new(self.reg_count));
+        let mut worklist = vec![entry];
+
+        while let Some(start) = worklist.pop() {
+            let mut out = defined[&start].clone();
+            let end = self.block_end(start);
+            for instr in &self.program[start..=end] {
+                let (_, dest) = reg_uses(&instr.op);
+                out.extend(dest.filter(|r| *r <= self.max_reg));
+            }
+
+            // Successors always start a block, since they follow a jump or a return, are
+            // the target of a jump, or are already the start of the next block.
+            for succ in self.succs(end) {
+                let changed = match defined.get_mut(&succ) {
+                    Some(regs) => regs.intersect(&out),
+                    None => {
+                        defined.insert(succ, out.clone());
+                        true
+                    }
+                };
+
+                if changed {
+                    worklist.push(succ);
+                }
+            }
+        }
+
+        let mut starts: Vec<usize> = defined.keys().copied().collect();
+        starts.sort_unstable();
+        for start in starts {
+            let mut regs = defined.remove(&start).unwrap();
+            for idx in start..=self.block_end(start) {
+                let (srcs, dest) = reg_uses(&self.program[idx].op);
+
+                // Registers above the maximum have already been reported.
+                let max_reg = self.max_reg;
+                for src in srcs.into_iter().filter(|r| *r <= max_reg) {
+                    if !regs.contains(src) && self.undef_regs.insert((idx, src)) {
+                        self.adderr(VerifyErrTy::RegUndefined(src), idx);
+                    }
+                }
+                regs.extend(dest.filter(|r| *r <= max_reg));
+            }
+        }
+    }
+}
+
+/// Returns whether each instruction in the program starts a basic block. Blocks start at
+/// the code section, at fn decls, at jump targets and after jumps and returns.
+fn block_starts(
+    program: &[Instr],
+    code_start: usize,
+    jumptab: &HashMap<usize, usize>,
+) -> Vec<bool> {
+    let mut starts = vec![false; program.len() + 1];
+    starts[code_start] = true;
+    for idx in jumptab.values() {
+        if *idx < program.len() {
+            starts[*idx] = true;
+        }
+    }
+
+    for (idx, instr) in program.iter().enumerate() {
+        match instr.op {
+            Op::Fn { .. } => starts[idx] = true,
+            Op::Jmpa { .. } | Op::JmpCnd { .. } | Op::FnRet => starts[idx + 1] = true,
+            _ => {}
+        };
+    }
+
+    starts.truncate(program.len());
+    starts
+}
+
+/// Returns one more than the highest register used in the program, ignoring registers
+/// above max_reg.
+fn reg_count(program: &[Instr], max_reg: usize) -> usize {
+    let mut count = 0;
+    for instr in program {
+        let (srcs, dest) = reg_uses(&instr.op);
+        for reg in srcs.into_iter().chain(dest) {
+            if reg <= max_reg {
+                count = count.max(reg + 1);
+            }
+        }
+    }
+    count
+}
+
+/// RegSet is a set of registers, stored as one bit per register.
+#[derive(Clone, Debug)]
+struct RegSet {
+    words: Vec<u64>,
+}
+
+impl RegSet {
+    /// Creates an empty set that can hold registers below count.
+    fn new(count: usize) -> RegSet {
+        RegSet {
+            words: vec![0; count.div_ceil(64)],
+        }
+    }
+
+    fn contains(&self, reg: usize) -> bool {
+        self.words[reg / 64] & (1 << (reg % 64)) != 0
+    }
+
+    fn extend(&mut self, regs: impl IntoIterator<Item = usize>) {
+        for reg in regs {
+            self.words[reg / 64] |= 1 << (reg % 64);
+        }
+    }
+
+    /// Removes every register that isn't in other. Returns true if any were removed.
+    fn intersect(&mut self, other: &RegSet) -> bool {
+        let mut changed = false;
+        for (word, other_word) in self.words.iter_mut().zip(&other.words) {
+            let next = *word & other_word;
+            changed |= next != *word;
+            *word = next;
+        }
+        changed
+    }
+}
+
+/// Returns the registers read by an op, and the register it writes to if there is one.
+fn reg_uses(op: &Op) -> (Vec<usize>, Option<usize>) {
+    match op {
+        Op::Binary {
+            src1, src2, dest, ..
+        } => (vec![*src1, *src2], Some(*dest)),
+        Op::Unary { src1, dest, .. } => (vec![*src1], Some(*dest)),
+        Op::Ldc { dest, .. }
+        | Op::Ldn { dest, .. }
+        | Op::Ldg { dest, .. }
+        | Op::Ldas { dest, .. }
+        | Op::Ldasg { dest, .. }
+        | Op::Ldf { dest, .. }
+        | Op::Tblg { dest, .. }
+        | Op::Tblgg { dest, .. }
+        | Op::FnStackPop { dest } => (Vec::new(), Some(*dest)),
+        Op::Mv { src, dest } => (vec![*src], Some(*dest)),
+        Op::Ldav { idx_reg, dest, .. } | Op::Ldavg { idx_reg, dest, .. } => {
+            (vec![*idx_reg], Some(*dest))
+        }
+        Op::Upda {
+            idx_reg, val_reg, ..
+        }
+        | Op::Updag {
+            idx_reg, val_reg, ..
+        } => (vec![*idx_reg, *val_reg], None),
+        Op::Str { src, .. }
+        | Op::Strg { src, .. }
+        | Op::JmpCnd { src, .. }
+        | Op::Incrr { src }
+        | Op::Decrr { src }
+        | Op::Callr { src, .. }
+        | Op::FnStackPush { src }
+        | Op::Tbli { src, .. }
+        | Op::Tblig { src, .. } => (vec![*src], None),
+        Op::Stc { .. }
+        | Op::Stcg { .. }
+        | Op::Stn { .. }
+        | Op::Jmpa { .. }
+        | Op::Fn { .. }
+        | Op::Call { .. }
+        | Op::StdCall { .. }
+        | Op::FnRet
+        | Op::Nop
+        | Op::Stop => (Vec::new(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sifc_bytecode::asm;
+
+    fn verify_asm(src: &str) -> Result<(), Vec<VerifyErrTy>> {
+        let prog = asm::assemble(src).unwrap();
+        match verify(&prog.program, prog.code_start, &prog.jumptab, &prog.fntab) {
+            Ok(()) => Ok(()),
+            Err(errs) => Err(errs.into_iter().map(|e| e.ty).collect()),
+        }
+    }
+
+    #[test]
+    fn valid_program() {
+        let result = verify_asm(
+            r#"
+SECTION_ decls.
+lbl0:
+fn @double ["n"]
+    fstpop r0
+    add r0 r0 r1
+    fstpush r1
+    ret
+SECTION_ code.
+lbl1:
+    ldc 1 r0
+    fstpush r0
+    call double 1
+    fstpop r1
+    fstpush r1
+    stdcall print 1
+lbl2:
+    incrr r1
+    lt r1 r0 r2
+    jmpt r2 lbl2
+"#,
+        );
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn invalid_code_start() {
+        let prog = asm::assemble("nop").unwrap();
+        let errs = verify(&prog.program, 2, &prog.jumptab, &prog.fntab).unwrap_err();
+        assert_eq!(errs[0].ty, VerifyErrTy::InvalidCodeStart(2));
+    }
+
+    #[test]
+    fn invalid_jump() {
+        let result = verify_asm("ldc true r0\njmpt r0 lbl3\njmpa lbl1");
+        assert_eq!(
+            result,
+            Err(vec![
+                VerifyErrTy::InvalidJumpTarget(3),
+                VerifyErrTy::InvalidJumpTarget(1)
+            ])
+        );
+    }
+
+    #[test]
+    fn jump_to_start() {
+        // The first instr of the program is a valid jump target.
+        let result = verify_asm("lbl0:\n    ldc 1 r0\n    jmpa lbl0");
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn invalid_fntab() {
+        let prog = asm::assemble("nop").unwrap();
+        let mut fntab = HashMap::new();
+        fntab.insert(String::from("f"), 0);
+        let errs = verify(&prog.program, 0, &prog.jumptab, &fntab).unwrap_err();
+        assert_eq!(errs[0].ty, VerifyErrTy::InvalidFnEntry(String::from("f")));
+    }
+
+    #[test]
+    fn invalid_call() {
+        let result = verify_asm(
+            r#"
+SECTION_ decls.
+fn @f ["a", "b"]
+    ret
+SECTION_ code.
+lbl1:
+    call g 0
+    call f 1
+    ldf @h [] r0
+"#,
+        );
+        assert_eq!(
+            result,
+            Err(vec![
+                VerifyErrTy::InvalidFnSym(String::from("g")),
+                VerifyErrTy::WrongFnParamCnt(String::from("f"), 2, 1),
+                VerifyErrTy::InvalidFnSym(String::from("h")),
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_std_call() {
+        let result = verify_asm("stdcall nope 1\nstdcall range 1");
+        assert_eq!(
+            result,
+            Err(vec![
+                VerifyErrTy::InvalidStdFn(String::from("nope")),
+                VerifyErrTy::WrongStdParamCnt(String::from("range"), 2, 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn reg_undefined() {
+        let result = verify_asm("ldc 1 r0\nadd r0 r1 r2\nfstpush r2");
+        assert_eq!(result, Err(vec![VerifyErrTy::RegUndefined(1)]));
+    }
+
+    #[test]
+    fn reg_undefined_on_one_path() {
+        // r1 is only defined when the branch to lbl2 isn't taken.
+        let result = verify_asm(
+            "
+lbl0:
+    ldc true r0
+    jmpt r0 lbl2
+lbl1:
+    ldc 1 r1
+lbl2:
+    fstpush r1
+",
+        );
+        assert_eq!(result, Err(vec![VerifyErrTy::RegUndefined(1)]));
+    }
+
+    #[test]
+    fn reg_defined_in_loop() {
+        let result = verify_asm(
+            "
+lbl0:
+    ldc 0 r0
+    ldc 3 r1
+lbl1:
+    incrr r0
+    lt r0 r1 r2
+    jmpt r2 lbl1
+lbl2:
+    fstpush r2
+",
+        );
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn reg_out_of_range() {
+        // Each register is reported once per instr, and isn't also reported as undefined.
+        let result = verify_asm("ldc 1 r4000000000\nadd r4000000000 r4000000000 r0");
+        let err = VerifyErrTy::RegOutOfRange(4000000000, DEFAULT_MAX_REG);
+        assert_eq!(result, Err(vec![err.clone(), err]));
+    }
+
+    #[test]
+    fn reg_not_shared_with_fn() {
+        // Calls get fresh registers, so r0 from the code section is undefined in f.
+        let result = verify_asm(
+            r#"
+SECTION_ decls.
+fn @f []
+    fstpush r0
+    ret
+SECTION_ code.
+lbl1:
+    ldc 1 r0
+    call f 0
+"#,
+        );
+        assert_eq!(result, Err(vec![VerifyErrTy::RegUndefined(0)]));
+    }
+}
@@ -1,14 +1,49 @@
-use crate::{config::VMConfig, dreg::DataRegisterList};
+use crate::{config::VMConfig, dreg::DataRegisterList, frame::Frame};
 use sifc_bytecode::{
     instr::Instr,
+    link::{self, GlobalTable},
     opc::{BinOpKind, JmpOpKind, Op, UnOpKind},
     sifv::SifVal,
 };
-use sifc_err::runtime_err::{RuntimeErr, RuntimeErrTy};
-use sifc_std::Std;
-use std::collections::HashMap;
+use sifc_err::{
+    runtime_err::{Limit, RuntimeErr, RuntimeErrTy, TraceFrame},
+    span::Span,
+};
+use sifc_std::{Context, Std};
+use std::{collections::HashMap, rc::Rc, time::Instant};
+
+/// Amount of data registers to create for each new call frame. Frames grow their
+/// register list as needed, so this is kept small to make calls cheap.
+const FRAME_DREG_COUNT: usize = 16;
+
+/// Number of instrs executed between checks of the deadline, as reading the clock for every
+/// instr would slow down execution.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// Var refers to a value in memory that an op loads or stores. Names are resolved when
+/// the op executes, first in the current call frame and then in the globals. Slots refer
+/// directly to a global, and are produced by linking the program.
+#[derive(Clone, Copy)]
+enum Var<'a> {
+    Name(&'a str),
+    Slot(usize),
+}
+
+/// CallSite is the location of one of the active calls in the vm, used to build a backtrace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallSite {
+    /// Name of the function being executed, or None for the code section.
+    pub name: Option<String>,
+
+    /// Program index of the instr being executed. For every call except the innermost,
+    /// this is the call instr that is waiting to return.
+    pub idx: usize,
+
+    /// Source span of the instr, which is unknown for instrs without a source location.
+    pub span: Span,
+}
 
-pub struct VM<'v> {
+pub struct VM {
     /// Contains all required sections and relevant instructions in one vector. This
     /// is usually built from extending vectors containing other sections.
     /// Sif bytecode currently contains two sections:
@@ -18,7 +53,10 @@ pub struct VM<'v> {
     /// 2. The code section This contains the instructions compiled from
     ///    the ast from the compiler and is assumed to be valid. The start of the code
     ///    section is where program execution begins.
-    prog: Vec<Instr>,
+    ///
+    /// The program is reference counted so the execution loop can borrow instructions
+    /// while the rest of the vm is mutated.
+    prog: Rc<Vec<Instr>>,
 
     /// Jump table, containing the label indices as keys and the code vector
     /// indices as values. This is generated by the compiler and is assumed
@@ -33,18 +71,26 @@ pub struct VM<'v> {
     /// until it has been declared and the declaration must appear before the call in the code
     fntab: HashMap<String, usize>,
 
-    /// Data registers. This is managed by the DataRegisterList struct, which handles getting
-    /// references to registers and growing the list when we want more registers.
+    /// Data registers. This is managed by the DataRegisterList struct, which stores register
+    /// contents in a flat vector and grows the list when we want more registers.
     dregs: DataRegisterList,
 
-    /// Heap section. This contains arrays, tables, and globals. We use the
-    /// name of the data as a key to retrieve and store information in the heap.
-    /// This is likely not memory efficient and a more sophisticated structure + allocating
-    /// space before vm startup could be more performant.
-    heap: HashMap<String, SifVal>,
+    /// Heap section. This contains arrays, tables, and globals. Globals are stored by the
+    /// slot assigned to their name when the program is linked, and slots that haven't been
+    /// stored to yet contain None. Names declared inside of functions are stored in call
+    /// frames, not here.
+    heap: Vec<Option<SifVal>>,
+
+    /// Number of heap slots that contain a value, which is checked against the heap limit.
+    stored_globals: usize,
+
+    /// Names of the globals in the heap, indexed by slot. Ops that weren't linked still
+    /// access globals by name, and this is used to find their slot. It's also used to
+    /// name globals when tracing and in errors.
+    globals: GlobalTable,
 
     /// Standard libary function mappings.
-    stdlib: Std<'v>,
+    stdlib: Std,
 
     /// Stack for storing function params and return values. We sacrifice a bit of memory efficiency
     /// by not sharing this stacke for function call locations, but this is easier to implement
@@ -52,11 +98,11 @@ pub struct VM<'v> {
     /// pushing it onto the call stack.
     fn_stack: Vec<SifVal>,
 
-    /// Stack that stores jump locations for calling functions and returning from them. Like a normal
-    /// calls stack, when a function is called we push the location to return to on to the stack. Once the
-    /// function has finished executing, we pop the location off the call stack and set ip to that
-    /// value.
-    call_stack: Vec<usize>,
+    /// Stack of call frames. Like a normal call stack, when a function is called we push a frame
+    /// containing the location to return to, the locals of the call and the registers of the
+    /// caller. Once the function has finished executing, we pop the frame off the call stack,
+    /// restore the caller registers and set ip to the return location.
+    call_stack: Vec<Frame>,
 
     /// Index of the start of the code vector. The IP initially points to this
     /// instruction, as this is where execution would normally begin.
@@ -70,535 +116,933 @@ pub struct VM<'v> {
     /// passed in from command line flags, and documentation for them should be in the
     /// command line usage/help.
     config: VMConfig,
+
+    /// Name of the file the program was loaded from, used to locate runtime errors and
+    /// traced instrs.
+    file: Option<String>,
+
+    /// Number of instrs executed since the start of the current run or host call, which
+    /// is checked against the fuel limit.
+    steps: u64,
+
+    /// Time that the current run or host call must finish by, if there's a timeout.
+    deadline: Option<Instant>,
 }
 
-impl<'v> VM<'v> {
+impl VM {
     pub fn init(
         full_prog: Vec<Instr>,
         code_start: usize,
         jt: HashMap<usize, usize>,
         ft: HashMap<String, usize>,
         conf: VMConfig,
-    ) -> VM<'v> {
-        let heap = HashMap::with_capacity(conf.initial_heap_size);
+    ) -> VM {
+        // Link the program, so that globals are accessed by slot rather than by name.
+        let mut prog = full_prog;
+        let mut globals = GlobalTable::new();
+        link::link(&mut prog, code_start, &jt, &mut globals);
+
+        let mut heap = Vec::with_capacity(conf.initial_heap_size.max(globals.len()));
+        heap.resize_with(globals.len(), || None);
         let reglist = DataRegisterList::init(conf.initial_dreg_count);
 
         VM {
-            prog: full_prog,
+            prog: Rc::new(prog),
             fntab: ft,
             jumptab: jt,
             dregs: reglist,
-            heap: heap,
+            heap,
+            stored_globals: 0,
+            globals,
             stdlib: Std::new(),
             fn_stack: Vec::new(),
             call_stack: Vec::new(),
             csi: code_start,
             ip: code_start,
             config: conf,
+            file: None,
+            steps: 0,
+            deadline: None,
         }
     }
 
+    /// Loads another program into the vm, keeping the globals and fns of the programs that
+    /// were run before it. The program is linked against the existing globals and appended
+    /// to the end of the current program, and fns it declares replace any existing fns with
+    /// the same name. The next call to run() executes the code section of the new program.
+    ///
+    /// Anonymous fn names must not be reused between the programs, which is done by resuming
+    /// each compiler from the anon count of the previous one.
+    pub fn load(
+        &mut self,
+        full_prog: Vec<Instr>,
+        code_start: usize,
+        jt: HashMap<usize, usize>,
+        ft: HashMap<String, usize>,
+    ) {
+        let mut prog = full_prog;
+        let mut jumptab = jt;
+        let mut fntab = ft;
+        link::link(&mut prog, code_start, &jumptab, &mut self.globals);
+
+        let idx_off = self.prog.len();
+        let lbl_off = match self.jumptab.keys().max() {
+            Some(lbl) => lbl + 1,
+            None => 0,
+        };
+        link::relocate(&mut prog, &mut jumptab, &mut fntab, idx_off, lbl_off);
+
+        Rc::make_mut(&mut self.prog).extend(prog);
+        self.jumptab.extend(jumptab);
+        self.fntab.extend(fntab);
+        self.heap
+            .resize_with(self.globals.len().max(self.heap.len()), || None);
+
+        // Anything left over from the previous run, such as the frames of a call that
+        // failed, is discarded.
+        self.dregs = DataRegisterList::init(self.config.initial_dreg_count);
+        self.fn_stack.clear();
+        self.call_stack.clear();
+        self.csi = idx_off + code_start;
+        self.ip = self.csi;
+    }
+
     pub fn run(&mut self) -> Result<(), RuntimeErr> {
-        while self.ip < self.prog.len() {
-            self.execute()?;
-            self.ip = self.ip + 1;
+        self.start_budget();
+
+        // The program is shared with the execution loop, so that each instruction can be
+        // borrowed while it executes instead of being cloned.
+        let prog = Rc::clone(&self.prog);
+        while self.ip < prog.len() {
+            self.execute(&prog[self.ip])?;
+        }
+        Ok(())
+    }
+
+    /// Calls a fn declared by the program with params, and runs until it returns. This
+    /// lets the host call into a program after it has been run, for example to use fns
+    /// it declares as callbacks. The fn can read the globals left by the program, and the
+    /// vm is left as it was before the call, so the host can make any number of calls.
+    /// If the call fails, the calls it made are unwound before the error is returned.
+    pub fn call_fn(&mut self, name: &str, params: Vec<SifVal>) -> Result<SifVal, RuntimeErr> {
+        self.start_budget();
+        let caller_ip = self.ip;
+        let depth = self.call_stack.len();
+        let stack_base = self.fn_stack.len();
+        let param_count = params.len();
+        self.fn_stack.extend(params);
+
+        let result = self.run_call(name, param_count, depth);
+        if result.is_err() {
+            // The registers from before the call are saved in its first frame.
+            if let Some(frame) = self.call_stack.drain(depth..).next() {
+                self.dregs = frame.caller_dregs
+            };
+            self.fn_stack.truncate(stack_base);
         }
+        self.ip = caller_ip;
+
+        result?;
+        match self.fn_stack.pop() {
+            Some(val) => Ok(val),
+            None => Err(self.newerr(RuntimeErrTy::EmptyFnStack)),
+        }
+    }
+
+    /// Enters a fn for call_fn() and executes instrs until the call stack is back to
+    /// depth, which happens when the fn returns.
+    fn run_call(&mut self, name: &str, param_count: usize, depth: usize) -> Result<(), RuntimeErr> {
+        self.call(name, param_count, HashMap::new())?;
+        self.ip += 1;
+
+        let prog = Rc::clone(&self.prog);
+        while self.call_stack.len() > depth {
+            match prog.get(self.ip) {
+                Some(instr) => self.execute(instr)?,
+                None => return Err(self.newerr(RuntimeErrTy::EmptyCallStack)),
+            };
+        }
+        Ok(())
+    }
+
+    /// Executes the instr at ip and advances to the next one. This can be called repeatedly
+    /// instead of run() to pause the program between instrs, for example to debug it. If an
+    /// error is returned, ip is left pointing at the instr that failed.
+    pub fn step(&mut self) -> Result<(), RuntimeErr> {
+        let prog = Rc::clone(&self.prog);
+        if let Some(instr) = prog.get(self.ip) {
+            self.execute(instr)?
+        };
         Ok(())
     }
 
-    pub fn inspect_dreg(&mut self, idx: usize) -> Option<SifVal> {
-        let reg = self.dregs.get(idx);
-        let contents = reg.borrow().cont.clone();
-        contents
+    /// Resets the fuel and deadline limits at the start of a run or host call. Instrs
+    /// executed with step() count towards the run they follow, or towards the first run if
+    /// the program is only stepped.
+    fn start_budget(&mut self) {
+        self.steps = 0;
+        self.deadline = self.config.limits.timeout.map(|t| Instant::now() + t);
+    }
+
+    /// Counts an executed instr against the fuel limit, and checks the deadline every
+    /// DEADLINE_CHECK_INTERVAL instrs.
+    fn tick(&mut self) -> Result<(), RuntimeErr> {
+        self.steps += 1;
+        match self.config.limits.fuel {
+            Some(fuel) if self.steps > fuel => {
+                return Err(self.newerr(RuntimeErrTy::LimitExceeded(Limit::Fuel(fuel))))
+            }
+            _ => (),
+        };
+
+        match (self.deadline, self.config.limits.timeout) {
+            (Some(deadline), Some(timeout))
+                if self.steps.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                    && Instant::now() >= deadline =>
+            {
+                Err(self.newerr(RuntimeErrTy::LimitExceeded(Limit::Timeout(timeout))))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// True if there are no more instrs to execute.
+    pub fn is_finished(&self) -> bool {
+        self.ip >= self.prog.len()
+    }
+
+    /// Returns the instr that will be executed by the next step, if there is one.
+    pub fn current_instr(&self) -> Option<&Instr> {
+        self.prog.get(self.ip)
+    }
+
+    /// Returns the number of calls that haven't returned yet.
+    pub fn call_depth(&self) -> usize {
+        self.call_stack.len()
+    }
+
+    /// Returns the params and locals of the innermost call, or None if no function is
+    /// being executed.
+    pub fn locals(&self) -> Option<&HashMap<String, SifVal>> {
+        match self.call_stack.last() {
+            Some(frame) => Some(&frame.locals),
+            None => None,
+        }
+    }
+
+    /// Returns the number of data registers available in the current call. Registers past
+    /// this have never been written to.
+    pub fn dreg_count(&self) -> usize {
+        self.dregs.register_count()
+    }
+
+    /// Returns the location of each active call, starting with the innermost one and
+    /// ending with the code section.
+    pub fn backtrace(&self) -> Vec<CallSite> {
+        let mut trace = Vec::with_capacity(self.call_stack.len() + 1);
+        let mut idx = self.ip;
+        for frame in self.call_stack.iter().rev() {
+            trace.push(self.call_site(Some(frame.name.clone()), idx));
+            idx = frame.ret_addr;
+        }
+        trace.push(self.call_site(None, idx));
+        trace
+    }
+
+    fn call_site(&self, name: Option<String>, idx: usize) -> CallSite {
+        let span = match self.prog.get(idx) {
+            Some(instr) => instr.span,
+            None => Span::default(),
+        };
+
+        CallSite { name, idx, span }
+    }
+
+    /// Replaces the std library used by std calls. This is used to run programs that
+    /// call functions registered by the host, and should be the same library that was
+    /// passed to the parser.
+    pub fn set_stdlib(&mut self, stdlib: Std) {
+        self.stdlib = stdlib;
+    }
+
+    /// Sets the name of the file the program was loaded from, which is reported along with
+    /// the source span in runtime errors and traces.
+    pub fn set_file(&mut self, name: &str) {
+        self.file = Some(name.to_string());
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    pub fn inspect_dreg(&self, idx: usize) -> Option<SifVal> {
+        self.dregs.get(idx).cloned()
     }
 
     pub fn inspect_heap(&self, name: &str) -> Option<&SifVal> {
-        self.heap.get(name)
+        match self.globals.slot(name) {
+            Some(slot) => self.load_global(slot),
+            None => None,
+        }
     }
 
-    fn execute(&mut self) -> Result<(), RuntimeErr> {
-        let idx = self.ip;
+    /// Returns the names of the globals, indexed by slot. The value of each can be found
+    /// with inspect_heap().
+    pub fn globals(&self) -> &GlobalTable {
+        &self.globals
+    }
 
+    /// Executes an instr and moves ip to the next instr to execute, which is the one after
+    /// it unless the instr jumps, calls or returns. If an error is returned, ip is left
+    /// pointing at the instr that failed.
+    fn execute(&mut self, instr: &Instr) -> Result<(), RuntimeErr> {
+        self.tick()?;
         if self.config.trace {
-            self.trace_instr(&self.prog[idx]);
+            self.trace_instr(instr)?;
         }
 
-        let curr = self.prog[idx].op.clone();
-        match curr {
-            Op::Ldc { dest, val } => self.loadc(dest, val)?,
-            Op::Ldn { dest, name } => self.loadn(dest, name)?,
-            Op::Mv { src, dest } => self.mv(src, dest)?,
-            Op::Ldas { name, dest } => self.loadarrs(name, dest)?,
+        match &instr.op {
+            // Constants are copied, so each evaluation of an array or table literal
+            // creates a new collection.
+            Op::Ldc { dest, val } => self.setreg(*dest, val.deep_copy())?,
+            Op::Ldn { dest, name } => self.loadn(*dest, Var::Name(name))?,
+            Op::Ldg { dest, slot } => self.loadn(*dest, Var::Slot(*slot))?,
+            Op::Mv { src, dest } => {
+                let to_move = self.dregs.get(*src).cloned();
+                self.setreg_contents(*dest, to_move)?;
+            }
+            Op::Ldas { name, dest } => self.loadarrs(Var::Name(name), *dest)?,
+            Op::Ldasg { slot, dest } => self.loadarrs(Var::Slot(*slot), *dest)?,
             Op::Ldav {
                 name,
                 idx_reg,
                 dest,
-            } => self.loadarrv(name, idx_reg, dest)?,
+            } => self.loadarrv(Var::Name(name), *idx_reg, *dest)?,
+            Op::Ldavg {
+                slot,
+                idx_reg,
+                dest,
+            } => self.loadarrv(Var::Slot(*slot), *idx_reg, *dest)?,
             Op::Upda {
                 name,
                 idx_reg,
                 val_reg,
-            } => self.newarrv(name, idx_reg, val_reg)?,
-            Op::Stc { name, val } => {
-                self.heap.insert(name.to_string(), val.clone());
-            }
-            Op::Str { name, src } => {
-                let reg = self.dregs.get(src);
-                let to_store = &reg.borrow().cont;
-                match to_store {
-                    Some(v) => self.heap.insert(name.to_string(), v.clone()),
-                    None => self.heap.insert(name.to_string(), SifVal::Null),
-                };
-            }
+            } => self.newarrv(Var::Name(name), *idx_reg, *val_reg)?,
+            Op::Updag {
+                slot,
+                idx_reg,
+                val_reg,
+            } => self.newarrv(Var::Slot(*slot), *idx_reg, *val_reg)?,
+            Op::Stc { name, val } => self.storec(Var::Name(name), val)?,
+            Op::Stcg { slot, val } => self.storec(Var::Slot(*slot), val)?,
+            Op::Str { name, src } => self.storer(Var::Name(name), *src)?,
+            Op::Strg { slot, src } => self.storer(Var::Slot(*slot), *src)?,
             Op::Stn { srcname, destname } => {
-                match self.heap.get(&srcname) {
-                    Some(v) => {
-                        let to_insert = v.clone();
-                        self.heap.insert(destname.to_string(), to_insert)
-                    }
-                    None => self.heap.insert(destname.to_string(), SifVal::Null),
-                };
-            }
-            Op::Jmpa { lblidx } => {
-                let codeidx = self.jumptab.get(&lblidx);
-                match codeidx {
-                    Some(i) => self.ip = *i - 1,
-                    None => return Err(self.newerr(RuntimeErrTy::InvalidJump)),
+                match self.load_val(Var::Name(srcname)) {
+                    Some(v) => self.store_name(destname, v)?,
+                    None => self.store_name(destname, SifVal::Null)?,
                 };
             }
+            Op::Jmpa { lblidx } => return self.jump(*lblidx),
             Op::JmpCnd { kind, src, lblidx } => {
-                let reg = self.dregs.get(src);
-                let contents = reg.borrow().cont.clone();
-                if contents.is_none() {
-                    return Err(self.newerr(RuntimeErrTy::TyMismatch));
-                }
-
-                match contents.unwrap() {
-                    SifVal::Bl(b) => {
-                        match kind {
-                            JmpOpKind::Jmpt => {
-                                if b {
-                                    let codeidx = self.jumptab.get(&lblidx);
-                                    match codeidx {
-                                        // jump to codeidx - 1 as it will be incremented at the end of
-                                        // the execution loop
-                                        Some(i) => self.ip = *i - 1,
-                                        None => return Err(self.newerr(RuntimeErrTy::InvalidJump)),
-                                    };
-                                }
-                            }
-                            JmpOpKind::Jmpf => {
-                                if !b {
-                                    let codeidx = self.jumptab.get(&lblidx);
-                                    match codeidx {
-                                        // jump to codeidx - 1 as it will be incremented at the end of
-                                        // the execution loop
-                                        Some(i) => self.ip = *i - 1,
-                                        None => return Err(self.newerr(RuntimeErrTy::InvalidJump)),
-                                    };
-                                }
-                            }
-                        };
-                    }
+                let cond = match self.dregs.get(*src) {
+                    Some(SifVal::Bl(b)) => *b,
                     _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
                 };
+
+                let should_jump = match kind {
+                    JmpOpKind::Jmpt => cond,
+                    JmpOpKind::Jmpf => !cond,
+                };
+                if should_jump {
+                    return self.jump(*lblidx);
+                }
             }
-            Op::Unary { kind, src1, dest } => self.unop(kind.clone(), src1, dest)?,
+            Op::Unary { kind, src1, dest } => self.unop(kind, *src1, *dest)?,
             Op::Binary {
                 kind,
                 src1,
                 src2,
                 dest,
-            } => self.binop(kind.clone(), src1, src2, dest)?,
-            Op::Incrr { src } => self.incrr(src)?,
-            Op::Decrr { src } => self.decrr(src)?,
-            Op::Fn { name, params: _ } => {
+            } => self.binop(kind, *src1, *src2, *dest)?,
+            Op::Incrr { src } => match self.dregs.get_mut(*src) {
+                Some(SifVal::Num(n)) => *n += 1.0,
+                Some(_) => return Err(self.newerr(RuntimeErrTy::InvalidIncrTy)),
+                None => return Err(self.newerr(RuntimeErrTy::InvalidIncr)),
+            },
+            Op::Decrr { src } => match self.dregs.get_mut(*src) {
+                Some(SifVal::Num(n)) => *n -= 1.0,
+                Some(_) => return Err(self.newerr(RuntimeErrTy::InvalidDecrTy)),
+                None => return Err(self.newerr(RuntimeErrTy::InvalidDecr)),
+            },
+            Op::Fn { name, .. } => {
                 // This case should never be executed, since fn decls
                 // should be in the decls section and not executed in the code loop.
                 self.fntab.insert(name.clone(), self.ip);
             }
             Op::FnRet => {
-                let ret_loc = self.call_stack.pop();
-                if ret_loc.is_none() {
+                let maybe_frame = self.call_stack.pop();
+                if maybe_frame.is_none() {
                     return Err(self.newerr(RuntimeErrTy::EmptyCallStack));
                 }
-                self.ip = ret_loc.unwrap();
+                let frame = maybe_frame.unwrap();
+
+                // Every call leaves exactly one value on the fn stack for the caller to pop.
+                // If the function returned without a value, we push null in its place.
+                let ret_val = match self.fn_stack.len() > frame.stack_base {
+                    true => self.fn_stack.pop().unwrap(),
+                    false => SifVal::Null,
+                };
+                self.fn_stack.truncate(frame.stack_base);
+                self.fn_stack.push(ret_val);
+
+                self.dregs = frame.caller_dregs;
+                self.ip = frame.ret_addr;
             }
-            Op::Call {
-                name,
-                param_count: _,
-            } => {
-                let maybe_loc = self.fntab.get(&name);
-                if maybe_loc.is_none() {
-                    return Err(self.newerr(RuntimeErrTy::InvalidFnSym(name.to_string())));
+            Op::Call { name, param_count } => self.call(name, *param_count, HashMap::new())?,
+            Op::Callr { src, param_count } => {
+                let (name, env) = match self.dregs.get(*src) {
+                    Some(SifVal::Fn { name, env }) => (name.clone(), env.clone()),
+                    Some(v) => return Err(self.newerr(RuntimeErrTy::NotCallable(v.to_string()))),
+                    None => {
+                        return Err(self.newerr(RuntimeErrTy::RegNoContents(self.reg_str(*src))))
+                    }
+                };
+                self.call(&name, *param_count, env)?;
+            }
+            Op::Ldf { name, env, dest } => {
+                // Capture the current value of each name into the env of the function value.
+                let mut captured = HashMap::with_capacity(env.len());
+                for n in env {
+                    let val = match self.load_name(n) {
+                        Some(v) => v.clone(),
+                        None => SifVal::Null,
+                    };
+                    captured.insert(n.clone(), val);
                 }
 
-                // Get the function location, and save our current location in cdr. This allows
-                // the call to return to our correct spot when completed. Then, jump to
-                // the location by setting ip to it.
-                let loc = maybe_loc.unwrap();
-                self.call_stack.push(self.ip);
-                self.ip = *loc;
+                let fnval = SifVal::Fn {
+                    name: name.clone(),
+                    env: captured,
+                };
+                self.setreg(*dest, fnval)?;
             }
             Op::StdCall { name, param_count } => {
                 // pop sifvals off stack up to param count, then
                 // look up fn name in lib table and run function with params
-                let mut params = Vec::new();
-                let mut i = 0;
-                while i < param_count {
-                    let v = self.fn_stack.pop();
-                    params.push(v.unwrap());
-                    i += 1;
+                if self.fn_stack.len() < *param_count {
+                    return Err(self.newerr(RuntimeErrTy::EmptyFnStack));
                 }
+                let params = self.fn_stack.split_off(self.fn_stack.len() - param_count);
+
                 // Std::call will return something always, but if the library function doesn't
-                // actually have a return value we will get SifVal::Null
-                let result = self.stdlib.call(&name, params);
-                self.fn_stack.push(result);
+                // actually have a return value we will get SifVal::Null. Errors from the library
+                // are given the location of this instruction. Std functions check the
+                // collection limit before building a collection, but host functions don't
+                // know about it, so results are checked again here.
+                let ctx = Context {
+                    io: &self.config.io,
+                    max_collection_len: self.config.limits.max_collection_len,
+                };
+                match self.stdlib.call(name, params, &ctx) {
+                    Ok(result) => {
+                        self.check_collection(&result)?;
+                        self.fn_stack.push(result);
+                    }
+                    Err(e) => return Err(self.newerr(e.ty)),
+                };
             }
             Op::FnStackPush { src } => {
-                let srcreg = self.dregs.get(src);
-                let to_push = srcreg.borrow().cont.clone();
-                self.fn_stack.push(to_push.unwrap());
+                let to_push = match self.dregs.get(*src) {
+                    Some(v) => v.clone(),
+                    None => {
+                        return Err(self.newerr(RuntimeErrTy::RegNoContents(self.reg_str(*src))))
+                    }
+                };
+                self.fn_stack.push(to_push);
             }
             Op::FnStackPop { dest } => {
                 let to_pop = self.fn_stack.pop();
-                self.dregs.set_contents(dest, to_pop);
-            }
-            Op::Tbli { tabname, key, src } => {
-                let srcreg = self.dregs.get(src);
-                let to_insert = srcreg.borrow().cont.clone();
-
-                match self.heap.get(&tabname) {
-                    Some(n) => match n {
-                        SifVal::Tab(hm) => {
-                            let mut map = hm.clone();
-                            map.insert(key.to_string(), to_insert.unwrap());
-                            self.heap.insert(tabname.to_string(), SifVal::Tab(map));
-                        }
-                        _ => {}
-                    },
-                    None => return Err(self.newerr(RuntimeErrTy::InvalidName(tabname.clone()))),
-                };
-            }
-            Op::Tblg { tabname, key, dest } => {
-                match self.heap.get(&tabname) {
-                    Some(n) => match n {
-                        SifVal::Tab(hm) => {
-                            let val = hm.get(&key).unwrap();
-                            self.dregs.set_contents(dest, Some(val.clone()));
-                        }
-                        _ => {}
-                    },
-                    None => return Err(self.newerr(RuntimeErrTy::InvalidName(tabname.clone()))),
-                };
+                self.setreg_contents(*dest, to_pop)?;
             }
+            Op::Tbli { tabname, key, src } => self.tbli(Var::Name(tabname), key, *src)?,
+            Op::Tblig { slot, key, src } => self.tbli(Var::Slot(*slot), key, *src)?,
+            Op::Tblg { tabname, key, dest } => self.tblg(Var::Name(tabname), key, *dest)?,
+            Op::Tblgg { slot, key, dest } => self.tblg(Var::Slot(*slot), key, *dest)?,
             Op::Stop => {
-                eprintln!("sif: stop instruction found, halting execution");
-                return Ok(());
+                let warning = "sif: stop instruction found, halting execution";
+                match self.config.io.eprintln(warning) {
+                    Ok(()) => {}
+                    Err(e) => return Err(self.newerr(RuntimeErrTy::IoErr(e.to_string()))),
+                };
             }
             Op::Nop => {}
         };
 
+        self.ip += 1;
         Ok(())
     }
 
-    fn loadc(&mut self, dest: usize, val: SifVal) -> Result<(), RuntimeErr> {
-        self.dregs.set_contents(dest, Some(val.clone()));
-        Ok(())
-    }
+    /// Calls the function with the given name. A new frame is created for the call
+    /// containing the function params and locals, with any names in env bound to their
+    /// captured values.
+    fn call(
+        &mut self,
+        name: &str,
+        param_count: usize,
+        env: HashMap<String, SifVal>,
+    ) -> Result<(), RuntimeErr> {
+        let maybe_loc = self.fntab.get(name);
+        if maybe_loc.is_none() {
+            return Err(self.newerr(RuntimeErrTy::InvalidFnSym(name.to_string())));
+        }
+        let loc = *maybe_loc.unwrap();
+
+        // The fn declaration contains the names of params and locals that belong
+        // in the new frame.
+        let names = match &self.prog[loc].op {
+            Op::Fn { params, locals, .. } => {
+                // Declared functions have their param count checked by the parser, but
+                // function values can be called with any number of params.
+                if params.len() != param_count {
+                    return Err(self.newerr(RuntimeErrTy::WrongFnParamCnt(
+                        name.to_string(),
+                        params.len(),
+                        param_count,
+                    )));
+                }
 
-    fn loadn(&mut self, dest: usize, name: String) -> Result<(), RuntimeErr> {
-        match self.heap.get(&name) {
-            Some(n) => self.dregs.set_contents(dest, Some(n.clone())),
-            None => return Err(self.newerr(RuntimeErrTy::InvalidName(name.clone()))),
+                let mut names = params.clone();
+                names.extend(locals.iter().cloned());
+                names
+            }
+            _ => return Err(self.newerr(RuntimeErrTy::InvalidFnSym(name.to_string()))),
         };
+
+        // Save our current location and registers in a new frame. This allows
+        // the call to return to our correct spot when completed. Then, jump to
+        // the location by setting ip to it.
+        let stack_base = self.fn_stack.len().saturating_sub(param_count);
+        let caller_dregs =
+            std::mem::replace(&mut self.dregs, DataRegisterList::init(FRAME_DREG_COUNT));
+        let mut frame = Frame::new(name, self.ip, names, caller_dregs, stack_base);
+        frame.locals.extend(env);
+        self.call_stack.push(frame);
+        self.ip = loc;
+
         Ok(())
     }
 
-    fn mv(&mut self, src: usize, dest: usize) -> Result<(), RuntimeErr> {
-        let srcreg = self.dregs.get(src);
-        let to_move = &srcreg.borrow().cont;
-        self.dregs.set_contents(dest, to_move.clone());
+    /// Jumps to the instruction at the given label. Jump ops return right after this, so
+    /// ip isn't advanced past the label's instruction.
+    fn jump(&mut self, lblidx: usize) -> Result<(), RuntimeErr> {
+        match self.jumptab.get(&lblidx) {
+            Some(i) => self.ip = *i,
+            None => return Err(self.newerr(RuntimeErrTy::InvalidJump)),
+        };
         Ok(())
     }
 
-    fn loadarrs(&mut self, name: String, dest: usize) -> Result<(), RuntimeErr> {
-        match self.heap.get(&name) {
-            Some(n) => match n {
-                SifVal::Arr(v) => self
-                    .dregs
-                    .set_contents(dest, Some(SifVal::Num(v.len() as f64))),
-                _ => return Err(self.newerr(RuntimeErrTy::NotAnArray(name.clone()))),
-            },
-            None => return Err(self.newerr(RuntimeErrTy::InvalidName(name.clone()))),
+    fn loadn(&mut self, dest: usize, var: Var) -> Result<(), RuntimeErr> {
+        match self.load_val(var) {
+            Some(val) => self.setreg(dest, val)?,
+            None => return Err(self.newerr(RuntimeErrTy::InvalidName(self.var_name(var)))),
         };
         Ok(())
     }
 
-    fn loadarrv(&mut self, name: String, idx_reg: usize, dest: usize) -> Result<(), RuntimeErr> {
-        let idx_reg = self.dregs.get(idx_reg);
-        let idx_sv = &idx_reg.borrow().cont;
-        if idx_sv.is_none() {
-            return Err(self.newerr(RuntimeErrTy::TyMismatch));
-        }
+    /// Stores a copy of a constant, which is checked against the collection limit as
+    /// constants aren't loaded into a register first.
+    fn storec(&mut self, var: Var, val: &SifVal) -> Result<(), RuntimeErr> {
+        self.check_collection(val)?;
+        self.store_var(var, val.deep_copy())
+    }
 
-        let maybe_to_idx = match &idx_sv.as_ref().unwrap() {
-            SifVal::Num(f) => Some(*f as usize),
-            _ => None,
+    /// Stores the contents of src register. An empty register stores null.
+    fn storer(&mut self, var: Var, src: usize) -> Result<(), RuntimeErr> {
+        let to_store = match self.dregs.get(src) {
+            Some(v) => v.clone(),
+            None => SifVal::Null,
         };
+        self.store_var(var, to_store)
+    }
 
-        if maybe_to_idx.is_none() {
-            return Err(self.newerr(RuntimeErrTy::TyMismatch));
-        }
-
-        let to_idx = maybe_to_idx.unwrap();
-
-        match self.heap.get(&name) {
-            Some(n) => match n {
-                SifVal::Arr(v) => self.dregs.set_contents(dest, Some(v[to_idx].clone())),
-                _ => return Err(self.newerr(RuntimeErrTy::NotAnArray(name.clone()))),
-            },
-            None => return Err(self.newerr(RuntimeErrTy::InvalidName(name.clone()))),
+    fn loadarrs(&mut self, var: Var, dest: usize) -> Result<(), RuntimeErr> {
+        let len = match self.load_var(var) {
+            Some(SifVal::Arr(v)) => v.borrow().len(),
+            Some(_) => return Err(self.newerr(RuntimeErrTy::NotAnArray(self.var_name(var)))),
+            None => return Err(self.newerr(RuntimeErrTy::InvalidName(self.var_name(var)))),
         };
-
-        Ok(())
+        self.setreg(dest, SifVal::Num(len as f64))
     }
 
-    fn newarrv(&mut self, name: String, idx_reg: usize, val_reg: usize) -> Result<(), RuntimeErr> {
-        let idx_reg = self.dregs.get(idx_reg);
-        let idx_sv = &idx_reg.borrow().cont;
-        if idx_sv.is_none() {
-            return Err(self.newerr(RuntimeErrTy::TyMismatch));
-        }
+    fn loadarrv(&mut self, var: Var, idx_reg: usize, dest: usize) -> Result<(), RuntimeErr> {
+        let to_idx = self.index_from_reg(idx_reg)?;
 
-        let maybe_to_idx = match &idx_sv.as_ref().unwrap() {
-            SifVal::Num(f) => Some(*f as usize),
-            _ => None,
+        let val = match self.load_var(var) {
+            Some(SifVal::Arr(v)) => {
+                let items = v.borrow();
+                match items.get(to_idx) {
+                    Some(item) => item.clone(),
+                    None => {
+                        return Err(self.newerr(RuntimeErrTy::IndexOutOfBounds(
+                            self.var_name(var),
+                            to_idx,
+                            items.len(),
+                        )))
+                    }
+                }
+            }
+            Some(_) => return Err(self.newerr(RuntimeErrTy::NotAnArray(self.var_name(var)))),
+            None => return Err(self.newerr(RuntimeErrTy::InvalidName(self.var_name(var)))),
         };
-        if maybe_to_idx.is_none() {
-            return Err(self.newerr(RuntimeErrTy::TyMismatch));
-        }
+        self.setreg(dest, val)
+    }
 
-        let to_idx = maybe_to_idx.unwrap();
+    /// Updates an item of an array in place. Every name referring to the array sees the
+    /// updated item.
+    fn newarrv(&mut self, var: Var, idx_reg: usize, val_reg: usize) -> Result<(), RuntimeErr> {
+        let to_idx = self.index_from_reg(idx_reg)?;
 
-        let val_reg = self.dregs.get(val_reg);
-        let val_sv = &val_reg.borrow().cont;
-        if val_sv.is_none() {
-            return Err(self.newerr(RuntimeErrTy::TyMismatch));
-        }
+        let val_sv = match self.dregs.get(val_reg) {
+            Some(v) => v.clone(),
+            None => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
+        };
 
-        let val_sv_r = val_sv.clone().unwrap();
-        let mut new_a: Vec<SifVal>;
+        match self.load_var(var) {
+            Some(arr @ SifVal::Arr(v)) => {
+                if val_sv.refers_to(arr) {
+                    return Err(self.newerr(RuntimeErrTy::CyclicStore(self.var_name(var))));
+                }
 
-        match self.heap.get(&name) {
-            Some(n) => match n {
-                SifVal::Arr(v) => {
-                    if to_idx >= v.len() {
-                        return Err(self.newerr(RuntimeErrTy::IndexOutOfBounds(
-                            name.clone(),
-                            to_idx,
-                            v.len(),
-                        )));
-                    }
-                    new_a = v.clone();
-                    new_a[to_idx] = val_sv_r;
+                let mut items = v.borrow_mut();
+                if to_idx >= items.len() {
+                    return Err(self.newerr(RuntimeErrTy::IndexOutOfBounds(
+                        self.var_name(var),
+                        to_idx,
+                        items.len(),
+                    )));
                 }
-                _ => return Err(self.newerr(RuntimeErrTy::NotAnArray(name.clone()))),
-            },
-            None => return Err(self.newerr(RuntimeErrTy::InvalidName(name.clone()))),
+                items[to_idx] = val_sv;
+            }
+            Some(_) => return Err(self.newerr(RuntimeErrTy::NotAnArray(self.var_name(var)))),
+            None => return Err(self.newerr(RuntimeErrTy::InvalidName(self.var_name(var)))),
         };
 
-        // This only works because we're guaranteed to return an error early before this name
-        // is set if anything is incorrect. If we can ever get here without setting new_a properly,
-        // this becomes incorrect.
-        self.heap.insert(name, SifVal::Arr(new_a));
-
         Ok(())
     }
 
-    fn unop(&mut self, kind: UnOpKind, src1: usize, dest: usize) -> Result<(), RuntimeErr> {
-        let srcreg = self.dregs.get(src1);
-        let mb_contents = srcreg.borrow().cont.clone();
-
-        if mb_contents.is_none() {
-            return Err(self.newerr(RuntimeErrTy::TyMismatch));
-        }
+    /// Inserts a value into a table in place. Every name referring to the table sees the
+    /// inserted value.
+    fn tbli(&mut self, var: Var, key: &str, src: usize) -> Result<(), RuntimeErr> {
+        let to_insert = match self.dregs.get(src) {
+            Some(v) => v.clone(),
+            None => return Err(self.newerr(RuntimeErrTy::RegNoContents(self.reg_str(src)))),
+        };
 
-        let contents = mb_contents.unwrap();
+        match self.load_var(var) {
+            Some(tab @ SifVal::Tab(hm)) => {
+                if to_insert.refers_to(tab) {
+                    return Err(self.newerr(RuntimeErrTy::CyclicStore(self.var_name(var))));
+                }
 
-        match kind {
-            UnOpKind::Lneg => {
-                match contents {
-                    SifVal::Bl(bl) => self.dregs.set_contents(dest, Some(SifVal::Bl(!bl))),
-                    _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-                };
-            }
-            UnOpKind::Nneg => {
-                match contents {
-                    SifVal::Num(num) => self.dregs.set_contents(dest, Some(SifVal::Num(-num))),
-                    _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
+                // Only new keys grow the table, so the limit is checked before they're
+                // inserted.
+                let mut entries = hm.borrow_mut();
+                match self.config.limits.max_collection_len {
+                    Some(max) if entries.len() >= max && !entries.contains_key(key) => {
+                        return Err(
+                            self.newerr(RuntimeErrTy::LimitExceeded(Limit::CollectionLen(max)))
+                        )
+                    }
+                    _ => (),
                 };
+                entries.insert(key.to_string(), to_insert);
             }
+            Some(_) => {}
+            None => return Err(self.newerr(RuntimeErrTy::InvalidName(self.var_name(var)))),
         };
 
         Ok(())
     }
 
+    fn tblg(&mut self, var: Var, key: &str, dest: usize) -> Result<(), RuntimeErr> {
+        let val = match self.load_var(var) {
+            Some(SifVal::Tab(hm)) => match hm.borrow().get(key) {
+                Some(v) => v.clone(),
+                None => {
+                    let name = self.var_name(var);
+                    return Err(self.newerr(RuntimeErrTy::MissingKey(name, key.to_string())));
+                }
+            },
+            Some(_) => return Err(self.newerr(RuntimeErrTy::NotATable(self.var_name(var)))),
+            None => return Err(self.newerr(RuntimeErrTy::InvalidName(self.var_name(var)))),
+        };
+        self.setreg(dest, val)
+    }
+
+    /// Reads an array index from a register. The register must contain a number.
+    fn index_from_reg(&self, idx_reg: usize) -> Result<usize, RuntimeErr> {
+        match self.dregs.get(idx_reg) {
+            Some(SifVal::Num(f)) => Ok(*f as usize),
+            _ => Err(self.newerr(RuntimeErrTy::TyMismatch)),
+        }
+    }
+
+    fn unop(&mut self, kind: &UnOpKind, src1: usize, dest: usize) -> Result<(), RuntimeErr> {
+        let result = match (kind, self.dregs.get(src1)) {
+            (UnOpKind::Lneg, Some(SifVal::Bl(bl))) => SifVal::Bl(!bl),
+            (UnOpKind::Nneg, Some(SifVal::Num(num))) => SifVal::Num(-num),
+            _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
+        };
+        self.setreg(dest, result)
+    }
+
     fn binop(
         &mut self,
-        kind: BinOpKind,
+        kind: &BinOpKind,
         src1: usize,
         src2: usize,
         dest: usize,
     ) -> Result<(), RuntimeErr> {
-        let src1reg = self.dregs.get(src1);
-        let src2reg = self.dregs.get(src2);
+        let contents1 = match self.dregs.get(src1) {
+            Some(v) => v,
+            None => return Err(self.newerr(RuntimeErrTy::RegNoContents(self.reg_str(src1)))),
+        };
+        let contents2 = match self.dregs.get(src2) {
+            Some(v) => v,
+            None => return Err(self.newerr(RuntimeErrTy::RegNoContents(self.reg_str(src2)))),
+        };
 
-        // TODO: what if src1 and src2 are the same reg? Can we still borrow?
-        let mb_contents1 = src1reg.borrow().cont.clone();
-        let mb_contents2 = src2reg.borrow().cont.clone();
+        // Operands are matched by reference, so registers are never cloned to compute
+        // a result.
+        let result = match (kind, contents1, contents2) {
+            (BinOpKind::Add, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Num(n1 + n2),
+            (BinOpKind::Sub, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Num(n1 - n2),
+            (BinOpKind::Mul, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Num(n1 * n2),
+            (BinOpKind::Div, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Num(n1 / n2),
+            (BinOpKind::Modu, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Num(n1 % n2),
+            (BinOpKind::Eq, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Bl(n1 == n2),
+            (BinOpKind::Neq, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Bl(n1 != n2),
+            (BinOpKind::LtEq, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Bl(n1 <= n2),
+            (BinOpKind::Lt, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Bl(n1 < n2),
+            (BinOpKind::GtEq, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Bl(n1 >= n2),
+            (BinOpKind::Gt, SifVal::Num(n1), SifVal::Num(n2)) => SifVal::Bl(n1 > n2),
+            (BinOpKind::Land, SifVal::Bl(b1), SifVal::Bl(b2)) => SifVal::Bl(*b1 && *b2),
+            (BinOpKind::Lnot, SifVal::Bl(b1), SifVal::Bl(b2)) => SifVal::Bl(b1 != b2),
+            (BinOpKind::Lor, SifVal::Bl(b1), SifVal::Bl(b2)) => SifVal::Bl(*b1 || *b2),
+            _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
+        };
+        self.setreg(dest, result)
+    }
 
-        if mb_contents1.is_none() {
-            return Err(self.newerr(RuntimeErrTy::RegNoContents(self.reg_str(src1))));
+    /// Looks up a name in the locals of the current call frame, falling back to the
+    /// global heap if the name is not a local or we aren't inside of a call.
+    fn load_name(&self, name: &str) -> Option<&SifVal> {
+        match self.call_stack.last() {
+            Some(frame) if frame.locals.contains_key(name) => frame.locals.get(name),
+            _ => match self.globals.slot(name) {
+                Some(slot) => self.load_global(slot),
+                None => None,
+            },
         }
+    }
 
-        if mb_contents2.is_none() {
-            return Err(self.newerr(RuntimeErrTy::RegNoContents(self.reg_str(src2))));
+    fn load_global(&self, slot: usize) -> Option<&SifVal> {
+        match self.heap.get(slot) {
+            Some(cont) => cont.as_ref(),
+            None => None,
         }
+    }
 
-        let contents1 = mb_contents1.unwrap().clone();
-        let contents2 = mb_contents2.unwrap().clone();
+    fn load_var(&self, var: Var) -> Option<&SifVal> {
+        match var {
+            Var::Name(name) => self.load_name(name),
+            Var::Slot(slot) => self.load_global(slot),
+        }
+    }
 
-        match kind {
-            BinOpKind::Add => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Num(n1 + n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Sub => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Num(n1 - n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Mul => match (contents1.clone(), contents2.clone()) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Num(n1 * n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Div => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Num(n1 / n2)));
+    /// Loads the value of a var like load_var, but names of declared functions are also
+    /// loaded as function values. This lets declared functions be passed around as values.
+    fn load_val(&self, var: Var) -> Option<SifVal> {
+        match self.load_var(var) {
+            Some(v) => Some(v.clone()),
+            None => {
+                let name = self.var_name(var);
+                match self.fntab.contains_key(&name) {
+                    true => Some(SifVal::Fn {
+                        name,
+                        env: HashMap::new(),
+                    }),
+                    false => None,
                 }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Modu => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Num(n1 % n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Eq => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Bl(n1 == n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Neq => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Bl(n1 != n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::LtEq => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Bl(n1 <= n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Lt => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Bl(n1 < n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::GtEq => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Bl(n1 >= n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Gt => match (contents1, contents2) {
-                (SifVal::Num(n1), SifVal::Num(n2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Bl(n1 > n2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Land => match (contents1, contents2) {
-                (SifVal::Bl(b1), SifVal::Bl(b2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Bl(b1 && b2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Lnot => match (contents1, contents2) {
-                (SifVal::Bl(b1), SifVal::Bl(b2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Bl(b1 != b2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
-            BinOpKind::Lor => match (contents1, contents2) {
-                (SifVal::Bl(b1), SifVal::Bl(b2)) => {
-                    self.dregs.set_contents(dest, Some(SifVal::Bl(b1 || b2)));
-                }
-                _ => return Err(self.newerr(RuntimeErrTy::TyMismatch)),
-            },
+            }
+        }
+    }
+
+    /// Stores a value by name. Names that are locals of the current call frame are
+    /// stored in that frame, and every other name is stored in the global heap.
+    fn store_name(&mut self, name: &str, val: SifVal) -> Result<(), RuntimeErr> {
+        let local = self
+            .call_stack
+            .last_mut()
+            .and_then(|frame| frame.locals.get_mut(name));
+
+        match local {
+            Some(local) => *local = val,
+            None => {
+                let slot = self.globals.intern(name);
+                self.store_global(slot, val)?;
+            }
         };
+        Ok(())
+    }
 
+    /// Stores a value in a global slot, growing the heap if needed. Slots are assigned to
+    /// every global the program names, including ones that are never stored, so the heap
+    /// limit is checked against the number of globals stored rather than the slot.
+    fn store_global(&mut self, slot: usize, val: SifVal) -> Result<(), RuntimeErr> {
+        if slot >= self.heap.len() {
+            self.heap.resize_with(slot + 1, || None);
+        }
+
+        if self.heap[slot].is_none() {
+            match self.config.limits.max_heap {
+                Some(max) if self.stored_globals >= max => {
+                    return Err(self.newerr(RuntimeErrTy::LimitExceeded(Limit::Heap(max))))
+                }
+                _ => self.stored_globals += 1,
+            };
+        }
+        self.heap[slot] = Some(val);
         Ok(())
     }
 
-    fn incrr(&mut self, src: usize) -> Result<(), RuntimeErr> {
-        let reg = self.dregs.get(src);
-        let contents = reg.borrow().cont.clone();
+    fn store_var(&mut self, var: Var, val: SifVal) -> Result<(), RuntimeErr> {
+        match var {
+            Var::Name(name) => self.store_name(name, val),
+            Var::Slot(slot) => self.store_global(slot, val),
+        }
+    }
 
-        // If contents are None, we have nothing to increment so we set a runtime err.
-        // If contents are Some, we must match on the val and ensure the kind of val
-        // can be incremented (ie. only a num). If it isn't we err. If it is, we can
-        // replace the value with an incremented one.
-        match contents {
-            Some(v) => match v {
-                SifVal::Num(n) => self.dregs.set_contents(src, Some(SifVal::Num(n + 1.0))),
-                _ => return Err(self.newerr(RuntimeErrTy::InvalidIncrTy)),
-            },
-            None => return Err(self.newerr(RuntimeErrTy::InvalidIncr)),
-        };
+    /// Sets a register, checking the register and collection limits. Collections that are
+    /// loaded from constants or names are checked here.
+    fn setreg(&mut self, dest: usize, val: SifVal) -> Result<(), RuntimeErr> {
+        self.check_dreg(dest)?;
+        self.check_collection(&val)?;
+        self.dregs.set(dest, val);
+        Ok(())
+    }
 
+    /// Sets the contents of a register to a value that has already been checked, such as
+    /// one moved from another register.
+    fn setreg_contents(&mut self, dest: usize, val: Option<SifVal>) -> Result<(), RuntimeErr> {
+        if val.is_some() {
+            self.check_dreg(dest)?;
+        }
+        self.dregs.set_contents(dest, val);
         Ok(())
     }
 
-    fn decrr(&mut self, src: usize) -> Result<(), RuntimeErr> {
-        let reg = self.dregs.get(src);
-        let contents = reg.borrow().cont.clone();
-        match contents {
-            Some(v) => match v {
-                SifVal::Num(n) => self.dregs.set_contents(src, Some(SifVal::Num(n - 1.0))),
-                _ => return Err(self.newerr(RuntimeErrTy::InvalidDecrTy)),
-            },
-            None => return Err(self.newerr(RuntimeErrTy::InvalidDecr)),
+    /// Checks that writing a value to a register stays within the register limit. Registers
+    /// are numbered across the whole program, so the limit applies to the registers that
+    /// hold a value in the current frame rather than to the register number.
+    fn check_dreg(&self, dest: usize) -> Result<(), RuntimeErr> {
+        if self.dregs.get(dest).is_some() {
+            return Ok(());
+        }
+        match self.config.limits.max_dregs {
+            Some(max) if self.dregs.live_count() >= max => {
+                Err(self.newerr(RuntimeErrTy::LimitExceeded(Limit::Dregs(max))))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the length of an array or table against the collection limit. Other values
+    /// are always within the limit.
+    fn check_collection(&self, val: &SifVal) -> Result<(), RuntimeErr> {
+        let max = match self.config.limits.max_collection_len {
+            Some(max) => max,
+            None => return Ok(()),
         };
 
-        Ok(())
+        let len = match val {
+            SifVal::Arr(items) => items.borrow().len(),
+            SifVal::Tab(entries) => entries.borrow().len(),
+            _ => 0,
+        };
+        match len > max {
+            true => Err(self.newerr(RuntimeErrTy::LimitExceeded(Limit::CollectionLen(max)))),
+            false => Ok(()),
+        }
     }
 
+    /// Returns the name of a var, for use in errors.
+    fn var_name(&self, var: Var) -> String {
+        match var {
+            Var::Name(name) => name.to_string(),
+            Var::Slot(slot) => self.slot_name(slot),
+        }
+    }
+
+    fn slot_name(&self, slot: usize) -> String {
+        match self.globals.name(slot) {
+            Some(name) => name.to_string(),
+            None => format!("g{}", slot),
+        }
+    }
+
+    /// Creates an error located at the source span of the instr being executed.
     fn newerr(&self, ty: RuntimeErrTy) -> RuntimeErr {
-        // TODO: instruction count doesnt work sometimes if error is in decl section
-        RuntimeErr::new(ty, self.ip + 1 - self.csi)
+        let span = match self.prog.get(self.ip) {
+            Some(instr) => instr.span,
+            None => Span::default(),
+        };
+        RuntimeErr::new(ty)
+            .at(span, self.file.clone())
+            .with_trace(self.err_trace())
     }
 
-    fn trace_instr(&self, instr: &Instr) {
-        println!("EXEC [code.{}]\t {:#}", instr.line, instr);
+    /// Builds the stack trace attached to runtime errors from the active calls. Functions
+    /// are located at the declaration that fntab points to.
+    fn err_trace(&self) -> Vec<TraceFrame> {
+        self.backtrace()
+            .into_iter()
+            .map(|site| {
+                let decl_span = match &site.name {
+                    Some(name) => match self.fntab.get(name).and_then(|i| self.prog.get(*i)) {
+                        Some(decl) => decl.span,
+                        None => Span::default(),
+                    },
+                    None => Span::default(),
+                };
+                TraceFrame {
+                    name: site.name,
+                    span: site.span,
+                    decl_span,
+                }
+            })
+            .collect()
+    }
+
+    /// Prints an instruction before it's executed, prefixed by its source location. Instrs
+    /// without a source span are prefixed by their line in the program instead. Slot indexed
+    /// ops are followed by the name of the global they access. Traces are written to the
+    /// output handle, so they're interleaved with what the program prints.
+    fn trace_instr(&self, instr: &Instr) -> Result<(), RuntimeErr> {
+        let loc = match instr.span.is_known() {
+            true => instr.span.with_file(self.file.as_deref()),
+            false => format!("code.{}", instr.line),
+        };
+        let line = match instr.op.slot() {
+            Some(slot) => format!("EXEC [{}]\t {:#}\t ; {}", loc, instr, self.slot_name(slot)),
+            None => format!("EXEC [{}]\t {:#}", loc, instr),
+        };
+        match self.config.io.println(&line) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(self.newerr(RuntimeErrTy::IoErr(e.to_string()))),
+        }
     }
 
     fn reg_str(&self, reg: usize) -> String {